@@ -0,0 +1,72 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[path = "build/proto.rs"]
+mod proto;
+
+/// The `.proto` files of the gRPC servers, generated into code by [`proto`].
+const PROTOS: [&str; 3] = [
+    "proto/pgevdb.proto",
+    "proto/health.proto",
+    "proto/reflection.proto",
+];
+
+fn main() {
+    // Expose the target triple so the binary can report (and later match artifacts to) its platform
+    println!(
+        "cargo:rustc-env=PGEVDB_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+
+    if env::var_os("CARGO_FEATURE_BUNDLED_EXTENSION").is_some() {
+        bundle_extension();
+    }
+    if env::var_os("CARGO_FEATURE_SERVER").is_some() {
+        generate_protos();
+    }
+}
+
+/// Generates the messages and descriptors of [`PROTOS`] into `OUT_DIR`.
+fn generate_protos() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=build/proto.rs");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    for path in PROTOS {
+        println!("cargo:rerun-if-changed={path}");
+        proto::generate(Path::new(path), &out_dir);
+    }
+}
+
+/// Copies the extension archive named by `PGEVDB_EXTENSION_ARCHIVE` into `OUT_DIR` so it can be
+/// embedded with `include_bytes!`. The archive must be the pgvecto.rs release zip for the
+/// target being built; build scripts deliberately don't download it themselves.
+fn bundle_extension() {
+    println!("cargo:rerun-if-env-changed=PGEVDB_EXTENSION_ARCHIVE");
+    println!("cargo:rerun-if-env-changed=PGEVDB_EXTENSION_ARCHIVE_VERSION");
+
+    let target = env::var("TARGET").unwrap_or_default();
+    let Ok(archive) = env::var("PGEVDB_EXTENSION_ARCHIVE") else {
+        panic!(
+            "The bundled-extension feature needs PGEVDB_EXTENSION_ARCHIVE set to the pgvecto.rs \
+             release zip for {target}, e.g. vectors-pg16_{target}_0.3.0.zip from \
+             https://github.com/tensorchord/pgvecto.rs/releases"
+        );
+    };
+    println!("cargo:rerun-if-changed={archive}");
+    let version = env::var("PGEVDB_EXTENSION_ARCHIVE_VERSION").unwrap_or_else(|_| "0.3.0".into());
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    std::fs::copy(&archive, out_dir.join("vectors.zip"))
+        .unwrap_or_else(|error| panic!("Failed to read {archive}: {error}"));
+    println!("cargo:rustc-env=PGEVDB_BUNDLED_EXTENSION_VERSION={version}");
+    // Release archives are named like vectors-pg16_<target>_<version>.zip; unknown if renamed
+    let pg_major = archive
+        .rsplit(['/', '\\'])
+        .next()
+        .and_then(|file_name| file_name.split_once("-pg"))
+        .and_then(|(_, rest)| rest.split_once('_'))
+        .map(|(major, _)| major)
+        .filter(|major| !major.is_empty() && major.bytes().all(|byte| byte.is_ascii_digit()))
+        .unwrap_or_default();
+    println!("cargo:rustc-env=PGEVDB_BUNDLED_EXTENSION_PG_MAJOR={pg_major}");
+}
@@ -0,0 +1,810 @@
+//! Generates the messages of the gRPC API from the files in `proto/`, so their field numbers and
+//! types can't drift from the definitions clients are generated from.
+//!
+//! Reads the proto3 the files use: messages of scalar, enum and message fields with implicit
+//! presence, `optional` or `repeated`, enums nested in messages or not, and services of methods
+//! that may stream. Anything else fails the build, naming what isn't supported.
+//!
+//! For each file, writes `<package>.rs` into `OUT_DIR`, with:
+//! - a struct per message implementing `crate::protobuf::Message`, and an enum per enum, whose
+//!   fields are `i32` as protobuf decoders keep unknown values;
+//! - `<SERVICE>_SERVICE` and `<SERVICE>_METHODS`, the name and method names of each service;
+//! - `FILE_NAME`, `SYMBOLS`, the fully-qualified names the file defines, and `FILE_DESCRIPTOR`,
+//!   the file as an encoded `google.protobuf.FileDescriptorProto`, for server reflection.
+
+use std::fmt::Write;
+use std::path::Path;
+
+/// Generates the code of the `.proto` file at `path` into `out_dir`.
+pub fn generate(path: &Path, out_dir: &Path) {
+    let source = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {error}", path.display()));
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let file = Parser::new(&source, name).file();
+    let descriptor = file.descriptor();
+    let stem = &file.package;
+    std::fs::write(out_dir.join(format!("{stem}.bin")), descriptor)
+        .unwrap_or_else(|error| panic!("Failed to write the descriptor of {name}: {error}"));
+    std::fs::write(out_dir.join(format!("{stem}.rs")), file.rust(stem))
+        .unwrap_or_else(|error| panic!("Failed to write the code of {name}: {error}"));
+}
+
+struct File {
+    name: String,
+    package: String,
+    messages: Vec<Message>,
+    enums: Vec<Enum>,
+    services: Vec<Service>,
+}
+
+struct Message {
+    name: String,
+    docs: Vec<String>,
+    fields: Vec<Field>,
+    enums: Vec<Enum>,
+}
+
+struct Field {
+    name: String,
+    docs: Vec<String>,
+    label: Label,
+    kind: String,
+    number: u32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Label {
+    Implicit,
+    Optional,
+    Repeated,
+}
+
+struct Enum {
+    name: String,
+    docs: Vec<String>,
+    values: Vec<(String, i32)>,
+}
+
+struct Service {
+    name: String,
+    methods: Vec<Method>,
+}
+
+struct Method {
+    name: String,
+    input: String,
+    output: String,
+    client_streaming: bool,
+    server_streaming: bool,
+}
+
+/// What a field holds, once its type name is resolved.
+enum Kind {
+    Scalar(Scalar),
+    /// With its name and fully-qualified name.
+    Message(String, String),
+    /// With its fully-qualified name; enum fields are `i32`s.
+    Enum(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scalar {
+    String,
+    Bytes,
+    Uint32,
+    Uint64,
+    Int32,
+    Int64,
+    Float,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "string" => Self::String,
+            "bytes" => Self::Bytes,
+            "uint32" => Self::Uint32,
+            "uint64" => Self::Uint64,
+            "int32" => Self::Int32,
+            "int64" => Self::Int64,
+            "float" => Self::Float,
+            _ => return None,
+        })
+    }
+
+    fn rust(self) -> &'static str {
+        match self {
+            Self::String => "String",
+            Self::Bytes => "Vec<u8>",
+            Self::Uint32 => "u32",
+            Self::Uint64 => "u64",
+            Self::Int32 => "i32",
+            Self::Int64 => "i64",
+            Self::Float => "f32",
+        }
+    }
+
+    /// Name of the methods of `Field` and `Encoder` for the type.
+    fn method(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Bytes => "bytes",
+            Self::Uint32 => "uint32",
+            Self::Uint64 => "uint64",
+            Self::Int32 => "int32",
+            Self::Int64 => "int64",
+            Self::Float => "float",
+        }
+    }
+
+    /// `google.protobuf.FieldDescriptorProto.Type`.
+    fn descriptor_type(self) -> u64 {
+        match self {
+            Self::Float => 2,
+            Self::Int64 => 3,
+            Self::Uint64 => 4,
+            Self::Int32 => 5,
+            Self::String => 9,
+            Self::Bytes => 12,
+            Self::Uint32 => 13,
+        }
+    }
+}
+
+impl File {
+    /// What the field `kind` names in `message`, looking in the message and then the file.
+    fn resolve(&self, message: &Message, kind: &str) -> Kind {
+        if let Some(scalar) = Scalar::parse(kind) {
+            return Kind::Scalar(scalar);
+        }
+        let package = &self.package;
+        if message.enums.iter().any(|nested| nested.name == kind) {
+            return Kind::Enum(format!(".{package}.{}.{kind}", message.name));
+        }
+        if self.enums.iter().any(|top| top.name == kind) {
+            return Kind::Enum(format!(".{package}.{kind}"));
+        }
+        if self.messages.iter().any(|top| top.name == kind) {
+            return Kind::Message(kind.to_string(), format!(".{package}.{kind}"));
+        }
+        panic!("{}: unknown type {kind} in {}", self.name, message.name);
+    }
+
+    fn rust(&self, stem: &str) -> String {
+        let mut code = format!(
+            "// Generated by build.rs from proto/{}; edit that instead.\n\n\
+             use crate::error::Result;\n\
+             use crate::protobuf::{{Encoder, Field, Message}};\n\n\
+             pub(crate) const FILE_NAME: &str = {:?};\n\n\
+             pub(crate) const FILE_DESCRIPTOR: &[u8] = \
+             include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{stem}.bin\"));\n\n\
+             pub(crate) const SYMBOLS: &[&str] = &{:?};\n",
+            self.name,
+            self.name,
+            self.symbols()
+        );
+        for service in &self.services {
+            let constant = screaming_snake_case(&service.name);
+            let methods: Vec<&str> = service.methods.iter().map(|m| m.name.as_str()).collect();
+            let _ = write!(
+                code,
+                "\npub(crate) const {constant}_SERVICE: &str = \"{}.{}\";\n\n\
+                 pub(crate) const {constant}_METHODS: &[&str] = &{methods:?};\n",
+                self.package, service.name
+            );
+        }
+        for enumeration in self
+            .enums
+            .iter()
+            .chain(self.messages.iter().flat_map(|m| &m.enums))
+        {
+            enumeration.rust(&mut code);
+        }
+        for message in &self.messages {
+            self.message_rust(message, &mut code);
+        }
+        code
+    }
+
+    fn message_rust(&self, message: &Message, code: &mut String) {
+        let fields: Vec<(&Field, Kind)> = message
+            .fields
+            .iter()
+            .map(|field| (field, self.resolve(message, &field.kind)))
+            .collect();
+        code.push('\n');
+        docs(code, "", &message.docs);
+        code.push_str("#[derive(Clone, Debug, Default, PartialEq)]\n");
+        if fields.is_empty() {
+            let _ = write!(
+                code,
+                "pub(crate) struct {name} {{}}\n\nimpl Message for {name} {{\n    \
+                 fn merge_field(&mut self, _: u32, _: Field<'_>) -> Result<()> {{\n        \
+                 Ok(())\n    }}\n\n    fn encode(&self, _: &mut Encoder) {{}}\n}}\n",
+                name = message.name
+            );
+            return;
+        }
+        let _ = writeln!(code, "pub(crate) struct {} {{", message.name);
+        for (field, kind) in &fields {
+            docs(code, "    ", &field.docs);
+            let rust = match kind {
+                Kind::Scalar(scalar) => scalar.rust(),
+                Kind::Enum(..) => "i32",
+                Kind::Message(name, _) => name,
+            };
+            // Message fields always have presence
+            let rust = match (field.label, kind) {
+                (Label::Repeated, _) => format!("Vec<{rust}>"),
+                (Label::Optional, _) | (Label::Implicit, Kind::Message(..)) => {
+                    format!("Option<{rust}>")
+                }
+                (Label::Implicit, _) => rust.to_string(),
+            };
+            let _ = writeln!(code, "    pub(crate) {}: {rust},", field.name);
+        }
+        let _ = writeln!(code, "}}\n\nimpl Message for {} {{", message.name);
+        code.push_str(
+            "    fn merge_field(&mut self, number: u32, field: Field<'_>) -> Result<()> {\n",
+        );
+        let merges: Vec<(u32, String)> = fields
+            .iter()
+            .map(|(field, kind)| (field.number, merge(field, kind)))
+            .collect();
+        if let [(number, merge)] = merges.as_slice() {
+            let _ = writeln!(
+                code,
+                "        if number == {number} {{\n            {merge};\n        }}"
+            );
+        } else {
+            code.push_str("        match number {\n");
+            for (number, merge) in &merges {
+                let _ = writeln!(code, "            {number} => {merge},");
+            }
+            code.push_str("            _ => {}\n        }\n");
+        }
+        code.push_str("        Ok(())\n    }\n\n    fn encode(&self, encoder: &mut Encoder) {\n");
+        for (field, kind) in &fields {
+            let _ = writeln!(code, "        {}", encode(field, kind));
+        }
+        code.push_str("    }\n}\n");
+    }
+
+    /// The fully-qualified names of the messages, enums, services and methods defined.
+    fn symbols(&self) -> Vec<String> {
+        let package = &self.package;
+        let mut symbols = Vec::new();
+        for enumeration in &self.enums {
+            symbols.push(format!("{package}.{}", enumeration.name));
+        }
+        for message in &self.messages {
+            symbols.push(format!("{package}.{}", message.name));
+            for nested in &message.enums {
+                symbols.push(format!("{package}.{}.{}", message.name, nested.name));
+            }
+        }
+        for service in &self.services {
+            symbols.push(format!("{package}.{}", service.name));
+            for method in &service.methods {
+                symbols.push(format!("{package}.{}.{}", service.name, method.name));
+            }
+        }
+        symbols
+    }
+
+    /// The file as an encoded `google.protobuf.FileDescriptorProto`.
+    fn descriptor(&self) -> Vec<u8> {
+        let mut file = Descriptor::default();
+        file.string(1, &self.name);
+        file.string(2, &self.package);
+        for message in &self.messages {
+            file.message(4, &self.message_descriptor(message));
+        }
+        for enumeration in &self.enums {
+            file.message(5, &enumeration.descriptor());
+        }
+        for service in &self.services {
+            let mut descriptor = Descriptor::default();
+            descriptor.string(1, &service.name);
+            for method in &service.methods {
+                let mut method_descriptor = Descriptor::default();
+                method_descriptor.string(1, &method.name);
+                method_descriptor.string(2, &format!(".{}.{}", self.package, method.input));
+                method_descriptor.string(3, &format!(".{}.{}", self.package, method.output));
+                if method.client_streaming {
+                    method_descriptor.varint_field(5, 1);
+                }
+                if method.server_streaming {
+                    method_descriptor.varint_field(6, 1);
+                }
+                descriptor.message(2, &method_descriptor);
+            }
+            file.message(6, &descriptor);
+        }
+        file.string(12, "proto3");
+        file.bytes
+    }
+
+    /// An encoded `google.protobuf.DescriptorProto`; `optional` fields each get the synthetic
+    /// oneof protoc gives them.
+    fn message_descriptor(&self, message: &Message) -> Descriptor {
+        let mut descriptor = Descriptor::default();
+        descriptor.string(1, &message.name);
+        let mut oneofs = Vec::new();
+        for field in &message.fields {
+            let mut field_descriptor = Descriptor::default();
+            field_descriptor.string(1, &field.name);
+            field_descriptor.varint_field(3, u64::from(field.number));
+            // LABEL_OPTIONAL, or LABEL_REPEATED
+            field_descriptor.varint_field(4, if field.label == Label::Repeated { 3 } else { 1 });
+            match self.resolve(message, &field.kind) {
+                Kind::Scalar(scalar) => field_descriptor.varint_field(5, scalar.descriptor_type()),
+                Kind::Message(_, full_name) => {
+                    field_descriptor.varint_field(5, 11);
+                    field_descriptor.string(6, &full_name);
+                }
+                Kind::Enum(full_name) => {
+                    field_descriptor.varint_field(5, 14);
+                    field_descriptor.string(6, &full_name);
+                }
+            }
+            if field.label == Label::Optional {
+                field_descriptor.varint_field(9, oneofs.len() as u64);
+                oneofs.push(format!("_{}", field.name));
+            }
+            field_descriptor.string(10, &lower_camel_case(&field.name));
+            if field.label == Label::Optional {
+                field_descriptor.varint_field(17, 1);
+            }
+            descriptor.message(2, &field_descriptor);
+        }
+        for nested in &message.enums {
+            descriptor.message(4, &nested.descriptor());
+        }
+        for oneof in &oneofs {
+            let mut oneof_descriptor = Descriptor::default();
+            oneof_descriptor.string(1, oneof);
+            descriptor.message(8, &oneof_descriptor);
+        }
+        descriptor
+    }
+}
+
+impl Enum {
+    fn rust(&self, code: &mut String) {
+        code.push('\n');
+        docs(code, "", &self.docs);
+        let _ = writeln!(
+            code,
+            "#[derive(Clone, Copy, Debug, PartialEq, Eq)]\n#[repr(i32)]\npub(crate) enum {} {{",
+            self.name
+        );
+        for (value, number) in &self.values {
+            let _ = writeln!(code, "    {} = {number},", upper_camel_case(value));
+        }
+        code.push_str("}\n");
+    }
+
+    /// An encoded `google.protobuf.EnumDescriptorProto`.
+    fn descriptor(&self) -> Descriptor {
+        let mut descriptor = Descriptor::default();
+        descriptor.string(1, &self.name);
+        for (name, number) in &self.values {
+            let mut value = Descriptor::default();
+            value.string(1, name);
+            // Negative int32s are sign-extended to 64 bits
+            value.varint_field(2, i64::from(*number) as u64);
+            descriptor.message(2, &value);
+        }
+        descriptor
+    }
+}
+
+/// The statement of `merge_field` that takes `field` from a decoded `field`.
+fn merge(field: &Field, kind: &Kind) -> String {
+    let name = &field.name;
+    let value = match kind {
+        Kind::Scalar(Scalar::Bytes) => "field.bytes()?.to_vec()".to_string(),
+        Kind::Scalar(scalar) => format!("field.{}()?", scalar.method()),
+        Kind::Enum(..) => "field.int32()?".to_string(),
+        Kind::Message(..) => "field.message()?".to_string(),
+    };
+    match (field.label, kind) {
+        (Label::Repeated, Kind::Scalar(Scalar::Float)) => {
+            format!("field.floats(&mut self.{name})?")
+        }
+        (Label::Repeated, Kind::Scalar(Scalar::Int64)) => {
+            format!("field.int64s(&mut self.{name})?")
+        }
+        (Label::Repeated, Kind::Scalar(Scalar::String | Scalar::Bytes) | Kind::Message(..)) => {
+            format!("self.{name}.push({value})")
+        }
+        (Label::Repeated, _) => panic!("repeated {} fields aren't supported", field.kind),
+        (Label::Optional, _) | (Label::Implicit, Kind::Message(..)) => {
+            format!("self.{name} = Some({value})")
+        }
+        (Label::Implicit, _) => format!("self.{name} = {value}"),
+    }
+}
+
+/// The statement of `encode` that writes `field`.
+fn encode(field: &Field, kind: &Kind) -> String {
+    let (name, number) = (&field.name, field.number);
+    match (field.label, kind) {
+        (Label::Repeated, Kind::Scalar(Scalar::Float)) => {
+            format!("encoder.floats({number}, &self.{name});")
+        }
+        (Label::Repeated, Kind::Scalar(Scalar::Int64)) => {
+            format!("encoder.int64s({number}, &self.{name});")
+        }
+        // Repeated values are written even when empty
+        (Label::Repeated, Kind::Scalar(scalar @ (Scalar::String | Scalar::Bytes))) => format!(
+            "for value in &self.{name} {{\n            encoder.optional_{}({number}, Some(value.{}()));\n        }}",
+            scalar.method(),
+            if *scalar == Scalar::String { "as_str" } else { "as_slice" }
+        ),
+        (Label::Repeated, Kind::Message(..)) => format!(
+            "for value in &self.{name} {{\n            encoder.message({number}, value);\n        }}"
+        ),
+        (Label::Implicit | Label::Optional, Kind::Message(..)) => format!(
+            "if let Some(value) = &self.{name} {{\n            encoder.message({number}, value);\n        }}"
+        ),
+        (Label::Optional, Kind::Scalar(scalar @ (Scalar::String | Scalar::Bytes))) => format!(
+            "encoder.optional_{}({number}, self.{name}.as_deref());",
+            scalar.method()
+        ),
+        (Label::Optional, Kind::Scalar(scalar)) => {
+            format!("encoder.optional_{}({number}, self.{name});", scalar.method())
+        }
+        (Label::Implicit, Kind::Scalar(Scalar::String)) => {
+            format!("encoder.string({number}, &self.{name});")
+        }
+        (Label::Implicit, Kind::Scalar(scalar)) if *scalar != Scalar::Bytes => {
+            format!("encoder.{}({number}, self.{name});", scalar.method())
+        }
+        (Label::Implicit, Kind::Enum(..)) => format!("encoder.int32({number}, self.{name});"),
+        _ => panic!("{name}: fields of type {} aren't supported here", field.kind),
+    }
+}
+
+fn docs(code: &mut String, indent: &str, docs: &[String]) {
+    for line in docs {
+        let _ = writeln!(
+            code,
+            "{indent}///{}{line}",
+            if line.is_empty() { "" } else { " " }
+        );
+    }
+}
+
+/// `SERVER_REFLECTION` for `ServerReflection`.
+fn screaming_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (position, character) in name.chars().enumerate() {
+        if character.is_ascii_uppercase() && position > 0 {
+            snake.push('_');
+        }
+        snake.push(character.to_ascii_uppercase());
+    }
+    snake
+}
+
+/// `NotServing` for `NOT_SERVING`.
+fn upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let lower = word.to_ascii_lowercase();
+            let mut characters = lower.chars();
+            characters
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + characters.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// `payloadJson` for `payload_json`, as protoc names fields in JSON.
+fn lower_camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    let mut upper = false;
+    for character in name.chars() {
+        if character == '_' {
+            upper = true;
+        } else if upper {
+            camel.push(character.to_ascii_uppercase());
+            upper = false;
+        } else {
+            camel.push(character);
+        }
+    }
+    camel
+}
+
+/// Builds an encoded descriptor message field by field, writing every field given.
+#[derive(Default)]
+struct Descriptor {
+    bytes: Vec<u8>,
+}
+
+impl Descriptor {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn varint_field(&mut self, number: u32, value: u64) {
+        self.varint(u64::from(number) << 3);
+        self.varint(value);
+    }
+
+    fn string(&mut self, number: u32, value: &str) {
+        self.length_delimited(number, value.as_bytes());
+    }
+
+    fn message(&mut self, number: u32, message: &Descriptor) {
+        self.length_delimited(number, &message.bytes);
+    }
+
+    fn length_delimited(&mut self, number: u32, bytes: &[u8]) {
+        self.varint(u64::from(number) << 3 | 2);
+        self.varint(bytes.len() as u64);
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+/// A token of a `.proto` file, with the comment lines right above it.
+struct Token {
+    text: String,
+    docs: Vec<String>,
+}
+
+struct Parser {
+    name: String,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(source: &str, name: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut docs = Vec::new();
+        let mut characters = source.char_indices().peekable();
+        while let Some((start, character)) = characters.next() {
+            match character {
+                '\n' => {
+                    // A blank line ends the comment above the next token
+                    if characters.peek().is_some_and(|&(_, next)| next == '\n') {
+                        docs.clear();
+                    }
+                }
+                _ if character.is_whitespace() => {}
+                '/' if source[start..].starts_with("//") => {
+                    let end = source[start..]
+                        .find('\n')
+                        .map_or(source.len(), |end| start + end);
+                    docs.push(source[start + 2..end].trim().to_string());
+                    while characters
+                        .peek()
+                        .is_some_and(|&(position, _)| position < end)
+                    {
+                        characters.next();
+                    }
+                }
+                '"' => {
+                    let end = source[start + 1..]
+                        .find('"')
+                        .map(|end| start + 1 + end)
+                        .unwrap_or_else(|| panic!("{name}: unterminated string"));
+                    tokens.push(Token {
+                        text: source[start..=end].to_string(),
+                        docs: std::mem::take(&mut docs),
+                    });
+                    while characters
+                        .peek()
+                        .is_some_and(|&(position, _)| position <= end)
+                    {
+                        characters.next();
+                    }
+                }
+                _ if character.is_ascii_alphanumeric() || character == '_' || character == '.' => {
+                    let mut end = start + 1;
+                    while let Some(&(position, next)) = characters.peek() {
+                        if !(next.is_ascii_alphanumeric() || next == '_' || next == '.') {
+                            break;
+                        }
+                        end = position + 1;
+                        characters.next();
+                    }
+                    tokens.push(Token {
+                        text: source[start..end].to_string(),
+                        docs: std::mem::take(&mut docs),
+                    });
+                }
+                '{' | '}' | '(' | ')' | ';' | '=' | '-' => tokens.push(Token {
+                    text: character.to_string(),
+                    docs: std::mem::take(&mut docs),
+                }),
+                _ => panic!("{name}: unsupported character {character:?}"),
+            }
+        }
+        Self {
+            name: name.to_string(),
+            tokens,
+            position: 0,
+        }
+    }
+
+    fn next(&mut self) -> &Token {
+        let Some(token) = self.tokens.get(self.position) else {
+            panic!("{}: unexpected end of file", self.name);
+        };
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens
+            .get(self.position)
+            .map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) {
+        let name = self.name.clone();
+        let found = &self.next().text;
+        assert!(
+            found == expected,
+            "{name}: expected {expected}, found {found}"
+        );
+    }
+
+    fn identifier(&mut self) -> String {
+        self.next().text.clone()
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> T {
+        let negative = self.peek() == Some("-");
+        if negative {
+            self.position += 1;
+        }
+        let text = self.identifier();
+        let text = if negative { format!("-{text}") } else { text };
+        text.parse()
+            .unwrap_or_else(|_| panic!("{}: invalid number {text}", self.name))
+    }
+
+    fn file(mut self) -> File {
+        let mut file = File {
+            name: self.name.clone(),
+            package: String::new(),
+            messages: Vec::new(),
+            enums: Vec::new(),
+            services: Vec::new(),
+        };
+        while self.position < self.tokens.len() {
+            let docs = self.tokens[self.position].docs.clone();
+            match self.identifier().as_str() {
+                "syntax" => {
+                    self.expect("=");
+                    let name = self.name.clone();
+                    let syntax = self.identifier();
+                    assert!(syntax == "\"proto3\"", "{name}: only proto3 is supported");
+                    self.expect(";");
+                }
+                "package" => {
+                    file.package = self.identifier();
+                    self.expect(";");
+                }
+                "message" => file.messages.push(self.message(docs)),
+                "enum" => file.enums.push(self.enumeration(docs)),
+                "service" => file.services.push(self.service()),
+                other => panic!("{}: {other} isn't supported", self.name),
+            }
+        }
+        assert!(!file.package.is_empty(), "{}: no package", self.name);
+        file
+    }
+
+    fn message(&mut self, docs: Vec<String>) -> Message {
+        let mut message = Message {
+            name: self.identifier(),
+            docs,
+            fields: Vec::new(),
+            enums: Vec::new(),
+        };
+        self.expect("{");
+        while self.peek() != Some("}") {
+            let docs = self.tokens[self.position].docs.clone();
+            let label = match self.peek() {
+                Some("enum") => {
+                    self.position += 1;
+                    message.enums.push(self.enumeration(docs));
+                    continue;
+                }
+                Some(unsupported @ ("message" | "oneof" | "map" | "reserved" | "option")) => {
+                    panic!("{}: {unsupported} in messages isn't supported", self.name)
+                }
+                Some("optional") => Label::Optional,
+                Some("repeated") => Label::Repeated,
+                _ => Label::Implicit,
+            };
+            if label != Label::Implicit {
+                self.position += 1;
+            }
+            let kind = self.identifier();
+            let name = self.identifier();
+            self.expect("=");
+            let number = self.number();
+            self.expect(";");
+            message.fields.push(Field {
+                name,
+                docs,
+                label,
+                kind,
+                number,
+            });
+        }
+        self.expect("}");
+        message
+    }
+
+    fn enumeration(&mut self, docs: Vec<String>) -> Enum {
+        let mut enumeration = Enum {
+            name: self.identifier(),
+            docs,
+            values: Vec::new(),
+        };
+        self.expect("{");
+        while self.peek() != Some("}") {
+            let name = self.identifier();
+            self.expect("=");
+            let number = self.number();
+            self.expect(";");
+            enumeration.values.push((name, number));
+        }
+        self.expect("}");
+        enumeration
+    }
+
+    fn service(&mut self) -> Service {
+        let mut service = Service {
+            name: self.identifier(),
+            methods: Vec::new(),
+        };
+        self.expect("{");
+        while self.peek() != Some("}") {
+            self.expect("rpc");
+            let name = self.identifier();
+            let (client_streaming, input) = self.method_type();
+            self.expect("returns");
+            let (server_streaming, output) = self.method_type();
+            self.expect(";");
+            service.methods.push(Method {
+                name,
+                input,
+                output,
+                client_streaming,
+                server_streaming,
+            });
+        }
+        self.expect("}");
+        service
+    }
+
+    /// `(Type)` or `(stream Type)`, and whether it streams.
+    fn method_type(&mut self) -> (bool, String) {
+        self.expect("(");
+        let streaming = self.peek() == Some("stream");
+        if streaming {
+            self.position += 1;
+        }
+        let name = self.identifier();
+        self.expect(")");
+        (streaming, name)
+    }
+}
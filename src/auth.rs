@@ -0,0 +1,414 @@
+//! Admission of requests to the [HTTP](crate::http) and [gRPC](crate::grpc) APIs, as the
+//! `[api]` table sets it up: authentication by keys, each with a [`Scope`], and per-client
+//! rate limits.
+
+use std::net::IpAddr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use hashlink::linked_hash_map::Entry;
+use hashlink::LinkedHashMap;
+
+use crate::config::{ApiConfig, ApiKey, Scope};
+
+/// Most clients whose recent requests are tracked; past it, the one that sent a request least
+/// recently is forgotten, and starts over with a full burst if it returns.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// What a request does with the database, checked against the scope of its key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    Read,
+    Write,
+}
+
+/// Why a request was refused.
+#[derive(Debug)]
+pub(crate) enum Denied {
+    /// It carries no key, or one that isn't configured.
+    Unauthenticated,
+    /// Its key's scope doesn't allow it.
+    Forbidden { name: String },
+    /// Its client is over its rate limit, and may retry after a while.
+    RateLimited { retry_after: Duration },
+}
+
+impl Denied {
+    pub(crate) fn message(&self) -> String {
+        match self {
+            Denied::Unauthenticated => {
+                "missing or unknown API key; send one as 'Authorization: Bearer KEY' or \
+                 'X-API-Key: KEY'"
+                    .to_string()
+            }
+            Denied::Forbidden { name } => {
+                format!("API key '{name}' is read-only")
+            }
+            Denied::RateLimited { retry_after } => {
+                format!(
+                    "too many requests; retry in {:.1} s",
+                    retry_after.as_secs_f64()
+                )
+            }
+        }
+    }
+}
+
+/// What a request must pass before it's served.
+#[derive(Debug)]
+pub(crate) struct Guard {
+    keys: Keys,
+    /// `None` when clients aren't rate limited.
+    limiter: Option<RateLimiter>,
+    /// Largest request body of a bulk insert, in bytes.
+    pub(crate) max_insert_bytes: usize,
+}
+
+impl Guard {
+    pub(crate) fn new(config: &ApiConfig) -> Self {
+        let limiter = (config.rate_limit_per_sec > 0).then(|| RateLimiter {
+            per_sec: f64::from(config.rate_limit_per_sec),
+            burst: f64::from(config.rate_limit_burst.unwrap_or(config.rate_limit_per_sec)),
+            buckets: Mutex::new(LinkedHashMap::new()),
+        });
+        Self {
+            keys: Keys {
+                keys: config.keys.clone(),
+            },
+            limiter,
+            max_insert_bytes: config.max_insert_bytes,
+        }
+    }
+
+    /// Admits a request from `address` for `access`, given the values of its `authorization`
+    /// and `x-api-key` headers, and counts it against the rate limit of its key, or of its
+    /// address without one. Returns the name of the key, or `None` if no keys are configured.
+    pub(crate) fn admit(
+        &self,
+        address: IpAddr,
+        authorization: Option<&str>,
+        api_key: Option<&str>,
+        access: Access,
+    ) -> Result<Option<&str>, Denied> {
+        let authorized = self.keys.authorize(authorization, api_key, access);
+        if let Some(limiter) = &self.limiter {
+            // Refused requests count too, against the address, so keys can't be guessed at
+            // full speed
+            let client = match authorized {
+                Ok(Some(name)) => Client::Key(name.to_string()),
+                _ => Client::Address(address),
+            };
+            limiter.acquire(client)?;
+        }
+        authorized
+    }
+}
+
+/// The keys clients may send. With none, every request is allowed.
+#[derive(Debug)]
+struct Keys {
+    keys: Vec<ApiKey>,
+}
+
+impl Keys {
+    /// Checks a request for `access` with the values of its `authorization` and `x-api-key`
+    /// headers, returning the name of its key, or `None` if no keys are configured.
+    fn authorize(
+        &self,
+        authorization: Option<&str>,
+        api_key: Option<&str>,
+        access: Access,
+    ) -> Result<Option<&str>, Denied> {
+        if self.keys.is_empty() {
+            return Ok(None);
+        }
+        let sent = authorization
+            .and_then(|value| {
+                let (scheme, token) = value.split_once(' ')?;
+                scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+            })
+            .or(api_key)
+            .ok_or(Denied::Unauthenticated)?;
+        // Every key is compared, so the time taken doesn't tell which one nearly matched
+        let key = self
+            .keys
+            .iter()
+            .fold(None, |found, key| {
+                if constant_time_eq(key.key.as_bytes(), sent.as_bytes()) {
+                    Some(key)
+                } else {
+                    found
+                }
+            })
+            .ok_or(Denied::Unauthenticated)?;
+        if access == Access::Write && key.scope == Scope::ReadOnly {
+            return Err(Denied::Forbidden {
+                name: key.name.clone(),
+            });
+        }
+        Ok(Some(&key.name))
+    }
+}
+
+/// Who a rate limit applies to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Client {
+    Key(String),
+    Address(IpAddr),
+}
+
+/// Requests a client may still make at once.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// A token bucket per client, holding up to `burst` requests and refilled with `per_sec`
+/// every second. Buckets are kept in the order their clients last sent a request, so the
+/// one to forget when too many are tracked is found at once.
+#[derive(Debug)]
+struct RateLimiter {
+    per_sec: f64,
+    burst: f64,
+    buckets: Mutex<LinkedHashMap<Client, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Takes a request from the bucket of `client`, or says how long until there's one.
+    fn acquire(&self, client: Client) -> Result<(), Denied> {
+        self.acquire_at(client, Instant::now())
+    }
+
+    /// [`acquire`](Self::acquire) at `now`.
+    fn acquire_at(&self, client: Client, now: Instant) -> Result<(), Denied> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&client) {
+            buckets.pop_front();
+        }
+        let bucket = match buckets.entry(client) {
+            Entry::Occupied(mut occupied) => {
+                occupied.to_back();
+                occupied.into_mut()
+            }
+            Entry::Vacant(vacant) => vacant.insert(Bucket {
+                tokens: self.burst,
+                refilled: now,
+            }),
+        };
+        let tokens = self.refill(bucket, now);
+        if tokens < 1.0 {
+            return Err(Denied::RateLimited {
+                retry_after: Duration::from_secs_f64((1.0 - tokens) / self.per_sec),
+            });
+        }
+        bucket.tokens = tokens - 1.0;
+        Ok(())
+    }
+
+    /// Adds the requests earned since `bucket` was last refilled, returning how many it holds.
+    fn refill(&self, bucket: &mut Bucket, now: Instant) -> f64 {
+        let earned = now.duration_since(bucket.refilled).as_secs_f64() * self.per_sec;
+        bucket.tokens = (bucket.tokens + earned).min(self.burst);
+        bucket.refilled = now;
+        bucket.tokens
+    }
+}
+
+/// Whether `a` and `b` are equal, in a time that only depends on their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const KEY: &str = "3f9c1d0e7b2a4c58a6e1f0d2b7c9e4a1";
+    const READ_ONLY_KEY: &str = "b81e6f2d94c04a7fa3d5e0c1f7a29b6e";
+
+    fn keys() -> Keys {
+        Keys {
+            keys: vec![
+                ApiKey {
+                    name: "ingest".to_string(),
+                    key: KEY.to_string(),
+                    scope: Scope::ReadWrite,
+                },
+                ApiKey {
+                    name: "frontend".to_string(),
+                    key: READ_ONLY_KEY.to_string(),
+                    scope: Scope::ReadOnly,
+                },
+            ],
+        }
+    }
+
+    fn limiter(per_sec: f64, burst: f64) -> RateLimiter {
+        RateLimiter {
+            per_sec,
+            burst,
+            buckets: Mutex::new(LinkedHashMap::new()),
+        }
+    }
+
+    fn address(index: u32) -> Client {
+        Client::Address(IpAddr::V4(Ipv4Addr::from(index)))
+    }
+
+    fn retry_after(result: &Result<(), Denied>) -> Option<Duration> {
+        match result {
+            Err(Denied::RateLimited { retry_after }) => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn accepts_bearer_tokens_and_api_key_headers() {
+        let keys = keys();
+        let bearer = format!("Bearer {KEY}");
+        let lowercase = format!("bearer  {KEY} ");
+        for (authorization, api_key) in [
+            (Some(bearer.as_str()), None),
+            (Some(lowercase.as_str()), None),
+            (None, Some(KEY)),
+            // Other schemes fall back to the X-API-Key header
+            (Some("Basic dXNlcjpwYXNz"), Some(KEY)),
+        ] {
+            assert!(
+                matches!(
+                    keys.authorize(authorization, api_key, Access::Write),
+                    Ok(Some("ingest"))
+                ),
+                "{authorization:?} {api_key:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_missing_and_unknown_keys() {
+        let keys = keys();
+        for (authorization, api_key) in [
+            (None, None),
+            (Some(KEY), None),
+            (Some("Bearer"), None),
+            (Some("Bearer 3f9c1d0e7b2a4c58a6e1f0d2b7c9e4a2"), None),
+            (None, Some("3f9c1d0e")),
+        ] {
+            assert!(
+                matches!(
+                    keys.authorize(authorization, api_key, Access::Read),
+                    Err(Denied::Unauthenticated)
+                ),
+                "{authorization:?} {api_key:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn read_only_keys_only_read() {
+        let keys = keys();
+        assert!(matches!(
+            keys.authorize(None, Some(READ_ONLY_KEY), Access::Read),
+            Ok(Some("frontend"))
+        ));
+        assert!(matches!(
+            keys.authorize(None, Some(READ_ONLY_KEY), Access::Write),
+            Err(Denied::Forbidden { name }) if name == "frontend"
+        ));
+    }
+
+    #[test]
+    fn admits_everyone_without_keys() {
+        let keys = Keys { keys: Vec::new() };
+        assert!(matches!(
+            keys.authorize(None, Some("anything"), Access::Write),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn refuses_past_the_burst_until_tokens_are_earned() {
+        let limiter = limiter(2.0, 3.0);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.acquire_at(address(1), start).is_ok());
+        }
+        assert_eq!(
+            retry_after(&limiter.acquire_at(address(1), start)),
+            Some(Duration::from_millis(500))
+        );
+        // Other clients have buckets of their own
+        assert!(limiter.acquire_at(address(2), start).is_ok());
+
+        let later = start + Duration::from_millis(250);
+        assert_eq!(
+            retry_after(&limiter.acquire_at(address(1), later)),
+            Some(Duration::from_millis(250))
+        );
+        assert!(limiter
+            .acquire_at(address(1), start + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn refills_up_to_the_burst() {
+        let limiter = limiter(10.0, 2.0);
+        let start = Instant::now();
+        assert!(limiter.acquire_at(address(1), start).is_ok());
+        let idle = start + Duration::from_secs(60);
+        assert!(limiter.acquire_at(address(1), idle).is_ok());
+        assert!(limiter.acquire_at(address(1), idle).is_ok());
+        assert!(retry_after(&limiter.acquire_at(address(1), idle)).is_some());
+    }
+
+    #[test]
+    fn forgets_the_least_recently_seen_client_when_full() {
+        let limiter = limiter(1.0, 1.0);
+        let now = Instant::now();
+        for index in 0..u32::try_from(MAX_TRACKED_CLIENTS).unwrap_or(u32::MAX) {
+            let _ = limiter.acquire_at(address(index), now);
+        }
+        // Client 0 comes back, so client 1 is now the least recently seen
+        assert!(retry_after(&limiter.acquire_at(address(0), now)).is_some());
+        assert!(limiter.acquire_at(address(u32::MAX), now).is_ok());
+
+        let buckets = limiter
+            .buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(buckets.len(), MAX_TRACKED_CLIENTS);
+        assert!(buckets.contains_key(&address(0)));
+        assert!(!buckets.contains_key(&address(1)));
+        assert!(buckets.contains_key(&address(2)));
+    }
+
+    #[test]
+    fn counts_refused_requests_against_the_address() {
+        let guard = Guard {
+            keys: keys(),
+            limiter: Some(limiter(1.0, 1.0)),
+            max_insert_bytes: 0,
+        };
+        let address = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert!(matches!(
+            guard.admit(address, None, Some("wrong"), Access::Read),
+            Err(Denied::Unauthenticated)
+        ));
+        assert!(matches!(
+            guard.admit(address, None, Some("wrong"), Access::Read),
+            Err(Denied::RateLimited { .. })
+        ));
+        // A valid key has its own bucket
+        assert!(matches!(
+            guard.admit(address, None, Some(KEY), Access::Read),
+            Ok(Some("ingest"))
+        ));
+    }
+}
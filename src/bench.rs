@@ -0,0 +1,55 @@
+use crate::cli::BenchCommand;
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::benchmark::{self, CandidateReport, CompareOptions, CompareReport};
+use pgevdb::{Collection, Config, Result};
+
+impl Report for CompareReport {
+    fn print_text(&self) {
+        println!(
+            "{}: {} vectors, {} queries, k={}",
+            self.collection, self.vectors, self.queries, self.k
+        );
+        println!(
+            "{:<2} {:<36} {:>9} {:>12} {:>9} {:>9} {:>9} {:>7}",
+            "", "index", "build s", "size bytes", "mean ms", "p50 ms", "p95 ms", "recall"
+        );
+        for (label, candidate) in [("A", &self.a), ("B", &self.b)] {
+            print_candidate(label, candidate);
+        }
+    }
+}
+
+fn print_candidate(label: &str, candidate: &CandidateReport) {
+    println!(
+        "{label:<2} {:<36} {:>9.2} {:>12} {:>9.2} {:>9.2} {:>9.2} {:>7.3}",
+        candidate.index,
+        candidate.build_secs,
+        candidate.index_bytes,
+        candidate.mean_ms,
+        candidate.p50_ms,
+        candidate.p95_ms,
+        candidate.recall
+    );
+}
+
+pub async fn run(command: BenchCommand, config: Config, output: OutputFormat) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+    match command {
+        BenchCommand::Compare {
+            collection,
+            a,
+            b,
+            sample,
+            queries,
+            k,
+        } => {
+            let collection = Collection::open(&pool, &collection).await?;
+            let options = CompareOptions { sample, queries, k };
+            emit(
+                output,
+                &benchmark::compare(&collection, &a, &b, &options).await?,
+            )
+        }
+    }
+}
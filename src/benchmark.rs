@@ -0,0 +1,225 @@
+//! Side-by-side comparison of index configurations on the same data and query workload.
+//!
+//! Each candidate is built on its own shadow copy of a collection, so the collection being
+//! tuned keeps serving searches with its current index while the comparison runs.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::collection::Collection;
+use crate::error::{Error, Result};
+use crate::index::IndexConfig;
+
+/// How much data and work a comparison uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompareOptions {
+    /// Copy a random sample of this many vectors instead of the whole collection.
+    pub sample: Option<u64>,
+    /// Number of query vectors, drawn at random from the copied vectors.
+    pub queries: u32,
+    /// Results per query; recall is measured at this cutoff.
+    pub k: u32,
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            sample: None,
+            queries: 100,
+            k: 10,
+        }
+    }
+}
+
+/// Measurements for one index configuration.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CandidateReport {
+    pub index: String,
+    pub build_secs: f64,
+    pub index_bytes: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    /// Share of the exact top `k` the index returned, averaged over queries.
+    pub recall: f64,
+}
+
+/// Result of [`compare`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CompareReport {
+    pub collection: String,
+    pub vectors: u64,
+    pub queries: u32,
+    pub k: u32,
+    pub a: CandidateReport,
+    pub b: CandidateReport,
+}
+
+/// Builds indexes `a` and `b` on shadow copies of `collection`, runs the same queries against
+/// both and reports build time, index size, latency and recall against exact search.
+///
+/// The shadow copies are dropped afterwards, also when the comparison fails.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if `queries` or `k` is 0 or the collection is empty, and
+/// [`Error::Database`] if copying the data or building an index fails.
+pub async fn compare(
+    collection: &Collection,
+    a: &IndexConfig,
+    b: &IndexConfig,
+    options: &CompareOptions,
+) -> Result<CompareReport> {
+    if options.queries == 0 || options.k == 0 {
+        return Err(Error::InvalidInput(
+            "queries and k must be at least 1".to_string(),
+        ));
+    }
+
+    let shadow_a = shadow(collection, "a", options.sample).await?;
+    let shadow_b = match shadow(&shadow_a, "b", None).await {
+        Ok(shadow_b) => shadow_b,
+        Err(error) => {
+            shadow_a.delete().await?;
+            return Err(error);
+        }
+    };
+    let report = compare_shadows(collection, &shadow_a, &shadow_b, a, b, options).await;
+    shadow_a.delete().await?;
+    shadow_b.delete().await?;
+    report
+}
+
+async fn compare_shadows(
+    collection: &Collection,
+    shadow_a: &Collection,
+    shadow_b: &Collection,
+    a: &IndexConfig,
+    b: &IndexConfig,
+    options: &CompareOptions,
+) -> Result<CompareReport> {
+    let pool = shadow_a.pool();
+    let (vectors,): (i64,) = sqlx::query_as(&format!("SELECT count(*) FROM {}", shadow_a.table()))
+        .fetch_one(pool)
+        .await?;
+    if vectors == 0 {
+        return Err(Error::InvalidInput(format!(
+            "collection '{}' is empty",
+            collection.name()
+        )));
+    }
+    let queries: Vec<(Vec<f32>,)> = sqlx::query_as(&format!(
+        "SELECT {} FROM {} ORDER BY random() LIMIT $1",
+        shadow_a.as_array("embedding"),
+        shadow_a.table()
+    ))
+    .bind(i64::from(options.queries))
+    .fetch_all(pool)
+    .await?;
+    let queries: Vec<Vec<f32>> = queries.into_iter().map(|(query,)| query).collect();
+
+    // Neither shadow has an index yet, so this is exact search
+    let mut exact = Vec::with_capacity(queries.len());
+    for query in &queries {
+        let results = shadow_a.search(query, options.k).await?;
+        exact.push(
+            results
+                .iter()
+                .map(|result| result.id)
+                .collect::<HashSet<_>>(),
+        );
+    }
+
+    Ok(CompareReport {
+        collection: collection.name().to_string(),
+        vectors: vectors.unsigned_abs(),
+        queries: u32::try_from(queries.len()).unwrap_or(u32::MAX),
+        k: options.k,
+        a: measure(shadow_a, a, &queries, &exact, options.k).await?,
+        b: measure(shadow_b, b, &queries, &exact, options.k).await?,
+    })
+}
+
+/// Copies `source`, or a random sample of it, into a new collection named after `label`.
+async fn shadow(source: &Collection, label: &str, sample: Option<u64>) -> Result<Collection> {
+    let name = format!("_bench_{label}_{}", std::process::id());
+    let shadow = Collection::create_with_storage(
+        source.pool(),
+        &name,
+        source.dimensions(),
+        source.metric(),
+        source.storage(),
+    )
+    .await?;
+    let limit = sample.map_or_else(String::new, |sample| {
+        format!(" ORDER BY random() LIMIT {sample}")
+    });
+    let copied = sqlx::query(&format!(
+        "INSERT INTO {} (id, embedding)
+         SELECT id, embedding FROM {} WHERE deleted_at IS NULL{limit}",
+        shadow.table(),
+        source.table()
+    ))
+    .execute(source.pool())
+    .await;
+    if let Err(error) = copied {
+        shadow.delete().await?;
+        return Err(error.into());
+    }
+    Ok(shadow)
+}
+
+/// Indexes `shadow` with `index` and times `queries` against it.
+#[allow(clippy::cast_precision_loss)]
+async fn measure(
+    shadow: &Collection,
+    index: &IndexConfig,
+    queries: &[Vec<f32>],
+    exact: &[HashSet<i64>],
+    k: u32,
+) -> Result<CandidateReport> {
+    let started = Instant::now();
+    shadow.create_index(index).await?;
+    let build = started.elapsed();
+
+    let mut latencies = Vec::with_capacity(queries.len());
+    let mut recall = 0.0;
+    for (query, exact) in queries.iter().zip(exact) {
+        let started = Instant::now();
+        let results = shadow.search(query, k).await?;
+        latencies.push(started.elapsed());
+        let found = results
+            .iter()
+            .filter(|result| exact.contains(&result.id))
+            .count();
+        recall += found as f64 / exact.len().max(1) as f64;
+    }
+    latencies.sort();
+
+    Ok(CandidateReport {
+        index: index.to_string(),
+        build_secs: build.as_secs_f64(),
+        index_bytes: shadow.index_size().await?.unwrap_or_default(),
+        mean_ms: millis(latencies.iter().sum::<Duration>()) / latencies.len() as f64,
+        p50_ms: millis(percentile(&latencies, 0.50)),
+        p95_ms: millis(percentile(&latencies, 0.95)),
+        recall: recall / queries.len() as f64,
+    })
+}
+
+/// Nearest-rank percentile of sorted, non-empty `latencies`.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn percentile(latencies: &[Duration], fraction: f64) -> Duration {
+    let rank = ((latencies.len() as f64 * fraction).ceil() as usize).max(1);
+    latencies[rank.min(latencies.len()) - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
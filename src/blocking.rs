@@ -0,0 +1,117 @@
+//! Synchronous wrappers over the async API, for CLI tools and GUI applications that don't run an
+//! async runtime of their own.
+//!
+//! Each [`PgEvDb`] owns a Tokio runtime that its calls are driven on, so these functions must not
+//! be called from within an async context.
+//!
+//! ```no_run
+//! # fn demo() -> pgevdb::Result<()> {
+//! let db = pgevdb::blocking::PgEvDb::new(pgevdb::PgEvDb::builder().build()?)?;
+//! let instance = db.start()?;
+//! instance.execute("CREATE TABLE IF NOT EXISTS items (id bigserial, embedding vector(3))")?;
+//! instance.stop()
+//! # }
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+
+use postgresql_embedded::PostgreSQL;
+use sqlx::postgres::PgPool;
+use tokio::runtime::Runtime;
+
+use crate::config::Config;
+use crate::error::Result;
+
+/// Blocking counterpart of [`crate::PgEvDb`].
+#[derive(Clone, Debug)]
+pub struct PgEvDb {
+    inner: crate::PgEvDb,
+    runtime: Arc<Runtime>,
+}
+
+impl PgEvDb {
+    /// Wraps a configured instance, starting the runtime its calls will run on.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`](crate::Error::Io) if the runtime can't be created.
+    pub fn new(inner: crate::PgEvDb) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &Config {
+        self.inner.config()
+    }
+
+    /// Blocking version of [`crate::PgEvDb::start`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`crate::PgEvDb::start`].
+    pub fn start(&self) -> Result<Instance> {
+        let (postgresql, pool) = self.runtime.block_on(self.inner.start())?;
+        Ok(Instance {
+            runtime: Arc::clone(&self.runtime),
+            postgresql: Some(postgresql),
+            pool,
+        })
+    }
+}
+
+/// A running server started by [`PgEvDb::start`]. The server stops when this is dropped.
+pub struct Instance {
+    runtime: Arc<Runtime>,
+    postgresql: Option<PostgreSQL>,
+    pool: PgPool,
+}
+
+impl Instance {
+    /// Pool connected to the configured database, for queries run through [`block_on`](Self::block_on).
+    #[must_use]
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Runs a future, such as an `sqlx` query against [`pool`](Self::pool), to completion.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Executes one SQL statement, returning the number of rows it affected.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`](crate::Error::Database) if the statement fails.
+    pub fn execute(&self, sql: &str) -> Result<u64> {
+        let result = self.block_on(sqlx::query(sql).execute(&self.pool))?;
+        Ok(result.rows_affected())
+    }
+
+    /// Closes the pool and stops the server, reporting shutdown errors that dropping would ignore.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PostgreSQL`](crate::Error::PostgreSQL) if the server doesn't stop cleanly.
+    pub fn stop(mut self) -> Result<()> {
+        self.runtime.block_on(self.pool.close());
+        if let Some(postgresql) = self.postgresql.take() {
+            self.runtime.block_on(postgresql.stop())?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // Close connections before the server goes away; PostgreSQL's own Drop stops the server
+        self.runtime.block_on(self.pool.close());
+    }
+}
@@ -0,0 +1,156 @@
+//! Database branches: full copies of a database inside the same embedded server, for
+//! experimenting on production-shaped data without touching the original.
+//!
+//! A branch is created with `CREATE DATABASE … TEMPLATE`, which copies the source's files
+//! directly and is much faster than a dump and restore, but requires that nobody is connected to
+//! the source while it runs. [`create`] therefore blocks new connections to the source and ends
+//! the existing ones for the duration of the copy; pools reconnect afterwards. Point
+//! `database_name` at a branch to work in it.
+
+use postgresql_embedded::PostgreSQL;
+use serde::Serialize;
+use sqlx::PgConnection;
+use tracing::info;
+
+use crate::database::{connect, exists, quote_identifier, MAINTENANCE_DATABASE, MAX_NAME_LENGTH};
+use crate::error::{Error, Result};
+use crate::identifier;
+
+/// Database comment marking a branch, followed by its source's name.
+const BRANCH_COMMENT: &str = "pgevdb branch of ";
+
+/// A database created by [`create`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Branch {
+    pub name: String,
+    /// Database the branch was copied from.
+    pub parent: String,
+    /// Size on disk.
+    pub bytes: u64,
+}
+
+/// Copies the database `from` into a new database `name`. Connections to `from`, including
+/// those of pools in this process, are closed while the copy runs.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if `name` isn't a valid branch name or already exists, or
+/// `from` is the maintenance database, [`Error::NotFound`] if `from` doesn't exist, and
+/// [`Error::Database`] if the copy fails.
+pub async fn create(postgresql: &PostgreSQL, name: &str, from: &str) -> Result<Branch> {
+    identifier::validate("branch", name, MAX_NAME_LENGTH)?;
+    // The copy runs over a connection to the maintenance database, and a database can't be
+    // copied while anyone is connected to it
+    if from == MAINTENANCE_DATABASE {
+        return Err(Error::InvalidInput(format!(
+            "'{from}' is the server's maintenance database, which can't be branched"
+        )));
+    }
+    let mut connection = connect(postgresql).await?;
+    if !exists(&mut connection, from).await? {
+        return Err(Error::NotFound(format!("database '{from}'")));
+    }
+    if exists(&mut connection, name).await? {
+        return Err(Error::InvalidInput(format!(
+            "database '{name}' already exists"
+        )));
+    }
+
+    let source = quote_identifier(from);
+    sqlx::query(&format!(
+        "ALTER DATABASE {source} WITH ALLOW_CONNECTIONS false"
+    ))
+    .execute(&mut connection)
+    .await?;
+    info!("Closing connections to '{from}' and copying it to '{name}'");
+    let copied = copy(&mut connection, name, from).await;
+    // Reopen the source even if the copy failed
+    sqlx::query(&format!(
+        "ALTER DATABASE {source} WITH ALLOW_CONNECTIONS true"
+    ))
+    .execute(&mut connection)
+    .await?;
+    copied?;
+
+    list_in(&mut connection)
+        .await?
+        .into_iter()
+        .find(|branch| branch.name == name)
+        .ok_or_else(|| Error::NotFound(format!("branch '{name}'")))
+}
+
+async fn copy(connection: &mut PgConnection, name: &str, from: &str) -> Result<()> {
+    sqlx::query(
+        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity
+         WHERE datname = $1 AND pid <> pg_backend_pid()",
+    )
+    .bind(from)
+    .execute(&mut *connection)
+    .await?;
+    sqlx::query(&format!(
+        "CREATE DATABASE {name} TEMPLATE {}",
+        quote_identifier(from)
+    ))
+    .execute(&mut *connection)
+    .await?;
+    // COMMENT takes no parameters
+    let comment = format!("{BRANCH_COMMENT}{from}").replace('\'', "''");
+    sqlx::query(&format!("COMMENT ON DATABASE {name} IS '{comment}'"))
+        .execute(&mut *connection)
+        .await?;
+    Ok(())
+}
+
+/// Every branch on the server, by name.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the server can't be queried.
+pub async fn list(postgresql: &PostgreSQL) -> Result<Vec<Branch>> {
+    list_in(&mut connect(postgresql).await?).await
+}
+
+async fn list_in(connection: &mut PgConnection) -> Result<Vec<Branch>> {
+    let rows: Vec<(String, String, i64)> = sqlx::query_as(
+        "SELECT datname, shobj_description(oid, 'pg_database'), pg_database_size(oid)
+         FROM pg_database
+         WHERE starts_with(shobj_description(oid, 'pg_database'), $1)
+         ORDER BY datname",
+    )
+    .bind(BRANCH_COMMENT)
+    .fetch_all(connection)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(name, comment, bytes)| Branch {
+            name,
+            parent: comment[BRANCH_COMMENT.len()..].to_string(),
+            bytes: bytes.unsigned_abs(),
+        })
+        .collect())
+}
+
+/// Drops the branch `name`, closing any connections to it. Databases that aren't branches are
+/// refused, so this can't drop the main database by mistake.
+///
+/// # Errors
+///
+/// Returns [`Error::NotFound`] if there is no branch called `name`, and [`Error::Database`] if
+/// it can't be dropped.
+pub async fn delete(postgresql: &PostgreSQL, name: &str) -> Result<()> {
+    let mut connection = connect(postgresql).await?;
+    if !list_in(&mut connection)
+        .await?
+        .iter()
+        .any(|branch| branch.name == name)
+    {
+        return Err(Error::NotFound(format!("branch '{name}'")));
+    }
+    sqlx::query(&format!(
+        "DROP DATABASE {} WITH (FORCE)",
+        quote_identifier(name)
+    ))
+    .execute(&mut connection)
+    .await?;
+    Ok(())
+}
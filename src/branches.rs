@@ -0,0 +1,94 @@
+use std::io::IsTerminal;
+
+use dialoguer::Confirm;
+use serde::Serialize;
+
+use crate::cli::BranchCommand;
+use crate::output::{emit, OutputFormat, Report};
+use pgevdb::branch::{self, Branch};
+use pgevdb::{Config, Error, PgEvDb, Result};
+
+impl Report for Branch {
+    fn print_text(&self) {
+        println!(
+            "{:<24} from {:<24} {}",
+            self.name,
+            self.parent,
+            indicatif::HumanBytes(self.bytes)
+        );
+    }
+}
+
+/// Result of `pgevdb branch list`.
+#[derive(Serialize)]
+struct ListReport {
+    branches: Vec<Branch>,
+}
+
+impl Report for ListReport {
+    fn print_text(&self) {
+        if self.branches.is_empty() {
+            println!("No branches");
+        }
+        for branch in &self.branches {
+            branch.print_text();
+        }
+    }
+}
+
+/// Result of `pgevdb branch delete`.
+#[derive(Serialize)]
+struct DeleteReport {
+    deleted: String,
+}
+
+impl Report for DeleteReport {
+    fn print_text(&self) {
+        println!("Deleted branch '{}'", self.deleted);
+    }
+}
+
+pub async fn run(command: BranchCommand, config: Config, output: OutputFormat) -> Result<()> {
+    let from_default = config.database_name.clone();
+    let (postgresql, pool) = PgEvDb::from_config(config)?.start().await?;
+    // Our own connections to the configured database would block copying it
+    pool.close().await;
+    match command {
+        BranchCommand::Create { name, from } => {
+            let from = from.unwrap_or(from_default);
+            emit(output, &branch::create(&postgresql, &name, &from).await?)
+        }
+        BranchCommand::List => emit(
+            output,
+            &ListReport {
+                branches: branch::list(&postgresql).await?,
+            },
+        ),
+        BranchCommand::Delete { name, yes } => {
+            if !yes {
+                confirm(&name)?;
+            }
+            branch::delete(&postgresql, &name).await?;
+            emit(output, &DeleteReport { deleted: name })
+        }
+    }
+}
+
+fn confirm(name: &str) -> Result<()> {
+    if !std::io::stderr().is_terminal() {
+        return Err(Error::Aborted(
+            "Refusing to delete a branch without confirmation; pass --yes to skip the prompt"
+                .to_string(),
+        ));
+    }
+    if !Confirm::new()
+        .with_prompt(format!(
+            "Delete branch '{name}' and all vectors stored in it?"
+        ))
+        .default(false)
+        .interact()?
+    {
+        return Err(Error::Aborted("Aborted".to_string()));
+    }
+    Ok(())
+}
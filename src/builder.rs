@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::{Backend, Config, Durability, Timeouts};
+use crate::error::Result;
+use crate::extension::Extension;
+use crate::instance::PgEvDb;
+
+/// Fluent builder for a [`PgEvDb`], created by [`PgEvDb::builder`].
+///
+/// Unset fields keep the [`Config`] defaults. Nothing is checked until [`build`](Self::build),
+/// which validates all settings together.
+#[derive(Clone, Debug, Default)]
+#[must_use]
+pub struct PgEvDbBuilder {
+    config: Config,
+    extensions: Vec<Arc<dyn Extension>>,
+}
+
+impl PgEvDbBuilder {
+    /// Exact PostgreSQL version to install, such as `16.3.0`.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.config.pg_version = version.into();
+        self
+    }
+
+    /// Root directory for the PostgreSQL installation, cluster data and password file.
+    pub fn data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.config.storage_dir = data_dir.into();
+        self
+    }
+
+    /// Port to listen on; `0` picks a free port on every start.
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    /// Database created on first start and connected to; other databases of the server, such
+    /// as [branches](crate::branch), are reached by building another instance with their name.
+    pub fn database_name(mut self, database_name: impl Into<String>) -> Self {
+        self.config.database_name = database_name.into();
+        self
+    }
+
+    /// Vector extension to install. An extension version that is still the previous backend's
+    /// default becomes the new backend's default.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        if self.config.extension_version == self.config.backend.default_extension_version() {
+            self.config.extension_version = backend.default_extension_version().to_string();
+        }
+        self.config.backend = backend;
+        self
+    }
+
+    /// Where downloads and source builds are cached, instead of the platform cache directory.
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.config.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Parent of the temporary directory the extension archive is extracted into, instead of
+    /// the system temp directory.
+    pub fn staging_dir(mut self, staging_dir: impl Into<PathBuf>) -> Self {
+        self.config.staging_dir = Some(staging_dir.into());
+        self
+    }
+
+    /// Directory for PostgreSQL's Unix-domain socket.
+    pub fn socket_dir(mut self, socket_dir: impl Into<PathBuf>) -> Self {
+        self.config.socket_dir = Some(socket_dir.into());
+        self
+    }
+
+    /// Unprivileged account to switch to when started as root. Unix only.
+    pub fn run_as_user(mut self, user: impl Into<String>) -> Self {
+        self.config.run_as_user = Some(user.into());
+        self
+    }
+
+    /// Proxy for downloads, instead of the `HTTPS_PROXY` and `HTTP_PROXY` environment variables.
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.config.proxy = Some(proxy.into());
+        self
+    }
+
+    /// GitHub token sent with GitHub API requests, for a higher rate limit.
+    pub fn github_token(mut self, token: impl Into<String>) -> Self {
+        self.config.github_token = Some(token.into());
+        self
+    }
+
+    /// Durability profile trading crash safety for write throughput.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.config.durability = durability;
+        self
+    }
+
+    /// Download, startup and query timeouts.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.config.timeouts = timeouts;
+        self
+    }
+
+    /// Version of the vector extension to install.
+    pub fn extension_version(mut self, version: impl Into<String>) -> Self {
+        self.config.extension_version = version.into();
+        self
+    }
+
+    /// Expected SHA-256 of the extension archive; a download with another hash is refused.
+    pub fn extension_sha256(mut self, sha256: impl Into<String>) -> Self {
+        self.config.extension_sha256 = Some(sha256.into());
+        self
+    }
+
+    /// Install the extension from this local archive instead of downloading it.
+    pub fn extension_archive(mut self, archive: impl Into<PathBuf>) -> Self {
+        self.config.extension_archive = Some(archive.into());
+        self
+    }
+
+    /// URL template of an extension archive mirror, with `{version}`, `{pg_major}` and `{triple}`
+    /// placeholders.
+    pub fn extension_url(mut self, template: impl Into<String>) -> Self {
+        self.config.extension_url = Some(template.into());
+        self
+    }
+
+    /// Compile the extension locally when no prebuilt archive matches the platform.
+    pub fn build_from_source(mut self, build_from_source: bool) -> Self {
+        self.config.build_from_source = build_from_source;
+        self
+    }
+
+    /// Another extension to install, configure and create on every start after the vector
+    /// extension, e.g. [`Contrib::new("pg_trgm")`](crate::extension::Contrib).
+    pub fn extension(mut self, extension: impl Extension + 'static) -> Self {
+        self.extensions.push(Arc::new(extension));
+        self
+    }
+
+    /// Applies the [environment overrides](crate::config::ENV_OVERRIDES), validates the
+    /// settings and returns the configured instance, without starting it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`](crate::Error::Config) if a setting is invalid on its own or
+    /// conflicts with another, e.g. a PostgreSQL version the backend has no builds for.
+    pub fn build(mut self) -> Result<PgEvDb> {
+        self.config = self.config.with_env_overrides()?;
+        let current_dir = std::env::current_dir()?;
+        if self.config.storage_dir.is_relative() {
+            self.config.storage_dir = current_dir.join(&self.config.storage_dir);
+        }
+        for dir in [
+            &mut self.config.cache_dir,
+            &mut self.config.staging_dir,
+            &mut self.config.socket_dir,
+            &mut self.config.extension_archive,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if dir.is_relative() {
+                *dir = current_dir.join(&*dir);
+            }
+        }
+        Ok(PgEvDb::from_config(self.config)?.with_extensions(self.extensions))
+    }
+}
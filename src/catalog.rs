@@ -0,0 +1,218 @@
+//! pgevdb's own metadata, kept in the `pgevdb` schema of the configured database.
+
+use sqlx::postgres::PgPool;
+
+use crate::error::{Error, Result};
+
+/// Catalog layout this binary reads and writes. Bump it together with a new entry in
+/// [`MIGRATIONS`] whenever the catalog tables change.
+pub const SCHEMA_VERSION: u32 = 17;
+
+/// SQL upgrading the catalog from version `n` (the index) to `n + 1`.
+const MIGRATIONS: &[&str] = &[
+    "CREATE SCHEMA IF NOT EXISTS pgevdb;
+     CREATE TABLE IF NOT EXISTS pgevdb.meta (key text PRIMARY KEY, value text NOT NULL);",
+    "CREATE SCHEMA collections;
+     CREATE TABLE pgevdb.collections (
+         name text PRIMARY KEY,
+         dimensions integer NOT NULL,
+         metric text NOT NULL,
+         created_at timestamptz NOT NULL DEFAULT now()
+     );",
+    "CREATE TABLE pgevdb.eval_datasets (
+         name text PRIMARY KEY,
+         created_at timestamptz NOT NULL DEFAULT now()
+     );
+     CREATE TABLE pgevdb.eval_queries (
+         dataset text NOT NULL REFERENCES pgevdb.eval_datasets ON DELETE CASCADE,
+         position integer NOT NULL,
+         embedding real[] NOT NULL,
+         relevant_ids bigint[] NOT NULL,
+         PRIMARY KEY (dataset, position)
+     );
+     CREATE TABLE pgevdb.eval_runs (
+         id bigserial PRIMARY KEY,
+         dataset text NOT NULL,
+         collection text NOT NULL,
+         k bigint NOT NULL,
+         queries bigint NOT NULL,
+         recall double precision NOT NULL,
+         mrr double precision NOT NULL,
+         ndcg double precision NOT NULL,
+         ran_at timestamptz NOT NULL DEFAULT now()
+     );",
+    "CREATE TABLE pgevdb.embedders (
+         id bigserial PRIMARY KEY,
+         provider text NOT NULL,
+         model text NOT NULL,
+         version text NOT NULL,
+         dimensions integer NOT NULL,
+         normalized boolean NOT NULL,
+         created_at timestamptz NOT NULL DEFAULT now(),
+         UNIQUE (provider, model, version)
+     );
+     ALTER TABLE pgevdb.collections ADD COLUMN embedder bigint REFERENCES pgevdb.embedders;",
+    "CREATE TABLE pgevdb.transforms (
+         collection text PRIMARY KEY REFERENCES pgevdb.collections ON DELETE CASCADE,
+         kind text NOT NULL,
+         input_dimensions integer NOT NULL,
+         output_dimensions integer NOT NULL,
+         mean real[],
+         components real[],
+         reduced_column boolean NOT NULL,
+         created_at timestamptz NOT NULL DEFAULT now()
+     );",
+    "CREATE TABLE pgevdb.centroids (
+         collection text NOT NULL REFERENCES pgevdb.collections ON DELETE CASCADE,
+         cluster integer NOT NULL,
+         centroid real[] NOT NULL,
+         size bigint NOT NULL,
+         PRIMARY KEY (collection, cluster)
+     );",
+    // Rows that existed before insert times were recorded keep a NULL inserted_at
+    "DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('ALTER TABLE collections.%I ADD COLUMN inserted_at timestamptz',
+                            collection);
+             EXECUTE format('ALTER TABLE collections.%I ALTER COLUMN inserted_at SET DEFAULT now()',
+                            collection);
+         END LOOP;
+     END $$;
+     CREATE TABLE pgevdb.drift_checks (
+         id bigserial PRIMARY KEY,
+         collection text NOT NULL REFERENCES pgevdb.collections ON DELETE CASCADE,
+         baseline_mean double precision NOT NULL,
+         baseline_stddev double precision NOT NULL,
+         recent_vectors bigint NOT NULL,
+         outliers bigint NOT NULL,
+         drifted_windows bigint NOT NULL,
+         checked_at timestamptz NOT NULL DEFAULT now()
+     );",
+    "DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('ALTER TABLE collections.%I ADD COLUMN content text', collection);
+         END LOOP;
+     END $$;",
+    "DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('ALTER TABLE collections.%I ADD COLUMN source text', collection);
+         END LOOP;
+     END $$;",
+    "ALTER TABLE pgevdb.collections
+         ADD COLUMN text_language text NOT NULL DEFAULT 'english',
+         ADD COLUMN text_unaccent boolean NOT NULL DEFAULT false;
+     DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('ALTER TABLE collections.%I
+                                 ADD COLUMN language text,
+                                 ADD COLUMN content_tsv tsvector', collection);
+             EXECUTE format('UPDATE collections.%I SET content_tsv = to_tsvector(''english'', content)
+                             WHERE content IS NOT NULL', collection);
+             EXECUTE format('CREATE INDEX %I ON collections.%I USING gin (content_tsv)',
+                            collection || '_content_idx', collection);
+         END LOOP;
+     END $$;",
+    "ALTER TABLE pgevdb.collections ADD COLUMN namespace text NOT NULL DEFAULT 'default';
+     CREATE TABLE pgevdb.quotas (
+         namespace text PRIMARY KEY,
+         max_rows bigint,
+         max_bytes bigint,
+         used_rows bigint NOT NULL DEFAULT 0,
+         used_bytes bigint NOT NULL DEFAULT 0,
+         reconciled_at timestamptz
+     );",
+    "CREATE TABLE pgevdb.usage_stats (
+         hour timestamptz NOT NULL,
+         collection text NOT NULL,
+         operation text NOT NULL,
+         shape text NOT NULL,
+         calls bigint NOT NULL,
+         total_ms double precision NOT NULL,
+         PRIMARY KEY (hour, collection, operation, shape)
+     );",
+    "DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('ALTER TABLE collections.%I ADD COLUMN payload jsonb', collection);
+         END LOOP;
+     END $$;",
+    "DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('CREATE INDEX %I ON collections.%I USING gin (payload jsonb_path_ops)',
+                            collection || '_payload_idx', collection);
+         END LOOP;
+     END $$;",
+    "ALTER TABLE pgevdb.collections ADD COLUMN storage text NOT NULL DEFAULT 'f32';",
+    "DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('ALTER TABLE collections.%I ADD COLUMN expires_at timestamptz',
+                            collection);
+             EXECUTE format('CREATE INDEX %I ON collections.%I (expires_at)
+                             WHERE expires_at IS NOT NULL', collection || '_expires_idx', collection);
+         END LOOP;
+     END $$;",
+    "DO $$
+     DECLARE collection text;
+     BEGIN
+         FOR collection IN SELECT name FROM pgevdb.collections LOOP
+             EXECUTE format('ALTER TABLE collections.%I ADD COLUMN deleted_at timestamptz',
+                            collection);
+             EXECUTE format('CREATE INDEX %I ON collections.%I (id)
+                             WHERE deleted_at IS NOT NULL', collection || '_deleted_idx', collection);
+         END LOOP;
+     END $$;",
+];
+
+/// The catalog version recorded in the database, or `None` if it has no catalog yet.
+pub async fn schema_version(pool: &PgPool) -> Result<Option<u32>> {
+    // to_regclass returns NULL instead of failing when the table doesn't exist
+    let (meta_table,): (Option<String>,) =
+        sqlx::query_as("SELECT to_regclass('pgevdb.meta')::text")
+            .fetch_one(pool)
+            .await?;
+    if meta_table.is_none() {
+        return Ok(None);
+    }
+
+    let version: Option<(String,)> =
+        sqlx::query_as("SELECT value FROM pgevdb.meta WHERE key = 'schema_version'")
+            .fetch_optional(pool)
+            .await?;
+    version
+        .map(|(version,)| {
+            version.parse().map_err(|_| {
+                Error::Incompatible(format!("unreadable catalog schema version '{version}'"))
+            })
+        })
+        .transpose()
+}
+
+/// Applies the migrations after version `from` in one transaction and records the new version.
+pub async fn migrate(pool: &PgPool, from: u32) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    for migration in &MIGRATIONS[from as usize..] {
+        sqlx::raw_sql(migration).execute(&mut *transaction).await?;
+    }
+    sqlx::query(
+        "INSERT INTO pgevdb.meta (key, value) VALUES ('schema_version', $1)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+    )
+    .bind(SCHEMA_VERSION.to_string())
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use dialoguer::Confirm;
+use serde::Serialize;
+
+use crate::info::dir_size;
+use crate::output::Report;
+use pgevdb::config::{Config, LEGACY_STAGING_DIR};
+use pgevdb::{Error, Result};
+
+/// A part of the storage directory that `pgevdb clean` can remove.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Re-downloadable artifacts: the PostgreSQL installation, download cache and leftover staging dirs.
+    Cache,
+    /// The database cluster and its password file. Irreversible.
+    Data,
+    /// The whole storage directory.
+    All,
+}
+
+/// Result of `pgevdb clean`.
+#[derive(Serialize)]
+pub struct CleanReport {
+    pub removed: Vec<RemovedPath>,
+    pub reclaimed_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct RemovedPath {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+impl Report for CleanReport {
+    fn print_text(&self) {
+        if self.removed.is_empty() {
+            println!("Nothing to clean");
+            return;
+        }
+        for removed in &self.removed {
+            println!(
+                "Removed {} ({})",
+                removed.path.display(),
+                indicatif::HumanBytes(removed.size_bytes)
+            );
+        }
+        println!("Reclaimed {}", indicatif::HumanBytes(self.reclaimed_bytes));
+    }
+}
+
+/// Removes the selected scopes after confirmation, unless `yes` is set.
+pub fn clean(config: &Config, scopes: &[Scope], yes: bool) -> Result<CleanReport> {
+    if scopes.is_empty() {
+        return Err(Error::Config(
+            "nothing selected to clean; pass --cache, --data or --all".to_string(),
+        ));
+    }
+    if config.data_dir().join("postmaster.pid").exists() {
+        return Err(Error::Aborted(format!(
+            "PostgreSQL is running from {}; stop it before cleaning",
+            config.data_dir().display()
+        )));
+    }
+
+    let targets: Vec<PathBuf> = targets(config, scopes)
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+    if targets.is_empty() {
+        return Ok(CleanReport {
+            removed: Vec::new(),
+            reclaimed_bytes: 0,
+        });
+    }
+
+    if !yes {
+        confirm(
+            &targets,
+            scopes.contains(&Scope::Data) || scopes.contains(&Scope::All),
+        )?;
+    }
+
+    let mut removed = Vec::with_capacity(targets.len());
+    for path in targets {
+        let size_bytes = if path.is_dir() {
+            let size = dir_size(&path)?;
+            std::fs::remove_dir_all(&path)?;
+            size
+        } else {
+            let size = path.metadata()?.len();
+            std::fs::remove_file(&path)?;
+            size
+        };
+        removed.push(RemovedPath { path, size_bytes });
+    }
+
+    Ok(CleanReport {
+        reclaimed_bytes: removed.iter().map(|removed| removed.size_bytes).sum(),
+        removed,
+    })
+}
+
+fn targets(config: &Config, scopes: &[Scope]) -> Vec<PathBuf> {
+    if scopes.contains(&Scope::All) {
+        let mut targets = vec![config.storage_dir.clone()];
+        // The cache lives in the platform cache directory unless configured otherwise
+        if !config.cache_dir().starts_with(&config.storage_dir) {
+            targets.push(config.cache_dir());
+        }
+        return targets;
+    }
+
+    let mut targets = Vec::new();
+    for scope in scopes {
+        match scope {
+            Scope::Cache => {
+                targets.push(config.installation_dir());
+                targets.push(config.cache_dir());
+                targets.extend(legacy_staging_dir(config));
+            }
+            Scope::Data => {
+                targets.push(config.data_dir());
+                targets.push(config.password_file());
+            }
+            Scope::All => {}
+        }
+    }
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+/// The staging directory of releases that extracted the extension archive into a fixed
+/// directory, if it's there and recognizably theirs: an unrelated directory of the same name
+/// must not be removed.
+fn legacy_staging_dir(config: &Config) -> Option<PathBuf> {
+    let dir = config.storage_dir.join(LEGACY_STAGING_DIR);
+    dir.join("vectors.control").is_file().then_some(dir)
+}
+
+fn confirm(targets: &[PathBuf], destroys_data: bool) -> Result<()> {
+    if !std::io::stderr().is_terminal() {
+        return Err(Error::Aborted(
+            "Refusing to delete files without confirmation; pass --yes to skip the prompt"
+                .to_string(),
+        ));
+    }
+
+    eprintln!("This will permanently delete:");
+    for path in targets {
+        eprintln!("  {}", path.display());
+    }
+    if destroys_data {
+        eprintln!("All databases and the vectors stored in them will be lost.");
+    }
+
+    if !Confirm::new()
+        .with_prompt("Continue?")
+        .default(false)
+        .interact()?
+    {
+        return Err(Error::Aborted("Aborted".to_string()));
+    }
+    Ok(())
+}
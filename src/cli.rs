@@ -0,0 +1,327 @@
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[cfg(feature = "server")]
+use clap::ArgGroup;
+use clap::{ArgAction, Parser, Subcommand};
+
+use crate::output::OutputFormat;
+use pgevdb::IndexConfig;
+
+/// Embedded PostgreSQL vector database.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the config file [default: ./pgevdb.toml if present, otherwise pgevdb.toml in
+    /// the platform config directory]
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Run in portable mode, keeping the config file and all data under DIR
+    #[arg(long, global = true, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+
+    /// Output format for command results
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Log more detail; repeat for more (-v: pgevdb debug, -vv: all debug, -vvv: trace)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Extra tracing filter directives, e.g. `sqlx=warn,pgevdb::progress=debug`
+    #[arg(long, global = true, value_name = "DIRECTIVES")]
+    pub log_filter: Option<String>,
+
+    /// Compile the vector extension locally when no prebuilt build matches this platform
+    /// (needs git, a Rust toolchain and cargo-pgrx)
+    #[arg(long, global = true)]
+    pub build_from_source: bool,
+
+    /// Install the vector extension from this local zip instead of downloading it
+    #[arg(long, global = true, value_name = "PATH")]
+    pub extension_archive: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Write a config file and provision PostgreSQL and the vector extension
+    Init {
+        /// Ask for each setting instead of using the defaults
+        #[arg(long)]
+        interactive: bool,
+
+        /// Overwrite an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Start PostgreSQL in the background; other commands use it until `stop`
+    Start,
+    /// Stop the background server
+    Stop,
+    /// Report whether the server is running and on which port
+    Status,
+    /// Open psql on the database
+    Sql {
+        /// Run this SQL and exit instead of starting an interactive session
+        #[arg(long, short)]
+        command: Option<String>,
+
+        /// Further psql arguments, after `--`
+        #[arg(last = true)]
+        psql_args: Vec<String>,
+    },
+    /// Serve the collections of the database over HTTP and gRPC APIs until Ctrl-C
+    #[cfg(feature = "server")]
+    #[command(group(ArgGroup::new("listeners").required(true).multiple(true)))]
+    Serve {
+        /// Address of the JSON HTTP API, e.g. `127.0.0.1:8080` or `0.0.0.0:8080` for every
+        /// interface
+        #[arg(long, value_name = "ADDRESS", group = "listeners")]
+        http: Option<SocketAddr>,
+
+        /// Address of the gRPC API, e.g. `127.0.0.1:50051`
+        #[arg(long, value_name = "ADDRESS", group = "listeners")]
+        grpc: Option<SocketAddr>,
+    },
+    /// Update the extension and pgevdb catalog in an existing database to the configured versions
+    Upgrade,
+    /// Measure retrieval quality against labeled query datasets
+    Eval {
+        #[command(subcommand)]
+        command: EvalCommand,
+    },
+    /// Load embeddings computed elsewhere into a collection
+    Import {
+        #[command(subcommand)]
+        command: ImportCommand,
+    },
+    /// Benchmark index configurations
+    Bench {
+        #[command(subcommand)]
+        command: BenchCommand,
+    },
+    /// Detect outliers and distribution drift in recently inserted vectors
+    Drift {
+        #[command(subcommand)]
+        command: DriftCommand,
+    },
+    /// Copy databases within the embedded server to experiment on real data
+    Branch {
+        #[command(subcommand)]
+        command: BranchCommand,
+    },
+    /// Delete orphaned and expired catalog rows and abandoned benchmark copies
+    Gc {
+        /// Keep usage statistics and drift check history for this many days
+        #[arg(long, default_value_t = 90)]
+        retention_days: u64,
+
+        /// Rows deleted per statement
+        #[arg(long, default_value_t = 1000)]
+        batch_size: u32,
+    },
+    /// Limit the rows and bytes each namespace of collections may store
+    Quota {
+        #[command(subcommand)]
+        command: QuotaCommand,
+    },
+    /// Summarize operation counts, query shapes and collection growth for capacity planning
+    Usage {
+        /// Length of the summarized period, in hours
+        #[arg(long, default_value_t = 168)]
+        window_hours: u64,
+    },
+    /// Create a sample `items` table and demonstrate vector operations on it
+    Demo,
+    /// Print an environment report for bug reports
+    Info,
+    /// Replace this binary with the latest release
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
+    /// Delete cached artifacts or data to reclaim disk space
+    Clean {
+        /// Remove the PostgreSQL installation, download cache and leftover staging files
+        #[arg(long)]
+        cache: bool,
+
+        /// Remove the database cluster (all stored vectors)
+        #[arg(long)]
+        data: bool,
+
+        /// Remove the whole storage directory
+        #[arg(long)]
+        all: bool,
+
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+impl Command {
+    /// Whether the command handles Ctrl-C itself rather than being dropped.
+    pub fn handles_ctrl_c(&self) -> bool {
+        match self {
+            // psql handles Ctrl-C itself
+            Command::Sql { .. } => true,
+            // Finishes the requests in progress
+            #[cfg(feature = "server")]
+            Command::Serve { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum EvalCommand {
+    /// Store a dataset, replacing any of the same name
+    Register {
+        dataset: String,
+
+        /// JSON Lines file with one `{"embedding": [...], "relevant_ids": [...]}` per query
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Search a collection with every query of a dataset and record recall, MRR and nDCG
+    Run {
+        dataset: String,
+
+        #[arg(long)]
+        collection: String,
+
+        /// Number of results scored per query
+        #[arg(short, long, default_value_t = 10)]
+        k: u32,
+    },
+    /// List earlier runs of a dataset
+    History { dataset: String },
+}
+
+#[derive(Subcommand)]
+pub enum BenchCommand {
+    /// Build two index configurations on copies of a collection and compare build time, size,
+    /// query latency and recall
+    Compare {
+        collection: String,
+
+        /// First index, e.g. `hnsw:m=16,ef_construction=100`
+        #[arg(long)]
+        a: IndexConfig,
+
+        /// Second index, e.g. `ivf:lists=100`
+        #[arg(long)]
+        b: IndexConfig,
+
+        /// Compare on a random sample of this many vectors instead of the whole collection
+        #[arg(long)]
+        sample: Option<u64>,
+
+        /// Number of query vectors, drawn from the collection
+        #[arg(long, default_value_t = 100)]
+        queries: u32,
+
+        /// Results per query; recall is measured at this cutoff
+        #[arg(short, long, default_value_t = 10)]
+        k: u32,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DriftCommand {
+    /// Compare recent windows of inserts against older vectors and record the result
+    Check {
+        collection: String,
+
+        /// Length of each window, in hours
+        #[arg(long, default_value_t = 24)]
+        window_hours: u64,
+
+        /// Number of recent windows to check; older vectors form the baseline
+        #[arg(long, default_value_t = 7)]
+        windows: u32,
+
+        /// Standard deviations from the baseline beyond which a vector is an outlier
+        #[arg(long, default_value_t = 3.0)]
+        threshold: f64,
+    },
+    /// List the summaries of earlier checks
+    History { collection: String },
+}
+
+#[derive(Subcommand)]
+pub enum QuotaCommand {
+    /// Set the limits of a namespace, replacing earlier ones; omitted limits are unlimited
+    Set {
+        namespace: String,
+
+        #[arg(long)]
+        max_rows: Option<u64>,
+
+        #[arg(long)]
+        max_bytes: Option<u64>,
+    },
+    /// Remove the limits of a namespace
+    Remove { namespace: String },
+    /// Move a collection to a namespace
+    Assign {
+        collection: String,
+
+        #[arg(long)]
+        namespace: String,
+    },
+    /// List namespaces with quotas and their usage
+    Show,
+    /// Recount usage from the collection tables; run periodically, e.g. from cron
+    Reconcile,
+}
+
+#[derive(Subcommand)]
+pub enum BranchCommand {
+    /// Copy a database into a new branch; connections to the source are closed while it runs
+    Create {
+        name: String,
+
+        /// Database to copy [default: the configured database]
+        #[arg(long)]
+        from: Option<String>,
+    },
+    /// List branches
+    List,
+    /// Drop a branch and everything in it
+    Delete {
+        name: String,
+
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImportCommand {
+    /// Import the output file of an OpenAI-compatible batch embeddings job; the trailing number
+    /// of each `custom_id` becomes the record id
+    OpenaiBatch {
+        collection: String,
+
+        /// Batch output file (JSON Lines)
+        #[arg(long)]
+        file: PathBuf,
+
+        /// JSON Lines file with `{"custom_id", "content", "source", "language"}` per record
+        #[arg(long)]
+        payload: Option<PathBuf>,
+    },
+}
@@ -0,0 +1,375 @@
+//! K-means clustering of a collection's vectors.
+//!
+//! Centroids are fitted on a random sample, then every stored vector is assigned to its nearest
+//! centroid in the collection's `cluster` column, which [`Filter::Cluster`](crate::Filter::Cluster)
+//! selects on. Centroids are kept in the catalog for routing and exploration.
+
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+
+use crate::collection::{Collection, Metric};
+use crate::error::{Error, Result};
+
+/// Rows assigned per round trip.
+const ASSIGN_BATCH: i64 = 1000;
+
+/// Sample size and iteration limit of a clustering run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClusterOptions {
+    /// Vectors the centroids are fitted on.
+    pub sample: u32,
+    /// Lloyd iterations at most; fitting stops earlier once no sample changes cluster.
+    pub iterations: u32,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            sample: 10_000,
+            iterations: 25,
+        }
+    }
+}
+
+/// One cluster of a collection.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Centroid {
+    pub cluster: u32,
+    pub centroid: Vec<f32>,
+    /// Vectors assigned to the cluster when it was computed.
+    pub size: u64,
+}
+
+/// Result of [`cluster`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ClusterReport {
+    pub collection: String,
+    pub k: u32,
+    pub centroids: Vec<Centroid>,
+    /// Mean squared distance of each vector to its centroid.
+    pub inertia: f64,
+}
+
+/// Fits `k` centroids on a sample of `collection` and assigns every stored vector to the nearest
+/// one, replacing earlier assignments. Vectors inserted afterwards stay unassigned until the next
+/// run.
+///
+/// Cosine collections are clustered on unit-normalized vectors (spherical k-means); others on
+/// the vectors as stored.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if `k` is 0 or larger than the sample.
+pub async fn cluster(
+    collection: &Collection,
+    k: u32,
+    options: &ClusterOptions,
+) -> Result<ClusterReport> {
+    let pool = collection.pool();
+    let table = collection.table();
+    let embedding = collection.as_array("embedding");
+    let normalize = collection.metric() == Metric::Cosine;
+
+    let rows: Vec<(Vec<f32>,)> = sqlx::query_as(&format!(
+        "SELECT {embedding} FROM {table} ORDER BY random() LIMIT $1"
+    ))
+    .bind(i64::from(options.sample))
+    .fetch_all(pool)
+    .await?;
+    let sample: Vec<Vec<f32>> = rows
+        .into_iter()
+        .map(|(vector,)| prepare(vector, normalize))
+        .collect();
+    if k == 0 || k as usize > sample.len() {
+        return Err(Error::InvalidInput(format!(
+            "k must be between 1 and the {} sampled vectors, got {k}",
+            sample.len()
+        )));
+    }
+    let centroids = kmeans(&sample, k as usize, options.iterations);
+
+    let mut transaction = pool.begin().await?;
+    sqlx::query(&format!(
+        "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS cluster integer"
+    ))
+    .execute(&mut *transaction)
+    .await?;
+    let mut sizes = vec![0_u64; centroids.len()];
+    let mut squared_distances = 0.0;
+    let mut last_id = 0;
+    loop {
+        let batch: Vec<(i64, Vec<f32>)> = sqlx::query_as(&format!(
+            "SELECT id, {embedding} FROM {table} WHERE id > $1 ORDER BY id LIMIT $2"
+        ))
+        .bind(last_id)
+        .bind(ASSIGN_BATCH)
+        .fetch_all(&mut *transaction)
+        .await?;
+        let Some(&(id, _)) = batch.last() else { break };
+        last_id = id;
+
+        let mut ids = Vec::with_capacity(batch.len());
+        let mut clusters = Vec::with_capacity(batch.len());
+        for (id, vector) in batch {
+            let (cluster, distance) = nearest(&centroids, &prepare(vector, normalize));
+            sizes[cluster] += 1;
+            squared_distances += f64::from(distance);
+            ids.push(id);
+            clusters.push(i32::try_from(cluster).unwrap_or(i32::MAX));
+        }
+        sqlx::query(&format!(
+            "UPDATE {table} SET cluster = assigned.cluster
+             FROM unnest($1::bigint[], $2::integer[]) AS assigned (id, cluster)
+             WHERE {table}.id = assigned.id"
+        ))
+        .bind(&ids)
+        .bind(&clusters)
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    sqlx::query("DELETE FROM pgevdb.centroids WHERE collection = $1")
+        .bind(collection.name())
+        .execute(&mut *transaction)
+        .await?;
+    for (cluster, (centroid, size)) in (0_i32..).zip(centroids.iter().zip(&sizes)) {
+        sqlx::query(
+            "INSERT INTO pgevdb.centroids (collection, cluster, centroid, size)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(collection.name())
+        .bind(cluster)
+        .bind(centroid)
+        .bind(i64::try_from(*size).unwrap_or(i64::MAX))
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+
+    let assigned: u64 = sizes.iter().sum();
+    #[allow(clippy::cast_precision_loss)]
+    let inertia = squared_distances / assigned.max(1) as f64;
+    Ok(ClusterReport {
+        collection: collection.name().to_string(),
+        k,
+        centroids: (0..)
+            .zip(centroids.into_iter().zip(sizes))
+            .map(|(cluster, (centroid, size))| Centroid {
+                cluster,
+                centroid,
+                size,
+            })
+            .collect(),
+        inertia,
+    })
+}
+
+/// The centroids of the last clustering of `collection`, empty if it was never clustered.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the catalog can't be read.
+pub async fn centroids(pool: &PgPool, collection: &str) -> Result<Vec<Centroid>> {
+    let rows: Vec<(i32, Vec<f32>, i64)> = sqlx::query_as(
+        "SELECT cluster, centroid, size FROM pgevdb.centroids WHERE collection = $1
+         ORDER BY cluster",
+    )
+    .bind(collection)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(cluster, centroid, size)| Centroid {
+            cluster: cluster.unsigned_abs(),
+            centroid,
+            size: size.unsigned_abs(),
+        })
+        .collect())
+}
+
+fn prepare(mut vector: Vec<f32>, normalize: bool) -> Vec<f32> {
+    if normalize {
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for x in &mut vector {
+                *x /= norm;
+            }
+        }
+    }
+    vector
+}
+
+/// Lloyd's algorithm with k-means++ seeding.
+fn kmeans(sample: &[Vec<f32>], k: usize, iterations: u32) -> Vec<Vec<f32>> {
+    let mut random = Xorshift(0x2545_F491_4F6C_DD1D);
+
+    // k-means++: each next seed is drawn with probability proportional to its squared distance
+    // from the nearest seed so far
+    let mut centroids = vec![sample[random.below(sample.len())].clone()];
+    let mut distances: Vec<f32> = sample
+        .iter()
+        .map(|vector| squared_distance(vector, &centroids[0]))
+        .collect();
+    while centroids.len() < k {
+        let total: f32 = distances.iter().sum();
+        let mut target = random.unit() * total;
+        let next = distances
+            .iter()
+            .position(|&distance| {
+                target -= distance;
+                target <= 0.0
+            })
+            .unwrap_or(sample.len() - 1);
+        centroids.push(sample[next].clone());
+        for (distance, vector) in distances.iter_mut().zip(sample) {
+            *distance = distance.min(squared_distance(vector, &sample[next]));
+        }
+    }
+
+    let mut assignments = vec![usize::MAX; sample.len()];
+    for _ in 0..iterations {
+        let mut changed = false;
+        for (assignment, vector) in assignments.iter_mut().zip(sample) {
+            let (cluster, _) = nearest(&centroids, vector);
+            changed |= *assignment != cluster;
+            *assignment = cluster;
+        }
+        if !changed {
+            break;
+        }
+
+        let dimensions = sample[0].len();
+        let mut sums = vec![vec![0.0_f32; dimensions]; k];
+        let mut counts = vec![0_u32; k];
+        for (&cluster, vector) in assignments.iter().zip(sample) {
+            counts[cluster] += 1;
+            for (sum, x) in sums[cluster].iter_mut().zip(vector) {
+                *sum += x;
+            }
+        }
+        // A cluster that lost all its members keeps its previous centroid
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            if count > 0 {
+                #[allow(clippy::cast_precision_loss)]
+                let count = count as f32;
+                *centroid = sum.into_iter().map(|x| x / count).collect();
+            }
+        }
+    }
+    centroids
+}
+
+/// Index of the centroid closest to `vector`, with its squared distance.
+fn nearest(centroids: &[Vec<f32>], vector: &[f32]) -> (usize, f32) {
+    centroids
+        .iter()
+        .map(|centroid| squared_distance(centroid, vector))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap_or_default()
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// Small deterministic generator, so reclustering the same sample gives the same clusters.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in `[0, 1)`.
+    #[allow(clippy::cast_precision_loss)]
+    fn unit(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1_u64 << 24) as f32
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three tight groups of four points around (0, 0), (10, 0) and (0, 10).
+    fn blobs() -> Vec<Vec<f32>> {
+        let offsets = [[0.1, 0.0], [-0.1, 0.0], [0.0, 0.1], [0.0, -0.1]];
+        [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]]
+            .iter()
+            .flat_map(|[x, y]| offsets.iter().map(move |[dx, dy]| vec![x + dx, y + dy]))
+            .collect()
+    }
+
+    #[test]
+    fn finds_separated_clusters() {
+        let sample = blobs();
+        let mut centroids = kmeans(&sample, 3, 20);
+        centroids.sort_by(|a, b| (a[0] - a[1]).total_cmp(&(b[0] - b[1])));
+        for (centroid, expected) in centroids.iter().zip([[0.0, 10.0], [0.0, 0.0], [10.0, 0.0]]) {
+            assert!(
+                squared_distance(centroid, &expected) < 1e-6,
+                "{centroids:?}"
+            );
+        }
+        // Every member is closest to its own group's centroid
+        for group in sample.chunks(4) {
+            let clusters: Vec<usize> = group
+                .iter()
+                .map(|vector| nearest(&centroids, vector).0)
+                .collect();
+            assert!(clusters.iter().all(|&cluster| cluster == clusters[0]));
+        }
+    }
+
+    #[test]
+    fn reclustering_gives_the_same_centroids() {
+        let sample = blobs();
+        assert_eq!(kmeans(&sample, 3, 20), kmeans(&sample, 3, 20));
+    }
+
+    #[test]
+    fn one_cluster_is_the_mean() {
+        let centroids = kmeans(&blobs(), 1, 10);
+        assert_eq!(centroids.len(), 1);
+        assert!(squared_distance(&centroids[0], &[10.0 / 3.0, 10.0 / 3.0]) < 1e-6);
+    }
+
+    #[test]
+    fn survives_more_clusters_than_distinct_points() {
+        let sample = vec![vec![1.0, 1.0]; 3];
+        let centroids = kmeans(&sample, 3, 10);
+        assert_eq!(centroids, vec![vec![1.0, 1.0]; 3]);
+    }
+
+    #[test]
+    fn nearest_reports_the_squared_distance() {
+        let centroids = [vec![0.0, 0.0], vec![3.0, 4.0]];
+        assert_eq!(nearest(&centroids, &[3.0, 3.0]), (1, 1.0));
+        assert_eq!(nearest(&centroids, &[1.0, 0.0]), (0, 1.0));
+    }
+
+    #[test]
+    fn normalizes_only_when_asked_and_leaves_zero_alone() {
+        assert_eq!(prepare(vec![3.0, 4.0], true), [0.6, 0.8]);
+        assert_eq!(prepare(vec![3.0, 4.0], false), [3.0, 4.0]);
+        assert_eq!(prepare(vec![0.0, 0.0], true), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn draws_units_and_bounded_indexes() {
+        let mut random = Xorshift(7);
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&random.unit()));
+            assert!(random.below(5) < 5);
+        }
+    }
+}
@@ -0,0 +1,3057 @@
+//! Named sets of fixed-dimension vectors compared with one distance metric.
+//!
+//! Each collection is a table in the `collections` schema, registered in the `pgevdb.collections`
+//! catalog table.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::pin::pin;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use futures_util::{Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::postgres::PgPool;
+use sqlx::{Postgres, QueryBuilder, Transaction};
+use tokio_util::sync::CancellationToken;
+
+use crate::clustering::{self, Centroid, ClusterOptions, ClusterReport};
+use crate::config::Backend;
+use crate::diversity;
+use crate::embedder::{self, Embedder};
+use crate::error::{Error, Result};
+use crate::extension;
+use crate::filter::Filter;
+use crate::hybrid::{self, HybridOptions, HybridResult};
+use crate::identifier;
+use crate::index::IndexConfig;
+use crate::maintenance::{self, DeleteOptions, DeleteReport};
+use crate::quota;
+use crate::rag::{self, Context, ContextOptions};
+use crate::search::{Rerank, SearchOptions};
+use crate::stats;
+use crate::text_search::{self, TextMatch, TextSearchConfig};
+use crate::transform::{self, Transform};
+use crate::vector::{BitVector, SparseVector, Vector};
+
+/// Longest vector pgvecto.rs accepts; pgvector refuses more than 16,000 dimensions itself.
+const MAX_DIMENSIONS: i32 = 65_535;
+/// `NAMEDATALEN - 1`, less room for index name suffixes.
+pub(crate) const MAX_NAME_LENGTH: usize = 48;
+/// Rows read and then written back in one `UPDATE` per batch when backfilling a reduced
+/// column.
+const BACKFILL_BATCH: i64 = 1000;
+
+/// How vectors in a collection are compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Squared Euclidean distance (`<->`), in `[0, ∞)`. pgvector's `<->` is the plain Euclidean
+    /// distance, which collections on pgvector square so distances mean the same on both
+    /// backends.
+    #[default]
+    L2,
+    /// Cosine distance (`<=>`), `1 - cos θ`, in `[0, 2]`.
+    Cosine,
+    /// Negative inner product (`<#>`), in `(-∞, ∞)`.
+    Dot,
+    /// Number of differing bits, in `[0, dimensions]`, for [binary](Storage::Binary)
+    /// collections only.
+    Hamming,
+    /// Jaccard distance of the set bits, `1 - |a ∩ b| / |a ∪ b|`, in `[0, 1]`, for
+    /// [binary](Storage::Binary) collections only.
+    Jaccard,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Metric::L2,
+        Metric::Cosine,
+        Metric::Dot,
+        Metric::Hamming,
+        Metric::Jaccard,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::L2 => "l2",
+            Metric::Cosine => "cosine",
+            Metric::Dot => "dot",
+            Metric::Hamming => "hamming",
+            Metric::Jaccard => "jaccard",
+        }
+    }
+
+    /// SQL operator computing this metric's distance on `backend`. Lower is always closer.
+    #[must_use]
+    pub fn operator(self, backend: Backend) -> &'static str {
+        match (backend, self) {
+            (Backend::PgVectoRs, Metric::L2 | Metric::Hamming)
+            | (Backend::PgVector, Metric::L2) => "<->",
+            (_, Metric::Cosine) => "<=>",
+            (_, Metric::Dot) => "<#>",
+            (Backend::PgVectoRs, Metric::Jaccard) | (Backend::PgVector, Metric::Hamming) => "<~>",
+            (Backend::PgVector, Metric::Jaccard) => "<%>",
+        }
+    }
+
+    /// Whether this metric compares bits, and so needs a [binary](Storage::Binary) collection.
+    #[must_use]
+    pub fn is_binary(self) -> bool {
+        matches!(self, Metric::Hamming | Metric::Jaccard)
+    }
+
+    /// Operator class an index of `backend` needs to serve this metric's operator on vectors
+    /// stored as `storage`.
+    pub(crate) fn operator_class(self, backend: Backend, storage: Storage) -> String {
+        let suffix = match (backend, self) {
+            (_, Metric::L2) | (Backend::PgVectoRs, Metric::Hamming) => "l2_ops",
+            (Backend::PgVectoRs, Metric::Cosine) => "cos_ops",
+            (Backend::PgVectoRs, Metric::Dot) => "dot_ops",
+            (Backend::PgVector, Metric::Cosine) => "cosine_ops",
+            (Backend::PgVector, Metric::Dot) => "ip_ops",
+            (Backend::PgVector, Metric::Hamming) => "hamming_ops",
+            (_, Metric::Jaccard) => "jaccard_ops",
+        };
+        format!("{}_{suffix}", storage.type_name(backend))
+    }
+
+    /// Converts a raw operator distance into a similarity in `[0, 1]` where higher is closer,
+    /// so results from any metric can be compared against the same threshold:
+    ///
+    /// | Metric | Score |
+    /// |--------|-------|
+    /// | L2     | `1 / (1 + d)` |
+    /// | Cosine | `1 - d / 2`, i.e. `(1 + cos θ) / 2` |
+    /// | Dot    | `1 / (1 + e^d)`, the logistic of the inner product; `0.5` for orthogonal vectors |
+    /// | Hamming | `1 / (1 + d)` |
+    /// | Jaccard | `1 - d` |
+    #[must_use]
+    pub fn score(self, distance: f32) -> f32 {
+        let score = match self {
+            Metric::L2 | Metric::Hamming => 1.0 / (1.0 + distance.max(0.0)),
+            Metric::Cosine => 1.0 - distance / 2.0,
+            Metric::Dot => 1.0 / (1.0 + distance.exp()),
+            Metric::Jaccard => 1.0 - distance,
+        };
+        score.clamp(0.0, 1.0)
+    }
+
+    /// This metric's distance between `a` and `b`, computed locally; L2 is squared as pgvecto.rs
+    /// reports it. Binary metrics count components above zero as set bits.
+    ///
+    /// ```
+    /// use pgevdb::Metric;
+    ///
+    /// assert_eq!(Metric::L2.distance(&[0.0, 3.0], &[4.0, 0.0]), 25.0);
+    /// assert_eq!(Metric::Cosine.distance(&[1.0, 0.0], &[0.0, 2.0]), 1.0);
+    /// ```
+    #[must_use]
+    pub fn distance(self, a: &[f32], b: &[f32]) -> f32 {
+        let dot = || a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        let norm = |vector: &[f32]| vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let bits = || a.iter().zip(b).map(|(x, y)| (*x > 0.0, *y > 0.0));
+        match self {
+            Metric::L2 => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum(),
+            Metric::Cosine => {
+                let norms = norm(a) * norm(b);
+                if norms > 0.0 {
+                    1.0 - dot() / norms
+                } else {
+                    1.0
+                }
+            }
+            Metric::Dot => -dot(),
+            #[allow(clippy::cast_precision_loss)]
+            Metric::Hamming => bits().filter(|(x, y)| x != y).count() as f32,
+            Metric::Jaccard => {
+                let (both, either) = bits().fold((0_u32, 0_u32), |(both, either), (x, y)| {
+                    (both + u32::from(x && y), either + u32::from(x || y))
+                });
+                if either == 0 {
+                    0.0
+                } else {
+                    #[allow(clippy::cast_precision_loss)]
+                    let similarity = both as f32 / either as f32;
+                    1.0 - similarity
+                }
+            }
+        }
+    }
+
+    /// The largest distance whose [score](Self::score) is at least `score`; infinite for
+    /// scores of `0` or less.
+    pub(crate) fn distance_at_score(self, score: f32) -> f32 {
+        if score <= 0.0 {
+            return f32::INFINITY;
+        }
+        let score = score.min(1.0);
+        match self {
+            Metric::L2 | Metric::Hamming => 1.0 / score - 1.0,
+            Metric::Cosine => 2.0 * (1.0 - score),
+            Metric::Dot => (1.0 / score - 1.0).ln(),
+            Metric::Jaccard => 1.0 - score,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Metric {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Metric::ALL
+            .into_iter()
+            .find(|metric| metric.name() == name)
+            .ok_or_else(|| Error::InvalidInput(format!("unknown metric '{name}'")))
+    }
+}
+
+/// How a collection stores its vectors. Queries and inserts take `f32` components, dense or
+/// [sparse](crate::SparseVector), which are converted on the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// 32-bit floats, the `vector` type.
+    #[default]
+    F32,
+    /// 16-bit floats, `vecf16` on pgvecto.rs and `halfvec` on pgvector: half the memory and
+    /// disk of `F32` for about three significant digits per component, which rarely changes
+    /// which neighbors a search finds.
+    F16,
+    /// Only the nonzero components, `svector` on pgvecto.rs and `sparsevec` on pgvector, for
+    /// sparse embeddings such as SPLADE's. Insert and search with
+    /// [`insert_sparse`](Collection::insert_sparse) and
+    /// [`search_sparse`](Collection::search_sparse); on pgvector, reading vectors back as dense
+    /// ones fails past the 16,000 dimensions of its `vector` type.
+    Sparse,
+    /// One bit per dimension, `bvector` on pgvecto.rs and `bit` on pgvector, compared by
+    /// [`Metric::Hamming`] or [`Metric::Jaccard`]. Insert and search with
+    /// [`insert_bits`](Collection::insert_bits) and [`search_bits`](Collection::search_bits);
+    /// dense vectors are binary-quantized as [`BitVector::from_dense`] does.
+    Binary,
+}
+
+impl Storage {
+    pub const ALL: [Storage; 4] = [Storage::F32, Storage::F16, Storage::Sparse, Storage::Binary];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Storage::F32 => "f32",
+            Storage::F16 => "f16",
+            Storage::Sparse => "sparse",
+            Storage::Binary => "binary",
+        }
+    }
+
+    /// Column type storing vectors in this precision on `backend`.
+    pub(crate) fn type_name(self, backend: Backend) -> &'static str {
+        match (self, backend) {
+            (Storage::F32, _) => "vector",
+            (Storage::F16, Backend::PgVectoRs) => "vecf16",
+            (Storage::F16, Backend::PgVector) => "halfvec",
+            (Storage::Sparse, Backend::PgVectoRs) => "svector",
+            (Storage::Sparse, Backend::PgVector) => "sparsevec",
+            (Storage::Binary, Backend::PgVectoRs) => "bvector",
+            (Storage::Binary, Backend::PgVector) => "bit",
+        }
+    }
+
+    /// `column`, stored this way on `backend`, as a `vector`, e.g. to read it back as `real[]`.
+    pub(crate) fn widen(self, backend: Backend, column: &str) -> String {
+        match (self, backend) {
+            (Storage::F32, _) => column.to_string(),
+            // pgvector has no cast from bit, so the bits are spelled out
+            (Storage::Binary, Backend::PgVector) => format!(
+                "ARRAY(SELECT get_bit({column}, i)::real
+                       FROM generate_series(0, length({column}) - 1) AS i)::vector"
+            ),
+            _ => format!("{column}::vector"),
+        }
+    }
+
+    /// SQL to put before and after a `real[]` expression to turn it into the column type on
+    /// `backend`.
+    pub(crate) fn narrow(self, backend: Backend) -> (&'static str, &'static str) {
+        match (self, backend) {
+            (Storage::F32, _) => ("", "::real[]::vector"),
+            (Storage::F16, Backend::PgVectoRs) => ("", "::real[]::vector::vecf16"),
+            (Storage::F16, Backend::PgVector) => ("", "::real[]::vector::halfvec"),
+            (Storage::Sparse, Backend::PgVectoRs) => ("", "::real[]::vector::svector"),
+            (Storage::Binary, Backend::PgVectoRs) => ("binarize(", "::real[]::vector)"),
+            // pgvector's vector holds at most 16,000 dimensions, fewer than sparsevec and bit,
+            // so these are spelled out from the components instead
+            (Storage::Sparse, Backend::PgVector) => (
+                "(SELECT ('{' || coalesce(string_agg(i || ':' || v, ',' ORDER BY i)
+                                                   FILTER (WHERE v <> 0), '')
+                          || '}/' || count(*))::sparsevec
+                  FROM unnest(",
+                "::real[]) WITH ORDINALITY AS c (v, i))",
+            ),
+            (Storage::Binary, Backend::PgVector) => (
+                "(SELECT string_agg(CASE WHEN v > 0 THEN '1' ELSE '0' END, '' ORDER BY i)::varbit
+                  FROM unnest(",
+                "::real[]) WITH ORDINALITY AS c (v, i))",
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Storage {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Storage::ALL
+            .into_iter()
+            .find(|storage| storage.name() == name)
+            .ok_or_else(|| Error::InvalidInput(format!("unknown storage '{name}'")))
+    }
+}
+
+/// One search hit, ordered nearest first.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub id: i64,
+    /// Raw distance reported by the metric's operator; lower is closer.
+    pub distance: f32,
+    /// Metric-aware similarity in `[0, 1]`; higher is closer. See [`Metric::score`].
+    pub score: f32,
+    /// Relevance assigned by a [`Reranker`](crate::rerank::Reranker), when the search reranked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rerank_score: Option<f32>,
+    /// Metadata stored with the vector, see [`Collection::insert_with_payload`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+}
+
+impl SearchResult {
+    /// The payload deserialized as `T`, or `None` if the record has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the payload doesn't deserialize as `T`.
+    pub fn payload_as<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        self.payload.clone().map(from_payload).transpose()
+    }
+}
+
+/// Text stored with a vector, for reranking, full-text search and citations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Content<'a> {
+    pub text: &'a str,
+    /// Document the text was taken from, such as a path or URL.
+    pub source: Option<&'a str>,
+    /// Text search language of this record, overriding the collection's
+    /// [`TextSearchConfig::language`].
+    pub language: Option<&'a str>,
+}
+
+/// A dense vector to store with any of an explicit id, content and payload, see
+/// [`Collection::insert_records`].
+#[cfg(feature = "server")]
+pub(crate) struct NewRow<'a> {
+    pub(crate) id: Option<i64>,
+    pub(crate) embedding: &'a [f32],
+    pub(crate) content: Option<Content<'a>>,
+    pub(crate) payload: Option<&'a Value>,
+}
+
+/// A stored vector.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Record {
+    pub id: i64,
+    pub embedding: Vec<f32>,
+    /// Text the vector was computed from, if it was stored.
+    pub content: Option<String>,
+    /// Document the content was taken from, such as a path or URL.
+    pub source: Option<String>,
+    /// Metadata stored with the vector, see [`Collection::insert_with_payload`].
+    pub payload: Option<Value>,
+}
+
+/// A vector to store or search for, in the form the caller has it.
+#[derive(Clone, Copy, Debug)]
+enum Embedding<'a> {
+    Dense(&'a [f32]),
+    Sparse(&'a SparseVector),
+    Bits(&'a BitVector),
+}
+
+impl<'a> Embedding<'a> {
+    /// Every component, for transforms and dense columns.
+    fn dense(self) -> Cow<'a, [f32]> {
+        match self {
+            Embedding::Dense(vector) => Cow::Borrowed(vector),
+            Embedding::Sparse(sparse) => Cow::Owned(sparse.to_dense()),
+            Embedding::Bits(bits) => Cow::Owned(bits.to_dense()),
+        }
+    }
+}
+
+/// One page of [`Collection::scroll`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Page {
+    pub records: Vec<Record>,
+    /// Cursor of the next page, `None` after the last one. Opaque; pass it back as is.
+    pub next: Option<String>,
+}
+
+/// How [`Collection::count`] counts records.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Exactness {
+    /// Counts every matching record; takes a scan of the collection or its filtered part.
+    #[default]
+    Exact,
+    /// The planner's estimate: `pg_class.reltuples` for the whole collection, the row estimate
+    /// of the filtered scan otherwise. Instant at any size, as fresh as the last `ANALYZE`.
+    Estimated,
+}
+
+/// Handle to a collection. Cheap to clone; it shares the pool it was opened with.
+#[derive(Clone, Debug)]
+pub struct Collection {
+    pool: PgPool,
+    name: String,
+    dimensions: u32,
+    metric: Metric,
+    /// Extension providing the `vector` type in the collection's database.
+    backend: Backend,
+    storage: Storage,
+    embedder: Option<Embedder>,
+    transform: Option<Transform>,
+    /// Whether the table has a `reduced` column maintained with `transform`.
+    reduced_column: bool,
+    text_search: TextSearchConfig,
+    namespace: String,
+}
+
+impl Collection {
+    /// Creates an empty collection storing 32-bit floats.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the name or dimension count is invalid or the
+    /// collection already exists.
+    pub async fn create(
+        pool: &PgPool,
+        name: &str,
+        dimensions: u32,
+        metric: Metric,
+    ) -> Result<Self> {
+        Self::create_with_storage(pool, name, dimensions, metric, Storage::F32).await
+    }
+
+    /// [`create`](Self::create) for a collection storing its vectors in `storage` precision.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the name or dimension count is invalid or the
+    /// collection already exists.
+    pub async fn create_with_storage(
+        pool: &PgPool,
+        name: &str,
+        dimensions: u32,
+        metric: Metric,
+        storage: Storage,
+    ) -> Result<Self> {
+        Self::create_in_namespace(
+            pool,
+            name,
+            dimensions,
+            metric,
+            storage,
+            quota::DEFAULT_NAMESPACE,
+        )
+        .await
+    }
+
+    /// [`create_with_storage`](Self::create_with_storage) directly in `namespace`.
+    pub(crate) async fn create_in_namespace(
+        pool: &PgPool,
+        name: &str,
+        dimensions: u32,
+        metric: Metric,
+        storage: Storage,
+        namespace: &str,
+    ) -> Result<Self> {
+        identifier::validate("collection", name, MAX_NAME_LENGTH)?;
+        let stored_dimensions = i32::try_from(dimensions)
+            .ok()
+            .filter(|dimensions| (1..=MAX_DIMENSIONS).contains(dimensions))
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "dimensions must be between 1 and {MAX_DIMENSIONS}, got {dimensions}"
+                ))
+            })?;
+
+        if metric.is_binary() != (storage == Storage::Binary) {
+            return Err(Error::InvalidInput(format!(
+                "the {metric} metric doesn't apply to {storage} storage; binary collections \
+                 take the hamming or jaccard metric and only they do"
+            )));
+        }
+
+        let backend = extension::installed_backend(pool).await?;
+        let column_type = storage.type_name(backend);
+        let mut transaction = pool.begin().await?;
+        let inserted = sqlx::query(
+            "INSERT INTO pgevdb.collections (name, dimensions, metric, storage, namespace)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (name) DO NOTHING",
+        )
+        .bind(name)
+        .bind(stored_dimensions)
+        .bind(metric.name())
+        .bind(storage.name())
+        .bind(namespace)
+        .execute(&mut *transaction)
+        .await?;
+        if inserted.rows_affected() == 0 {
+            return Err(Error::InvalidInput(format!(
+                "collection '{name}' already exists"
+            )));
+        }
+        sqlx::query(&format!(
+            "CREATE TABLE collections.{name} (
+                id bigserial PRIMARY KEY,
+                embedding {column_type}({dimensions}) NOT NULL,
+                inserted_at timestamptz DEFAULT now(),
+                content text,
+                source text,
+                language text,
+                content_tsv tsvector,
+                payload jsonb,
+                expires_at timestamptz,
+                deleted_at timestamptz
+            )"
+        ))
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query(&format!(
+            "CREATE INDEX {name}_content_idx ON collections.{name} USING gin (content_tsv)"
+        ))
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query(&format!(
+            "CREATE INDEX {name}_payload_idx ON collections.{name} USING gin (payload jsonb_path_ops)"
+        ))
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query(&format!(
+            "CREATE INDEX {name}_expires_idx ON collections.{name} (expires_at)
+             WHERE expires_at IS NOT NULL"
+        ))
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query(&format!(
+            "CREATE INDEX {name}_deleted_idx ON collections.{name} (id)
+             WHERE deleted_at IS NOT NULL"
+        ))
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(Self {
+            pool: pool.clone(),
+            name: name.to_string(),
+            dimensions,
+            metric,
+            backend,
+            storage,
+            embedder: None,
+            transform: None,
+            reduced_column: false,
+            text_search: TextSearchConfig::default(),
+            namespace: namespace.to_string(),
+        })
+    }
+
+    /// Opens an existing collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if there is no collection called `name`.
+    pub async fn open(pool: &PgPool, name: &str) -> Result<Self> {
+        type Row = (
+            i32,
+            String,
+            String,
+            bool,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<bool>,
+            String,
+            String,
+        );
+        let row: Option<Row> = sqlx::query_as(
+            "SELECT c.dimensions, c.metric, c.text_language, c.text_unaccent,
+                    e.provider, e.model, e.version, e.normalized, c.namespace, c.storage
+             FROM pgevdb.collections c LEFT JOIN pgevdb.embedders e ON e.id = c.embedder
+             WHERE c.name = $1",
+        )
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+        let (
+            dimensions,
+            metric,
+            language,
+            unaccent,
+            provider,
+            model,
+            version,
+            normalized,
+            namespace,
+            storage,
+        ) = row.ok_or_else(|| Error::NotFound(format!("collection '{name}'")))?;
+        let dimensions = dimensions.unsigned_abs();
+
+        let (transform, reduced_column) = transform::load(pool, name)
+            .await?
+            .map_or((None, false), |(transform, reduced_column)| {
+                (Some(transform), reduced_column)
+            });
+
+        Ok(Self {
+            pool: pool.clone(),
+            name: name.to_string(),
+            dimensions,
+            metric: metric.parse()?,
+            backend: extension::installed_backend(pool).await?,
+            storage: storage.parse()?,
+            embedder: provider.zip(model).zip(version.zip(normalized)).map(
+                |((provider, model), (version, normalized))| Embedder {
+                    provider,
+                    model,
+                    version,
+                    dimensions,
+                    normalized,
+                },
+            ),
+            transform,
+            reduced_column,
+            text_search: TextSearchConfig { language, unaccent },
+            namespace,
+        })
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn dimensions(&self) -> u32 {
+        self.dimensions
+    }
+
+    #[must_use]
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    #[must_use]
+    pub fn storage(&self) -> Storage {
+        self.storage
+    }
+
+    /// The namespace whose [quota](crate::quota::Quota) this collection's records count against.
+    #[must_use]
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Moves the collection to `namespace`. Usage already counted against the old namespace
+    /// stays there until both are [reconciled](crate::quota::reconcile).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `namespace` is empty.
+    pub async fn set_namespace(&mut self, namespace: &str) -> Result<()> {
+        if namespace.is_empty() {
+            return Err(Error::InvalidInput("namespace must not be empty".into()));
+        }
+        sqlx::query("UPDATE pgevdb.collections SET namespace = $1 WHERE name = $2")
+            .bind(namespace)
+            .bind(&self.name)
+            .execute(&self.pool)
+            .await?;
+        self.namespace = namespace.to_string();
+        Ok(())
+    }
+
+    /// The model this collection's vectors come from, if it was bound to one.
+    #[must_use]
+    pub fn embedder(&self) -> Option<&Embedder> {
+        self.embedder.as_ref()
+    }
+
+    /// Registers `embedder` if needed and records it as the source of this collection's vectors.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the embedder's dimensions differ from the collection's,
+    /// or if the collection already holds vectors from another embedder.
+    pub async fn bind_embedder(&mut self, embedder: &Embedder) -> Result<()> {
+        if embedder.dimensions != self.dimensions {
+            return Err(Error::InvalidInput(format!(
+                "embedder {embedder} produces {} dimensions, collection '{}' has {}",
+                embedder.dimensions, self.name, self.dimensions
+            )));
+        }
+        if let Some(bound) = self.embedder.as_ref().filter(|bound| *bound != embedder) {
+            let (populated,): (bool,) =
+                sqlx::query_as(&format!("SELECT EXISTS (SELECT FROM {})", self.table()))
+                    .fetch_one(&self.pool)
+                    .await?;
+            if populated {
+                return Err(Error::InvalidInput(format!(
+                    "collection '{}' holds vectors from {bound}; re-embed into a new collection \
+                     to switch to {embedder}",
+                    self.name
+                )));
+            }
+        }
+
+        let id = embedder::register(&self.pool, embedder).await?;
+        sqlx::query("UPDATE pgevdb.collections SET embedder = $1 WHERE name = $2")
+            .bind(id)
+            .bind(&self.name)
+            .execute(&self.pool)
+            .await?;
+        self.embedder = Some(embedder.clone());
+        Ok(())
+    }
+
+    /// The dimensionality reduction stored with this collection, if any.
+    #[must_use]
+    pub fn transform(&self) -> Option<&Transform> {
+        self.transform.as_ref()
+    }
+
+    /// Stores `transform` with the collection, replacing any earlier one. With `reduced_column`,
+    /// a reduced copy of every vector is kept in a shadow column, backfilled now and written by
+    /// every later insert, for [`search_reduced`](Self::search_reduced).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the transform's input dimensions differ from the
+    /// collection's.
+    ///
+    /// Everything happens in one transaction, so dropping the future rolls the backfill back and
+    /// leaves the previous transform in place.
+    pub async fn set_transform(
+        &mut self,
+        transform: Transform,
+        reduced_column: bool,
+    ) -> Result<()> {
+        self.set_transform_with_cancellation(transform, reduced_column, &CancellationToken::new())
+            .await
+    }
+
+    /// [`set_transform`](Self::set_transform), rolling the backfill back once `cancel` fires.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Cancelled`] if cancelled, and the errors of
+    /// [`set_transform`](Self::set_transform) otherwise.
+    pub async fn set_transform_with_cancellation(
+        &mut self,
+        transform: Transform,
+        reduced_column: bool,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        if transform.input_dimensions() != self.dimensions {
+            return Err(Error::InvalidInput(format!(
+                "transform expects {} dimensions, collection '{}' has {}",
+                transform.input_dimensions(),
+                self.name,
+                self.dimensions
+            )));
+        }
+        let (mean, components) = match &transform {
+            Transform::Truncate { .. } => (None, None),
+            Transform::Pca { mean, components } => (Some(mean.clone()), Some(components.concat())),
+        };
+
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO pgevdb.transforms
+                 (collection, kind, input_dimensions, output_dimensions, mean, components,
+                  reduced_column)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT (collection) DO UPDATE SET
+                 kind = excluded.kind,
+                 input_dimensions = excluded.input_dimensions,
+                 output_dimensions = excluded.output_dimensions,
+                 mean = excluded.mean,
+                 components = excluded.components,
+                 reduced_column = excluded.reduced_column,
+                 created_at = now()",
+        )
+        .bind(&self.name)
+        .bind(transform.kind())
+        .bind(i32::try_from(transform.input_dimensions()).unwrap_or(i32::MAX))
+        .bind(i32::try_from(transform.output_dimensions()).unwrap_or(i32::MAX))
+        .bind(mean)
+        .bind(components)
+        .bind(reduced_column)
+        .execute(&mut *transaction)
+        .await?;
+        // Dropping the column also drops its index, which was built for the old transform
+        sqlx::query(&format!(
+            "ALTER TABLE {} DROP COLUMN IF EXISTS reduced",
+            self.table()
+        ))
+        .execute(&mut *transaction)
+        .await?;
+        if reduced_column {
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN reduced {}({})",
+                self.table(),
+                self.storage.type_name(self.backend),
+                transform.output_dimensions()
+            ))
+            .execute(&mut *transaction)
+            .await?;
+
+            // Ids may be anything a bigint holds, including i64::MIN, so the first batch starts
+            // at it inclusively
+            let mut after = (">=", i64::MIN);
+            let output_dimensions =
+                i32::try_from(transform.output_dimensions()).unwrap_or(i32::MAX);
+            loop {
+                if cancel.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                let batch: Vec<(i64, Vec<f32>)> = sqlx::query_as(&format!(
+                    "SELECT id, {} FROM {} WHERE id {} $1 ORDER BY id LIMIT $2",
+                    self.as_array("embedding"),
+                    self.table(),
+                    after.0
+                ))
+                .bind(after.1)
+                .bind(BACKFILL_BATCH)
+                .fetch_all(&mut *transaction)
+                .await?;
+                let Some(&(last_id, _)) = batch.last() else {
+                    break;
+                };
+                after = (">", last_id);
+
+                let mut ids = Vec::with_capacity(batch.len());
+                let mut reduced = Vec::new();
+                for (id, embedding) in batch {
+                    ids.push(id);
+                    reduced.extend(transform.apply(&embedding)?);
+                }
+                // The reduced vectors go as one flat array, sliced per id
+                sqlx::query(&format!(
+                    "UPDATE {table} AS t SET reduced = {value}
+                     FROM (SELECT id, ($2::real[])[(n - 1) * $3 + 1 : n * $3] AS reduced
+                           FROM unnest($1::bigint[]) WITH ORDINALITY AS u (id, n)) AS b
+                     WHERE t.id = b.id",
+                    table = self.table(),
+                    value = self.vector_value("b.reduced")
+                ))
+                .bind(ids)
+                .bind(reduced)
+                .bind(output_dimensions)
+                .execute(&mut *transaction)
+                .await?;
+            }
+        }
+        transaction.commit().await?;
+
+        self.transform = Some(transform);
+        self.reduced_column = reduced_column;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn text_search(&self) -> &TextSearchConfig {
+        &self.text_search
+    }
+
+    /// Changes the language full-text search indexes and parses queries in, and re-indexes the
+    /// stored content with it. Records inserted with their own language keep it, with accents
+    /// handled as `config` says.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if PostgreSQL has no text search configuration for the
+    /// language, or for a language of an existing record.
+    pub async fn set_text_search(&mut self, config: TextSearchConfig) -> Result<()> {
+        text_search::regconfig(&self.pool, &config.language, config.unaccent).await?;
+        let languages: Vec<(String,)> = sqlx::query_as(&format!(
+            "SELECT DISTINCT language FROM {} WHERE language IS NOT NULL",
+            self.table()
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        for (language,) in &languages {
+            text_search::regconfig(&self.pool, language, config.unaccent).await?;
+        }
+
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE pgevdb.collections SET text_language = $1, text_unaccent = $2
+             WHERE name = $3",
+        )
+        .bind(&config.language)
+        .bind(config.unaccent)
+        .bind(&self.name)
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query(&format!(
+            "UPDATE {} SET content_tsv = to_tsvector(
+                 (CASE WHEN $2 THEN 'pgevdb.' || coalesce(language, $1) || '_unaccent'
+                       ELSE coalesce(language, $1) END)::regconfig,
+                 content)
+             WHERE content IS NOT NULL",
+            self.table()
+        ))
+        .bind(&config.language)
+        .bind(config.unaccent)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        self.text_search = config;
+        Ok(())
+    }
+
+    /// Full-text search of the stored content, best matches first. `query_text` is parsed
+    /// like a web search box (`"exact phrase"`, `or`, `-excluded`) in `language`, or the
+    /// collection's language; records indexed in another language rarely match.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if PostgreSQL has no text search configuration for
+    /// `language`.
+    pub async fn search_text(
+        &self,
+        query_text: &str,
+        language: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<TextMatch>> {
+        let started = Instant::now();
+        let language = language.unwrap_or(&self.text_search.language);
+        let regconfig =
+            text_search::regconfig(&self.pool, language, self.text_search.unaccent).await?;
+        let rows: Vec<(i64, f32)> = sqlx::query_as(&format!(
+            "SELECT id, ts_rank_cd(content_tsv, query) AS rank
+             FROM {}, websearch_to_tsquery($1::regconfig, $2) query
+             WHERE content_tsv @@ query AND deleted_at IS NULL
+             ORDER BY rank DESC LIMIT $3",
+            self.table()
+        ))
+        .bind(regconfig)
+        .bind(query_text)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        self.record("text_search", &format!("{language} k={limit}"), started)
+            .await;
+        Ok(rows
+            .into_iter()
+            .map(|(id, rank)| TextMatch { id, rank })
+            .collect())
+    }
+
+    /// Makes this an adaptive-dimension collection for Matryoshka embeddings: full vectors stay
+    /// stored, while [`search_with`](Self::search_with) searches their first `dimensions`
+    /// components, which [`create_reduced_index`](Self::create_reduced_index) indexes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] unless `0 < dimensions <= self.dimensions()`.
+    pub async fn set_search_dimensions(&mut self, dimensions: u32) -> Result<()> {
+        let transform = Transform::truncate(self.dimensions, dimensions)?;
+        self.set_transform(transform, true).await
+    }
+
+    /// Drops the collection and all of its vectors.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the table can't be dropped.
+    pub async fn delete(self) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(&format!("DROP TABLE IF EXISTS {}", self.table()))
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM pgevdb.collections WHERE name = $1")
+            .bind(&self.name)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Deletes the records with these ids in one statement, returning how many there were. Ids
+    /// the collection doesn't have are skipped. For large deletions by condition, use
+    /// [`delete_where`](Self::delete_where).
+    ///
+    /// Quota usage isn't reduced until the next [reconciliation](crate::quota::reconcile).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the statement fails.
+    pub async fn delete_ids(&self, ids: &[i64]) -> Result<u64> {
+        let started = Instant::now();
+        let deleted = sqlx::query(&format!("DELETE FROM {} WHERE id = ANY($1)", self.table()))
+            .bind(ids)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        self.record("delete", "ids", started).await;
+        Ok(deleted)
+    }
+
+    /// Deletes the records matching `filter` with [`maintenance::delete_where`] and its default
+    /// options: in batches of 1000, then re-analyzing the collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if a batch fails; earlier batches stay deleted.
+    pub async fn delete_where(&self, filter: &Filter) -> Result<DeleteReport> {
+        maintenance::delete_where(self, filter, &DeleteOptions::default()).await
+    }
+
+    /// Marks the records with these ids as deleted, returning how many were live. Searches,
+    /// scans and counts skip them from then on, but they keep their space and their place in
+    /// the index until [`compact`](Self::compact) removes them, so deleting stays one quick
+    /// update however large the collection. Storing a record again under its id revives it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the statement fails.
+    pub async fn soft_delete(&self, ids: &[i64]) -> Result<u64> {
+        let started = Instant::now();
+        let deleted = sqlx::query(&format!(
+            "UPDATE {} SET deleted_at = now() WHERE id = ANY($1) AND deleted_at IS NULL",
+            self.table()
+        ))
+        .bind(ids)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        self.record("delete", "soft", started).await;
+        Ok(deleted)
+    }
+
+    /// [`soft_delete`](Self::soft_delete) of the records matching `filter`, in one statement.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the statement fails.
+    pub async fn soft_delete_where(&self, filter: &Filter) -> Result<u64> {
+        let started = Instant::now();
+        let mut builder =
+            QueryBuilder::new(format!("UPDATE {} SET deleted_at = now()", self.table()));
+        filter.push_where(&mut builder);
+        let deleted = builder.build().execute(&self.pool).await?.rows_affected();
+        self.record("delete", "soft filtered", started).await;
+        Ok(deleted)
+    }
+
+    /// Revives the [soft-deleted](Self::soft_delete) records with these ids that weren't
+    /// compacted yet, returning how many there were.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the statement fails.
+    pub async fn restore(&self, ids: &[i64]) -> Result<u64> {
+        Ok(sqlx::query(&format!(
+            "UPDATE {} SET deleted_at = NULL WHERE id = ANY($1) AND deleted_at IS NOT NULL",
+            self.table()
+        ))
+        .bind(ids)
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    /// Removes the [soft-deleted](Self::soft_delete) records for good with
+    /// [`maintenance::compact`] and its default options: in batches of 1000, then re-analyzing
+    /// the collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if a batch fails; earlier batches stay deleted.
+    pub async fn compact(&self) -> Result<DeleteReport> {
+        maintenance::compact(self, &DeleteOptions::default()).await
+    }
+
+    /// Makes records inserted from now on expire `ttl` after they were stored, or never with
+    /// `None`, the default. Storing a record again under its id restarts its time. Expired
+    /// records stay until [`maintenance::delete_expired`] or a job of
+    /// [`maintenance::spawn_expiry`] or [`maintenance::schedule_expiry`] deletes them; searches
+    /// that must skip them before that filter with `!Filter::Expired`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the default can't be changed.
+    pub async fn set_ttl(&self, ttl: Option<Duration>) -> Result<()> {
+        let default = match ttl {
+            Some(ttl) => format!(
+                "SET DEFAULT now() + make_interval(secs => {})",
+                ttl.as_secs_f64()
+            ),
+            None => "DROP DEFAULT".to_string(),
+        };
+        sqlx::query(&format!(
+            "ALTER TABLE {} ALTER COLUMN expires_at {default}",
+            self.table()
+        ))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Makes the records with these ids expire `ttl` from now, or never with `None`, returning
+    /// how many there were. See [`set_ttl`](Self::set_ttl) for how expired records are deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the statement fails.
+    pub async fn set_expiry(&self, ids: &[i64], ttl: Option<Duration>) -> Result<u64> {
+        Ok(sqlx::query(&format!(
+            "UPDATE {} SET expires_at = now() + make_interval(secs => $2) WHERE id = ANY($1)",
+            self.table()
+        ))
+        .bind(ids)
+        .bind(ttl.map(|ttl| ttl.as_secs_f64()))
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    /// Builds an index over the collection's vectors, replacing any existing one. Searches use
+    /// it as soon as this returns. Searches run while the old index is dropped and the new one
+    /// built fall back to exact scans; use [`reindex`](Self::reindex) on a live collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the backend has no index of this type, and
+    /// [`Error::Database`] if the build fails, for example because the statement timeout expires
+    /// first.
+    ///
+    /// Dropping the future doesn't stop a build the server already started; it runs to
+    /// completion in the background. Use [`create_index_with_cancellation`] to abort it.
+    ///
+    /// [`create_index_with_cancellation`]: Self::create_index_with_cancellation
+    pub async fn create_index(&self, index: &IndexConfig) -> Result<()> {
+        self.create_index_with_cancellation(index, &CancellationToken::new())
+            .await
+    }
+
+    /// [`create_index`](Self::create_index), aborting the build on the server once `cancel`
+    /// fires. The aborted build is rolled back, leaving the collection without an index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Cancelled`] if cancelled, and the errors of
+    /// [`create_index`](Self::create_index) otherwise.
+    pub async fn create_index_with_cancellation(
+        &self,
+        index: &IndexConfig,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        // Refuse an index type the backend lacks before dropping the current index
+        index.using(self.backend, "embedding", "")?;
+        self.drop_index().await?;
+        self.build_index("embedding", &self.index_name(), index, false, cancel)
+            .await
+    }
+
+    /// Replaces the collection's index without downtime: the new index is built concurrently
+    /// next to the old one, which keeps serving searches and inserts meanwhile, and then swapped
+    /// in within one short transaction. If the build fails, the old index stays in place.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the build or the swap fails.
+    pub async fn reindex(&self, index: &IndexConfig) -> Result<()> {
+        self.reindex_with_cancellation(index, &CancellationToken::new())
+            .await
+    }
+
+    /// [`reindex`](Self::reindex), aborting the concurrent build once `cancel` fires. The
+    /// half-built index is dropped and the old one keeps serving searches.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Cancelled`] if cancelled, and the errors of [`reindex`](Self::reindex)
+    /// otherwise.
+    pub async fn reindex_with_cancellation(
+        &self,
+        index: &IndexConfig,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let index_name = self.index_name();
+        let shadow_name = format!("{}_reindex", self.name);
+        // A failed concurrent build leaves an invalid index behind
+        let drop_shadow = format!("DROP INDEX IF EXISTS collections.{shadow_name}");
+        sqlx::query(&drop_shadow).execute(&self.pool).await?;
+        if let Err(error) = self
+            .build_index("embedding", &shadow_name, index, true, cancel)
+            .await
+        {
+            sqlx::query(&drop_shadow).execute(&self.pool).await?;
+            return Err(error);
+        }
+
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query(&format!("DROP INDEX IF EXISTS collections.{index_name}"))
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query(&format!(
+            "ALTER INDEX collections.{shadow_name} RENAME TO {index_name}"
+        ))
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// [`create_index`](Self::create_index) for the reduced shadow column.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the collection has no reduced column, and
+    /// [`Error::Database`] if the build fails.
+    pub async fn create_reduced_index(&self, index: &IndexConfig) -> Result<()> {
+        if !self.reduced_column {
+            return Err(self.no_reduced_column());
+        }
+        let index_name = format!("{}_reduced_idx", self.name);
+        sqlx::query(&format!("DROP INDEX IF EXISTS collections.{index_name}"))
+            .execute(&self.pool)
+            .await?;
+        self.build_index(
+            "reduced",
+            &index_name,
+            index,
+            false,
+            &CancellationToken::new(),
+        )
+        .await
+    }
+
+    /// Drops the collection's index, if any; searches fall back to exact scans.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the index can't be dropped.
+    pub async fn drop_index(&self) -> Result<()> {
+        sqlx::query(&format!(
+            "DROP INDEX IF EXISTS collections.{}",
+            self.index_name()
+        ))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// On-disk size of the collection's index in bytes, or `None` without one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the size can't be queried.
+    pub async fn index_size(&self) -> Result<Option<u64>> {
+        let (size,): (Option<i64>,) = sqlx::query_as("SELECT pg_relation_size(to_regclass($1))")
+            .bind(format!("collections.{}", self.index_name()))
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(size.map(i64::unsigned_abs))
+    }
+
+    /// Number of records matching `filter`, or of all records with `None`, counted as
+    /// `exactness` says. [Soft-deleted](Self::soft_delete) records aren't counted, except by
+    /// the estimate of the whole collection until they are compacted. A collection never
+    /// analyzed has no estimate of its size yet, so it is counted exactly without a filter.
+    ///
+    /// ```no_run
+    /// # async fn demo(docs: &pgevdb::Collection) -> pgevdb::Result<()> {
+    /// use pgevdb::{Exactness, Filter};
+    ///
+    /// let total = docs.count(None, Exactness::Estimated).await?;
+    /// let english = docs.count(Some(&Filter::eq("lang", "en")), Exactness::Exact).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the count or the estimate can't be queried.
+    pub async fn count(&self, filter: Option<&Filter>, exactness: Exactness) -> Result<u64> {
+        let filter = filter.unwrap_or(&Filter::All);
+        if exactness == Exactness::Estimated {
+            if *filter == Filter::All {
+                // reltuples is -1 until the first VACUUM or ANALYZE
+                let (estimate,): (Option<f32>,) =
+                    sqlx::query_as("SELECT reltuples FROM pg_class WHERE oid = to_regclass($1)")
+                        .bind(self.table())
+                        .fetch_optional(&self.pool)
+                        .await?
+                        .unwrap_or((None,));
+                if let Some(estimate) = estimate.filter(|estimate| *estimate >= 0.0) {
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    return Ok(estimate.round() as u64);
+                }
+            } else {
+                let mut explain = QueryBuilder::new(format!(
+                    "EXPLAIN (FORMAT JSON) SELECT 1 FROM {}",
+                    self.table()
+                ));
+                filter.push_where(&mut explain);
+                let (plan,): (Value,) = explain.build_query_as().fetch_one(&self.pool).await?;
+                if let Some(rows) = plan[0]["Plan"]["Plan Rows"].as_f64() {
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    return Ok(rows.max(0.0).round() as u64);
+                }
+            }
+        }
+        let mut count = QueryBuilder::new(format!("SELECT count(*) FROM {}", self.table()));
+        filter.push_where(&mut count);
+        let (count,): (i64,) = count.build_query_as().fetch_one(&self.pool).await?;
+        Ok(count.unsigned_abs())
+    }
+
+    /// Stores a vector, returning its generated id.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions,
+    /// and [`Error::QuotaExceeded`] if the record would take the collection's namespace past
+    /// its quota.
+    pub async fn insert(&self, embedding: &[f32]) -> Result<i64> {
+        self.insert_row(None, Embedding::Dense(embedding), None, None)
+            .await
+    }
+
+    /// [`insert`](Self::insert), storing `payload` serialized as JSON with the vector, e.g. the
+    /// title, URL or tags of the document it was computed from. Search results and records
+    /// carry it back, and [`Filter`]s can select by it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions
+    /// or `payload` can't be serialized as JSON, and [`Error::QuotaExceeded`] if the record
+    /// would take the collection's namespace past its quota.
+    pub async fn insert_with_payload<T: Serialize + ?Sized>(
+        &self,
+        embedding: &[f32],
+        payload: &T,
+    ) -> Result<i64> {
+        let payload = to_payload(payload)?;
+        self.insert_row(None, Embedding::Dense(embedding), None, Some(&payload))
+            .await
+    }
+
+    /// Stores a sparse vector, returning its generated id. Sparse vectors can go into any
+    /// collection, but only one with [`Storage::Sparse`] keeps them sparse.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions,
+    /// and [`Error::QuotaExceeded`] if the record would take the collection's namespace past
+    /// its quota.
+    pub async fn insert_sparse(&self, embedding: &SparseVector) -> Result<i64> {
+        self.insert_row(None, Embedding::Sparse(embedding), None, None)
+            .await
+    }
+
+    /// Stores a bit vector, returning its generated id. Bit vectors can go into any collection,
+    /// but only one with [`Storage::Binary`] keeps them as bits.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions,
+    /// and [`Error::QuotaExceeded`] if the record would take the collection's namespace past
+    /// its quota.
+    pub async fn insert_bits(&self, embedding: &BitVector) -> Result<i64> {
+        self.insert_row(None, Embedding::Bits(embedding), None, None)
+            .await
+    }
+
+    /// [`insert_bits`](Self::insert_bits), storing `payload` with the vector as
+    /// [`insert_with_payload`](Self::insert_with_payload) does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions
+    /// or `payload` can't be serialized as JSON, and [`Error::QuotaExceeded`] if the record
+    /// would take the collection's namespace past its quota.
+    pub async fn insert_bits_with_payload<T: Serialize + ?Sized>(
+        &self,
+        embedding: &BitVector,
+        payload: &T,
+    ) -> Result<i64> {
+        let payload = to_payload(payload)?;
+        self.insert_row(None, Embedding::Bits(embedding), None, Some(&payload))
+            .await
+    }
+
+    /// [`insert_sparse`](Self::insert_sparse), storing `payload` with the vector as
+    /// [`insert_with_payload`](Self::insert_with_payload) does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions
+    /// or `payload` can't be serialized as JSON, and [`Error::QuotaExceeded`] if the record
+    /// would take the collection's namespace past its quota.
+    pub async fn insert_sparse_with_payload<T: Serialize + ?Sized>(
+        &self,
+        embedding: &SparseVector,
+        payload: &T,
+    ) -> Result<i64> {
+        let payload = to_payload(payload)?;
+        self.insert_row(None, Embedding::Sparse(embedding), None, Some(&payload))
+            .await
+    }
+
+    /// Replaces the payload of record `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `payload` can't be serialized as JSON, and
+    /// [`Error::NotFound`] if the collection has no record `id` or it was
+    /// [soft-deleted](Self::soft_delete).
+    pub async fn set_payload<T: Serialize + ?Sized>(&self, id: i64, payload: &T) -> Result<()> {
+        let payload = to_payload(payload)?;
+        let updated = sqlx::query(&format!(
+            "UPDATE {} SET payload = $2 WHERE id = $1 AND deleted_at IS NULL",
+            self.table()
+        ))
+        .bind(id)
+        .bind(payload)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(self.no_record(id));
+        }
+        Ok(())
+    }
+
+    /// Merges the top-level keys of `changes`, which must serialize as a JSON object, into the
+    /// payload of record `id`, replacing the values of keys it already has and keeping the
+    /// others. A record without a payload, or with one that isn't an object, gets `changes` as
+    /// its payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `changes` doesn't serialize as a JSON object, and
+    /// [`Error::NotFound`] if the collection has no record `id` or it was
+    /// [soft-deleted](Self::soft_delete).
+    pub async fn update_payload<T: Serialize + ?Sized>(&self, id: i64, changes: &T) -> Result<()> {
+        let changes = to_payload(changes)?;
+        if !changes.is_object() {
+            return Err(Error::InvalidInput(
+                "payload changes must be a JSON object".to_string(),
+            ));
+        }
+        let updated = sqlx::query(&format!(
+            "UPDATE {} SET payload = CASE jsonb_typeof(payload)
+                 WHEN 'object' THEN payload || $2 ELSE $2 END
+             WHERE id = $1 AND deleted_at IS NULL",
+            self.table()
+        ))
+        .bind(id)
+        .bind(changes)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(self.no_record(id));
+        }
+        Ok(())
+    }
+
+    /// The payload of record `id` deserialized as `T`, or `None` if it has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if the collection has no record `id`, and
+    /// [`Error::InvalidInput`] if the payload doesn't deserialize as `T`.
+    pub async fn payload<T: DeserializeOwned>(&self, id: i64) -> Result<Option<T>> {
+        let row: Option<(Option<Value>,)> = sqlx::query_as(&format!(
+            "SELECT payload FROM {} WHERE id = $1 AND deleted_at IS NULL",
+            self.table()
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let (payload,) = row.ok_or_else(|| self.no_record(id))?;
+        payload.map(from_payload).transpose()
+    }
+
+    /// [`insert`](Self::insert) for many vectors, returning their ids in order. Loads of at
+    /// least [`ANALYZE_THRESHOLD`](maintenance::ANALYZE_THRESHOLD) vectors are followed by
+    /// [`maintenance::analyze`], so the first searches afterwards are planned for the new size.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if a vector doesn't have the collection's dimensions,
+    /// before any is stored, and [`Error::QuotaExceeded`] if the namespace's quota runs out;
+    /// vectors before it stay stored.
+    pub async fn insert_many<I>(&self, embeddings: I) -> Result<Vec<i64>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[f32]>,
+    {
+        let embeddings: Vec<I::Item> = embeddings.into_iter().collect();
+        self.check_all_dimensions(embeddings.iter().map(AsRef::as_ref))?;
+        let mut ids = Vec::with_capacity(embeddings.len());
+        for embedding in &embeddings {
+            ids.push(self.insert(embedding.as_ref()).await?);
+        }
+        maintenance::analyze_after_ingest(self, ids.len() as u64).await?;
+        Ok(ids)
+    }
+
+    /// [`insert_or_update`](Self::insert_or_update) for many `(id, vector)` pairs without
+    /// content, e.g. vectors keyed by the ids of another database. Large loads are analyzed
+    /// like those of [`insert_many`](Self::insert_many).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if a vector doesn't have the collection's dimensions,
+    /// before any is stored, and [`Error::QuotaExceeded`] if the namespace's quota runs out;
+    /// vectors before it stay stored.
+    pub async fn insert_or_update_many<I, V>(&self, records: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (i64, V)>,
+        V: AsRef<[f32]>,
+    {
+        let records: Vec<(i64, V)> = records.into_iter().collect();
+        self.check_all_dimensions(records.iter().map(|(_, embedding)| embedding.as_ref()))?;
+        for (id, embedding) in &records {
+            self.insert_or_update(*id, embedding.as_ref(), None).await?;
+        }
+        maintenance::analyze_after_ingest(self, records.len() as u64).await?;
+        Ok(())
+    }
+
+    /// [`insert`](Self::insert), storing the text the vector was computed from alongside it
+    /// for rerankers and applications to read back.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions.
+    pub async fn insert_with_content(&self, embedding: &[f32], content: &str) -> Result<i64> {
+        self.insert_content(
+            embedding,
+            &Content {
+                text: content,
+                ..Content::default()
+            },
+        )
+        .await
+    }
+
+    /// [`insert_with_content`](Self::insert_with_content) for a chunk of a larger document,
+    /// recording the document as `source` so retrieval can cite it and limit chunks per
+    /// document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions.
+    pub async fn insert_chunk(
+        &self,
+        embedding: &[f32],
+        content: &str,
+        source: &str,
+    ) -> Result<i64> {
+        self.insert_content(
+            embedding,
+            &Content {
+                text: content,
+                source: Some(source),
+                language: None,
+            },
+        )
+        .await
+    }
+
+    /// Stores a vector with its text, indexing the text for [`search_text`](Self::search_text)
+    /// in the record's language or the collection's.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions
+    /// or PostgreSQL has no text search configuration for the record's language.
+    pub async fn insert_content(&self, embedding: &[f32], content: &Content<'_>) -> Result<i64> {
+        self.insert_row(None, Embedding::Dense(embedding), Some(content), None)
+            .await
+    }
+
+    /// Stores a vector under a caller-chosen `id`, replacing the vector and content already
+    /// stored under it but keeping its payload, for importing records whose ids come from
+    /// elsewhere. Ids generated by later plain inserts continue after the highest one stored
+    /// this way.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions,
+    /// and [`Error::QuotaExceeded`] if the record would take the collection's namespace past
+    /// its quota. Replacing a record is charged like a new one until the next
+    /// [reconciliation](crate::quota::reconcile).
+    pub async fn insert_or_update(
+        &self,
+        id: i64,
+        embedding: &[f32],
+        content: Option<&Content<'_>>,
+    ) -> Result<()> {
+        self.insert_row(Some(id), Embedding::Dense(embedding), content, None)
+            .await?;
+        Ok(())
+    }
+
+    /// [`insert_or_update`](Self::insert_or_update), also replacing the payload, for
+    /// re-ingesting a document whose metadata may have changed. The record is written by one
+    /// `INSERT ... ON CONFLICT` statement, so searches see either the old vector and payload or
+    /// the new ones, never a mix.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `embedding` doesn't have the collection's dimensions
+    /// or `payload` can't be serialized as JSON, and [`Error::QuotaExceeded`] if the record
+    /// would take the collection's namespace past its quota.
+    pub async fn insert_or_update_with_payload<T: Serialize + ?Sized>(
+        &self,
+        id: i64,
+        embedding: &[f32],
+        content: Option<&Content<'_>>,
+        payload: &T,
+    ) -> Result<()> {
+        let payload = to_payload(payload)?;
+        self.insert_row(
+            Some(id),
+            Embedding::Dense(embedding),
+            content,
+            Some(&payload),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Stores dense vectors, each with any of an explicit id, content and payload, for callers
+    /// that get all of them as data, such as the [HTTP API](crate::http). They're stored in one
+    /// transaction, so if one fails none is.
+    #[cfg(feature = "server")]
+    pub(crate) async fn insert_records(&self, records: &[NewRow<'_>]) -> Result<Vec<i64>> {
+        self.check_all_dimensions(records.iter().map(|record| record.embedding))?;
+        let started = Instant::now();
+        // Resolved before the transaction takes a connection, once per language
+        let mut regconfigs = HashMap::new();
+        for record in records {
+            let language = record.content.and_then(|content| content.language);
+            if let std::collections::hash_map::Entry::Vacant(entry) = regconfigs.entry(language) {
+                entry.insert(self.regconfig(language).await?);
+            }
+        }
+
+        let mut transaction = self.pool.begin().await?;
+        let quota = quota::lock(&mut transaction, &self.namespace).await?;
+        let mut ids = Vec::with_capacity(records.len());
+        let mut bytes = 0;
+        for record in records {
+            let language = record.content.and_then(|content| content.language);
+            let (id, size) = self
+                .insert_in(
+                    &mut transaction,
+                    record.id,
+                    Embedding::Dense(record.embedding),
+                    record.content.as_ref(),
+                    record.payload,
+                    regconfigs[&language].clone(),
+                )
+                .await?;
+            ids.push(id);
+            bytes += u64::from(size.unsigned_abs());
+        }
+        if let Some(quota) = quota {
+            quota::charge(&mut transaction, &quota, ids.len() as u64, bytes).await?;
+        }
+        transaction.commit().await?;
+        self.record("insert", &format!("batch n={}", ids.len()), started)
+            .await;
+        Ok(ids)
+    }
+
+    async fn insert_row(
+        &self,
+        id: Option<i64>,
+        embedding: Embedding<'_>,
+        content: Option<&Content<'_>>,
+        payload: Option<&Value>,
+    ) -> Result<i64> {
+        self.check_embedding(embedding)?;
+        let started = Instant::now();
+        let regconfig = self
+            .regconfig(content.and_then(|content| content.language))
+            .await?;
+
+        // The quota row stays locked until commit, so concurrent inserts are charged in turn
+        let mut transaction = self.pool.begin().await?;
+        let quota = quota::lock(&mut transaction, &self.namespace).await?;
+        let (id, size) = self
+            .insert_in(&mut transaction, id, embedding, content, payload, regconfig)
+            .await?;
+        if let Some(quota) = quota {
+            quota::charge(&mut transaction, &quota, 1, size.unsigned_abs().into()).await?;
+        }
+        transaction.commit().await?;
+        self.record("insert", "", started).await;
+        Ok(id)
+    }
+
+    /// Inserts one record in `transaction`, returning its id and size.
+    async fn insert_in(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        id: Option<i64>,
+        embedding: Embedding<'_>,
+        content: Option<&Content<'_>>,
+        payload: Option<&Value>,
+        regconfig: String,
+    ) -> Result<(i64, i32)> {
+        let explicit_id = id.is_some();
+        let reduced = self.reduce(&embedding.dense())?;
+        let (id, size): (i64, i32) = self
+            .insert_query(id, embedding, content, payload, regconfig, reduced)
+            .build_query_as()
+            .fetch_one(&mut **transaction)
+            .await?;
+        if explicit_id {
+            // Keep generated ids clear of explicit ones
+            let sequence = format!("{}_id_seq", self.table());
+            sqlx::query(&format!(
+                "SELECT setval('{sequence}', $1) FROM {sequence} WHERE last_value < $1"
+            ))
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Ok((id, size))
+    }
+
+    /// `INSERT` of one record returning its id and size, an upsert if `id` is given.
+    fn insert_query<'a>(
+        &self,
+        id: Option<i64>,
+        embedding: Embedding<'_>,
+        content: Option<&Content<'a>>,
+        payload: Option<&'a Value>,
+        regconfig: String,
+        reduced: Option<Vec<f32>>,
+    ) -> QueryBuilder<'a, Postgres> {
+        let text = content.map(|content| content.text);
+        let has_reduced = reduced.is_some();
+        let mut query = QueryBuilder::new(format!("INSERT INTO {} AS t (", self.table()));
+        if id.is_some() {
+            query.push("id, ");
+        }
+        query.push("embedding, content, source, language, content_tsv");
+        if payload.is_some() {
+            query.push(", payload");
+        }
+        if has_reduced {
+            query.push(", reduced");
+        }
+        query.push(") VALUES (");
+        if let Some(id) = id {
+            query.push_bind(id).push(", ");
+        }
+        self.push_embedding(&mut query, embedding);
+        query
+            .push(", ")
+            .push_bind(text)
+            .push(", ")
+            .push_bind(content.and_then(|content| content.source))
+            .push(", ")
+            .push_bind(content.and_then(|content| content.language))
+            .push(", to_tsvector(")
+            .push_bind(regconfig)
+            .push("::regconfig, ")
+            .push_bind(text)
+            .push(")");
+        if let Some(payload) = payload {
+            query.push(", ").push_bind(payload);
+        }
+        if let Some(reduced) = reduced {
+            query.push(", ");
+            self.push_vector(&mut query, reduced);
+        }
+        query.push(")");
+        if id.is_some() {
+            query.push(
+                " ON CONFLICT (id) DO UPDATE SET embedding = excluded.embedding,
+                  content = excluded.content, source = excluded.source,
+                  language = excluded.language, content_tsv = excluded.content_tsv,
+                  inserted_at = now(), expires_at = excluded.expires_at, deleted_at = NULL",
+            );
+            if payload.is_some() {
+                query.push(", payload = excluded.payload");
+            }
+            if has_reduced {
+                query.push(", reduced = excluded.reduced");
+            }
+        }
+        query.push(" RETURNING id, pg_column_size(t.*)");
+        query
+    }
+
+    /// [`insert`](Self::insert) for a vector produced by `embedder`, refused unless the
+    /// collection is unbound or bound to that same embedder and the vector has the embedder's
+    /// shape.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the embedders don't match or the vector doesn't have the
+    /// dimensions or normalization `embedder` declares.
+    pub async fn insert_embedded(&self, embedder: &Embedder, embedding: &[f32]) -> Result<i64> {
+        if let Some(bound) = self.embedder.as_ref().filter(|bound| *bound != embedder) {
+            return Err(Error::InvalidInput(format!(
+                "collection '{}' holds vectors from {bound}, got one from {embedder}",
+                self.name
+            )));
+        }
+        embedder.check(embedding)?;
+        self.insert(embedding).await
+    }
+
+    /// Mean of the records matching `filter`, computed by the server so the vectors never leave
+    /// the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if no record matches.
+    pub async fn centroid(&self, filter: &Filter) -> Result<Vector> {
+        let mut builder = QueryBuilder::new(format!(
+            "SELECT avg({})::real[] FROM {}",
+            self.storage.widen(self.backend, "embedding"),
+            self.table()
+        ));
+        filter.push_where(&mut builder);
+        let (centroid,): (Option<Vec<f32>>,) =
+            builder.build_query_as().fetch_one(&self.pool).await?;
+        centroid.map(Vector::new).ok_or_else(|| {
+            Error::NotFound(format!("records matching the filter in '{}'", self.name))
+        })
+    }
+
+    /// Weighted sum of stored vectors, `Σ weight · vector(id)` over `terms`, computed by the
+    /// server. Negative weights subtract, so `[(liked, 1.0), (disliked, -0.5)]` steers a
+    /// recommendation query away from an example.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `terms` is empty and [`Error::NotFound`] if an id
+    /// doesn't exist or was [soft-deleted](Self::soft_delete).
+    pub async fn combine(&self, terms: &[(i64, f32)]) -> Result<Vector> {
+        if terms.is_empty() {
+            return Err(Error::InvalidInput(
+                "combine needs at least one vector".to_string(),
+            ));
+        }
+        let (ids, weights): (Vec<i64>, Vec<f32>) = terms.iter().copied().unzip();
+        let (found, combined): (i64, Option<Vec<f32>>) = sqlx::query_as(&format!(
+            "WITH terms AS (
+                 SELECT t.weight, {components} AS components
+                 FROM unnest($1::bigint[], $2::real[]) AS t (id, weight)
+                 JOIN {table} e ON e.id = t.id AND e.deleted_at IS NULL
+             )
+             SELECT (SELECT count(*) FROM terms),
+                    (SELECT array_agg(total ORDER BY position) FROM (
+                         SELECT u.position, sum(terms.weight * u.x) AS total
+                         FROM terms, unnest(terms.components) WITH ORDINALITY AS u (x, position)
+                         GROUP BY u.position
+                     ) sums)",
+            components = self.as_array("e.embedding"),
+            table = self.table()
+        ))
+        .bind(&ids)
+        .bind(&weights)
+        .fetch_one(&self.pool)
+        .await?;
+        match combined {
+            Some(combined) if usize::try_from(found).is_ok_and(|found| found == terms.len()) => {
+                Ok(Vector::new(combined))
+            }
+            _ => Err(Error::NotFound(format!(
+                "some of the records {ids:?} in '{}'",
+                self.name
+            ))),
+        }
+    }
+
+    /// The `limit` records most like the `positive` examples and least like the `negative`
+    /// ones, leaving out the examples themselves. The query is the mean of the positive
+    /// vectors, moved away from the mean of the negative ones by the distance between the two:
+    /// `2 · mean(positive) - mean(negative)`, computed by the server with
+    /// [`combine`](Self::combine).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `positive` is empty and [`Error::NotFound`] if an id
+    /// doesn't exist.
+    pub async fn recommend(
+        &self,
+        positive: &[i64],
+        negative: &[i64],
+        limit: u32,
+    ) -> Result<Vec<SearchResult>> {
+        if positive.is_empty() {
+            return Err(Error::InvalidInput(
+                "recommend needs at least one positive example".to_string(),
+            ));
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let (positive_weight, negative_weight) = if negative.is_empty() {
+            (1.0 / positive.len() as f32, 0.0)
+        } else {
+            (2.0 / positive.len() as f32, -1.0 / negative.len() as f32)
+        };
+        let terms: Vec<(i64, f32)> = positive
+            .iter()
+            .map(|id| (*id, positive_weight))
+            .chain(negative.iter().map(|id| (*id, negative_weight)))
+            .collect();
+        let query = self.combine(&terms).await?;
+        let examples = Filter::Ids(positive.iter().chain(negative).copied().collect());
+        self.search_with(&query, &SearchOptions::new(limit).filter(!examples))
+            .await
+    }
+
+    /// Content and source of the records with `ids`, for those that exist.
+    pub(crate) async fn contents(
+        &self,
+        ids: &[i64],
+    ) -> Result<HashMap<i64, (Option<String>, Option<String>)>> {
+        let rows: Vec<(i64, Option<String>, Option<String>)> = sqlx::query_as(&format!(
+            "SELECT id, content, source FROM {} WHERE id = ANY($1) AND deleted_at IS NULL",
+            self.table()
+        ))
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, content, source)| (id, (content, source)))
+            .collect())
+    }
+
+    /// Payloads of the records among `ids` that have one.
+    pub(crate) async fn payloads(&self, ids: &[i64]) -> Result<HashMap<i64, Value>> {
+        let rows: Vec<(i64, Value)> = sqlx::query_as(&format!(
+            "SELECT id, payload FROM {} WHERE id = ANY($1) AND payload IS NOT NULL AND deleted_at IS NULL",
+            self.table()
+        ))
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Keyword and vector search merged into one ranking with [`hybrid::hybrid_search`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions.
+    pub async fn hybrid_search(
+        &self,
+        query_text: &str,
+        query: &[f32],
+        limit: u32,
+    ) -> Result<Vec<HybridResult>> {
+        hybrid::hybrid_search(self, query_text, query, limit).await
+    }
+
+    /// [`hybrid_search`](Self::hybrid_search) with the fusion strategy and candidate count of
+    /// `options`, see [`hybrid::hybrid_search_with`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions.
+    pub async fn hybrid_search_with(
+        &self,
+        query_text: &str,
+        query: &[f32],
+        options: &HybridOptions,
+    ) -> Result<Vec<HybridResult>> {
+        hybrid::hybrid_search_with(self, query_text, query, options).await
+    }
+
+    /// Builds a prompt-ready context for `query_text` with [`rag::retrieve_context`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`rag::retrieve_context`].
+    pub async fn retrieve_context(
+        &self,
+        query_text: &str,
+        options: &ContextOptions,
+    ) -> Result<Context> {
+        rag::retrieve_context(self, query_text, options).await
+    }
+
+    /// Groups the stored vectors into `k` clusters with [`clustering::cluster`] and its default
+    /// options.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `k` is 0 or exceeds the number of sampled vectors.
+    pub async fn cluster(&self, k: u32) -> Result<ClusterReport> {
+        clustering::cluster(self, k, &ClusterOptions::default()).await
+    }
+
+    /// Centroids of the last [`cluster`](Self::cluster) run, empty if there was none.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the catalog can't be read.
+    pub async fn centroids(&self) -> Result<Vec<Centroid>> {
+        clustering::centroids(&self.pool, &self.name).await
+    }
+
+    /// Finds the `limit` vectors closest to `query`, nearest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions.
+    pub async fn search(&self, query: &[f32], limit: u32) -> Result<Vec<SearchResult>> {
+        self.search_stream(query, limit).try_collect().await
+    }
+
+    /// [`search`](Self::search), yielding results as the server returns them instead of
+    /// buffering them, for large limits.
+    ///
+    /// The first item is an [`Error::InvalidInput`] if `query` doesn't have the collection's
+    /// dimensions.
+    pub fn search_stream<'a>(
+        &'a self,
+        query: &'a [f32],
+        limit: u32,
+    ) -> impl Stream<Item = Result<SearchResult>> + Send + 'a {
+        let query = self.check_dimensions(query).map(|()| query.to_vec());
+        async_stream::try_stream! {
+            let started = Instant::now();
+            let mut results = pin!(self.nearest("embedding", self.metric, query, &Filter::All, limit));
+            while let Some(result) = results.try_next().await? {
+                yield result;
+            }
+            self.record("search", &format!("knn k={limit}"), started).await;
+        }
+    }
+
+    /// Searches as configured by the collection and `options`: by the reduced or prefix column
+    /// when the collection keeps one, optionally re-ranked by full vectors, and like
+    /// [`search`](Self::search) otherwise. With a reranker, the candidates are then reordered by
+    /// relevance to the query text.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions or a
+    /// reranker doesn't score every candidate, and [`Error::Reranker`] if an HTTP reranker
+    /// fails.
+    pub async fn search_with(
+        &self,
+        query: &[f32],
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_embedding(Embedding::Dense(query), options)
+            .await
+    }
+
+    /// Finds the `limit` vectors closest to the sparse `query`, nearest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions.
+    pub async fn search_sparse(
+        &self,
+        query: &SparseVector,
+        limit: u32,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_sparse_with(query, &SearchOptions::new(limit))
+            .await
+    }
+
+    /// [`search_with`](Self::search_with) for a sparse `query`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions or a
+    /// reranker doesn't score every candidate, and [`Error::Reranker`] if an HTTP reranker
+    /// fails.
+    pub async fn search_sparse_with(
+        &self,
+        query: &SparseVector,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_embedding(Embedding::Sparse(query), options)
+            .await
+    }
+
+    /// Finds the `limit` vectors closest to the bit vector `query`, nearest first; in a
+    /// [binary](Storage::Binary) collection by its Hamming or Jaccard distance.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions.
+    pub async fn search_bits(&self, query: &BitVector, limit: u32) -> Result<Vec<SearchResult>> {
+        self.search_bits_with(query, &SearchOptions::new(limit))
+            .await
+    }
+
+    /// [`search_with`](Self::search_with) for a bit vector `query`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions, the
+    /// search metric doesn't suit the collection's storage or a reranker doesn't score every
+    /// candidate, and [`Error::Reranker`] if an HTTP reranker fails.
+    pub async fn search_bits_with(
+        &self,
+        query: &BitVector,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_embedding(Embedding::Bits(query), options).await
+    }
+
+    async fn search_embedding(
+        &self,
+        query: Embedding<'_>,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let mut shape = format!("knn k={}", options.limit);
+        if let Some(metric) = options.metric.filter(|metric| *metric != self.metric) {
+            shape.push(' ');
+            shape.push_str(metric.name());
+        }
+        if options.filter != Filter::All {
+            shape.push_str(" filtered");
+        }
+        if let Some(probes) = options.probes {
+            let _ = write!(shape, " probes={probes}");
+        }
+        if let Some(ef_search) = options.ef_search {
+            let _ = write!(shape, " ef_search={ef_search}");
+        }
+        if options.max_distance.is_some() || options.min_score.is_some() {
+            shape.push_str(" cutoff");
+        }
+        if options.group.is_some() {
+            shape.push_str(" grouped");
+        }
+        if options.score.is_some() {
+            shape.push_str(" scored");
+        }
+        if options.offset > 0 {
+            let _ = write!(shape, " offset={}", options.offset);
+        }
+        if self.reduced_column {
+            shape.push_str(if options.refine {
+                " reduced refine"
+            } else {
+                " reduced"
+            });
+        }
+        if options.rerank.is_none() && options.mmr.is_none() {
+            let results = self.ranked(query, options, options.end()).await?;
+            self.record("search", &shape, started).await;
+            return Ok(options.page(results));
+        }
+        let mut results = self
+            .ranked(query, options, options.candidate_count())
+            .await?;
+        if let Some(rerank) = &options.rerank {
+            self.rerank(rerank, &mut results).await?;
+            shape.push_str(" rerank");
+        }
+        if let Some(lambda) = options.mmr {
+            results = self.diversify(&results, options, lambda).await?;
+            shape.push_str(" mmr");
+        }
+        self.record("search", &shape, started).await;
+        Ok(options.page(results))
+    }
+
+    /// Scores `results` with `rerank` and sorts them by that score, best first.
+    async fn rerank(&self, rerank: &Rerank, results: &mut [SearchResult]) -> Result<()> {
+        let ids: Vec<i64> = results.iter().map(|result| result.id).collect();
+        let documents: Vec<String> = if let Some(key) = &rerank.payload_key {
+            let payloads = self.payloads(&ids).await?;
+            ids.iter()
+                .map(|id| {
+                    payloads
+                        .get(id)
+                        .and_then(|payload| payload.get(key))
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string()
+                })
+                .collect()
+        } else {
+            let mut contents = self.contents(&ids).await?;
+            ids.iter()
+                .map(|id| {
+                    contents
+                        .remove(id)
+                        .and_then(|(content, _)| content)
+                        .unwrap_or_default()
+                })
+                .collect()
+        };
+
+        let scores = rerank.reranker.rerank(&rerank.query, &documents).await?;
+        if scores.len() != documents.len() {
+            return Err(Error::InvalidInput(format!(
+                "reranker returned {} scores for {} candidates",
+                scores.len(),
+                documents.len()
+            )));
+        }
+        for (result, score) in results.iter_mut().zip(scores) {
+            result.rerank_score = Some(score);
+        }
+        let relevance = |result: &SearchResult| result.rerank_score.unwrap_or(f32::NEG_INFINITY);
+        results.sort_by(|a, b| relevance(b).total_cmp(&relevance(a)));
+        Ok(())
+    }
+
+    /// The first results of `results` in maximal marginal relevance order, enough for the
+    /// requested page. Relevance is the rerank score where there is one, else the score.
+    async fn diversify(
+        &self,
+        results: &[SearchResult],
+        options: &SearchOptions,
+        lambda: f32,
+    ) -> Result<Vec<SearchResult>> {
+        let ids: Vec<i64> = results.iter().map(|result| result.id).collect();
+        let mut vectors: HashMap<i64, Vec<f32>> = sqlx::query_as::<_, (i64, Vec<f32>)>(&format!(
+            "SELECT id, {} FROM {} WHERE id = ANY($1) AND deleted_at IS NULL",
+            self.as_array("embedding"),
+            self.table()
+        ))
+        .bind(&ids)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .collect();
+        let candidates: Vec<(f32, Vec<f32>)> = results
+            .iter()
+            .map(|result| {
+                (
+                    result.rerank_score.unwrap_or(result.score),
+                    vectors.remove(&result.id).unwrap_or_default(),
+                )
+            })
+            .collect();
+        let metric = options.metric.unwrap_or(self.metric);
+        Ok(
+            diversity::mmr(metric, lambda, &candidates, options.end() as usize)
+                .into_iter()
+                .map(|index| results[index].clone())
+                .collect(),
+        )
+    }
+
+    /// The `limit` nearest results by vector distance, searching the reduced column and
+    /// refining by full vectors as configured.
+    async fn ranked(
+        &self,
+        query: Embedding<'_>,
+        options: &SearchOptions,
+        limit: u32,
+    ) -> Result<Vec<SearchResult>> {
+        self.check_embedding(query)?;
+        let metric = options.metric.unwrap_or(self.metric);
+        if metric.is_binary() != (self.storage == Storage::Binary) {
+            return Err(Error::InvalidInput(format!(
+                "the {metric} metric doesn't apply to {} storage",
+                self.storage
+            )));
+        }
+        let score = options.score.as_ref();
+        if let Some(score) = score {
+            score.check()?;
+        }
+        // The cutoff, grouping and scoring apply to the nearest results, so the index still
+        // serves the search
+        let unsquared_l2 = self.unsquared_l2(metric);
+        let cutoff = options.max_sql_distance(metric, unsquared_l2);
+        let group = options.group.as_ref();
+        let nearest = if group.is_some() || score.is_some() {
+            options.candidate_count().max(limit)
+        } else {
+            limit
+        };
+        let push_order = |builder: &mut QueryBuilder<'_, Postgres>| match score {
+            Some(score) => {
+                score.push(builder, metric, unsquared_l2);
+                builder.push(" DESC, distance");
+            }
+            None => {
+                builder.push("distance");
+            }
+        };
+        let mut builder = QueryBuilder::new("");
+        if let Some(group) = group {
+            // Records without the key form a group each
+            builder
+                .push(
+                    "SELECT id, distance, payload FROM (
+                         SELECT id, distance, payload,
+                                row_number() OVER (PARTITION BY payload -> ",
+                )
+                .push_bind(group.key.clone())
+                .push(", CASE WHEN payload -> ")
+                .push_bind(group.key.clone())
+                .push(" IS NULL THEN id END ORDER BY ");
+            push_order(&mut builder);
+            builder.push(") AS group_rank FROM (");
+        } else if cutoff.is_some() || score.is_some() {
+            builder.push("SELECT id, distance, payload FROM (");
+        }
+        self.push_candidates(&mut builder, metric, query, options, nearest)?;
+        if group.is_some() || cutoff.is_some() || score.is_some() {
+            builder.push(") nearest");
+            if let Some(cutoff) = cutoff {
+                builder.push(" WHERE distance <= ").push_bind(cutoff);
+            }
+            if let Some(group) = group {
+                builder
+                    .push(") grouped WHERE group_rank <= ")
+                    .push_bind(i64::from(group.per_group));
+            }
+            builder.push(" ORDER BY ");
+            push_order(&mut builder);
+            if group.is_some() || score.is_some() {
+                builder.push(" LIMIT ").push_bind(i64::from(limit));
+            }
+        }
+        Ok(self
+            .fetch_ranked(builder, options)
+            .await?
+            .into_iter()
+            .map(|(id, distance, payload)| self.result(metric, id, distance, payload))
+            .collect())
+    }
+
+    /// Appends the query for the `nearest` results of [`ranked`](Self::ranked), as `id`,
+    /// `distance` and `payload` nearest first, searching the reduced column and refining as
+    /// configured.
+    fn push_candidates<'q>(
+        &self,
+        builder: &mut QueryBuilder<'q, Postgres>,
+        metric: Metric,
+        query: Embedding<'_>,
+        options: &'q SearchOptions,
+        nearest: u32,
+    ) -> Result<()> {
+        match self.transform.as_ref().filter(|_| self.reduced_column) {
+            None => {
+                self.push_nearest(
+                    builder,
+                    "embedding",
+                    metric,
+                    query,
+                    &options.filter,
+                    nearest,
+                );
+            }
+            Some(transform) if !options.refine => {
+                let reduced = transform.apply(&query.dense())?;
+                let reduced = Embedding::Dense(&reduced);
+                self.push_nearest(
+                    builder,
+                    "reduced",
+                    metric,
+                    reduced,
+                    &options.filter,
+                    nearest,
+                );
+            }
+            Some(transform) => {
+                // Both stages run in one statement: the inner query can use the reduced index,
+                // the outer one computes exact distances for just the candidates
+                let reduced = transform.apply(&query.dense())?;
+                let operator = metric.operator(self.backend);
+                builder.push(format!("SELECT id, (embedding {operator} "));
+                self.push_embedding(builder, query);
+                builder.push(format!(
+                    ")::real AS distance, payload FROM (SELECT id, embedding, payload FROM {}",
+                    self.table()
+                ));
+                options.filter.push_where(builder);
+                builder.push(format!(" ORDER BY reduced {operator} "));
+                self.push_vector(builder, reduced);
+                builder
+                    .push(" LIMIT ")
+                    .push_bind(i64::from(options.candidate_count().max(nearest)))
+                    .push(") candidates ORDER BY distance LIMIT ")
+                    .push_bind(i64::from(nearest));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a query of [`ranked`](Self::ranked), in a transaction with the index search
+    /// settings of `options` if it has any.
+    async fn fetch_ranked(
+        &self,
+        mut builder: QueryBuilder<'_, Postgres>,
+        options: &SearchOptions,
+    ) -> Result<Vec<(i64, f32, Option<Value>)>> {
+        let settings = options.index_settings(self.backend);
+        if settings.is_empty() {
+            return Ok(builder.build_query_as().fetch_all(&self.pool).await?);
+        }
+        let mut transaction = self.pool.begin().await?;
+        for (name, value) in settings {
+            sqlx::query("SELECT set_config($1, $2, true)")
+                .bind(name)
+                .bind(value)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        let rows = builder
+            .build_query_as()
+            .fetch_all(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(rows)
+    }
+
+    /// [`search`](Self::search) for many queries in one round trip, returning the results of
+    /// each query in the order of `queries`. Every query is its own nearest-neighbor search
+    /// served by the index, so this is as exact as searching one at a time.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if a query doesn't have the collection's dimensions.
+    pub async fn search_many<V: AsRef<[f32]>>(
+        &self,
+        queries: &[V],
+        limit: u32,
+    ) -> Result<Vec<Vec<SearchResult>>> {
+        self.check_all_dimensions(queries.iter().map(AsRef::as_ref))?;
+        if queries.is_empty() {
+            return Ok(Vec::new());
+        }
+        let started = Instant::now();
+        // All queries travel as one flat array, sliced per query on the server
+        let components: Vec<f32> = queries
+            .iter()
+            .flat_map(|query| query.as_ref().iter().copied())
+            .collect();
+        let dimensions = self.dimensions;
+        let query = self.vector_value(&format!(
+            "($1::real[])[(q.position - 1) * {dimensions} + 1 : q.position * {dimensions}]"
+        ));
+        let rows: Vec<(i32, i64, f32, Option<Value>)> = sqlx::query_as(&format!(
+            "SELECT q.position, n.id, n.distance, n.payload
+             FROM generate_series(1, $2) AS q (position)
+             CROSS JOIN LATERAL (
+                 SELECT id, (embedding {operator} {query})::real AS distance, payload
+                 FROM {table}
+                 WHERE deleted_at IS NULL
+                 ORDER BY embedding {operator} {query} LIMIT $3
+             ) n
+             ORDER BY q.position, n.distance",
+            operator = self.metric.operator(self.backend),
+            table = self.table()
+        ))
+        .bind(components)
+        .bind(i32::try_from(queries.len()).unwrap_or(i32::MAX))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        let mut results = vec![Vec::new(); queries.len()];
+        for (position, id, distance, payload) in rows {
+            let index = usize::try_from(position - 1).unwrap_or_default();
+            results[index].push(self.result(self.metric, id, distance, payload));
+        }
+        self.record(
+            "search",
+            &format!("batch n={} k={limit}", queries.len()),
+            started,
+        )
+        .await;
+        Ok(results)
+    }
+
+    /// Range search: every vector within `max_distance` of `query`, nearest first, up to
+    /// `limit`. `max_distance` is in the metric's raw distance, so squared for L2.
+    ///
+    /// The nearest `limit` vectors are found first, which an index accelerates like any
+    /// search, and then cut at the radius; a full result means more vectors may lie within it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `query` doesn't have the collection's dimensions.
+    pub async fn search_within(
+        &self,
+        query: &[f32],
+        max_distance: f32,
+        limit: u32,
+    ) -> Result<Vec<SearchResult>> {
+        self.check_dimensions(query)?;
+        let started = Instant::now();
+        let rows: Vec<(i64, f32, Option<Value>)> = sqlx::query_as(&format!(
+            "SELECT id, distance, payload FROM (
+                 SELECT id, (embedding {operator} {query})::real AS distance, payload
+                 FROM {table}
+                 WHERE deleted_at IS NULL
+                 ORDER BY embedding {operator} {query} LIMIT $3
+             ) nearest
+             WHERE distance <= $2
+             ORDER BY distance",
+            operator = self.metric.operator(self.backend),
+            query = self.vector_value("$1"),
+            table = self.table()
+        ))
+        .bind(query)
+        .bind(if self.unsquared_l2(self.metric) {
+            max_distance.max(0.0).sqrt()
+        } else {
+            max_distance
+        })
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        self.record("search", &format!("range k={limit}"), started)
+            .await;
+        Ok(rows
+            .into_iter()
+            .map(|(id, distance, payload)| self.result(self.metric, id, distance, payload))
+            .collect())
+    }
+
+    /// Coarse search on the reduced shadow column: `query` is reduced with the stored transform
+    /// and compared with the reduced vectors. Distances and scores are those of the reduced
+    /// space; re-rank the hits with full vectors where precision matters.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the collection has no reduced column or `query` doesn't
+    /// have the collection's dimensions.
+    pub async fn search_reduced(&self, query: &[f32], limit: u32) -> Result<Vec<SearchResult>> {
+        let transform = self
+            .transform
+            .as_ref()
+            .filter(|_| self.reduced_column)
+            .ok_or_else(|| self.no_reduced_column())?;
+        let started = Instant::now();
+        let query = transform.apply(query);
+        let results = self
+            .nearest("reduced", self.metric, query, &Filter::All, limit)
+            .try_collect()
+            .await?;
+        self.record("search", &format!("reduced k={limit}"), started)
+            .await;
+        Ok(results)
+    }
+
+    /// Streams the `limit` rows matching `filter` whose `column` is nearest to `query` by
+    /// `metric`.
+    fn nearest<'a>(
+        &'a self,
+        column: &'static str,
+        metric: Metric,
+        query: Result<Vec<f32>>,
+        filter: &'a Filter,
+        limit: u32,
+    ) -> impl Stream<Item = Result<SearchResult>> + Send + 'a {
+        async_stream::try_stream! {
+            let query = query?;
+            let mut builder = QueryBuilder::new("");
+            self.push_nearest(&mut builder, column, metric, Embedding::Dense(&query), filter, limit);
+            let mut rows = builder
+                .build_query_as::<(i64, f32, Option<Value>)>()
+                .fetch(&self.pool);
+            while let Some((id, distance, payload)) = rows.try_next().await? {
+                yield self.result(metric, id, distance, payload);
+            }
+        }
+    }
+
+    /// Appends a query for the id, `metric` distance and payload of the `limit` rows matching
+    /// `filter` whose `column` is nearest to `query`.
+    fn push_nearest(
+        &self,
+        builder: &mut QueryBuilder<'_, Postgres>,
+        column: &'static str,
+        metric: Metric,
+        query: Embedding<'_>,
+        filter: &Filter,
+        limit: u32,
+    ) {
+        let operator = metric.operator(self.backend);
+        // pgvector's distances are double precision, pgvecto.rs's real
+        builder.push(format!("SELECT id, ({column} {operator} "));
+        self.push_embedding(builder, query);
+        builder.push(format!(
+            ")::real AS distance, payload FROM {}",
+            self.table()
+        ));
+        filter.push_where(builder);
+        // The index only serves an ORDER BY on the operator itself, not on the distance alias
+        builder.push(format!(" ORDER BY {column} {operator} "));
+        self.push_embedding(builder, query);
+        builder.push(" LIMIT ").push_bind(i64::from(limit));
+    }
+
+    /// Appends `embedding` as a value of the collection's storage type, binding its components
+    /// as parameters.
+    fn push_embedding(&self, builder: &mut QueryBuilder<'_, Postgres>, embedding: Embedding<'_>) {
+        match (embedding, self.storage, self.backend) {
+            (Embedding::Sparse(sparse), Storage::Sparse, Backend::PgVectoRs) => {
+                let indices: Vec<i32> = sparse
+                    .indices()
+                    .iter()
+                    .map(|index| i32::try_from(*index).unwrap_or(i32::MAX))
+                    .collect();
+                builder
+                    .push("to_svector(")
+                    .push_bind(i32::try_from(sparse.dimensions()).unwrap_or(i32::MAX))
+                    .push(", ")
+                    .push_bind(indices)
+                    .push("::int[], ")
+                    .push_bind(sparse.values().to_vec())
+                    .push("::real[])");
+            }
+            (Embedding::Sparse(sparse), Storage::Sparse, Backend::PgVector) => {
+                // pgvector reads sparse vectors as text, with 1-based indices
+                let mut text = String::from("{");
+                for (position, (index, value)) in
+                    sparse.indices().iter().zip(sparse.values()).enumerate()
+                {
+                    if position > 0 {
+                        text.push(',');
+                    }
+                    let _ = write!(text, "{}:{value}", index + 1);
+                }
+                let _ = write!(text, "}}/{}", sparse.dimensions());
+                builder.push_bind(text).push("::sparsevec");
+            }
+            (Embedding::Bits(bits), Storage::Binary, Backend::PgVectoRs) => {
+                let elements: Vec<&str> =
+                    bits.iter().map(|bit| if bit { "1" } else { "0" }).collect();
+                builder
+                    .push_bind(format!("[{}]", elements.join(",")))
+                    .push("::bvector");
+            }
+            (Embedding::Bits(bits), Storage::Binary, Backend::PgVector) => {
+                let digits: String = bits.iter().map(|bit| if bit { '1' } else { '0' }).collect();
+                builder
+                    .push_bind(digits)
+                    .push(format!("::bit({})", bits.dimensions()));
+            }
+            (embedding, ..) => self.push_vector(builder, embedding.dense().into_owned()),
+        }
+    }
+
+    /// Streams the records matching `filter` in id order. Rows are decoded as they arrive from
+    /// the server, so exports of any size don't have to fit in memory.
+    pub fn scan_stream<'a>(
+        &'a self,
+        filter: &'a Filter,
+    ) -> impl Stream<Item = Result<Record>> + Send + 'a {
+        async_stream::try_stream! {
+            let mut builder = QueryBuilder::new(format!(
+                "SELECT id, {}, content, source, payload FROM {}",
+                self.as_array("embedding"),
+                self.table()
+            ));
+            filter.push_where(&mut builder);
+            builder.push(" ORDER BY id");
+            let mut rows = builder
+                .build_query_as::<(i64, Vec<f32>, Option<String>, Option<String>, Option<Value>)>()
+                .fetch(&self.pool);
+            while let Some((id, embedding, content, source, payload)) = rows.try_next().await? {
+                yield Record { id, embedding, content, source, payload };
+            }
+        }
+    }
+
+    /// One page of up to `limit` records matching `filter`, in id order, starting after
+    /// `cursor`: `None` for the first page, then the [`next`](Page::next) cursor of the page
+    /// before. Unlike [`scan_stream`](Self::scan_stream), no connection is held between pages,
+    /// so an export can pause or resume in another process. Records inserted while scrolling
+    /// appear on a later page if their id is past the cursor.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `cursor` wasn't returned by `scroll`.
+    pub async fn scroll(&self, filter: &Filter, cursor: Option<&str>, limit: u32) -> Result<Page> {
+        let after = match cursor {
+            None => i64::MIN,
+            Some(cursor) => cursor
+                .parse()
+                .map_err(|_| Error::InvalidInput(format!("invalid scroll cursor '{cursor}'")))?,
+        };
+        let mut builder = QueryBuilder::new(format!(
+            "SELECT id, {}, content, source, payload FROM {} WHERE deleted_at IS NULL AND id > ",
+            self.as_array("embedding"),
+            self.table()
+        ));
+        builder.push_bind(after);
+        if *filter != Filter::All {
+            builder.push(" AND ");
+            filter.push_condition(&mut builder);
+        }
+        // One record more than asked tells whether another page follows
+        builder
+            .push(" ORDER BY id LIMIT ")
+            .push_bind(i64::from(limit) + 1);
+        let mut records: Vec<Record> = builder
+            .build_query_as::<(i64, Vec<f32>, Option<String>, Option<String>, Option<Value>)>()
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(id, embedding, content, source, payload)| Record {
+                id,
+                embedding,
+                content,
+                source,
+                payload,
+            })
+            .collect();
+        let next = if records.len() > limit as usize {
+            records.truncate(limit as usize);
+            records.last().map(|record| record.id.to_string())
+        } else {
+            None
+        };
+        Ok(Page { records, next })
+    }
+
+    pub(crate) fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Schema-qualified table name. Safe to interpolate because names are validated.
+    pub(crate) fn table(&self) -> String {
+        format!("collections.{}", self.name)
+    }
+
+    /// Text search configuration that indexes content in `language`, or in the collection's
+    /// language if the record has none.
+    pub(crate) async fn regconfig(&self, language: Option<&str>) -> Result<String> {
+        Ok(match language {
+            Some(language) => {
+                text_search::regconfig(&self.pool, language, self.text_search.unaccent).await?
+            }
+            None if self.text_search.unaccent => {
+                format!("pgevdb.{}_unaccent", self.text_search.language)
+            }
+            None => self.text_search.language.clone(),
+        })
+    }
+
+    pub(crate) fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// `value`, a `real[]` expression such as a parameter, as the type vectors are stored as.
+    pub(crate) fn vector_value(&self, value: &str) -> String {
+        let (before, after) = self.storage.narrow(self.backend);
+        format!("{before}{value}{after}")
+    }
+
+    /// Appends `vector` as a [`vector_value`](Self::vector_value), binding it as a parameter.
+    fn push_vector(&self, builder: &mut QueryBuilder<'_, Postgres>, vector: Vec<f32>) {
+        let (before, after) = self.storage.narrow(self.backend);
+        builder.push(before).push_bind(vector).push(after);
+    }
+
+    /// `column` read back as `real[]`, whatever type its vectors are stored as.
+    pub(crate) fn as_array(&self, column: &str) -> String {
+        format!("{}::real[]", self.storage.widen(self.backend, column))
+    }
+
+    /// Whether the table has a `reduced` column maintained with the transform.
+    pub(crate) fn has_reduced_column(&self) -> bool {
+        self.reduced_column
+    }
+
+    /// The reduced copy of `embedding` to store, if the collection keeps a reduced column.
+    pub(crate) fn reduce(&self, embedding: &[f32]) -> Result<Option<Vec<f32>>> {
+        self.transform
+            .as_ref()
+            .filter(|_| self.reduced_column)
+            .map(|transform| transform.apply(embedding))
+            .transpose()
+    }
+
+    /// Runs `CREATE INDEX` on a connection of its own, so that `cancel` can abort it with
+    /// `pg_cancel_backend`. The build isn't reported as cancelled until the server has stopped
+    /// it.
+    async fn build_index(
+        &self,
+        column: &str,
+        index_name: &str,
+        index: &IndexConfig,
+        concurrently: bool,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let started = Instant::now();
+        let (concurrently, shape_suffix) = if concurrently {
+            (" CONCURRENTLY", " concurrently")
+        } else {
+            ("", "")
+        };
+        let using = index.using(
+            self.backend,
+            column,
+            &self.metric.operator_class(self.backend, self.storage),
+        )?;
+        let statement = format!(
+            "CREATE INDEX{concurrently} {index_name} ON {table} {using}",
+            table = self.table()
+        );
+        let mut connection = self.pool.acquire().await?;
+        let (pid,): (i32,) = sqlx::query_as("SELECT pg_backend_pid()")
+            .fetch_one(&mut *connection)
+            .await?;
+        let cancelled = {
+            let mut build = pin!(sqlx::query(&statement).execute(&mut *connection));
+            tokio::select! {
+                result = &mut build => {
+                    result?;
+                    false
+                }
+                () = cancel.cancelled() => {
+                    sqlx::query("SELECT pg_cancel_backend($1)")
+                        .bind(pid)
+                        .execute(&self.pool)
+                        .await?;
+                    // Fails with query_canceled, unless the build finished first
+                    let _ = build.await;
+                    true
+                }
+            }
+        };
+        drop(connection);
+        if cancelled {
+            // Whatever the build left behind: an invalid index from a concurrent build, or a
+            // finished one that beat the cancellation
+            sqlx::query(&format!("DROP INDEX IF EXISTS collections.{index_name}"))
+                .execute(&self.pool)
+                .await?;
+            return Err(Error::Cancelled);
+        }
+        self.record(
+            "index_build",
+            &format!("{column} {index}{shape_suffix}"),
+            started,
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Whether `metric`'s operator returns plain rather than squared L2 distances, as
+    /// pgvector's `<->` does.
+    fn unsquared_l2(&self, metric: Metric) -> bool {
+        metric == Metric::L2 && self.backend == Backend::PgVector
+    }
+
+    /// The search result for a raw `metric` operator `distance`, squared where the operator
+    /// doesn't.
+    fn result(
+        &self,
+        metric: Metric,
+        id: i64,
+        distance: f32,
+        payload: Option<Value>,
+    ) -> SearchResult {
+        let distance = if self.unsquared_l2(metric) {
+            distance * distance
+        } else {
+            distance
+        };
+        SearchResult {
+            id,
+            distance,
+            score: metric.score(distance),
+            rerank_score: None,
+            payload,
+        }
+    }
+
+    /// Counts a call of `operation` in the [usage statistics](stats).
+    pub(crate) async fn record(&self, operation: &str, shape: &str, started: Instant) {
+        stats::record(&self.pool, &self.name, operation, shape, started).await;
+    }
+
+    fn no_record(&self, id: i64) -> Error {
+        Error::NotFound(format!("collection '{}' has no record {id}", self.name))
+    }
+
+    fn no_reduced_column(&self) -> Error {
+        Error::InvalidInput(format!(
+            "collection '{}' has no reduced column; store a transform with one first",
+            self.name
+        ))
+    }
+
+    fn index_name(&self) -> String {
+        format!("{}_embedding_idx", self.name)
+    }
+
+    fn check_embedding(&self, embedding: Embedding<'_>) -> Result<()> {
+        match embedding {
+            Embedding::Dense(vector) => self.check_dimensions(vector),
+            Embedding::Sparse(sparse) if sparse.dimensions() == self.dimensions => Ok(()),
+            Embedding::Sparse(sparse) => Err(Error::InvalidInput(format!(
+                "collection '{}' has {} dimensions, got a sparse vector with {}",
+                self.name,
+                self.dimensions,
+                sparse.dimensions()
+            ))),
+            Embedding::Bits(bits) if bits.dimensions() == self.dimensions => Ok(()),
+            Embedding::Bits(bits) => Err(Error::InvalidInput(format!(
+                "collection '{}' has {} dimensions, got a bit vector with {}",
+                self.name,
+                self.dimensions,
+                bits.dimensions()
+            ))),
+        }
+    }
+
+    pub(crate) fn check_dimensions(&self, vector: &[f32]) -> Result<()> {
+        if vector.len() == self.dimensions as usize {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(format!(
+                "collection '{}' has {} dimensions, got a vector with {}",
+                self.name,
+                self.dimensions,
+                vector.len()
+            )))
+        }
+    }
+
+    /// [`check_dimensions`](Self::check_dimensions) for a batch, naming the offending position.
+    pub(crate) fn check_all_dimensions<'v>(
+        &self,
+        vectors: impl IntoIterator<Item = &'v [f32]>,
+    ) -> Result<()> {
+        for (position, vector) in vectors.into_iter().enumerate() {
+            self.check_dimensions(vector).map_err(|error| match error {
+                Error::InvalidInput(reason) => {
+                    Error::InvalidInput(format!("vector {position}: {reason}"))
+                }
+                error => error,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+fn to_payload<T: Serialize + ?Sized>(payload: &T) -> Result<Value> {
+    serde_json::to_value(payload).map_err(|error| {
+        Error::InvalidInput(format!("payload isn't representable as JSON: {error}"))
+    })
+}
+
+fn from_payload<T: DeserializeOwned>(payload: Value) -> Result<T> {
+    serde_json::from_value(payload).map_err(|error| {
+        Error::InvalidInput(format!("payload doesn't have the expected shape: {error}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn scores_distances_per_metric() {
+        for (metric, distance, score) in [
+            (Metric::L2, 0.0, 1.0),
+            (Metric::L2, 1.0, 0.5),
+            (Metric::L2, 3.0, 0.25),
+            (Metric::Hamming, 4.0, 0.2),
+            (Metric::Cosine, 0.0, 1.0),
+            (Metric::Cosine, 1.0, 0.5),
+            (Metric::Cosine, 2.0, 0.0),
+            (Metric::Dot, 0.0, 0.5),
+            (Metric::Jaccard, 0.25, 0.75),
+        ] {
+            assert!(
+                close(metric.score(distance), score),
+                "{metric} at {distance}: {}",
+                metric.score(distance)
+            );
+        }
+        // A larger inner product, so a more negative distance, scores higher
+        assert!(Metric::Dot.score(-4.0) > Metric::Dot.score(4.0));
+    }
+
+    #[test]
+    fn clamps_scores_to_the_unit_interval() {
+        for metric in Metric::ALL {
+            for distance in [-1e6, -1.0, 0.0, 1.0, 1e6, f32::INFINITY] {
+                let score = metric.score(distance);
+                assert!(
+                    (0.0..=1.0).contains(&score),
+                    "{metric} at {distance}: {score}"
+                );
+            }
+        }
+        // Rounding can make an L2 distance slightly negative
+        assert!(close(Metric::L2.score(-1e-7), 1.0));
+    }
+
+    #[test]
+    fn distance_at_score_inverts_score() {
+        for metric in Metric::ALL {
+            for score in [0.1, 0.25, 0.5, 0.9, 1.0] {
+                let distance = metric.distance_at_score(score);
+                assert!(
+                    close(metric.score(distance), score),
+                    "{metric} at {score}: {distance}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn distance_at_score_bounds_what_passes_a_threshold() {
+        assert!(Metric::L2.distance_at_score(0.0).is_infinite());
+        assert!(Metric::Cosine.distance_at_score(-1.0).is_infinite());
+        // Scores above 1 are unreachable, and act like 1
+        assert!(close(Metric::L2.distance_at_score(2.0), 0.0));
+        assert!(close(Metric::Cosine.distance_at_score(0.5), 1.0));
+        assert!(close(Metric::Dot.distance_at_score(0.5), 0.0));
+    }
+}
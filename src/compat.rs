@@ -0,0 +1,94 @@
+//! Checks that the data directory and database match what this binary expects, so version skew
+//! fails at startup with instructions rather than with obscure errors later.
+
+use semver::Version;
+use sqlx::postgres::PgPool;
+
+use crate::catalog;
+use crate::config::{Backend, Config};
+use crate::error::{Error, Result};
+
+/// Compares the major version that initialized the data directory with the configured one.
+pub fn check_data_dir(config: &Config) -> Result<()> {
+    let version_file = config.data_dir().join("PG_VERSION");
+    if !version_file.exists() {
+        return Ok(());
+    }
+
+    let initialized_major = std::fs::read_to_string(version_file)?;
+    let initialized_major = initialized_major.trim();
+    if initialized_major != config.pg_major() {
+        return Err(Error::Incompatible(format!(
+            "{} was initialized by PostgreSQL {initialized_major}, but pg_version is {}; set \
+             pg_version to a {initialized_major}.x release, since data can't be upgraded across \
+             major versions in place",
+            config.data_dir().display(),
+            config.pg_version
+        )));
+    }
+    Ok(())
+}
+
+/// Compares the extension version and catalog version recorded in the database with the
+/// configured and built-in ones, creating the catalog in a new database.
+pub async fn check_database(pool: &PgPool, config: &Config) -> Result<()> {
+    let extension_version = extension_version(pool, config.backend).await?;
+    if let Some(version) = extension_version.filter(|version| *version != config.extension_version)
+    {
+        check_extension_upgrade(config, &version)?;
+        return Err(Error::Incompatible(format!(
+            "the database uses {} {version}, but extension_version is {}; run `pgevdb upgrade`",
+            config.backend.name(),
+            config.extension_version
+        )));
+    }
+
+    match catalog::schema_version(pool).await? {
+        None => catalog::migrate(pool, 0).await,
+        Some(version) if version == catalog::SCHEMA_VERSION => Ok(()),
+        Some(version) if version < catalog::SCHEMA_VERSION => Err(Error::Incompatible(format!(
+            "the pgevdb catalog is at version {version}, but this binary expects {}; run \
+             `pgevdb upgrade`",
+            catalog::SCHEMA_VERSION
+        ))),
+        Some(version) => Err(newer_catalog(version)),
+    }
+}
+
+/// Refuses to take the extension from version `from` to the configured version if that would be a
+/// downgrade, which extensions ship no scripts for.
+pub fn check_extension_upgrade(config: &Config, from: &str) -> Result<()> {
+    let (Ok(from_version), Ok(to_version)) = (
+        Version::parse(from),
+        Version::parse(&config.extension_version),
+    ) else {
+        return Ok(());
+    };
+    if from_version > to_version {
+        return Err(Error::Incompatible(format!(
+            "{} {from} is installed, which is newer than extension_version {}; extensions can't be \
+             downgraded, so set extension_version to {from} or later",
+            config.backend.name(),
+            config.extension_version
+        )));
+    }
+    Ok(())
+}
+
+pub fn newer_catalog(version: u32) -> Error {
+    Error::Incompatible(format!(
+        "the database was last used by a newer pgevdb (catalog version {version}, this binary \
+         supports up to {}); update with `pgevdb self-update`",
+        catalog::SCHEMA_VERSION
+    ))
+}
+
+/// Version of the backend's extension created in the database, if it has been created.
+pub async fn extension_version(pool: &PgPool, backend: Backend) -> Result<Option<String>> {
+    let version: Option<(String,)> =
+        sqlx::query_as("SELECT extversion FROM pg_extension WHERE extname = $1")
+            .bind(backend.extension_name())
+            .fetch_optional(pool)
+            .await?;
+    Ok(version.map(|(version,)| version))
+}
@@ -0,0 +1,938 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use directories::ProjectDirs;
+use postgresql_embedded::Settings;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::extension;
+use crate::platform::Platform;
+
+pub const DEFAULT_PG_VERSION: &str = "16.3.0";
+pub const DEFAULT_DATABASE_NAME: &str = "test";
+pub const DEFAULT_EXTENSION_VERSION: &str = "0.3.0";
+/// `extension_version` that installs the newest pgvecto.rs release with a build for the host and
+/// PostgreSQL major version.
+pub const LATEST_EXTENSION_VERSION: &str = "latest";
+/// Default `extension_version` of the pgvector backend.
+pub const DEFAULT_PGVECTOR_VERSION: &str = "0.7.4";
+/// Where extension archives are downloaded from, unless `extension_url` names a mirror.
+pub const DEFAULT_EXTENSION_URL: &str = "https://github.com/tensorchord/pgvecto.rs/releases/download/v{version}/vectors-pg{pg_major}_{triple}_{version}.zip";
+pub const CONFIG_FILE_NAME: &str = "pgevdb.toml";
+/// `NAMEDATALEN - 1`: PostgreSQL truncates longer identifiers.
+const MAX_IDENTIFIER_LENGTH: usize = 63;
+/// Shortest [`ApiKey`] accepted, e.g. 16 random bytes in hex.
+pub const MIN_API_KEY_LENGTH: usize = 32;
+/// Where releases before the configurable `staging_dir` extracted the extension archive. Still
+/// removed by `pgevdb clean` from the storage directory, if it holds the extension's control
+/// file.
+pub const LEGACY_STAGING_DIR: &str = "vectors";
+
+/// Environment variables that override settings from the config file or builder, and the
+/// settings they override (`timeouts.` ones are in the `[timeouts]` table).
+pub const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("PGEVDB_DATA_DIR", "storage_dir"),
+    ("PGEVDB_STORAGE_DIR", "storage_dir"),
+    ("PGEVDB_CACHE_DIR", "cache_dir"),
+    ("PGEVDB_PG_VERSION", "pg_version"),
+    ("PGEVDB_DATABASE_NAME", "database_name"),
+    ("PGEVDB_PORT", "port"),
+    ("PGEVDB_BACKEND", "backend"),
+    ("PGEVDB_EXTENSION_VERSION", "extension_version"),
+    ("PGEVDB_EXTENSION_SHA256", "extension_sha256"),
+    ("PGEVDB_EXTENSION_ARCHIVE", "extension_archive"),
+    ("PGEVDB_EXTENSION_URL", "extension_url"),
+    ("PGEVDB_BUILD_FROM_SOURCE", "build_from_source"),
+    ("PGEVDB_DURABILITY", "durability"),
+    ("PGEVDB_STAGING_DIR", "staging_dir"),
+    ("PGEVDB_SOCKET_DIR", "socket_dir"),
+    ("PGEVDB_RUN_AS_USER", "run_as_user"),
+    ("PGEVDB_PROXY", "proxy"),
+    ("PGEVDB_GITHUB_TOKEN", "github_token"),
+    ("PGEVDB_TIMEOUTS_DOWNLOAD_SECS", "timeouts.download_secs"),
+    (
+        "PGEVDB_TIMEOUTS_DOWNLOAD_RETRIES",
+        "timeouts.download_retries",
+    ),
+    ("PGEVDB_TIMEOUTS_STARTUP_SECS", "timeouts.startup_secs"),
+    ("PGEVDB_TIMEOUTS_STATEMENT_SECS", "timeouts.statement_secs"),
+];
+
+/// Vector search extension providing the `vector` type and distance operators.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    #[default]
+    #[serde(rename = "pgvecto.rs")]
+    PgVectoRs,
+    /// pgvector, which publishes no prebuilt builds: it's installed from `extension_archive`,
+    /// an `extension_url` mirror or a source build.
+    #[serde(rename = "pgvector")]
+    PgVector,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::PgVectoRs, Backend::PgVector];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::PgVectoRs => "pgvecto.rs",
+            Backend::PgVector => "pgvector",
+        }
+    }
+
+    /// Name of the extension's shared library, control file and SQL script prefix, and of the
+    /// extension in `CREATE EXTENSION`.
+    #[must_use]
+    pub fn extension_name(self) -> &'static str {
+        match self {
+            Backend::PgVectoRs => "vectors",
+            Backend::PgVector => "vector",
+        }
+    }
+
+    /// `extension_version` installed unless another is configured.
+    #[must_use]
+    pub fn default_extension_version(self) -> &'static str {
+        match self {
+            Backend::PgVectoRs => DEFAULT_EXTENSION_VERSION,
+            Backend::PgVector => DEFAULT_PGVECTOR_VERSION,
+        }
+    }
+
+    /// PostgreSQL major versions the extension publishes builds for, or for pgvector, builds
+    /// from source against.
+    #[must_use]
+    pub fn supported_pg_majors(self) -> &'static [u64] {
+        match self {
+            Backend::PgVectoRs => &[14, 15, 16],
+            Backend::PgVector => &[13, 14, 15, 16, 17],
+        }
+    }
+}
+
+/// Trade-off between crash safety and write throughput.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    /// PostgreSQL defaults: every commit is flushed to disk.
+    #[default]
+    Safe,
+    /// Commits return before the WAL is flushed; a crash may lose the last few transactions
+    /// but can't corrupt the cluster.
+    Balanced,
+    /// No fsync at all; a crash can corrupt the cluster. Only for disposable data.
+    Fast,
+}
+
+impl Durability {
+    pub const ALL: [Durability; 3] = [Durability::Safe, Durability::Balanced, Durability::Fast];
+
+    #[must_use]
+    pub fn description(self) -> &'static str {
+        match self {
+            Durability::Safe => "safe (fsync every commit)",
+            Durability::Balanced => "balanced (asynchronous commit)",
+            Durability::Fast => "fast (no fsync, disposable data only)",
+        }
+    }
+
+    /// Server settings applied at startup for this profile.
+    fn server_configuration(self) -> HashMap<String, String> {
+        let settings: &[(&str, &str)] = match self {
+            Durability::Safe => &[],
+            Durability::Balanced => &[("synchronous_commit", "off")],
+            Durability::Fast => &[
+                ("fsync", "off"),
+                ("synchronous_commit", "off"),
+                ("full_page_writes", "off"),
+            ],
+        };
+        settings
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect()
+    }
+}
+
+/// Limits that keep a stalled network or a hung server from blocking the caller indefinitely.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    /// Seconds a download may go without receiving data before it fails.
+    pub download_secs: u64,
+    /// Times a failed download is retried, with exponential backoff, resuming where it stopped
+    /// if the server supports range requests.
+    pub download_retries: u32,
+    /// Seconds to wait for the server to accept connections after starting it.
+    pub startup_secs: u64,
+    /// `statement_timeout` in seconds for every query on the returned pool; `0` disables it.
+    pub statement_secs: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            download_secs: 60,
+            download_retries: 4,
+            startup_secs: 30,
+            statement_secs: 300,
+        }
+    }
+}
+
+impl Timeouts {
+    #[must_use]
+    pub fn download(&self) -> Duration {
+        Duration::from_secs(self.download_secs)
+    }
+
+    #[must_use]
+    pub fn startup(&self) -> Duration {
+        Duration::from_secs(self.startup_secs)
+    }
+
+    /// `None` when statements may run indefinitely.
+    #[must_use]
+    pub fn statement(&self) -> Option<Duration> {
+        (self.statement_secs > 0).then(|| Duration::from_secs(self.statement_secs))
+    }
+}
+
+/// An extension declared in the config file, set up by the same pipeline as the vector extension:
+///
+/// ```toml
+/// [[extensions]]
+/// name = "pg_trgm"
+///
+/// [[extensions]]
+/// name = "pg_cron"
+/// version = "1.6.4"
+/// url = "https://mirror.example.com/pg_cron-pg{pg_major}_{triple}_{version}.zip"
+/// settings = { shared_preload_libraries = "pg_cron", "cron.database_name" = "test" }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtensionConfig {
+    /// Name in `CREATE EXTENSION`.
+    pub name: String,
+    /// Filled in for `{version}` in `url`.
+    pub version: String,
+    /// URL template of the extension's zip archive, with the placeholders of `extension_url`;
+    /// without one, the extension must ship with PostgreSQL, as the contrib extensions do.
+    pub url: Option<String>,
+    /// Expected SHA-256 of the archive, in hex.
+    pub sha256: Option<String>,
+    /// Server settings the extension needs; list settings such as `shared_preload_libraries`
+    /// are added to rather than replaced.
+    pub settings: BTreeMap<String, String>,
+}
+
+/// What an [`ApiKey`] lets its clients do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Listing and describing collections, reading records and searching.
+    #[default]
+    ReadOnly,
+    /// Everything, including creating and deleting collections and writing records.
+    ReadWrite,
+}
+
+/// A key that clients of `pgevdb serve` send as `Authorization: Bearer KEY` or `X-API-Key: KEY`:
+///
+/// ```toml
+/// [[api.keys]]
+/// name = "ingest"
+/// key = "3f9c1d0e7b2a4c58a6e1f0d2b7c9e4a1"
+/// scope = "read_write"
+///
+/// [[api.keys]]
+/// name = "search-frontend"
+/// key = "b81e6f2d94c04a7fa3d5e0c1f7a29b6e"
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKey {
+    /// Who the key was given to, for logs and error messages.
+    pub name: String,
+    /// The secret itself, at least [`MIN_API_KEY_LENGTH`] printable ASCII characters.
+    pub key: String,
+    /// Defaults to [`Scope::ReadOnly`].
+    pub scope: Scope,
+}
+
+/// Settings of the HTTP and gRPC APIs of `pgevdb serve`, the `[api]` table:
+///
+/// ```toml
+/// [api]
+/// rate_limit_per_sec = 50
+/// rate_limit_burst = 200
+/// max_insert_bytes = 16777216
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Keys accepted from clients. Without any, the APIs are open to every client that can
+    /// connect.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<ApiKey>,
+    /// Requests per second each client may make on average, counted separately by each API;
+    /// `0` doesn't limit them. Clients are told apart by their key, or without one by their
+    /// IP address.
+    pub rate_limit_per_sec: u32,
+    /// Requests a client may make at once after idling, above the average rate; defaults to
+    /// one second's worth.
+    pub rate_limit_burst: Option<u32>,
+    /// Largest request body of a bulk insert, in bytes. Bodies of other requests are limited
+    /// to 1 MiB.
+    pub max_insert_bytes: usize,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            rate_limit_per_sec: 0,
+            rate_limit_burst: None,
+            max_insert_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Effective configuration for an embedded instance.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Root directory holding the PostgreSQL installation, cluster data and password file.
+    pub storage_dir: PathBuf,
+    /// Where downloads and source builds are cached; defaults to the platform cache directory.
+    pub cache_dir: Option<PathBuf>,
+    pub pg_version: String,
+    pub database_name: String,
+    /// Port to listen on; `0` picks a free port on every start.
+    pub port: u16,
+    pub backend: Backend,
+    /// Version of the backend's extension; defaults to the backend's
+    /// [default](Backend::default_extension_version). [`LATEST_EXTENSION_VERSION`] installs the
+    /// newest compatible pgvecto.rs release and keeps it until `pgevdb upgrade`.
+    pub extension_version: String,
+    /// Expected SHA-256 of the extension archive, in hex. When set, an archive with another
+    /// hash is refused instead of installed.
+    pub extension_sha256: Option<String>,
+    /// Local extension archive installed instead of downloading one, for machines without
+    /// internet access. Must be the release build for this platform and PostgreSQL major version.
+    pub extension_archive: Option<PathBuf>,
+    /// URL template of extension archives, for an internal mirror of the GitHub releases;
+    /// `{version}`, `{pg_major}` and `{triple}` are filled in. Defaults to
+    /// [`DEFAULT_EXTENSION_URL`] for pgvecto.rs; pgvector has no default.
+    pub extension_url: Option<String>,
+    /// Compile the extension locally when no prebuilt archive matches the platform.
+    pub build_from_source: bool,
+    pub durability: Durability,
+    /// Parent of the temporary directory the extension archive is extracted into; defaults to
+    /// the system temp directory.
+    pub staging_dir: Option<PathBuf>,
+    /// Directory for PostgreSQL's Unix-domain socket; defaults to the server's built-in location.
+    pub socket_dir: Option<PathBuf>,
+    /// Resolve relative paths against the directory of the binary instead of the current
+    /// directory, so the whole instance can be moved or shipped as one folder.
+    pub portable: bool,
+    /// Unprivileged account to switch to when started as root, since PostgreSQL refuses to run
+    /// as root. Unix only.
+    pub run_as_user: Option<String>,
+    /// Proxy URL for downloads, e.g. `http://proxy.example.com:3128`; defaults to the
+    /// `HTTPS_PROXY` and `HTTP_PROXY` environment variables.
+    pub proxy: Option<String>,
+    /// GitHub token sent with GitHub API requests, which raises the rate limit that CI runners
+    /// sharing an address run into.
+    pub github_token: Option<String>,
+    pub timeouts: Timeouts,
+    /// Further extensions set up after the vector extension, as `[[extensions]]` tables.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<ExtensionConfig>,
+    pub api: ApiConfig,
+    /// Directory that relative paths are resolved against in portable mode.
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            storage_dir: default_storage_dir(),
+            cache_dir: None,
+            pg_version: DEFAULT_PG_VERSION.to_string(),
+            database_name: DEFAULT_DATABASE_NAME.to_string(),
+            port: 0,
+            backend: Backend::default(),
+            extension_version: DEFAULT_EXTENSION_VERSION.to_string(),
+            extension_sha256: None,
+            extension_archive: None,
+            extension_url: None,
+            build_from_source: false,
+            durability: Durability::default(),
+            staging_dir: None,
+            socket_dir: None,
+            portable: false,
+            run_as_user: None,
+            proxy: None,
+            github_token: None,
+            timeouts: Timeouts::default(),
+            extensions: Vec::new(),
+            api: ApiConfig::default(),
+            root: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file at `path`, falling back to defaults when it doesn't exist, and
+    /// applies the [environment overrides](ENV_OVERRIDES).
+    ///
+    /// Relative paths are resolved against the current directory, or against `root` in portable
+    /// mode, where the storage and cache directories also default to `data` and `data/cache`
+    /// under the root.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if the file or an override isn't valid or has invalid values,
+    /// and [`Error::Io`] if the file can't be read.
+    pub fn load(path: &Path, root: Option<&Path>) -> Result<Self> {
+        let mut table = if path.exists() {
+            read_table(path)?
+        } else {
+            toml::Table::new()
+        };
+        apply_env_overrides(&mut table)?;
+        let mut config: Config = table
+            .clone()
+            .try_into()
+            .map_err(|error| Error::Config(format!("{}: {error}", path.display())))?;
+        if !table.contains_key("extension_version") {
+            config.extension_version = config.backend.default_extension_version().to_string();
+        }
+        config
+            .validate()
+            .map_err(|error| Error::Config(format!("{}: {error}", path.display())))?;
+
+        let base_dir = if let Some(root) = root {
+            config.portable = true;
+            config.root = Some(root.to_path_buf());
+            if !table.contains_key("storage_dir") {
+                config.storage_dir = PathBuf::from("data");
+            }
+            if !table.contains_key("cache_dir") {
+                config.cache_dir = Some(Path::new("data").join("cache"));
+            }
+            root.to_path_buf()
+        } else {
+            std::env::current_dir()?
+        };
+
+        config.storage_dir = base_dir.join(&config.storage_dir);
+        for dir in [
+            &mut config.cache_dir,
+            &mut config.staging_dir,
+            &mut config.socket_dir,
+            &mut config.extension_archive,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *dir = base_dir.join(&dir);
+        }
+        Ok(config)
+    }
+
+    /// Applies the [environment overrides](ENV_OVERRIDES) to a config assembled in code.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if an override has an invalid value.
+    pub fn with_env_overrides(self) -> Result<Self> {
+        let root = self.root.clone();
+        let mut table =
+            toml::Table::try_from(&self).map_err(|error| Error::Config(error.to_string()))?;
+        apply_env_overrides(&mut table)?;
+        let mut config: Config = table
+            .try_into()
+            .map_err(|error| Error::Config(format!("environment override: {error}")))?;
+        config.root = root;
+        Ok(config)
+    }
+
+    /// Writes the config to `path` as TOML. In portable mode, paths under the root are written
+    /// relative to it so the folder can be moved.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file or its parent directory can't be written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut config = self.clone();
+        if let Some(root) = &self.root {
+            let relative = |dir: &mut PathBuf| {
+                if let Ok(stripped) = dir.strip_prefix(root) {
+                    *dir = stripped.to_path_buf();
+                }
+            };
+            relative(&mut config.storage_dir);
+            for dir in [
+                &mut config.cache_dir,
+                &mut config.staging_dir,
+                &mut config.socket_dir,
+                &mut config.extension_archive,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                relative(dir);
+            }
+        }
+        let contents =
+            toml::to_string_pretty(&config).map_err(|error| Error::Config(error.to_string()))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Checks the settings individually and against each other, so a bad combination fails
+    /// before anything is downloaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] describing the first invalid setting.
+    pub fn validate(&self) -> Result<()> {
+        let pg_version = Version::parse(&self.pg_version).map_err(|error| {
+            Error::Config(format!(
+                "pg_version '{}' isn't an exact version such as {DEFAULT_PG_VERSION}: {error}",
+                self.pg_version
+            ))
+        })?;
+        let supported = self.backend.supported_pg_majors();
+        if !supported.contains(&pg_version.major) {
+            return Err(Error::Config(format!(
+                "{} doesn't support PostgreSQL {}; supported major versions: {}",
+                self.backend.name(),
+                pg_version.major,
+                supported
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+        if self.extension_version == LATEST_EXTENSION_VERSION {
+            if self.backend != Backend::PgVectoRs {
+                return Err(Error::Config(format!(
+                    "extension_version '{LATEST_EXTENSION_VERSION}' looks up pgvecto.rs releases; \
+                     pin a {} version instead",
+                    self.backend.name()
+                )));
+            }
+        } else {
+            Version::parse(&self.extension_version).map_err(|error| {
+                Error::Config(format!(
+                    "extension_version '{}': {error}",
+                    self.extension_version
+                ))
+            })?;
+        }
+        if let Some(sha256) = &self.extension_sha256 {
+            validate_sha256("extension_sha256", sha256)?;
+        }
+        if let Some(file_name) = self
+            .extension_archive
+            .as_deref()
+            .and_then(Path::file_name)
+            .and_then(|file_name| file_name.to_str())
+        {
+            extension::check_pg_major(file_name, self)?;
+        }
+        self.validate_extensions()?;
+        self.validate_api_keys()?;
+        if self.api.rate_limit_burst == Some(0) {
+            return Err(Error::Config(
+                "api.rate_limit_burst must be at least 1".to_string(),
+            ));
+        }
+        if self.api.max_insert_bytes == 0 {
+            return Err(Error::Config(
+                "api.max_insert_bytes must be at least 1".to_string(),
+            ));
+        }
+
+        if self.database_name.is_empty()
+            || self.database_name.len() > MAX_IDENTIFIER_LENGTH
+            || self.database_name.contains('\0')
+        {
+            return Err(Error::Config(format!(
+                "database_name '{}' must be 1 to {MAX_IDENTIFIER_LENGTH} bytes without NUL \
+                 characters",
+                self.database_name
+            )));
+        }
+        if self.timeouts.download_secs == 0 || self.timeouts.startup_secs == 0 {
+            return Err(Error::Config(
+                "timeouts.download_secs and timeouts.startup_secs must be at least 1".to_string(),
+            ));
+        }
+        if self.storage_dir.as_os_str().is_empty() {
+            return Err(Error::Config("storage_dir can't be empty".to_string()));
+        }
+        if let Some(template) = &self.extension_url {
+            let sample = extension::expand_url(
+                template,
+                &self.extension_version,
+                self.pg_major(),
+                "x86_64-unknown-linux-gnu",
+            );
+            reqwest::Url::parse(&sample)
+                .map_err(|error| Error::Config(format!("extension_url '{template}': {error}")))?;
+        }
+        if let Some(proxy) = &self.proxy {
+            reqwest::Proxy::all(proxy)
+                .map_err(|_| Error::Config(format!("proxy '{proxy}' isn't a valid URL")))?;
+        }
+        if cfg!(not(unix)) && self.run_as_user.is_some() {
+            // Windows has no setuid; pg_ctl drops administrator rights by itself there
+            return Err(Error::Config(
+                "run_as_user is only supported on Unix".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the `[[extensions]]` tables: unique names other than the vector extension's, valid
+    /// URLs and hashes, and no two extensions wanting different values of the same setting.
+    fn validate_extensions(&self) -> Result<()> {
+        let mut names = vec![self.backend.extension_name()];
+        let mut settings: HashMap<&str, (&str, &str)> = HashMap::new();
+        for extension in &self.extensions {
+            let name = extension.name.as_str();
+            if name.is_empty() || name.len() > MAX_IDENTIFIER_LENGTH || name.contains('"') {
+                return Err(Error::Config(format!(
+                    "extension name '{name}' must be 1 to {MAX_IDENTIFIER_LENGTH} bytes without \
+                     double quotes"
+                )));
+            }
+            if names.contains(&name) {
+                return Err(Error::Config(format!(
+                    "extension '{name}' is set up more than once"
+                )));
+            }
+            names.push(name);
+            if let Some(template) = &extension.url {
+                let sample = extension::expand_url(
+                    template,
+                    &extension.version,
+                    self.pg_major(),
+                    "x86_64-unknown-linux-gnu",
+                );
+                reqwest::Url::parse(&sample).map_err(|error| {
+                    Error::Config(format!("url of extension '{name}' '{template}': {error}"))
+                })?;
+            }
+            if let Some(sha256) = &extension.sha256 {
+                validate_sha256(&format!("sha256 of extension '{name}'"), sha256)?;
+            }
+            for (setting, value) in &extension.settings {
+                if extension::LIST_SETTINGS.contains(&setting.as_str()) {
+                    continue;
+                }
+                match settings.insert(setting, (name, value)) {
+                    Some((other, other_value)) if other_value != value => {
+                        return Err(Error::Config(format!(
+                            "extensions '{other}' and '{name}' set {setting} to different values"
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the `[[api.keys]]` tables: unique names and keys, long enough to resist guessing
+    /// and sendable in a header.
+    fn validate_api_keys(&self) -> Result<()> {
+        for (index, api_key) in self.api.keys.iter().enumerate() {
+            let name = &api_key.name;
+            if name.is_empty() {
+                return Err(Error::Config(format!(
+                    "api.keys entry {} has no name",
+                    index + 1
+                )));
+            }
+            if api_key.key.len() < MIN_API_KEY_LENGTH
+                || !api_key.key.bytes().all(|byte| byte.is_ascii_graphic())
+            {
+                return Err(Error::Config(format!(
+                    "API key '{name}' must be at least {MIN_API_KEY_LENGTH} printable ASCII \
+                     characters without spaces"
+                )));
+            }
+            for other in &self.api.keys[..index] {
+                if other.name == *name {
+                    return Err(Error::Config(format!(
+                        "API key name '{name}' is used more than once"
+                    )));
+                }
+                if other.key == api_key.key {
+                    return Err(Error::Config(format!(
+                        "API keys '{}' and '{name}' are the same key",
+                        other.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Major version of `pg_version`, e.g. `16` for `16.3.0`.
+    #[must_use]
+    pub fn pg_major(&self) -> &str {
+        self.pg_version.split('.').next().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn installation_dir(&self) -> PathBuf {
+        self.storage_dir.join("pg")
+    }
+
+    #[must_use]
+    pub fn data_dir(&self) -> PathBuf {
+        self.storage_dir.join("pg_data")
+    }
+
+    #[must_use]
+    pub fn password_file(&self) -> PathBuf {
+        self.storage_dir.join(".pgpass")
+    }
+
+    /// Downloaded artifacts that can be fetched again.
+    #[must_use]
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir
+            .clone()
+            .or_else(|| project_dirs().map(|dirs| dirs.cache_dir().to_path_buf()))
+            .unwrap_or_else(|| self.storage_dir.join("cache"))
+    }
+
+    /// Directory of the installed PostgreSQL binaries for the configured version.
+    #[must_use]
+    pub fn pg_dir(&self) -> PathBuf {
+        self.installation_dir().join(&self.pg_version)
+    }
+
+    /// Version of the extension files installed for `pg_version`, read from `default_version` in
+    /// the control file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the control file exists but can't be read.
+    pub fn installed_extension_version(&self) -> Result<Option<String>> {
+        let control_file = self
+            .pg_dir()
+            .join("share")
+            .join("extension")
+            .join(format!("{}.control", self.backend.extension_name()));
+        if !control_file.exists() {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read_to_string(control_file)?;
+        Ok(contents.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "default_version").then(|| value.trim().trim_matches('\'').to_string())
+        }))
+    }
+
+    /// `extension_url`, or the pgvecto.rs GitHub releases by default.
+    #[must_use]
+    pub fn extension_url(&self) -> &str {
+        self.extension_url
+            .as_deref()
+            .unwrap_or(DEFAULT_EXTENSION_URL)
+    }
+
+    /// URL of the prebuilt extension archive installed on `platform`, which differs by operating
+    /// system, architecture and PostgreSQL major version.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnsupportedPlatform`] with the reason if no prebuilt build runs on
+    /// `platform`.
+    pub fn extension_artifact_url(&self, platform: Platform) -> Result<String> {
+        extension::resolve_artifact(self, platform).map(|artifact| artifact.url)
+    }
+
+    /// Server settings applied at startup.
+    fn server_configuration(&self) -> HashMap<String, String> {
+        let mut configuration = self.durability.server_configuration();
+        if let Some(socket_dir) = &self.socket_dir {
+            configuration.insert(
+                "unix_socket_directories".to_string(),
+                socket_dir.to_string_lossy().into_owned(),
+            );
+        }
+        configuration
+    }
+
+    /// Builds the `postgresql_embedded` settings for this configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if `pg_version` isn't a valid version, and [`Error::Io`] if the
+    /// password file can't be read.
+    pub fn settings(&self) -> Result<Settings> {
+        let mut settings = Settings {
+            password_file: self.password_file(),
+            installation_dir: self.installation_dir(),
+            data_dir: self.data_dir(),
+            port: self.port,
+            temporary: false,
+            version: VersionReq::parse(format!("={}", self.pg_version).as_str()).map_err(
+                |error| Error::Config(format!("pg_version '{}': {error}", self.pg_version)),
+            )?,
+            configuration: self.server_configuration(),
+            timeout: Some(self.timeouts.startup()),
+            ..Settings::default()
+        };
+        // Reuse the password generated on first run
+        if settings.password_file.exists() {
+            settings.password = std::fs::read_to_string(&settings.password_file)?;
+        }
+        Ok(settings)
+    }
+}
+
+/// Sets the settings named in [`ENV_OVERRIDES`] from the environment variables that are set.
+fn validate_sha256(setting: &str, sha256: &str) -> Result<()> {
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::Config(format!(
+            "{setting} '{sha256}' isn't a SHA-256 of 64 hex digits"
+        )));
+    }
+    Ok(())
+}
+
+fn apply_env_overrides(table: &mut toml::Table) -> Result<()> {
+    for (variable, key) in ENV_OVERRIDES {
+        let Ok(raw) = std::env::var(variable) else {
+            continue;
+        };
+        let invalid =
+            |expected: &str| Error::Config(format!("{variable}: '{raw}' isn't {expected}"));
+        let value = if *key == "port" || key.ends_with("_secs") || key.ends_with("_retries") {
+            toml::Value::Integer(raw.parse().map_err(|_| invalid("a number"))?)
+        } else if *key == "build_from_source" {
+            toml::Value::Boolean(match raw.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" => true,
+                "false" | "0" | "no" => false,
+                _ => return Err(invalid("true or false")),
+            })
+        } else {
+            toml::Value::String(raw.clone())
+        };
+        match key.split_once('.') {
+            Some((section, key)) => {
+                let section = table
+                    .entry(section)
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+                if let toml::Value::Table(section) = section {
+                    section.insert(key.to_string(), value);
+                }
+            }
+            None => {
+                table.insert((*key).to_string(), value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_table(path: &Path) -> Result<toml::Table> {
+    let contents = std::fs::read_to_string(path)?;
+    toml::from_str(&contents).map_err(|error| Error::Config(format!("{}: {error}", path.display())))
+}
+
+/// The root of a portable installation: `root` when given, otherwise the executable's directory
+/// if a config file next to it sets `portable = true`.
+///
+/// # Errors
+///
+/// Returns [`Error::Config`] if the config file next to the executable isn't valid TOML.
+pub fn portable_root(root: Option<PathBuf>) -> Result<Option<PathBuf>> {
+    if let Some(root) = root {
+        return Ok(Some(std::env::current_dir()?.join(root)));
+    }
+
+    let Some(exe_dir) = std::env::current_exe()?.parent().map(Path::to_path_buf) else {
+        return Ok(None);
+    };
+    let config_file = exe_dir.join(CONFIG_FILE_NAME);
+    if !config_file.exists() {
+        return Ok(None);
+    }
+    let portable = read_table(&config_file)?
+        .get("portable")
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false);
+    Ok(portable.then_some(exe_dir))
+}
+
+/// Platform-conventional directories: XDG on Linux, `~/Library/Application Support` and
+/// `~/Library/Caches` on macOS, `%APPDATA%` and `%LOCALAPPDATA%` on Windows. `None` when there is
+/// no home directory, e.g. for some service accounts.
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "pgevdb")
+}
+
+/// The config file used when `--config` isn't given: `pgevdb.toml` in the portable root, or in
+/// the current directory if one exists there, otherwise the one in the platform config directory.
+#[must_use]
+pub fn default_config_file(root: Option<&Path>) -> PathBuf {
+    if let Some(root) = root {
+        return root.join(CONFIG_FILE_NAME);
+    }
+    let local = PathBuf::from(CONFIG_FILE_NAME);
+    if local.exists() {
+        return local;
+    }
+    project_dirs().map_or(local, |dirs| dirs.config_dir().join(CONFIG_FILE_NAME))
+}
+
+/// The platform's local data directory, falling back to `./data` without a home directory.
+/// Local rather than roaming on Windows, since clusters shouldn't sync between machines.
+fn default_storage_dir() -> PathBuf {
+    project_dirs().map_or_else(
+        || PathBuf::from("data"),
+        |dirs| dirs.data_local_dir().to_path_buf(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_a_rate_limit_burst_of_zero() -> Result<()> {
+        let mut config = Config::default();
+        config.api.rate_limit_per_sec = 10;
+        config.api.rate_limit_burst = Some(1);
+        config.validate()?;
+
+        config.api.rate_limit_burst = Some(0);
+        let Err(Error::Config(message)) = config.validate() else {
+            panic!("a burst of 0 was accepted");
+        };
+        assert!(message.contains("rate_limit_burst"));
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+//! Databases of the embedded server besides the configured one, e.g. one per application or
+//! environment sharing a single PostgreSQL process.
+//!
+//! The functions here manage the databases themselves. [`Instance::create_database`] and
+//! [`Instance::database`] also set up the extensions and the pgevdb catalog and return a pool
+//! of the database's own, which is what applications usually want:
+//!
+//! ```no_run
+//! # async fn demo(db: &pgevdb::Instance) -> pgevdb::Result<()> {
+//! let staging = db.create_database("staging").await?;
+//! let docs = pgevdb::Collection::create(&staging, "docs", 384, pgevdb::Metric::Cosine).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Instance::create_database`]: crate::Instance::create_database
+//! [`Instance::database`]: crate::Instance::database
+
+use postgresql_embedded::PostgreSQL;
+use serde::Serialize;
+use sqlx::{Connection, PgConnection};
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::identifier;
+
+/// `NAMEDATALEN - 1`.
+pub(crate) const MAX_NAME_LENGTH: usize = 63;
+/// The maintenance database every server has, which pgevdb connects to for these operations.
+pub(crate) const MAINTENANCE_DATABASE: &str = "postgres";
+
+/// A database of the embedded server.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Database {
+    pub name: String,
+    /// Size on disk.
+    pub bytes: u64,
+}
+
+/// Creates the empty database `name`. It has neither the vector extension nor the pgevdb
+/// catalog until [`Instance::create_database`](crate::Instance::create_database) or
+/// [`PgEvDb::start`](crate::PgEvDb::start) sets them up.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if `name` isn't a valid database name or already exists.
+pub async fn create(postgresql: &PostgreSQL, name: &str) -> Result<()> {
+    identifier::validate("database", name, MAX_NAME_LENGTH)?;
+    let mut connection = connect(postgresql).await?;
+    if exists(&mut connection, name).await? {
+        return Err(Error::InvalidInput(format!(
+            "database '{name}' already exists"
+        )));
+    }
+    info!("Creating database '{name}'");
+    sqlx::query(&format!("CREATE DATABASE {}", quote_identifier(name)))
+        .execute(&mut connection)
+        .await?;
+    Ok(())
+}
+
+/// Every database on the server except templates and the maintenance database, by name.
+/// [Branches](crate::branch) are listed too.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the server can't be queried.
+pub async fn list(postgresql: &PostgreSQL) -> Result<Vec<Database>> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT datname, pg_database_size(oid) FROM pg_database
+         WHERE NOT datistemplate AND datname <> $1
+         ORDER BY datname",
+    )
+    .bind(MAINTENANCE_DATABASE)
+    .fetch_all(&mut connect(postgresql).await?)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(name, bytes)| Database {
+            name,
+            bytes: bytes.unsigned_abs(),
+        })
+        .collect())
+}
+
+/// Whether the server has a database called `name`.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the server can't be queried.
+pub async fn exists_on(postgresql: &PostgreSQL, name: &str) -> Result<bool> {
+    exists(&mut connect(postgresql).await?, name).await
+}
+
+/// Drops the database `name` with everything in it, closing any connections to it, including
+/// those of pools in this process.
+///
+/// # Errors
+///
+/// Returns [`Error::NotFound`] if there is no database called `name`, [`Error::InvalidInput`]
+/// for templates and the maintenance database, and [`Error::Database`] if it can't be dropped.
+pub async fn delete(postgresql: &PostgreSQL, name: &str) -> Result<()> {
+    let mut connection = connect(postgresql).await?;
+    let template: Option<(bool,)> =
+        sqlx::query_as("SELECT datistemplate FROM pg_database WHERE datname = $1")
+            .bind(name)
+            .fetch_optional(&mut connection)
+            .await?;
+    match template {
+        None => return Err(Error::NotFound(format!("database '{name}'"))),
+        Some((true,)) => {
+            return Err(Error::InvalidInput(format!(
+                "'{name}' is a template database"
+            )))
+        }
+        Some((false,)) if name == MAINTENANCE_DATABASE => {
+            return Err(Error::InvalidInput(format!(
+                "'{name}' is the server's maintenance database"
+            )))
+        }
+        Some((false,)) => {}
+    }
+    info!("Dropping database '{name}'");
+    sqlx::query(&format!(
+        "DROP DATABASE {} WITH (FORCE)",
+        quote_identifier(name)
+    ))
+    .execute(&mut connection)
+    .await?;
+    Ok(())
+}
+
+/// Connects to the maintenance database, which is never created, copied or dropped.
+pub(crate) async fn connect(postgresql: &PostgreSQL) -> Result<PgConnection> {
+    Ok(PgConnection::connect(&postgresql.settings().url(MAINTENANCE_DATABASE)).await?)
+}
+
+pub(crate) async fn exists(connection: &mut PgConnection, name: &str) -> Result<bool> {
+    let (exists,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT FROM pg_database WHERE datname = $1)")
+            .bind(name)
+            .fetch_one(connection)
+            .await?;
+    Ok(exists)
+}
+
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,160 @@
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use tracing::info;
+
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::{Collection, Config, Error, IndexConfig, Metric, Result};
+
+/// Collection the similarity search runs on, recreated on every run. The prefix keeps it
+/// clear of the collections of applications, which it would replace.
+const COLLECTION: &str = "pgevdb_demo";
+
+/// Results of the demo flow.
+#[derive(Serialize)]
+struct DemoReport {
+    operations: Vec<VectorOperation>,
+    similar_vectors: Vec<SimilarVector>,
+}
+
+#[derive(Serialize)]
+struct VectorOperation {
+    name: &'static str,
+    query: &'static str,
+    result: f32,
+}
+
+#[derive(Serialize)]
+struct SimilarVector {
+    id: i64,
+    distance: f32,
+}
+
+impl Report for DemoReport {
+    fn print_text(&self) {
+        for operation in &self.operations {
+            println!("{}: {}", operation.query, operation.result);
+        }
+
+        println!("Similar vectors:");
+        for similar in &self.similar_vectors {
+            println!("ID: {}, Distance: {}", similar.id, similar.distance);
+        }
+    }
+}
+
+/// Sets up the embedded instance and the extension, then exercises some vector operations.
+pub async fn run(config: Config, output: OutputFormat) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+
+    // Some tests to verify the extension is working
+
+    info!("Creating table 'items' with vector column");
+    create_table_items(&pool).await?;
+
+    info!("Inserting vector data");
+    insert_vector_data(&pool).await?;
+
+    info!("Demonstrating vector operations");
+    let operations = demonstrate_vector_operations(&pool).await?;
+
+    info!("Searching for similar vectors in an HNSW-indexed collection");
+    let similar_vectors = search_similar_vectors(&pool).await?;
+
+    emit(
+        output,
+        &DemoReport {
+            operations,
+            similar_vectors,
+        },
+    )
+}
+
+async fn create_table_items(pool: &PgPool) -> Result<()> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS items (
+            id bigserial PRIMARY KEY,
+            embedding vector(3) NOT NULL
+        );",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn insert_vector_data(pool: &PgPool) -> Result<()> {
+    sqlx::query("INSERT INTO items (embedding) VALUES ('[1,2,3]'), ('[4,5,6]');")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        "INSERT INTO items (embedding) VALUES (ARRAY[1, 2, 3]::real[]), (ARRAY[4, 5, 6]::real[]);",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+async fn demonstrate_vector_operations(pool: &PgPool) -> Result<Vec<VectorOperation>> {
+    let queries = [
+        (
+            "squared_euclidean_distance",
+            "SELECT '[1, 2, 3]'::vector <-> '[3, 2, 1]'::vector AS squared_euclidean_distance;",
+        ),
+        (
+            "negative_dot_product",
+            "SELECT '[1, 2, 3]'::vector <#> '[3, 2, 1]'::vector AS negative_dot_product;",
+        ),
+        (
+            "cosine_distance",
+            "SELECT '[1, 2, 3]'::vector <=> '[3, 2, 1]'::vector AS cosine_distance;",
+        ),
+    ];
+
+    let mut operations = Vec::with_capacity(queries.len());
+    for (name, query) in queries {
+        let result: (f32,) = sqlx::query_as(query).fetch_one(pool).await?;
+        operations.push(VectorOperation {
+            name,
+            query,
+            result: result.0,
+        });
+    }
+
+    Ok(operations)
+}
+
+async fn search_similar_vectors(pool: &PgPool) -> Result<Vec<SimilarVector>> {
+    match Collection::open(pool, COLLECTION).await {
+        Ok(collection) => collection.delete().await?,
+        Err(Error::NotFound(_)) => {}
+        Err(error) => return Err(error),
+    }
+    let collection = Collection::create(pool, COLLECTION, 3, Metric::L2).await?;
+    collection
+        .insert_many([
+            [1.0, 2.0, 3.0],
+            [4.0, 5.0, 6.0],
+            [3.0, 2.0, 1.0],
+            [6.0, 5.0, 4.0],
+        ])
+        .await?;
+    collection
+        .create_index(&IndexConfig::Hnsw {
+            m: 16,
+            ef_construction: 64,
+        })
+        .await?;
+
+    Ok(collection
+        .search(&[3.0, 2.0, 1.0], 5)
+        .await?
+        .into_iter()
+        .map(|result| SimilarVector {
+            id: result.id,
+            distance: result.distance,
+        })
+        .collect())
+}
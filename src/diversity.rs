@@ -0,0 +1,130 @@
+//! Result diversification by maximal marginal relevance (MMR).
+//!
+//! The nearest neighbors of a query are often near-duplicates of each other: the same passage
+//! stored twice, or overlapping chunks of one document. [`mmr`] picks results one at a time,
+//! trading relevance to the query against similarity to the results already picked, so a RAG
+//! context covers more ground in the same number of passages.
+
+use crate::collection::Metric;
+
+/// Orders `candidates`, each a relevance to the query in `[0, 1]` (such as a
+/// [score](Metric::score)) and a vector, by maximal marginal relevance and returns the indices
+/// of the first `limit`. Each pick maximizes
+/// `lambda · relevance - (1 - lambda) · max similarity to the picks before`, with similarities
+/// the [scores](Metric::score) of `metric`'s distances. `lambda` is clamped to `[0, 1]`: `1`
+/// keeps the relevance order, lower values favor variety.
+///
+/// ```
+/// use pgevdb::diversity::mmr;
+/// use pgevdb::Metric;
+///
+/// let candidates = [(0.9, vec![1.0, 0.0]), (0.89, vec![1.0, 0.0]), (0.7, vec![0.0, 1.0])];
+/// assert_eq!(mmr(Metric::Cosine, 1.0, &candidates, 2), [0, 1]);
+/// assert_eq!(mmr(Metric::Cosine, 0.5, &candidates, 2), [0, 2]);
+/// ```
+#[must_use]
+pub fn mmr<V: AsRef<[f32]>>(
+    metric: Metric,
+    lambda: f32,
+    candidates: &[(f32, V)],
+    limit: usize,
+) -> Vec<usize> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    let mut picked: Vec<usize> = Vec::with_capacity(limit.min(candidates.len()));
+    // Highest similarity of each candidate to any pick so far
+    let mut redundancy = vec![f32::NEG_INFINITY; candidates.len()];
+    while picked.len() < limit {
+        let best = candidates
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !picked.contains(index))
+            .map(|(index, (relevance, _))| {
+                let penalty = if picked.is_empty() {
+                    0.0
+                } else {
+                    redundancy[index]
+                };
+                (index, lambda * relevance - (1.0 - lambda) * penalty)
+            })
+            // The first of equal candidates wins, keeping the input order for ties
+            .fold(
+                None,
+                |best: Option<(usize, f32)>, (index, value)| match best {
+                    Some((_, best_value)) if best_value >= value => best,
+                    _ => Some((index, value)),
+                },
+            );
+        let Some((pick, _)) = best else { break };
+        picked.push(pick);
+        let vector = candidates[pick].1.as_ref();
+        for (index, (_, other)) in candidates.iter().enumerate() {
+            let similarity = metric.score(metric.distance(vector, other.as_ref()));
+            redundancy[index] = redundancy[index].max(similarity);
+        }
+    }
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two copies of one passage ahead of a different, slightly less relevant one, and a fourth
+    /// close to the third.
+    fn candidates() -> Vec<(f32, Vec<f32>)> {
+        vec![
+            (0.9, vec![1.0, 0.0]),
+            (0.89, vec![1.0, 0.0]),
+            (0.8, vec![0.0, 1.0]),
+            (0.7, vec![0.1, 1.0]),
+        ]
+    }
+
+    #[test]
+    fn lambda_one_keeps_the_relevance_order() {
+        assert_eq!(mmr(Metric::Cosine, 1.0, &candidates(), 4), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn skips_duplicates_of_earlier_picks() {
+        assert_eq!(mmr(Metric::Cosine, 0.5, &candidates(), 2), [0, 2]);
+        // The fourth is nearly the third and less relevant than the copy of the first
+        assert_eq!(mmr(Metric::Cosine, 0.5, &candidates(), 4), [0, 2, 1, 3]);
+    }
+
+    #[test]
+    fn lambda_zero_only_seeks_variety() {
+        // The first pick has nothing to be redundant with, so relevance is ignored throughout
+        // and ties keep the input order
+        let picks = mmr(Metric::L2, 0.0, &candidates(), 2);
+        assert_eq!(picks, [0, 2]);
+    }
+
+    #[test]
+    fn clamps_lambda() {
+        let candidates = candidates();
+        assert_eq!(
+            mmr(Metric::Cosine, 7.0, &candidates, 4),
+            mmr(Metric::Cosine, 1.0, &candidates, 4)
+        );
+        assert_eq!(
+            mmr(Metric::Cosine, -1.0, &candidates, 4),
+            mmr(Metric::Cosine, 0.0, &candidates, 4)
+        );
+    }
+
+    #[test]
+    fn returns_at_most_the_candidates() {
+        assert_eq!(mmr(Metric::Cosine, 0.5, &candidates(), 10).len(), 4);
+        let none: [(f32, Vec<f32>); 0] = [];
+        assert!(mmr(Metric::Cosine, 0.5, &none, 3).is_empty());
+        assert!(mmr(Metric::Cosine, 0.5, &candidates(), 0).is_empty());
+    }
+
+    #[test]
+    fn picks_each_candidate_once() {
+        let mut picks = mmr(Metric::L2, 0.3, &candidates(), 4);
+        picks.sort_unstable();
+        assert_eq!(picks, [0, 1, 2, 3]);
+    }
+}
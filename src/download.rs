@@ -0,0 +1,283 @@
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::header::RANGE;
+use reqwest::{NoProxy, StatusCode};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::progress::Progress;
+
+/// HTTP client shared by every download; GitHub's API rejects requests without a user agent.
+///
+/// `idle_timeout` bounds connecting and each wait for more data rather than the whole transfer,
+/// so large downloads on slow links still finish while stalled ones fail. Without an explicit
+/// `proxy`, the `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` environment variables apply.
+pub(crate) fn client(
+    idle_timeout: Duration,
+    proxy: Option<&str>,
+) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(concat!("pgevdb/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(idle_timeout)
+        .read_timeout(idle_timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?.no_proxy(NoProxy::from_env()));
+    }
+    builder.build()
+}
+
+/// Delay before the first retry of a download; doubled for each further one.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Downloads `url` into memory, reporting progress under `label` as the body arrives.
+///
+/// Connection failures, stalls, server errors and rate limiting are retried
+/// `timeouts.download_retries` times with exponential backoff. A retry after part of the body
+/// arrived asks only for the rest with a range request, and starts over if the server doesn't
+/// support those.
+///
+/// # Errors
+///
+/// Returns [`Error::DownloadFailed`] when the retries are used up or the failure isn't
+/// transient, such as a 404.
+///
+/// The body is only held in memory, so dropping the future discards what arrived and leaves
+/// nothing behind on disk.
+pub async fn download(url: &str, label: &str, config: &Config) -> Result<Vec<u8>> {
+    download_with_cancellation(url, label, config, &CancellationToken::new()).await
+}
+
+/// [`download`], stopping with [`Error::Cancelled`] once `cancel` fires, including while
+/// waiting to retry.
+///
+/// # Errors
+///
+/// Returns [`Error::Cancelled`] if cancelled, and the errors of [`download`] otherwise.
+pub async fn download_with_cancellation(
+    url: &str,
+    label: &str,
+    config: &Config,
+    cancel: &CancellationToken,
+) -> Result<Vec<u8>> {
+    let download_failed = |source| Error::DownloadFailed {
+        url: url.to_string(),
+        source,
+    };
+    let timeouts = config.timeouts;
+    let client = client(timeouts.download(), config.proxy.as_deref()).map_err(download_failed)?;
+
+    let mut bytes = Vec::new();
+    let mut progress = None;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut retries_left = timeouts.download_retries;
+    loop {
+        let fetched = tokio::select! {
+            () = cancel.cancelled() => return Err(Error::Cancelled),
+            fetched = fetch_rest(&client, url, label, &mut bytes, &mut progress) => fetched,
+        };
+        match fetched {
+            Ok(()) => break,
+            Err(error) if retries_left > 0 && is_transient(&error) => {
+                warn!(
+                    "Downloading {url} failed after {} bytes ({error}); retrying in {}s",
+                    bytes.len(),
+                    backoff.as_secs()
+                );
+                tokio::select! {
+                    () = cancel.cancelled() => return Err(Error::Cancelled),
+                    () = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                retries_left -= 1;
+            }
+            Err(error) => return Err(download_failed(error)),
+        }
+    }
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+
+    Ok(bytes)
+}
+
+/// Appends the part of `url` after the `bytes` received so far, starting the progress report on
+/// the first response.
+async fn fetch_rest(
+    client: &reqwest::Client,
+    url: &str,
+    label: &str,
+    bytes: &mut Vec<u8>,
+    progress: &mut Option<Progress>,
+) -> reqwest::Result<()> {
+    let mut request = client.get(url);
+    if !bytes.is_empty() {
+        request = request.header(RANGE, format!("bytes={}-", bytes.len()));
+    }
+    let mut response = request.send().await?.error_for_status()?;
+    if !bytes.is_empty() && response.status() != StatusCode::PARTIAL_CONTENT {
+        info!("{url} doesn't support resuming; downloading it again");
+        bytes.clear();
+        *progress = None;
+    }
+    let progress =
+        progress.get_or_insert_with(|| Progress::bytes(label, response.content_length()));
+
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        progress.inc(chunk.len() as u64);
+    }
+    Ok(())
+}
+
+/// Whether a failed request may succeed when repeated.
+fn is_transient(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        None => error.is_timeout() || error.is_connect() || error.is_request() || error.is_body(),
+    }
+}
+
+/// Returns the contents of `cache_file`, or fetches them with `fetch` and stores them there for
+/// next time. The file is written under a temporary name and renamed into place, so an
+/// interrupted or cancelled download never leaves a truncated artifact in the cache; failing to
+/// write it removes the temporary file and only logs a warning.
+pub(crate) async fn cached(
+    cache_file: &Path,
+    fetch: impl Future<Output = Result<Vec<u8>>>,
+) -> Result<Vec<u8>> {
+    if cache_file.is_file() {
+        info!("Using cached {}", cache_file.display());
+        return Ok(std::fs::read(cache_file)?);
+    }
+
+    let bytes = fetch.await?;
+    let partial_file = cache_file.with_extension("partial");
+    let stored = cache_file
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&partial_file, &bytes))
+        .and_then(|()| std::fs::rename(&partial_file, cache_file));
+    if let Err(error) = stored {
+        warn!("Couldn't cache {}: {error}", cache_file.display());
+        let _ = std::fs::remove_file(&partial_file);
+    }
+    Ok(bytes)
+}
+
+/// Fetches and deserializes a JSON document, such as a GitHub API response. Requests to the
+/// GitHub API carry the configured `github_token`.
+///
+/// # Errors
+///
+/// Returns [`Error::DownloadFailed`] if the request fails, stalls for the download timeout, or
+/// the body isn't the expected JSON.
+pub async fn get_json<T: DeserializeOwned>(url: &str, config: &Config) -> Result<T> {
+    let download_failed = |source| Error::DownloadFailed {
+        url: url.to_string(),
+        source,
+    };
+    let mut request = client(config.timeouts.download(), config.proxy.as_deref())
+        .map_err(download_failed)?
+        .get(url);
+    if let Some(token) = &config.github_token {
+        if url.starts_with("https://api.github.com/") {
+            request = request.bearer_auth(token);
+        }
+    }
+    request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(download_failed)?
+        .json()
+        .await
+        .map_err(download_failed)
+}
+
+/// Lowercase hex SHA-256 of `bytes`.
+#[must_use]
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Checks that `bytes` hash to `expected`, a hex SHA-256 in either case.
+///
+/// # Errors
+///
+/// Returns [`Error::ChecksumMismatch`] naming `artifact` if they don't.
+pub fn verify_sha256(artifact: &str, bytes: &[u8], expected: &str) -> Result<()> {
+    let expected = expected.to_lowercase();
+    let actual = sha256_hex(bytes);
+    if expected != actual {
+        return Err(Error::ChecksumMismatch {
+            artifact: artifact.to_string(),
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serves the first kilobyte of a megabyte body and then stalls without closing.
+    async fn stalling_server() -> std::io::Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/vectors.zip", listener.local_addr()?);
+        tokio::spawn(async move {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1048576\r\n\r\n")
+                .await;
+            let _ = stream.write_all(&[0; 1024]).await;
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn stops_a_stalled_download_when_cancelled() -> std::io::Result<()> {
+        let url = stalling_server().await?;
+        let config = Config::default();
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            canceller.cancel();
+        });
+
+        let started = Instant::now();
+        let result = download_with_cancellation(&url, "Downloading", &config, &cancel).await;
+        assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+        assert!(started.elapsed() < config.timeouts.download());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn leaves_no_cache_entry_for_a_cancelled_download() -> std::io::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let cache_file = dir.path().join("vectors.zip");
+        let result = cached(&cache_file, async { Err(Error::Cancelled) }).await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+        Ok(())
+    }
+}
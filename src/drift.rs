@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::cli::DriftCommand;
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::monitoring::{self, DriftOptions, DriftReport};
+use pgevdb::{Collection, Config, Result};
+
+impl Report for DriftReport {
+    fn print_text(&self) {
+        println!(
+            "{}: baseline of {} vectors, distance to centroid {:.4} ± {:.4}",
+            self.collection, self.baseline_vectors, self.baseline_mean, self.baseline_stddev
+        );
+        for window in &self.windows {
+            println!(
+                "  window -{:<3} {:>8} vectors  mean {:.4}  shift {:+.2}σ  {:>5} outliers{}",
+                window.age,
+                window.vectors,
+                window.mean_distance,
+                window.shift,
+                window.outliers,
+                if window.drifted { "  DRIFT" } else { "" }
+            );
+        }
+        println!(
+            "{} recent vectors, {} outliers, {} drifted windows",
+            self.recent_vectors, self.outliers, self.drifted_windows
+        );
+        for outlier in &self.listed_outliers {
+            println!(
+                "  outlier {:<12} distance {:.4} ({:+.1}σ)",
+                outlier.id, outlier.distance, outlier.z_score
+            );
+        }
+    }
+}
+
+/// Result of `pgevdb drift history`.
+#[derive(Serialize)]
+struct HistoryReport {
+    checks: Vec<DriftReport>,
+}
+
+impl Report for HistoryReport {
+    fn print_text(&self) {
+        if self.checks.is_empty() {
+            println!("No checks recorded");
+        }
+        for check in &self.checks {
+            println!(
+                "{}  baseline {:.4} ± {:.4}  {:>8} recent  {:>6} outliers  {} drifted windows",
+                check.checked_at,
+                check.baseline_mean,
+                check.baseline_stddev,
+                check.recent_vectors,
+                check.outliers,
+                check.drifted_windows
+            );
+        }
+    }
+}
+
+pub async fn run(command: DriftCommand, config: Config, output: OutputFormat) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+    match command {
+        DriftCommand::Check {
+            collection,
+            window_hours,
+            windows,
+            threshold,
+        } => {
+            let collection = Collection::open(&pool, &collection).await?;
+            let options = DriftOptions {
+                window: Duration::from_secs(window_hours.saturating_mul(3600)),
+                windows,
+                outlier_threshold: threshold,
+                ..DriftOptions::default()
+            };
+            emit(
+                output,
+                &monitoring::check_drift(&collection, &options).await?,
+            )
+        }
+        DriftCommand::History { collection } => emit(
+            output,
+            &HistoryReport {
+                checks: monitoring::drift_history(&pool, &collection).await?,
+            },
+        ),
+    }
+}
@@ -0,0 +1,238 @@
+//! Registry of the embedding models that produce a database's vectors.
+//!
+//! Vectors from different models, or from different versions of one model, live in unrelated
+//! spaces even when their dimensions agree, so comparing them returns confident nonsense rather
+//! than an error. Binding a collection to a registered embedder lets
+//! [`Collection::insert_embedded`](crate::Collection::insert_embedded) refuse vectors from any
+//! other model.
+//!
+//! [`EmbeddingModel`] computes embeddings for query text, for pipelines such as
+//! [`rag::retrieve_context`](crate::rag::retrieve_context).
+
+use std::fmt;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+use crate::download;
+use crate::error::{Error, Result};
+
+/// Identity and output shape of an embedding model.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Embedder {
+    /// Who serves the model, e.g. `openai` or `local`.
+    pub provider: String,
+    pub model: String,
+    /// Model revision; bump it whenever the same name starts producing different vectors.
+    pub version: String,
+    pub dimensions: u32,
+    /// Whether the model emits unit-length vectors.
+    pub normalized: bool,
+}
+
+impl Embedder {
+    /// Rejects `vector` unless it has this model's dimensions and, for normalizing models, unit
+    /// length.
+    pub(crate) fn check(&self, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dimensions as usize {
+            return Err(Error::InvalidInput(format!(
+                "embedder {self} produces {} dimensions, got a vector with {}",
+                self.dimensions,
+                vector.len()
+            )));
+        }
+        if self.normalized {
+            let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if (norm - 1.0).abs() > 1e-3 {
+                return Err(Error::InvalidInput(format!(
+                    "embedder {self} produces unit vectors, got one of length {norm}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Embedder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}@{}", self.provider, self.model, self.version)
+    }
+}
+
+/// Adds `embedder` to the registry, or checks it against the entry already registered under the
+/// same provider, model and version.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if an entry with the same identity has other dimensions or
+/// normalization.
+pub async fn register(pool: &PgPool, embedder: &Embedder) -> Result<i64> {
+    let dimensions = i32::try_from(embedder.dimensions)
+        .map_err(|_| Error::InvalidInput(format!("embedder {embedder}: too many dimensions")))?;
+    sqlx::query(
+        "INSERT INTO pgevdb.embedders (provider, model, version, dimensions, normalized)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (provider, model, version) DO NOTHING",
+    )
+    .bind(&embedder.provider)
+    .bind(&embedder.model)
+    .bind(&embedder.version)
+    .bind(dimensions)
+    .bind(embedder.normalized)
+    .execute(pool)
+    .await?;
+
+    let (id, registered_dimensions, registered_normalized): (i64, i32, bool) = sqlx::query_as(
+        "SELECT id, dimensions, normalized FROM pgevdb.embedders
+         WHERE provider = $1 AND model = $2 AND version = $3",
+    )
+    .bind(&embedder.provider)
+    .bind(&embedder.model)
+    .bind(&embedder.version)
+    .fetch_one(pool)
+    .await?;
+    if registered_dimensions != dimensions || registered_normalized != embedder.normalized {
+        return Err(Error::InvalidInput(format!(
+            "embedder {embedder} is registered with {registered_dimensions} dimensions \
+             (normalized: {registered_normalized}); register changed output under a new version"
+        )));
+    }
+    Ok(id)
+}
+
+/// Every registered embedder.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the registry can't be read.
+pub async fn list(pool: &PgPool) -> Result<Vec<Embedder>> {
+    let rows: Vec<(String, String, String, i32, bool)> = sqlx::query_as(
+        "SELECT provider, model, version, dimensions, normalized FROM pgevdb.embedders
+         ORDER BY provider, model, version",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(
+            |(provider, model, version, dimensions, normalized)| Embedder {
+                provider,
+                model,
+                version,
+                dimensions: dimensions.unsigned_abs(),
+                normalized,
+            },
+        )
+        .collect())
+}
+
+/// Computes embeddings for text. Implement it for local models; [`HttpEmbeddingModel`] calls a
+/// hosted embeddings API.
+pub trait EmbeddingModel: fmt::Debug + Send + Sync {
+    /// One embedding per text, in order.
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>>;
+}
+
+/// An OpenAI-compatible embeddings API: a `POST` of `{"model", "input"}` answered by
+/// `{"data": [{"index", "embedding"}]}`.
+#[derive(Clone, Debug)]
+pub struct HttpEmbeddingModel {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    timeout: Duration,
+}
+
+impl HttpEmbeddingModel {
+    /// `url` is the full endpoint, e.g. `https://api.openai.com/v1/embeddings`.
+    #[must_use]
+    pub fn new(url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            model: model.into(),
+            api_key: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Sent as a bearer token.
+    #[must_use]
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Bounds connecting and each wait for response data.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn call(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            model: &'a str,
+            input: &'a [String],
+        }
+        #[derive(Deserialize)]
+        struct Response {
+            data: Vec<Embedding>,
+        }
+        #[derive(Deserialize)]
+        struct Embedding {
+            index: usize,
+            embedding: Vec<f32>,
+        }
+
+        let failed = |source| Error::DownloadFailed {
+            url: self.url.clone(),
+            source,
+        };
+        let mut request = download::client(self.timeout, None)
+            .map_err(failed)?
+            .post(&self.url)
+            .json(&Request {
+                model: &self.model,
+                input: texts,
+            });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response: Response = request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(failed)?
+            .json()
+            .await
+            .map_err(failed)?;
+
+        let mut embeddings = vec![None; texts.len()];
+        for embedding in response.data {
+            if let Some(slot) = embeddings.get_mut(embedding.index) {
+                *slot = Some(embedding.embedding);
+            }
+        }
+        embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(index, embedding)| {
+                embedding.ok_or_else(|| {
+                    Error::InvalidInput(format!(
+                        "{} returned no embedding for input {index}",
+                        self.url
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+impl EmbeddingModel for HttpEmbeddingModel {
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(self.call(texts))
+    }
+}
@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Result type returned by pgevdb's public functions.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Failure classes reported by pgevdb.
+///
+/// Each variant maps to a documented process exit code (see [`Error::exit_code`]) so scripts can
+/// branch on the kind of failure. Exit codes are a compatibility surface: don't renumber them.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The config file, a flag or an environment setting is invalid.
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+
+    /// The user declined a confirmation prompt, or one couldn't be shown.
+    #[error("{0}")]
+    Aborted(String),
+
+    /// PostgreSQL would run as root, or the storage directory isn't writable.
+    #[error("{0}")]
+    Privileges(String),
+
+    /// An artifact couldn't be downloaded.
+    #[error("Failed to download {url}")]
+    DownloadFailed {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// A downloaded artifact doesn't match its published checksum.
+    #[error("Checksum mismatch for {artifact}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        artifact: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// A reranking API couldn't be reached, refused the request or answered with something
+    /// other than scores.
+    #[error("Reranker {url} failed")]
+    Reranker {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// No build exists for the host platform.
+    #[error("Unsupported platform {0}")]
+    UnsupportedPlatform(String),
+
+    /// The extension files couldn't be unpacked or copied into the PostgreSQL installation.
+    #[error("Failed to install the vector extension: {message}")]
+    ExtensionInstall {
+        message: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The server didn't come up; `pg_ctl` gave up waiting or the postmaster exited.
+    #[error("PostgreSQL failed to start")]
+    StartupTimeout(#[source] postgresql_embedded::Error),
+
+    /// Installing, initializing or stopping the embedded server failed.
+    #[error("PostgreSQL error")]
+    PostgreSQL(#[from] postgresql_embedded::Error),
+
+    /// The data directory or database was created by versions this binary can't use as is.
+    #[error("Incompatible data: {0}")]
+    Incompatible(String),
+
+    /// A request to the library was malformed, e.g. a vector with the wrong dimensions.
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    /// A collection or record doesn't exist.
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    /// An insert would take a namespace past its row or byte quota.
+    #[error("Namespace '{namespace}' would exceed its quota of {limit} {resource}")]
+    QuotaExceeded {
+        namespace: String,
+        /// `rows` or `bytes`.
+        resource: &'static str,
+        limit: u64,
+    },
+
+    /// A server is already running on the data directory, e.g. one left running by
+    /// `pgevdb start`.
+    #[error("PostgreSQL is already running on {} (port {port}); connect to it or stop it first", data_dir.display())]
+    AlreadyRunning { data_dir: PathBuf, port: u16 },
+
+    /// A query against the running server failed.
+    #[error("Database error")]
+    Database(#[from] sqlx::Error),
+
+    /// The operation was cancelled through its `CancellationToken` or by Ctrl-C.
+    #[error("Cancelled")]
+    Cancelled,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to serialize output")]
+    Output(#[from] serde_json::Error),
+}
+
+impl Error {
+    /// Process exit code for this failure class.
+    ///
+    /// | Code | Meaning |
+    /// |------|---------|
+    /// | 1    | I/O or other unexpected failure |
+    /// | 2    | Invalid command line usage (reported by clap) |
+    /// | 3    | Invalid configuration |
+    /// | 4    | Aborted at a confirmation prompt |
+    /// | 5    | Running as root or without write access to the storage directory |
+    /// | 10   | Download failed |
+    /// | 11   | Unsupported platform |
+    /// | 12   | Extension install failed |
+    /// | 13   | Checksum verification failed |
+    /// | 14   | Reranking API failed |
+    /// | 20   | PostgreSQL failed to start |
+    /// | 21   | Embedded PostgreSQL setup or shutdown failed |
+    /// | 22   | Database query failed |
+    /// | 23   | Data directory or database needs an upgrade |
+    /// | 24   | Invalid input, such as a vector with the wrong dimensions |
+    /// | 25   | Collection or record not found |
+    /// | 26   | Namespace quota exceeded |
+    /// | 27   | PostgreSQL is already running on the data directory |
+    /// | 130  | Cancelled (Ctrl-C) |
+    #[must_use]
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io(_) | Error::Output(_) => 1,
+            Error::Config(_) => 3,
+            Error::Aborted(_) => 4,
+            Error::Privileges(_) => 5,
+            Error::DownloadFailed { .. } => 10,
+            Error::UnsupportedPlatform(_) => 11,
+            Error::ExtensionInstall { .. } => 12,
+            Error::ChecksumMismatch { .. } => 13,
+            Error::Reranker { .. } => 14,
+            Error::StartupTimeout(_) => 20,
+            Error::PostgreSQL(_) => 21,
+            Error::Database(_) => 22,
+            Error::Incompatible(_) => 23,
+            Error::InvalidInput(_) => 24,
+            Error::NotFound(_) => 25,
+            Error::QuotaExceeded { .. } => 26,
+            Error::AlreadyRunning { .. } => 27,
+            Error::Cancelled => 130,
+        }
+    }
+
+    /// Prints the error and its chain of causes to stderr and returns the matching exit code.
+    #[must_use]
+    pub fn report(&self) -> ExitCode {
+        eprintln!("Error: {self}");
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            eprintln!("  Caused by: {cause}");
+            source = cause.source();
+        }
+        ExitCode::from(self.exit_code())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<dialoguer::Error> for Error {
+    fn from(error: dialoguer::Error) -> Self {
+        let dialoguer::Error::IO(error) = error;
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::BufRead;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::cli::EvalCommand;
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::evaluation::{self, EvalQuery, EvalReport};
+use pgevdb::{Collection, Config, Error, Result};
+
+/// Result of `pgevdb eval register`.
+#[derive(Serialize)]
+struct RegisterReport {
+    dataset: String,
+    queries: usize,
+}
+
+impl Report for RegisterReport {
+    fn print_text(&self) {
+        println!(
+            "Registered dataset '{}' with {} queries",
+            self.dataset, self.queries
+        );
+    }
+}
+
+impl Report for EvalReport {
+    fn print_text(&self) {
+        println!(
+            "{} on {} ({} queries, k={}): recall {:.3}, MRR {:.3}, nDCG {:.3}",
+            self.dataset, self.collection, self.queries, self.k, self.recall, self.mrr, self.ndcg
+        );
+    }
+}
+
+/// Result of `pgevdb eval history`.
+#[derive(Serialize)]
+struct HistoryReport {
+    runs: Vec<EvalReport>,
+}
+
+impl Report for HistoryReport {
+    fn print_text(&self) {
+        if self.runs.is_empty() {
+            println!("No runs recorded");
+        }
+        for run in &self.runs {
+            println!(
+                "{}  {:<20} k={:<4} recall {:.3}  MRR {:.3}  nDCG {:.3}",
+                run.ran_at, run.collection, run.k, run.recall, run.mrr, run.ndcg
+            );
+        }
+    }
+}
+
+pub async fn run(command: EvalCommand, config: Config, output: OutputFormat) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+    match command {
+        EvalCommand::Register { dataset, file } => {
+            let queries = read_queries(&file)?;
+            evaluation::register_dataset(&pool, &dataset, &queries).await?;
+            emit(
+                output,
+                &RegisterReport {
+                    dataset,
+                    queries: queries.len(),
+                },
+            )
+        }
+        EvalCommand::Run {
+            dataset,
+            collection,
+            k,
+        } => {
+            let collection = Collection::open(&pool, &collection).await?;
+            emit(
+                output,
+                &evaluation::evaluate(&collection, &dataset, k).await?,
+            )
+        }
+        EvalCommand::History { dataset } => emit(
+            output,
+            &HistoryReport {
+                runs: evaluation::history(&pool, &dataset).await?,
+            },
+        ),
+    }
+}
+
+/// Reads one JSON query object per line, skipping blank lines.
+fn read_queries(path: &Path) -> Result<Vec<EvalQuery>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut queries = Vec::new();
+    for (number, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        queries.push(serde_json::from_str(&line).map_err(|error| {
+            Error::InvalidInput(format!("{}:{}: {error}", path.display(), number + 1))
+        })?);
+    }
+    Ok(queries)
+}
@@ -0,0 +1,219 @@
+//! Labeled query sets for measuring retrieval quality, and a history of how collections scored
+//! on them.
+//!
+//! A dataset is a list of query vectors, each with the ids a perfect search would return. Running
+//! it against a collection records recall, MRR and nDCG at a cutoff `k`, so a regression after
+//! changing index settings or embedders shows up in [`history`].
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+use crate::collection::Collection;
+use crate::error::{Error, Result};
+
+/// A query vector and the ids of the records relevant to it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvalQuery {
+    pub embedding: Vec<f32>,
+    pub relevant_ids: Vec<i64>,
+}
+
+/// Scores from one run of a dataset against a collection, averaged over its queries.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EvalReport {
+    pub dataset: String,
+    pub collection: String,
+    pub k: u32,
+    pub queries: u32,
+    /// Share of the relevant ids found in the top `k`.
+    pub recall: f64,
+    /// Mean reciprocal rank of the first relevant result, `0` when none is in the top `k`.
+    pub mrr: f64,
+    /// Normalized discounted cumulative gain with binary relevance.
+    pub ndcg: f64,
+    /// When the run finished, as reported by the server.
+    pub ran_at: String,
+}
+
+/// Stores `queries` as dataset `name`, replacing any dataset of that name.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if `queries` is empty or a query has no relevant ids.
+pub async fn register_dataset(pool: &PgPool, name: &str, queries: &[EvalQuery]) -> Result<()> {
+    if queries.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "dataset '{name}' has no queries"
+        )));
+    }
+    if let Some(position) = queries
+        .iter()
+        .position(|query| query.relevant_ids.is_empty())
+    {
+        return Err(Error::InvalidInput(format!(
+            "query {position} of dataset '{name}' has no relevant ids"
+        )));
+    }
+
+    let mut transaction = pool.begin().await?;
+    sqlx::query("DELETE FROM pgevdb.eval_datasets WHERE name = $1")
+        .bind(name)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query("INSERT INTO pgevdb.eval_datasets (name) VALUES ($1)")
+        .bind(name)
+        .execute(&mut *transaction)
+        .await?;
+    for (position, query) in (0_i32..).zip(queries) {
+        sqlx::query(
+            "INSERT INTO pgevdb.eval_queries (dataset, position, embedding, relevant_ids)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(name)
+        .bind(position)
+        .bind(&query.embedding)
+        .bind(&query.relevant_ids)
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Searches `collection` with every query of `dataset`, scores the top `k` results and records
+/// the run.
+///
+/// # Errors
+///
+/// Returns [`Error::NotFound`] if the dataset doesn't exist, and [`Error::InvalidInput`] if its
+/// queries don't match the collection's dimensions.
+pub async fn evaluate(collection: &Collection, dataset: &str, k: u32) -> Result<EvalReport> {
+    let pool = collection.pool();
+    if k == 0 {
+        return Err(Error::InvalidInput("k must be at least 1".to_string()));
+    }
+    let queries: Vec<(Vec<f32>, Vec<i64>)> = sqlx::query_as(
+        "SELECT embedding, relevant_ids FROM pgevdb.eval_queries WHERE dataset = $1
+         ORDER BY position",
+    )
+    .bind(dataset)
+    .fetch_all(pool)
+    .await?;
+    if queries.is_empty() {
+        return Err(Error::NotFound(format!("evaluation dataset '{dataset}'")));
+    }
+
+    let mut totals = Scores::default();
+    for (embedding, relevant_ids) in &queries {
+        let results = collection.search(embedding, k).await?;
+        let ranked: Vec<i64> = results.iter().map(|result| result.id).collect();
+        totals.add(&Scores::of(&ranked, relevant_ids, k as usize));
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let count = queries.len() as f64;
+
+    let (ran_at,): (String,) = sqlx::query_as(
+        "INSERT INTO pgevdb.eval_runs (dataset, collection, k, queries, recall, mrr, ndcg)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING ran_at::text",
+    )
+    .bind(dataset)
+    .bind(collection.name())
+    .bind(i64::from(k))
+    .bind(i64::try_from(queries.len()).unwrap_or(i64::MAX))
+    .bind(totals.recall / count)
+    .bind(totals.mrr / count)
+    .bind(totals.ndcg / count)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(EvalReport {
+        dataset: dataset.to_string(),
+        collection: collection.name().to_string(),
+        k,
+        queries: u32::try_from(queries.len()).unwrap_or(u32::MAX),
+        recall: totals.recall / count,
+        mrr: totals.mrr / count,
+        ndcg: totals.ndcg / count,
+        ran_at,
+    })
+}
+
+/// Earlier runs of `dataset`, oldest first.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the runs can't be read.
+pub async fn history(pool: &PgPool, dataset: &str) -> Result<Vec<EvalReport>> {
+    let rows: Vec<(String, i64, i64, f64, f64, f64, String)> = sqlx::query_as(
+        "SELECT collection, k, queries, recall, mrr, ndcg, ran_at::text FROM pgevdb.eval_runs
+         WHERE dataset = $1 ORDER BY ran_at",
+    )
+    .bind(dataset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(collection, k, queries, recall, mrr, ndcg, ran_at)| EvalReport {
+                dataset: dataset.to_string(),
+                collection,
+                k: u32::try_from(k).unwrap_or_default(),
+                queries: u32::try_from(queries).unwrap_or_default(),
+                recall,
+                mrr,
+                ndcg,
+                ran_at,
+            },
+        )
+        .collect())
+}
+
+/// Per-query scores, or their sums across queries.
+#[derive(Default)]
+struct Scores {
+    recall: f64,
+    mrr: f64,
+    ndcg: f64,
+}
+
+impl Scores {
+    /// Scores the top `k` ranked ids against the relevant ids, with binary relevance.
+    #[allow(clippy::cast_precision_loss)]
+    fn of(ranked: &[i64], relevant_ids: &[i64], k: usize) -> Self {
+        let relevant: HashSet<i64> = relevant_ids.iter().copied().collect();
+        let hits: Vec<usize> = ranked
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| relevant.contains(id))
+            .map(|(rank, _)| rank)
+            .collect();
+        // Rank is 0-based here, so position r is discounted by log2(r + 2)
+        let dcg: f64 = hits
+            .iter()
+            .map(|&rank| 1.0 / (rank as f64 + 2.0).log2())
+            .sum();
+        let ideal_dcg: f64 = (0..relevant.len().min(k))
+            .map(|rank| 1.0 / (rank as f64 + 2.0).log2())
+            .sum();
+
+        Self {
+            recall: hits.len() as f64 / relevant.len() as f64,
+            mrr: hits.first().map_or(0.0, |&rank| 1.0 / (rank as f64 + 1.0)),
+            ndcg: if ideal_dcg > 0.0 {
+                dcg / ideal_dcg
+            } else {
+                0.0
+            },
+        }
+    }
+
+    fn add(&mut self, other: &Scores) {
+        self.recall += other.recall;
+        self.mrr += other.mrr;
+        self.ndcg += other.ndcg;
+    }
+}
@@ -0,0 +1,895 @@
+//! Extensions installed, configured and created by the setup pipeline of
+//! [`PgEvDb::start`](crate::PgEvDb::start).
+//!
+//! The configured [vector backend](Backend) is always set up, followed by the
+//! [`ExtensionConfig`]s of the config file. Further extensions implement [`Extension`] and are
+//! added with [`PgEvDbBuilder::extension`](crate::PgEvDbBuilder::extension):
+//! [`Contrib`] covers those shipped with PostgreSQL, such as `pg_trgm`, and an implementation
+//! returning a [`Source::Download`] covers third-party ones such as `postgis` or `pg_cron`. All
+//! of them go through the same steps on every start, each skipped if already done: their files
+//! are installed, the server settings they need applied (restarting the server once if any
+//! requires it), and `CREATE EXTENSION` run.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+use serde::Deserialize;
+use sqlx::postgres::PgPool;
+use tempfile::TempDir;
+use tracing::info;
+
+use crate::config::{Backend, Config, ExtensionConfig};
+use crate::download::{cached, download, get_json, sha256_hex, verify_sha256};
+use crate::error::{Error, Result};
+use crate::platform::{Arch, Libc, Os, Platform};
+use crate::progress::Progress;
+use crate::source_build;
+
+/// Settings holding comma-separated lists, which extensions add entries to instead of replacing.
+pub(crate) const LIST_SETTINGS: &[&str] = &[
+    "shared_preload_libraries",
+    "session_preload_libraries",
+    "search_path",
+];
+
+/// A PostgreSQL extension and what it takes to install it.
+pub trait Extension: fmt::Debug + Send + Sync {
+    /// Name in `CREATE EXTENSION`, which is also the stem of its control file and SQL scripts.
+    fn name(&self) -> &str;
+
+    /// Shared library the extension loads, without the platform suffix, or `None` for extensions
+    /// written in SQL only. Its presence in the installation marks the extension as installed.
+    fn library(&self) -> Option<&str> {
+        Some(self.name())
+    }
+
+    /// Where the extension's files come from for `config` on `platform`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::UnsupportedPlatform`] if there is no build for `platform`.
+    fn source(&self, config: &Config, platform: Platform) -> Result<Source>;
+
+    /// Expected SHA-256 of the archive, in hex; an archive with another hash is refused.
+    fn sha256<'a>(&'a self, _config: &'a Config) -> Option<&'a str> {
+        None
+    }
+
+    /// Whether a file in the archive belongs in `share/extension`: by default the control file
+    /// and the `name--*` SQL scripts.
+    fn is_schema_file(&self, file_name: &str) -> bool {
+        let name = self.name();
+        file_name == format!("{name}.control") || file_name.starts_with(&format!("{name}--"))
+    }
+
+    /// Server settings the extension needs, applied with `ALTER SYSTEM`. Values of
+    /// `shared_preload_libraries`, `session_preload_libraries` and `search_path` are added to
+    /// the existing list rather than replacing it.
+    fn settings(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Statement that creates the extension in the database; must be a no-op if it exists.
+    fn create_statement(&self) -> String {
+        format!(
+            "CREATE EXTENSION IF NOT EXISTS \"{}\"",
+            self.name().replace('"', "\"\"")
+        )
+    }
+}
+
+/// Where an [`Extension`]'s files come from.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Source {
+    /// Shipped with PostgreSQL; there is nothing to install.
+    Included,
+    /// A zip archive of the library, control file and SQL scripts, downloaded into the cache
+    /// directory once.
+    Download(Artifact),
+    /// A local zip archive.
+    Archive(PathBuf),
+    /// A zip archive compiled into the binary.
+    Bundled(&'static [u8]),
+    /// Compiled by pgevdb at the configured release tag; only the vector backends support this.
+    SourceBuild,
+}
+
+impl Extension for ExtensionConfig {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The archive at `url` for the platform, or none without a URL.
+    fn source(&self, config: &Config, platform: Platform) -> Result<Source> {
+        let Some(template) = &self.url else {
+            return Ok(Source::Included);
+        };
+        let triple = target_triple(platform).ok_or_else(|| {
+            Error::UnsupportedPlatform(format!(
+                "{platform}: no target triple to look up {} builds by",
+                self.name
+            ))
+        })?;
+        let pg_major = config.pg_major();
+        Ok(Source::Download(Artifact {
+            url: expand_url(template, &self.version, pg_major, triple),
+            file_name: format!("{}-pg{pg_major}_{triple}_{}.zip", self.name, self.version),
+        }))
+    }
+
+    fn sha256<'a>(&'a self, _config: &'a Config) -> Option<&'a str> {
+        self.sha256.as_deref()
+    }
+
+    fn settings(&self) -> Vec<(String, String)> {
+        self.settings
+            .iter()
+            .map(|(setting, value)| (setting.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// A downloadable build of an extension for one platform.
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    pub url: String,
+    /// Release file name, which identifies the build wherever it's downloaded from.
+    pub file_name: String,
+}
+
+impl Extension for Backend {
+    fn name(&self) -> &str {
+        self.extension_name()
+    }
+
+    /// A configured `extension_archive`, else the archive bundled into the binary if it's the
+    /// configured version, else a download; when no prebuilt archive matches the platform and
+    /// `build_from_source` is enabled, a source build.
+    fn source(&self, config: &Config, platform: Platform) -> Result<Source> {
+        if let Some(path) = &config.extension_archive {
+            return Ok(Source::Archive(path.clone()));
+        }
+        if let Some(archive) = bundled_archive(config) {
+            info!("Using the extension archive bundled into this binary");
+            return Ok(Source::Bundled(archive));
+        }
+        match resolve_artifact(config, platform) {
+            Err(Error::UnsupportedPlatform(reason)) if config.build_from_source => {
+                info!("No prebuilt extension for {reason}; building from source");
+                Ok(Source::SourceBuild)
+            }
+            artifact => artifact.map(Source::Download),
+        }
+    }
+
+    fn sha256<'a>(&'a self, config: &'a Config) -> Option<&'a str> {
+        config.extension_sha256.as_deref()
+    }
+
+    /// pgvecto.rs must be preloaded and creates its type and operators in the `vectors` schema;
+    /// pgvector is loaded on first use and creates them in the current schema.
+    fn settings(&self) -> Vec<(String, String)> {
+        match self {
+            Backend::PgVectoRs => vec![
+                // PostgreSQL appends the platform's library suffix (.so, .dylib, .dll) itself,
+                // so the bare name works everywhere
+                (
+                    "shared_preload_libraries".to_string(),
+                    "vectors".to_string(),
+                ),
+                ("search_path".to_string(), "vectors".to_string()),
+            ],
+            Backend::PgVector => Vec::new(),
+        }
+    }
+}
+
+/// An extension shipped with PostgreSQL, such as `pg_trgm`, `hstore` or `pgcrypto`, which only
+/// needs creating.
+#[derive(Clone, Debug)]
+pub struct Contrib {
+    name: String,
+    settings: Vec<(String, String)>,
+}
+
+impl Contrib {
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            settings: Vec::new(),
+        }
+    }
+
+    /// Adds a server setting the extension needs, e.g. `shared_preload_libraries` for
+    /// `pg_stat_statements`.
+    #[must_use]
+    pub fn setting(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.settings.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl Extension for Contrib {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self, _config: &Config, _platform: Platform) -> Result<Source> {
+        Ok(Source::Included)
+    }
+
+    fn settings(&self) -> Vec<(String, String)> {
+        self.settings.clone()
+    }
+}
+
+/// Picks the release archive of the configured backend for `platform` and the configured
+/// PostgreSQL major version, at the configured `extension_url`.
+pub(crate) fn resolve_artifact(config: &Config, platform: Platform) -> Result<Artifact> {
+    let triple = match config.backend {
+        Backend::PgVectoRs => pgvecto_rs_triple(platform)?,
+        Backend::PgVector => mirror_triple(config, platform)?,
+    };
+
+    let version = &config.extension_version;
+    let pg_major = config.pg_major();
+    Ok(Artifact {
+        url: expand_url(config.extension_url(), version, pg_major, triple),
+        file_name: format!(
+            "{}-pg{pg_major}_{triple}_{version}.zip",
+            config.backend.extension_name()
+        ),
+    })
+}
+
+/// Target triple of the pgvecto.rs release build that runs on `platform`.
+fn pgvecto_rs_triple(platform: Platform) -> Result<&'static str> {
+    if platform.libc == Libc::Musl {
+        return Err(Error::UnsupportedPlatform(format!(
+            "{platform}: pgvecto.rs only publishes glibc builds, which fail to load on musl-based \
+             systems such as Alpine; pass --build-from-source or use a glibc-based image"
+        )));
+    }
+
+    match (platform.os, platform.arch) {
+        (Os::Linux, Arch::X86_64) => Ok("x86_64-unknown-linux-gnu"),
+        // Built for the armv8-a baseline, so it runs on Graviton and Raspberry Pi 3 and later
+        (Os::Linux, Arch::Aarch64) => Ok("aarch64-unknown-linux-gnu"),
+        (Os::Linux, Arch::Other("arm")) => Err(Error::UnsupportedPlatform(format!(
+            "{platform}: pgvecto.rs only publishes 64-bit ARM builds; install a 64-bit OS \
+             (e.g. the arm64 Raspberry Pi OS image)"
+        ))),
+        (Os::MacOS, Arch::Aarch64) => Ok("aarch64-apple-darwin"),
+        (Os::MacOS, Arch::X86_64) => Ok("x86_64-apple-darwin"),
+        (Os::Windows, _) => Err(Error::UnsupportedPlatform(format!(
+            "{platform}: pgvecto.rs doesn't publish Windows builds of its extension; pass \
+             --build-from-source to compile it locally"
+        ))),
+        _ => Err(Error::UnsupportedPlatform(format!(
+            "{platform}: no prebuilt pgvecto.rs extension; pass --build-from-source to compile \
+             it locally"
+        ))),
+    }
+}
+
+/// Target triple of `platform` in the archive names of an `extension_url` mirror, for backends
+/// without release builds of their own.
+fn mirror_triple(config: &Config, platform: Platform) -> Result<&'static str> {
+    let backend = config.backend.name();
+    if config.extension_url.is_none() {
+        return Err(Error::UnsupportedPlatform(format!(
+            "{platform}: {backend} doesn't publish prebuilt builds; set extension_url to a mirror \
+             of your own builds, pass --extension-archive, or pass --build-from-source"
+        )));
+    }
+
+    target_triple(platform).ok_or_else(|| {
+        Error::UnsupportedPlatform(format!(
+            "{platform}: no target triple to look up {backend} builds by; pass \
+             --build-from-source to compile it locally"
+        ))
+    })
+}
+
+/// Rust-style target triple of `platform`, by which mirrored archives are named.
+fn target_triple(platform: Platform) -> Option<&'static str> {
+    match (platform.os, platform.arch, platform.libc) {
+        (Os::Linux, Arch::X86_64, Libc::Musl) => Some("x86_64-unknown-linux-musl"),
+        (Os::Linux, Arch::X86_64, _) => Some("x86_64-unknown-linux-gnu"),
+        (Os::Linux, Arch::Aarch64, Libc::Musl) => Some("aarch64-unknown-linux-musl"),
+        (Os::Linux, Arch::Aarch64, _) => Some("aarch64-unknown-linux-gnu"),
+        (Os::MacOS, Arch::Aarch64, _) => Some("aarch64-apple-darwin"),
+        (Os::MacOS, Arch::X86_64, _) => Some("x86_64-apple-darwin"),
+        (Os::Windows, Arch::X86_64, _) => Some("x86_64-pc-windows-msvc"),
+        _ => None,
+    }
+}
+
+/// Most recent pgvecto.rs releases, newest first.
+const RELEASES_URL: &str =
+    "https://api.github.com/repos/tensorchord/pgvecto.rs/releases?per_page=100";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    draft: bool,
+    prerelease: bool,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+}
+
+/// Looks up the newest pgvecto.rs release that publishes a build for `platform` and the
+/// configured PostgreSQL major version, or with `build_from_source` on a platform without
+/// builds, the newest release. Drafts and prereleases are skipped.
+///
+/// # Errors
+///
+/// Returns [`Error::DownloadFailed`] if the release list can't be fetched, e.g. because the
+/// GitHub API rate limit is exhausted (set `github_token`), and [`Error::UnsupportedPlatform`]
+/// if no release has a matching build.
+pub(crate) async fn latest_version(config: &Config, platform: Platform) -> Result<String> {
+    let triple = match pgvecto_rs_triple(platform) {
+        Ok(triple) => Some(triple),
+        Err(Error::UnsupportedPlatform(_)) if config.build_from_source => None,
+        Err(error) => return Err(error),
+    };
+    let pg_major = config.pg_major();
+    let releases: Vec<Release> = get_json(RELEASES_URL, config).await?;
+    releases
+        .iter()
+        .filter(|release| !release.draft && !release.prerelease)
+        .filter_map(|release| {
+            let version = Version::parse(release.tag_name.trim_start_matches('v')).ok()?;
+            let has_build = triple.map_or(true, |triple| {
+                let file_name = format!("vectors-pg{pg_major}_{triple}_{version}.zip");
+                release.assets.iter().any(|asset| asset.name == file_name)
+            });
+            has_build.then_some(version)
+        })
+        .max()
+        .map(|version| version.to_string())
+        .ok_or_else(|| {
+            Error::UnsupportedPlatform(format!(
+                "{platform}: none of the recent pgvecto.rs releases has a build for PostgreSQL \
+                 {pg_major}; pin an extension_version"
+            ))
+        })
+}
+
+/// Fills the `{version}`, `{pg_major}` and `{triple}` placeholders of an `extension_url`.
+#[must_use]
+pub fn expand_url(template: &str, version: &str, pg_major: &str, triple: &str) -> String {
+    template
+        .replace("{version}", version)
+        .replace("{pg_major}", pg_major)
+        .replace("{triple}", triple)
+}
+
+/// Whether the library exists under the name `pg_version` loads, so a library installed under
+/// another name (by a release that got the macOS suffix wrong) is installed again. Extensions
+/// without a library are installed once their control file is.
+pub(crate) fn is_installed(extension: &dyn Extension, config: &Config, platform: Platform) -> bool {
+    let pg_dir = config.pg_dir();
+    match extension.library() {
+        Some(library) => pg_dir
+            .join("lib")
+            .join(platform.library_file_name(library, config.pg_major()))
+            .exists(),
+        None => pg_dir
+            .join("share")
+            .join("extension")
+            .join(format!("{}.control", extension.name()))
+            .exists(),
+    }
+}
+
+/// Fetches the files of `extension` from its [`Source`] and copies them into the PostgreSQL
+/// installation directory.
+pub(crate) async fn install(
+    extension: &dyn Extension,
+    config: &Config,
+    platform: Platform,
+) -> Result<()> {
+    let name = extension.name();
+    info!("Setting up the {name} extension");
+    let pg_dir = &config.pg_dir();
+    let copy_failed = |source| Error::ExtensionInstall {
+        message: format!("copying files into {}", pg_dir.display()),
+        source,
+    };
+
+    let mut archive_name = format!("bundled {name} archive");
+    let mut cache_entry = None;
+    let bytes = match extension.source(config, platform)? {
+        Source::Included => return Ok(()),
+        Source::Archive(path) => {
+            if let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) {
+                check_pg_major(file_name, config)?;
+            }
+            info!("Installing {name} from {}", path.display());
+            archive_name = path.display().to_string();
+            Cow::Owned(
+                std::fs::read(&path).map_err(|source| Error::ExtensionInstall {
+                    message: format!("reading {}", path.display()),
+                    source,
+                })?,
+            )
+        }
+        Source::Bundled(archive) => Cow::Borrowed(archive),
+        Source::SourceBuild => {
+            if name != config.backend.extension_name() {
+                return Err(Error::UnsupportedPlatform(format!(
+                    "{platform}: pgevdb can only build the vector extension from source, not {name}"
+                )));
+            }
+            let build_dir = source_build::build(config, platform).await?;
+            return copy_extension_files(extension, &build_dir, config, platform)
+                .map_err(copy_failed);
+        }
+        Source::Download(artifact) => {
+            check_pg_major(&artifact.file_name, config)?;
+            archive_name = artifact.file_name;
+            let cache_file = config.cache_dir().join("extensions").join(&archive_name);
+            if !cache_file.is_file() {
+                info!("Downloading {name} from {}", artifact.url);
+            }
+            let bytes = cached(
+                &cache_file,
+                download(&artifact.url, &format!("Downloading {name}"), config),
+            )
+            .await?;
+            cache_entry = Some(cache_file);
+            Cow::Owned(bytes)
+        }
+    };
+
+    if let Some(expected) = extension.sha256(config) {
+        if let Err(error) = verify_sha256(&archive_name, &bytes, expected) {
+            // Don't keep serving a bad archive from the cache
+            if let Some(cache_entry) = cache_entry {
+                let _ = std::fs::remove_file(cache_entry);
+            }
+            return Err(error);
+        }
+    } else {
+        info!("SHA-256 of {archive_name} is {}", sha256_hex(&bytes));
+    }
+
+    // Extract zip. The staging directory is deleted when `staging` drops, including on errors.
+    let staging = staging_dir(config)?;
+    let target = staging.path();
+    info!("Extracting zip to {}", target.display());
+    extract_zip(&bytes, target).map_err(|source| Error::ExtensionInstall {
+        message: format!("extracting archive to {}", target.display()),
+        source,
+    })?;
+
+    copy_extension_files(extension, target, config, platform).map_err(copy_failed)?;
+
+    info!("The {name} extension install is complete");
+
+    Ok(())
+}
+
+/// Refuses an archive whose release file name, like `vectors-pg16_<triple>_<version>.zip`, says
+/// it was built for another PostgreSQL major version than the configured one: its library would
+/// only fail once `CREATE EXTENSION` loads it. Names without a `-pgNN_` part aren't checked.
+pub(crate) fn check_pg_major(file_name: &str, config: &Config) -> Result<()> {
+    let archive_major = file_name
+        .split_once("-pg")
+        .and_then(|(_, rest)| rest.split_once('_'))
+        .map(|(major, _)| major)
+        .filter(|major| !major.is_empty() && major.bytes().all(|byte| byte.is_ascii_digit()));
+    match archive_major {
+        Some(major) if major != config.pg_major() => Err(Error::Config(format!(
+            "{file_name} is built for PostgreSQL {major}, but pg_version is {}; use the \
+             archive built for PostgreSQL {}",
+            config.pg_version,
+            config.pg_major()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Creates a uniquely named temporary directory under the configured staging dir, or the system
+/// temp directory.
+fn staging_dir(config: &Config) -> Result<TempDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("pgevdb-extension-");
+    let staging = match &config.staging_dir {
+        Some(parent) => {
+            std::fs::create_dir_all(parent)?;
+            builder.tempdir_in(parent)
+        }
+        None => builder.tempdir(),
+    };
+    staging.map_err(|source| Error::ExtensionInstall {
+        message: "creating a staging directory".to_string(),
+        source,
+    })
+}
+
+/// The pgvecto.rs archive compiled into the binary by the `bundled-extension` feature, if it's
+/// the requested backend and version and, as far as its file name told, PostgreSQL major version.
+#[cfg(feature = "bundled-extension")]
+fn bundled_archive(config: &Config) -> Option<&'static [u8]> {
+    let pg_major = env!("PGEVDB_BUNDLED_EXTENSION_PG_MAJOR");
+    (config.backend == Backend::PgVectoRs
+        && config.extension_version == env!("PGEVDB_BUNDLED_EXTENSION_VERSION")
+        && (pg_major.is_empty() || pg_major == config.pg_major()))
+    .then_some(include_bytes!(concat!(env!("OUT_DIR"), "/vectors.zip")).as_slice())
+}
+
+#[cfg(not(feature = "bundled-extension"))]
+fn bundled_archive(_config: &Config) -> Option<&'static [u8]> {
+    None
+}
+
+/// Copies the library, control file and SQL scripts from the extracted archive into `pg_dir`.
+fn copy_extension_files(
+    extension: &dyn Extension,
+    staging_dir: &Path,
+    config: &Config,
+    platform: Platform,
+) -> std::io::Result<()> {
+    // Get PostgreSQL directories
+    let pg_dir = config.pg_dir();
+    let pkglibdir = pg_dir.join("lib");
+    let extension_dir = pg_dir.join("share").join("extension");
+
+    let library = extension
+        .library()
+        .map(|library| {
+            let library_file = platform.library_file_name(library, config.pg_major());
+            find_library(staging_dir, library, &library_file).map(|path| (path, library_file))
+        })
+        .transpose()?;
+
+    info!("Copying schema files to {}", extension_dir.display());
+    // Copy all version-specific SQL files along with the control file
+    for entry in std::fs::read_dir(staging_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let is_schema_file = file_name
+            .to_str()
+            .is_some_and(|name| extension.is_schema_file(name));
+        if is_schema_file && entry.file_type()?.is_file() {
+            std::fs::copy(entry.path(), extension_dir.join(&file_name))?;
+        }
+    }
+
+    // The library goes last and is renamed into place, since its presence is what marks the
+    // extension as installed: an interrupted install is then redone on the next start. Archives
+    // built by pgrx don't always use the suffix PostgreSQL expects on the platform (macOS builds
+    // may ship `vectors.so`), so install under the expected name.
+    if let Some((library, library_file)) = library {
+        info!("Copying library to {}", pkglibdir.display());
+        let installed_library = pkglibdir.join(&library_file);
+        let partial_library = pkglibdir.join(format!("{library_file}.partial"));
+        std::fs::copy(library, &partial_library)?;
+        clear_quarantine(&partial_library);
+        std::fs::rename(partial_library, installed_library)?;
+    }
+
+    Ok(())
+}
+
+/// Locates the extension's shared library among the extracted files.
+fn find_library(staging_dir: &Path, name: &str, library_file: &str) -> std::io::Result<PathBuf> {
+    let candidates = [
+        library_file.to_string(),
+        format!("{name}.so"),
+        format!("{name}.dylib"),
+    ];
+    candidates
+        .iter()
+        .map(|candidate| staging_dir.join(candidate))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("archive doesn't contain {library_file}"),
+            )
+        })
+}
+
+/// Removes the macOS quarantine attribute, which stops `dlopen` from loading downloaded libraries.
+#[cfg(target_os = "macos")]
+fn clear_quarantine(path: &Path) {
+    // Fails harmlessly when the attribute isn't set
+    let _ = std::process::Command::new("xattr")
+        .args(["-d", "com.apple.quarantine"])
+        .arg(path)
+        .output();
+}
+
+#[cfg(not(target_os = "macos"))]
+fn clear_quarantine(_path: &Path) {}
+
+/// Extracts a zip archive into `target`, skipping entries that would escape it.
+fn extract_zip(bytes: &[u8], target: &Path) -> std::io::Result<()> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut progress = Progress::items("Extracting extension", Some(archive.len() as u64), "files");
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(relative_path) = entry.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+        let path = target.join(relative_path);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::io::copy(&mut entry, &mut File::create(&path)?)?;
+        }
+        progress.inc(1);
+    }
+    progress.finish();
+
+    Ok(())
+}
+
+/// What [`configure`] changed, and so what it takes for the change to apply.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Reconfigured {
+    Unchanged,
+    /// Settings were reloaded; only new sessions see them.
+    Reconnect,
+    /// A setting such as `shared_preload_libraries` is only read at server start.
+    Restart,
+}
+
+/// Applies the settings all `extensions` need with `ALTER SYSTEM`, adding to list settings
+/// while keeping their existing entries, and leaving settings that already have the value alone.
+///
+/// Changes to the same list from several extensions are merged, so the server needs at most one
+/// restart however many extensions it's given.
+pub(crate) async fn configure(
+    pool: &PgPool,
+    extensions: &[&dyn Extension],
+) -> Result<Reconfigured> {
+    let mut reconfigured = Reconfigured::Unchanged;
+    // Values set by earlier extensions in this pass, which the server doesn't report until the
+    // configuration is reloaded
+    let mut pending: HashMap<String, String> = HashMap::new();
+    for extension in extensions {
+        for (setting, value) in extension.settings() {
+            let current = match pending.get(&setting) {
+                Some(value) => value.clone(),
+                None => current_setting(pool, &setting).await?.unwrap_or_default(),
+            };
+            let is_list = LIST_SETTINGS.contains(&setting.as_str());
+            let new = if is_list {
+                if contains(&current, &value) {
+                    continue;
+                }
+                append(&current, &value)
+            } else if current == value {
+                continue;
+            } else {
+                value
+            };
+
+            info!("Setting {setting} for the {} extension", extension.name());
+            // SHOW returns search_path in the list syntax ALTER SYSTEM accepts, e.g.
+            // `"$user", public`; other settings are written as one string
+            let statement = if setting == "search_path" {
+                format!("ALTER SYSTEM SET search_path = {new}")
+            } else {
+                format!("ALTER SYSTEM SET {setting} = '{}'", new.replace('\'', "''"))
+            };
+            sqlx::query(&statement).execute(pool).await?;
+            reconfigured = reconfigured.max(if needs_restart(pool, &setting).await? {
+                Reconfigured::Restart
+            } else {
+                Reconfigured::Reconnect
+            });
+            pending.insert(setting, new);
+        }
+    }
+    if reconfigured == Reconfigured::Reconnect {
+        sqlx::query("SELECT pg_reload_conf()").execute(pool).await?;
+    }
+    Ok(reconfigured)
+}
+
+/// Value of `setting`, or `None` for a setting of a library that isn't loaded yet.
+async fn current_setting(pool: &PgPool, setting: &str) -> Result<Option<String>> {
+    let (value,): (Option<String>,) = sqlx::query_as("SELECT current_setting($1, true)")
+        .bind(setting)
+        .fetch_one(pool)
+        .await?;
+    Ok(value)
+}
+
+/// Whether `setting` is only read at server start, as are settings of libraries that aren't
+/// loaded yet, which are read when they're loaded.
+async fn needs_restart(pool: &PgPool, setting: &str) -> Result<bool> {
+    let context: Option<(String,)> =
+        sqlx::query_as("SELECT context FROM pg_settings WHERE name = $1")
+            .bind(setting)
+            .fetch_optional(pool)
+            .await?;
+    Ok(context.map_or(true, |(context,)| context == "postmaster"))
+}
+
+/// Whether a comma-separated setting lists `name`.
+fn contains(list: &str, name: &str) -> bool {
+    list.split(',')
+        .any(|entry| entry.trim().trim_matches('"') == name)
+}
+
+fn append(list: &str, name: &str) -> String {
+    if list.trim().is_empty() || list.trim() == "\"\"" {
+        name.to_string()
+    } else {
+        format!("{list}, {name}")
+    }
+}
+
+/// Creates `extension` in the database.
+pub(crate) async fn enable(pool: &PgPool, extension: &dyn Extension) -> Result<()> {
+    sqlx::query(&extension.create_statement())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The backend whose extension is created in the database; pgvecto.rs if there is none yet.
+pub(crate) async fn installed_backend(pool: &PgPool) -> Result<Backend> {
+    let names: Vec<(String,)> = sqlx::query_as("SELECT extname::text FROM pg_extension")
+        .fetch_all(pool)
+        .await?;
+    Ok(Backend::ALL
+        .into_iter()
+        .find(|backend| names.iter().any(|(name,)| name == backend.extension_name()))
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linux(arch: Arch, libc: Libc) -> Platform {
+        Platform {
+            os: Os::Linux,
+            arch,
+            libc,
+        }
+    }
+
+    fn unsupported(result: &Result<&str>) -> bool {
+        matches!(result, Err(Error::UnsupportedPlatform(_)))
+    }
+
+    #[test]
+    fn resolves_the_aarch64_release_build() -> Result<()> {
+        let platform = linux(Arch::Aarch64, Libc::Gnu);
+        assert_eq!(pgvecto_rs_triple(platform)?, "aarch64-unknown-linux-gnu");
+        let config = Config {
+            pg_version: "16.3.0".to_string(),
+            extension_version: "0.3.0".to_string(),
+            ..Config::default()
+        };
+        let artifact = resolve_artifact(&config, platform)?;
+        assert_eq!(
+            artifact.url,
+            "https://github.com/tensorchord/pgvecto.rs/releases/download/v0.3.0/\
+             vectors-pg16_aarch64-unknown-linux-gnu_0.3.0.zip"
+        );
+        assert_eq!(
+            artifact.file_name,
+            "vectors-pg16_aarch64-unknown-linux-gnu_0.3.0.zip"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn picks_the_baseline_aarch64_build_without_simd_extensions() -> Result<()> {
+        // The armv8-a build, with no suffix for a variant needing SVE or other extensions a
+        // Raspberry Pi lacks
+        let config = Config::default();
+        let artifact = resolve_artifact(&config, linux(Arch::Aarch64, Libc::Gnu))?;
+        assert_eq!(
+            artifact.file_name,
+            format!(
+                "vectors-pg{}_aarch64-unknown-linux-gnu_{}.zip",
+                config.pg_major(),
+                config.extension_version
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn names_macos_release_builds_by_apple_triple() -> Result<()> {
+        let config = Config {
+            pg_version: "16.3.0".to_string(),
+            extension_version: "0.3.0".to_string(),
+            ..Config::default()
+        };
+        for (arch, triple) in [
+            (Arch::Aarch64, "aarch64-apple-darwin"),
+            (Arch::X86_64, "x86_64-apple-darwin"),
+        ] {
+            let macos = Platform {
+                os: Os::MacOS,
+                arch,
+                libc: Libc::Gnu,
+            };
+            let artifact = resolve_artifact(&config, macos)?;
+            assert_eq!(
+                artifact.file_name,
+                format!("vectors-pg16_{triple}_0.3.0.zip")
+            );
+            assert_eq!(
+                artifact.url,
+                format!(
+                    "https://github.com/tensorchord/pgvecto.rs/releases/download/v0.3.0/\
+                     vectors-pg16_{triple}_0.3.0.zip"
+                )
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_32_bit_arm_and_musl_aarch64() {
+        let arm = linux(Arch::Other("arm"), Libc::Gnu);
+        let Err(Error::UnsupportedPlatform(message)) = pgvecto_rs_triple(arm) else {
+            panic!("32-bit ARM resolved");
+        };
+        assert!(message.contains("64-bit"));
+        assert!(unsupported(&pgvecto_rs_triple(linux(
+            Arch::Aarch64,
+            Libc::Musl
+        ))));
+        let mirrored = Config {
+            backend: Backend::PgVector,
+            extension_url: Some("https://mirror.example/{triple}.zip".to_string()),
+            ..Config::default()
+        };
+        assert!(unsupported(&mirror_triple(&mirrored, arm)));
+    }
+
+    #[test]
+    fn names_aarch64_mirror_builds_by_libc() -> Result<()> {
+        let config = Config {
+            backend: Backend::PgVector,
+            extension_url: Some("https://mirror.example/{triple}.zip".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(
+            mirror_triple(&config, linux(Arch::Aarch64, Libc::Gnu))?,
+            "aarch64-unknown-linux-gnu"
+        );
+        assert_eq!(
+            mirror_triple(&config, linux(Arch::Aarch64, Libc::Musl))?,
+            "aarch64-unknown-linux-musl"
+        );
+        // Without a mirror there's nothing to resolve
+        let unmirrored = Config {
+            backend: Backend::PgVector,
+            ..Config::default()
+        };
+        assert!(unsupported(&mirror_triple(
+            &unmirrored,
+            linux(Arch::Aarch64, Libc::Gnu)
+        )));
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+//! Conditions selecting which records of a collection an operation applies to.
+//!
+//! Besides ids and clusters, filters test the JSON [payload](crate::Collection::insert_with_payload)
+//! of records and combine with [`and`](Filter::and), [`or`](Filter::or) and `!`:
+//!
+//! ```
+//! use pgevdb::Filter;
+//!
+//! let recent_english = Filter::eq("lang", "en").and(Filter::gte("year", 2020));
+//! ```
+//!
+//! Keys and values are always bound as query parameters, never spliced into the SQL.
+//!
+//! Filters serialize as JSON with one key naming the variant, e.g.
+//! `{"and": [{"payload": {"key": "lang", "comparison": "eq", "value": "en"}}, {"ids": [1, 2]}]}`,
+//! which is also how the HTTP API of `pgevdb serve` takes them.
+
+use std::ops::Not;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Postgres, QueryBuilder};
+
+/// Records to include in a scan.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// Every record.
+    #[default]
+    All,
+    /// Records with one of these ids.
+    Ids(Vec<i64>),
+    /// Records assigned to this cluster by the last [`clustering::cluster`] run.
+    ///
+    /// [`clustering::cluster`]: crate::clustering::cluster
+    Cluster(u32),
+    /// Records whose payload has a top-level `key` comparing to `value` as `comparison` says.
+    Payload {
+        key: String,
+        comparison: Comparison,
+        value: Value,
+    },
+    /// Records whose expiry time has passed, see
+    /// [`Collection::set_ttl`](crate::Collection::set_ttl).
+    Expired,
+    /// Records matching every filter; none at all matches every record.
+    And(Vec<Filter>),
+    /// Records matching any of the filters; none at all matches no record.
+    Or(Vec<Filter>),
+    /// Records not matching the filter.
+    Not(Box<Filter>),
+}
+
+/// How a [`Filter::Payload`] compares a payload value with the filter's.
+///
+/// Only values of the same JSON type are compared: `gte("year", 2020)` skips records whose
+/// `year` is a string. Records without the key match no comparison, not even `Ne`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Comparison {
+    fn operator(self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "<>",
+            Comparison::Gt => ">",
+            Comparison::Gte => ">=",
+            Comparison::Lt => "<",
+            Comparison::Lte => "<=",
+        }
+    }
+}
+
+impl Filter {
+    /// Records whose payload's `key` equals `value`.
+    #[must_use]
+    pub fn eq(key: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::payload(key, Comparison::Eq, value)
+    }
+
+    /// Records whose payload has `key` with a value other than `value`.
+    #[must_use]
+    pub fn ne(key: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::payload(key, Comparison::Ne, value)
+    }
+
+    /// Records whose payload's `key` is greater than `value`.
+    #[must_use]
+    pub fn gt(key: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::payload(key, Comparison::Gt, value)
+    }
+
+    /// Records whose payload's `key` is at least `value`.
+    #[must_use]
+    pub fn gte(key: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::payload(key, Comparison::Gte, value)
+    }
+
+    /// Records whose payload's `key` is less than `value`.
+    #[must_use]
+    pub fn lt(key: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::payload(key, Comparison::Lt, value)
+    }
+
+    /// Records whose payload's `key` is at most `value`.
+    #[must_use]
+    pub fn lte(key: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::payload(key, Comparison::Lte, value)
+    }
+
+    fn payload(key: impl Into<String>, comparison: Comparison, value: impl Into<Value>) -> Self {
+        Filter::Payload {
+            key: key.into(),
+            comparison,
+            value: value.into(),
+        }
+    }
+
+    /// Records matching both this filter and `other`.
+    #[must_use]
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            filter => Filter::And(vec![filter, other]),
+        }
+    }
+
+    /// Records matching this filter, `other` or both.
+    #[must_use]
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            filter => Filter::Or(vec![filter, other]),
+        }
+    }
+
+    /// Appends a `WHERE` clause selecting the records matching this filter that aren't
+    /// [soft-deleted](crate::Collection::soft_delete), binding its values as parameters.
+    pub(crate) fn push_where(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push(" WHERE deleted_at IS NULL");
+        if *self != Filter::All {
+            builder.push(" AND ");
+            self.push_condition(builder);
+        }
+    }
+
+    /// Appends this filter as a boolean expression, binding its values as parameters.
+    pub(crate) fn push_condition(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Filter::All => {
+                builder.push("true");
+            }
+            Filter::Ids(ids) => {
+                builder.push("(id = ANY(").push_bind(ids.clone()).push("))");
+            }
+            Filter::Cluster(cluster) => {
+                builder
+                    .push("(cluster = ")
+                    .push_bind(i32::try_from(*cluster).unwrap_or(i32::MAX))
+                    .push(")");
+            }
+            Filter::Expired => {
+                builder.push("coalesce(expires_at <= now(), false)");
+            }
+            Filter::Payload {
+                key,
+                comparison: Comparison::Eq,
+                value,
+            } if !value.is_array() && !value.is_object() => {
+                // Containment can use the collection's GIN index on the payload. It's equality
+                // only for scalars: arrays and objects would also match larger ones, e.g.
+                // `["a"]` would match `["a", "b"]`
+                let mut object = serde_json::Map::new();
+                object.insert(key.clone(), value.clone());
+                builder
+                    .push("coalesce(payload @> ")
+                    .push_bind(Value::Object(object))
+                    .push(", false)");
+            }
+            Filter::Payload {
+                key,
+                comparison,
+                value,
+            } => {
+                builder
+                    .push("coalesce(jsonb_typeof(payload -> ")
+                    .push_bind(key.clone())
+                    .push(") = jsonb_typeof(")
+                    .push_bind(value.clone())
+                    .push(") AND payload -> ")
+                    .push_bind(key.clone())
+                    .push(format!(" {} ", comparison.operator()))
+                    .push_bind(value.clone())
+                    .push(", false)");
+            }
+            Filter::And(filters) | Filter::Or(filters) if filters.is_empty() => {
+                builder.push(if matches!(self, Filter::And(_)) {
+                    "true"
+                } else {
+                    "false"
+                });
+            }
+            Filter::And(filters) | Filter::Or(filters) => {
+                let separator = if matches!(self, Filter::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                builder.push("(");
+                for (position, filter) in filters.iter().enumerate() {
+                    if position > 0 {
+                        builder.push(separator);
+                    }
+                    filter.push_condition(builder);
+                }
+                builder.push(")");
+            }
+            Filter::Not(filter) => {
+                builder.push("(NOT ");
+                filter.push_condition(builder);
+                builder.push(")");
+            }
+        }
+    }
+}
+
+impl Not for Filter {
+    type Output = Filter;
+
+    /// Records not matching this filter.
+    fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sql(filter: &Filter) -> String {
+        let mut builder = QueryBuilder::new("");
+        filter.push_condition(&mut builder);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn eq_of_scalar_uses_containment() {
+        assert_eq!(
+            sql(&Filter::eq("lang", "en")),
+            "coalesce(payload @> $1, false)"
+        );
+    }
+
+    #[test]
+    fn eq_of_array_compares_whole_value() {
+        // Containment would also match {"tags": ["a", "b"]}
+        let filter = Filter::eq("tags", json!(["a"]));
+        assert_eq!(
+            sql(&filter),
+            "coalesce(jsonb_typeof(payload -> $1) = jsonb_typeof($2) AND payload -> $3 = $4, false)"
+        );
+        let filter = Filter::eq("author", json!({ "name": "Ada" }));
+        assert!(!sql(&filter).contains("@>"));
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::maintenance::{self, GcOptions, GcReport};
+use pgevdb::{Config, Result};
+
+impl Report for GcReport {
+    fn print_text(&self) {
+        for name in &self.dropped_collections {
+            println!("Dropped abandoned collection '{name}'");
+        }
+        for reclaimed in &self.reclaimed {
+            println!("{:>10} rows  {}", reclaimed.rows, reclaimed.target);
+        }
+        println!("Reclaimed {} rows", self.total_rows);
+    }
+}
+
+pub async fn run(
+    retention_days: u64,
+    batch_size: u32,
+    config: Config,
+    output: OutputFormat,
+) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+    let options = GcOptions {
+        retention: Duration::from_secs(retention_days.saturating_mul(86_400)),
+        batch_size,
+        ..GcOptions::default()
+    };
+    emit(
+        output,
+        &maintenance::collect_garbage(&pool, &options).await?,
+    )
+}
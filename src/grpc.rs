@@ -0,0 +1,1510 @@
+//! gRPC API, for typed clients in other languages and low-latency calls between services. Built
+//! with the `server` feature and run by `pgevdb serve --grpc ADDRESS`, alongside or instead of
+//! the [HTTP API](crate::http), whose operations it mirrors.
+//!
+//! The service is `pgevdb.v1.Vectors`, defined by `proto/pgevdb.proto` in the repository and
+//! [`PROTO`]; generate clients from it with `protoc` or any gRPC toolchain. `build.rs` generates
+//! the server's messages from the same file. Payloads and filters travel as JSON text in the
+//! forms the HTTP API takes.
+//!
+//! Failed calls end with a status for the [`Error`]: `INVALID_ARGUMENT` for invalid input,
+//! `NOT_FOUND` for missing collections and records, `RESOURCE_EXHAUSTED` for exceeded quotas
+//! and oversized messages, and `INTERNAL` otherwise. Calls with a `grpc-timeout` end with
+//! `DEADLINE_EXCEEDED` when it passes, cancelling their query.
+//!
+//! Messages may be compressed with gzip. Responses are compressed for clients that accept it,
+//! but for small messages, which it wouldn't shrink.
+//!
+//! When the [`ApiConfig`] has keys, every call must carry one in `authorization: Bearer KEY`
+//! or `x-api-key: KEY` metadata, or ends with `UNAUTHENTICATED`. Read-only keys end calls of
+//! the methods that write, `CreateCollection`, `DeleteCollection`, `InsertRecords` and
+//! `DeleteRecords`, with `PERMISSION_DENIED`.
+//!
+//! With a rate limit, calls of clients that exceed it end with `RESOURCE_EXHAUSTED`. Request
+//! messages of `InsertRecords` are limited to the configured
+//! [`max_insert_bytes`](ApiConfig::max_insert_bytes) and others to 1 MiB.
+//!
+//! The server also answers, to every client:
+//! - `grpc.health.v1.Health/Check`, the standard health check, with `SERVING` until it shuts
+//!   down;
+//! - server reflection, `grpc.reflection.v1.ServerReflection` and its `v1alpha` predecessor, so
+//!   tools like `grpcurl` can list and call its methods without the `.proto` files.
+
+use std::fmt::Write as _;
+use std::io::{Read, Write as _};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ::http::{HeaderMap, HeaderValue, Request, Response};
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use h2::server::SendResponse;
+use h2::RecvStream;
+use serde_json::Value;
+use sqlx::postgres::PgPool;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::auth::{Access, Denied, Guard};
+use crate::collection::{Record as StoredRecord, SearchResult as Hit};
+use crate::config::ApiConfig;
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::http::{self as api, CollectionInfo};
+use crate::protobuf::Message;
+
+use health::{HealthCheckRequest, HealthCheckResponse, ServingStatus, HEALTH_SERVICE};
+use reflection::{
+    ErrorResponse, FileDescriptorResponse, ListServiceResponse, ServerReflectionRequest,
+    ServerReflectionResponse, ServiceResponse, SERVER_REFLECTION_SERVICE,
+};
+use vectors::{
+    Collection, CreateCollectionRequest, DeleteCollectionRequest, DeleteCollectionResponse,
+    DeleteRecordsRequest, DeleteRecordsResponse, GetCollectionRequest, GetRecordRequest,
+    InsertRecordsRequest, InsertRecordsResponse, ListCollectionsRequest, ListCollectionsResponse,
+    NewRecord, Record, SearchRequest, SearchResponse, SearchResult, VECTORS_SERVICE,
+};
+
+/// `proto/pgevdb.proto`, the definition of the service.
+pub const PROTO: &str = include_str!("../proto/pgevdb.proto");
+
+// The messages of the files in proto/, generated by build.rs. Every message and field is
+// generated whether the server uses it or not, and comments are copied as they are.
+
+#[allow(dead_code, clippy::doc_markdown)]
+mod vectors {
+    include!(concat!(env!("OUT_DIR"), "/pgevdb.v1.rs"));
+}
+
+#[allow(dead_code, clippy::doc_markdown)]
+mod health {
+    include!(concat!(env!("OUT_DIR"), "/grpc.health.v1.rs"));
+}
+
+#[allow(dead_code, clippy::doc_markdown)]
+mod reflection {
+    include!(concat!(env!("OUT_DIR"), "/grpc.reflection.v1.rs"));
+}
+
+/// The reflection service before it was stable, which clients still ask for first.
+const SERVER_REFLECTION_V1ALPHA_SERVICE: &str = "grpc.reflection.v1alpha.ServerReflection";
+/// Largest request message accepted but for `InsertRecords`, as for bodies of the HTTP API.
+const MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+/// Smallest response message compressed; gzip's header and trailer outweigh what it would save
+/// on smaller ones.
+const MIN_COMPRESSED_BYTES: usize = 1024;
+/// How long a new connection may take to start HTTP/2.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Methods that change the database, refused to read-only keys.
+const WRITE_METHODS: &[&str] = &[
+    "CreateCollection",
+    "DeleteCollection",
+    "InsertRecords",
+    "DeleteRecords",
+];
+
+/// The files server reflection describes, with their encoded descriptors and the symbols they
+/// define.
+const FILES: [(&str, &[u8], &[&str]); 2] = [
+    (
+        vectors::FILE_NAME,
+        vectors::FILE_DESCRIPTOR,
+        vectors::SYMBOLS,
+    ),
+    (health::FILE_NAME, health::FILE_DESCRIPTOR, health::SYMBOLS),
+];
+
+/// Serves the API of the database `pool` connects to on `listener` to the clients `api`
+/// allows until `cancel` is cancelled, then lets calls in progress finish and returns how many
+/// were served.
+///
+/// # Errors
+///
+/// Doesn't fail once listening: failed calls end with an error status, and failures to accept
+/// a connection are logged and retried.
+pub async fn serve(
+    pool: &PgPool,
+    listener: TcpListener,
+    api: &ApiConfig,
+    cancel: CancellationToken,
+) -> Result<u64> {
+    let guard = Arc::new(Guard::new(api));
+    let served = Arc::new(AtomicU64::new(0));
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, address)) => {
+                    connections.spawn(connection(
+                        stream,
+                        address.ip(),
+                        pool.clone(),
+                        Arc::clone(&guard),
+                        Arc::clone(&served),
+                        cancel.clone(),
+                    ));
+                }
+                Err(error) => {
+                    warn!("Failed to accept a connection: {error}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            Some(_) = connections.join_next() => {}
+            () = cancel.cancelled() => break,
+        }
+    }
+    while connections.join_next().await.is_some() {}
+    Ok(served.load(Ordering::Relaxed))
+}
+
+/// Serves the calls of one HTTP/2 connection, each in a task of its own, until the client
+/// closes it or the server shuts down.
+async fn connection(
+    stream: TcpStream,
+    address: IpAddr,
+    pool: PgPool,
+    guard: Arc<Guard>,
+    served: Arc<AtomicU64>,
+    cancel: CancellationToken,
+) {
+    let _ = stream.set_nodelay(true);
+    let mut connection =
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, h2::server::handshake(stream)).await {
+            Ok(Ok(connection)) => connection,
+            Ok(Err(error)) => {
+                debug!("HTTP/2 handshake failed: {error}");
+                return;
+            }
+            Err(_) => return,
+        };
+    let mut shutting_down = false;
+    loop {
+        // Accepting also drives the connection, so it continues after the shutdown notice
+        // until the calls in progress are answered
+        let accepted = tokio::select! {
+            accepted = connection.accept() => accepted,
+            () = cancel.cancelled(), if !shutting_down => {
+                connection.graceful_shutdown();
+                shutting_down = true;
+                continue;
+            }
+        };
+        match accepted {
+            Some(Ok((request, respond))) => {
+                let pool = pool.clone();
+                let guard = Arc::clone(&guard);
+                let served = Arc::clone(&served);
+                let serving = !cancel.is_cancelled();
+                tokio::spawn(async move {
+                    call(&pool, &guard, address, serving, request, respond).await;
+                    served.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            Some(Err(error)) => {
+                debug!("Connection failed: {error}");
+                return;
+            }
+            None => return,
+        }
+    }
+}
+
+/// The outcome of a failed call.
+struct Status {
+    code: u16,
+    message: String,
+}
+
+impl Status {
+    const INVALID_ARGUMENT: u16 = 3;
+    const DEADLINE_EXCEEDED: u16 = 4;
+    const NOT_FOUND: u16 = 5;
+    const PERMISSION_DENIED: u16 = 7;
+    const RESOURCE_EXHAUSTED: u16 = 8;
+    const UNIMPLEMENTED: u16 = 12;
+    const INTERNAL: u16 = 13;
+    const UNAUTHENTICATED: u16 = 16;
+
+    fn new(code: u16, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        let code = match error {
+            Error::InvalidInput(_) => Status::INVALID_ARGUMENT,
+            Error::NotFound(_) => Status::NOT_FOUND,
+            Error::QuotaExceeded { .. } => Status::RESOURCE_EXHAUSTED,
+            _ => Status::INTERNAL,
+        };
+        if code == Status::INTERNAL {
+            warn!("Call failed: {error}");
+        }
+        Status::new(code, error.to_string())
+    }
+}
+
+impl From<Denied> for Status {
+    fn from(denied: Denied) -> Self {
+        let code = match denied {
+            Denied::Unauthenticated => Status::UNAUTHENTICATED,
+            Denied::Forbidden { .. } => Status::PERMISSION_DENIED,
+            Denied::RateLimited { .. } => Status::RESOURCE_EXHAUSTED,
+        };
+        Status::new(code, denied.message())
+    }
+}
+
+/// How the messages of a call are compressed, as its `grpc-encoding` header says.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    Identity,
+    Gzip,
+}
+
+impl Encoding {
+    /// The encoding of the request messages of a call with `headers`.
+    fn of_request(headers: &HeaderMap) -> Result<Self, Status> {
+        match headers.get("grpc-encoding").map(HeaderValue::as_bytes) {
+            None | Some(b"identity") => Ok(Self::Identity),
+            Some(b"gzip") => Ok(Self::Gzip),
+            Some(other) => Err(Status::new(
+                Status::UNIMPLEMENTED,
+                format!(
+                    "messages compressed with {} aren't supported; use gzip",
+                    String::from_utf8_lossy(other)
+                ),
+            )),
+        }
+    }
+
+    /// The encoding to answer a call with `headers` with: gzip if the client accepts it.
+    fn of_response(headers: &HeaderMap) -> Self {
+        let accepts_gzip = headers
+            .get_all("grpc-accept-encoding")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|encoding| encoding.trim() == "gzip");
+        if accepts_gzip {
+            Self::Gzip
+        } else {
+            Self::Identity
+        }
+    }
+}
+
+/// Answers one call from `address`, with the response messages or a status. The health
+/// service reports `serving`.
+async fn call(
+    pool: &PgPool,
+    guard: &Guard,
+    address: IpAddr,
+    serving: bool,
+    request: Request<RecvStream>,
+    mut respond: SendResponse<Bytes>,
+) {
+    let started = Instant::now();
+    let path = request.uri().path().to_string();
+    let response_encoding = Encoding::of_response(request.headers());
+    let (service, method) = split_path(&path);
+    let reflection = method == "ServerReflectionInfo"
+        && [SERVER_REFLECTION_SERVICE, SERVER_REFLECTION_V1ALPHA_SERVICE].contains(&service);
+    let result = match Encoding::of_request(request.headers()) {
+        Ok(encoding) if reflection => {
+            let body = request.into_body();
+            if let Err(error) =
+                stream_reflection(body, &mut respond, encoding, response_encoding).await
+            {
+                debug!("Failed to answer {path}: {error}");
+            }
+            debug!("{path} -> streamed in {:?}", started.elapsed());
+            return;
+        }
+        Ok(encoding) => answer(pool, guard, address, serving, request, encoding).await,
+        Err(status) => Err(status),
+    };
+    let code = result.as_ref().map_or_else(|status| status.code, |_| 0);
+    debug!("{path} -> {code} in {:?}", started.elapsed());
+    if let Err(error) = write_result(&mut respond, result, response_encoding) {
+        debug!("Failed to answer {path}: {error}");
+    }
+}
+
+/// The service and method of a call's path, `/SERVICE/METHOD`.
+fn split_path(path: &str) -> (&str, &str) {
+    path.strip_prefix('/')
+        .and_then(|path| path.split_once('/'))
+        .unwrap_or((path, ""))
+}
+
+/// Runs a unary call if the guard admits it, within its `grpc-timeout`.
+async fn answer(
+    pool: &PgPool,
+    guard: &Guard,
+    address: IpAddr,
+    serving: bool,
+    request: Request<RecvStream>,
+    encoding: Encoding,
+) -> Result<Vec<u8>, Status> {
+    let deadline = timeout(request.headers())?;
+    let path = request.uri().path().to_string();
+    let (service, method) = split_path(&path);
+    let run = async {
+        match service {
+            VECTORS_SERVICE => {
+                admit(guard, address, request.headers(), method)?;
+                let limit = if method == "InsertRecords" {
+                    guard.max_insert_bytes
+                } else {
+                    MAX_MESSAGE_BYTES
+                };
+                let message = read_message(request.into_body(), limit, encoding).await?;
+                dispatch(pool, method, &message).await
+            }
+            HEALTH_SERVICE => {
+                let message =
+                    read_message(request.into_body(), MAX_MESSAGE_BYTES, encoding).await?;
+                check_health(method, &message, serving)
+            }
+            _ => Err(Status::new(
+                Status::UNIMPLEMENTED,
+                format!("no such service: {service}"),
+            )),
+        }
+    };
+    match deadline {
+        // Dropping the call when it passes cancels its query
+        Some(deadline) => tokio::time::timeout(deadline, run)
+            .await
+            .unwrap_or_else(|_| Err(Status::new(Status::DEADLINE_EXCEEDED, "deadline exceeded"))),
+        None => run.await,
+    }
+}
+
+/// How long a call with `headers` may take, from its `grpc-timeout` header: up to 8 digits and
+/// a unit, `H`, `M`, `S`, `m`, `u` or `n` for hours down to nanoseconds.
+fn timeout(headers: &HeaderMap) -> Result<Option<Duration>, Status> {
+    let Some(value) = headers.get("grpc-timeout") else {
+        return Ok(None);
+    };
+    let invalid = || Status::new(Status::INVALID_ARGUMENT, "invalid grpc-timeout header");
+    let value = value.to_str().map_err(|_| invalid())?;
+    let Some((amount, unit)) = value
+        .char_indices()
+        .last()
+        .map(|(position, _)| value.split_at(position))
+    else {
+        return Err(invalid());
+    };
+    if amount.is_empty() || amount.len() > 8 || !amount.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return Err(invalid()),
+    };
+    Ok(Some(timeout))
+}
+
+/// Checks the key in the metadata `headers` of a call of `method` from `address`, and the rate
+/// limit.
+fn admit(guard: &Guard, address: IpAddr, headers: &HeaderMap, method: &str) -> Result<(), Status> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let access = if WRITE_METHODS.contains(&method) {
+        Access::Write
+    } else {
+        Access::Read
+    };
+    if let Some(name) = guard.admit(
+        address,
+        header("authorization"),
+        header("x-api-key"),
+        access,
+    )? {
+        debug!("{method} with key '{name}'");
+    }
+    Ok(())
+}
+
+/// Reads the single request message of a unary call, of up to `limit` bytes.
+async fn read_message(
+    mut body: RecvStream,
+    limit: usize,
+    encoding: Encoding,
+) -> Result<Vec<u8>, Status> {
+    let mut buffer = Vec::new();
+    let Some(message) = next_message(&mut body, &mut buffer, limit, encoding).await? else {
+        return Err(Status::new(
+            Status::INVALID_ARGUMENT,
+            "missing request message",
+        ));
+    };
+    if next_message(&mut body, &mut buffer, limit, encoding)
+        .await?
+        .is_some()
+    {
+        return Err(Status::new(
+            Status::INVALID_ARGUMENT,
+            "unary calls take one request message",
+        ));
+    }
+    Ok(message)
+}
+
+/// Reads the next request message of `body` of up to `limit` bytes, keeping what arrived
+/// after it in `buffer`, or returns `None` at the end of the requests.
+async fn next_message(
+    body: &mut RecvStream,
+    buffer: &mut Vec<u8>,
+    limit: usize,
+    encoding: Encoding,
+) -> Result<Option<Vec<u8>>, Status> {
+    loop {
+        if let Some(length) = frame_length(buffer, limit)? {
+            let message = unframe(&buffer[..length], encoding, limit)?;
+            buffer.drain(..length);
+            return Ok(Some(message));
+        }
+        match body.data().await {
+            Some(chunk) => {
+                let chunk = chunk.map_err(|error| {
+                    Status::new(Status::INTERNAL, format!("request failed: {error}"))
+                })?;
+                let _ = body.flow_control().release_capacity(chunk.len());
+                buffer.extend_from_slice(&chunk);
+            }
+            None if buffer.is_empty() => return Ok(None),
+            None => {
+                return Err(Status::new(
+                    Status::INVALID_ARGUMENT,
+                    "request ends within a message",
+                ))
+            }
+        }
+    }
+}
+
+/// The length of the gRPC frame at the start of `bytes`, or `None` if it hasn't all arrived.
+/// A frame is a compression flag, the message's length as 4 big-endian bytes, then the
+/// message, of up to `limit` bytes.
+fn frame_length(bytes: &[u8], limit: usize) -> Result<Option<usize>, Status> {
+    let Some(&[_, a, b, c, d]) = bytes.get(..5) else {
+        return Ok(None);
+    };
+    let length = u32::from_be_bytes([a, b, c, d]) as usize;
+    if length > limit {
+        return Err(too_large(limit));
+    }
+    Ok((bytes.len() >= 5 + length).then_some(5 + length))
+}
+
+/// The message of a whole frame, decompressed, of up to `limit` bytes.
+fn unframe(frame: &[u8], encoding: Encoding, limit: usize) -> Result<Vec<u8>, Status> {
+    let message = &frame[5..];
+    match (frame[0], encoding) {
+        (0, _) => Ok(message.to_vec()),
+        (1, Encoding::Gzip) => {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(message)
+                .take(limit as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(|error| {
+                    Status::new(
+                        Status::INTERNAL,
+                        format!("failed to decompress a request message: {error}"),
+                    )
+                })?;
+            if decompressed.len() > limit {
+                return Err(too_large(limit));
+            }
+            Ok(decompressed)
+        }
+        (1, Encoding::Identity) => Err(Status::new(
+            Status::INTERNAL,
+            "compressed message without a grpc-encoding",
+        )),
+        (flag, _) => Err(Status::new(
+            Status::INVALID_ARGUMENT,
+            format!("invalid compression flag {flag}"),
+        )),
+    }
+}
+
+fn too_large(limit: usize) -> Status {
+    Status::new(
+        Status::RESOURCE_EXHAUSTED,
+        format!("request messages are limited to {limit} bytes"),
+    )
+}
+
+/// `message` in a frame as [`unframe`] reads it, compressed if the encoding is gzip and it's
+/// large enough to gain from it.
+fn frame(message: &[u8], encoding: Encoding) -> Vec<u8> {
+    let compressed = (encoding == Encoding::Gzip && message.len() >= MIN_COMPRESSED_BYTES)
+        .then(|| {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+            encoder
+                .write_all(message)
+                .and_then(|()| encoder.finish())
+                .ok()
+        })
+        .flatten();
+    let (flag, message) = match &compressed {
+        Some(compressed) => (1, compressed.as_slice()),
+        None => (0, message),
+    };
+    let mut framed = Vec::with_capacity(5 + message.len());
+    framed.push(flag);
+    framed.extend_from_slice(
+        &u32::try_from(message.len())
+            .unwrap_or(u32::MAX)
+            .to_be_bytes(),
+    );
+    framed.extend_from_slice(message);
+    framed
+}
+
+/// The response headers of a call whose messages have `encoding`.
+fn response_head(encoding: Encoding) -> Response<()> {
+    let mut response = Response::new(());
+    let headers = response.headers_mut();
+    headers.insert("content-type", HeaderValue::from_static("application/grpc"));
+    headers.insert("grpc-accept-encoding", HeaderValue::from_static("gzip"));
+    if encoding == Encoding::Gzip {
+        headers.insert("grpc-encoding", HeaderValue::from_static("gzip"));
+    }
+    response
+}
+
+/// Adds the `grpc-status` and `grpc-message` of a call's outcome to `headers`.
+fn insert_status(headers: &mut HeaderMap, status: Option<&Status>) {
+    let Some(status) = status else {
+        headers.insert("grpc-status", HeaderValue::from_static("0"));
+        return;
+    };
+    headers.insert("grpc-status", HeaderValue::from(status.code));
+    if let Ok(message) = HeaderValue::from_str(&percent_encode(&status.message)) {
+        headers.insert("grpc-message", message);
+    }
+}
+
+/// Sends the response headers, then the message and an OK status as trailers, or only the
+/// failure status.
+fn write_result(
+    respond: &mut SendResponse<Bytes>,
+    result: Result<Vec<u8>, Status>,
+    encoding: Encoding,
+) -> Result<(), h2::Error> {
+    match result {
+        Ok(message) => {
+            let mut stream = respond.send_response(response_head(encoding), false)?;
+            stream.send_data(Bytes::from(frame(&message, encoding)), false)?;
+            let mut trailers = HeaderMap::new();
+            insert_status(&mut trailers, None);
+            stream.send_trailers(trailers)
+        }
+        Err(status) => {
+            let mut response = response_head(Encoding::Identity);
+            insert_status(response.headers_mut(), Some(&status));
+            respond.send_response(response, true).map(|_| ())
+        }
+    }
+}
+
+/// Percent-encodes a status message as the `grpc-message` header requires.
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (0x20..0x7f).contains(&byte) && byte != b'%' {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Runs `method` of the vectors service on its decoded request, returning the encoded
+/// response.
+async fn dispatch(pool: &PgPool, method: &str, message: &[u8]) -> Result<Vec<u8>, Status> {
+    let response = match method {
+        "ListCollections" => {
+            ListCollectionsRequest::decode(message)?;
+            ListCollectionsResponse {
+                collections: api::list_collections(pool)
+                    .await?
+                    .iter()
+                    .map(Collection::from)
+                    .collect(),
+            }
+            .to_bytes()
+        }
+        "CreateCollection" => {
+            let request = CreateCollectionRequest::decode(message)?;
+            let info = api::create_collection(
+                pool,
+                api::CreateCollection {
+                    name: request.name,
+                    dimensions: request.dimensions,
+                    metric: parse_or_default(&request.metric)?,
+                    storage: parse_or_default(&request.storage)?,
+                },
+            )
+            .await?;
+            Collection::from(&info).to_bytes()
+        }
+        "GetCollection" => {
+            let request = GetCollectionRequest::decode(message)?;
+            Collection::from(&api::describe_collection(pool, &request.name).await?).to_bytes()
+        }
+        "DeleteCollection" => {
+            let request = DeleteCollectionRequest::decode(message)?;
+            api::delete_collection(pool, &request.name).await?;
+            DeleteCollectionResponse {}.to_bytes()
+        }
+        "InsertRecords" => {
+            let request = InsertRecordsRequest::decode(message)?;
+            let records = request
+                .records
+                .into_iter()
+                .map(NewRecord::into_http)
+                .collect::<Result<Vec<_>>>()?;
+            InsertRecordsResponse {
+                ids: api::insert_records(pool, &request.collection, &records).await?,
+            }
+            .to_bytes()
+        }
+        "GetRecord" => {
+            let request = GetRecordRequest::decode(message)?;
+            Record::from(api::get_record(pool, &request.collection, request.id).await?).to_bytes()
+        }
+        "DeleteRecords" => {
+            let request = DeleteRecordsRequest::decode(message)?;
+            DeleteRecordsResponse {
+                deleted: api::delete_records(pool, &request.collection, &request.ids).await?,
+            }
+            .to_bytes()
+        }
+        "Search" => {
+            let request = SearchRequest::decode(message)?;
+            let collection = request.collection.clone();
+            let results = api::search(pool, &collection, request.into_http()?).await?;
+            SearchResponse {
+                results: results.into_iter().map(SearchResult::from).collect(),
+            }
+            .to_bytes()
+        }
+        _ => {
+            return Err(Status::new(
+                Status::UNIMPLEMENTED,
+                format!("no such method: {method}"),
+            ))
+        }
+    };
+    Ok(response)
+}
+
+/// Runs `method` of the health service, which reports the server and its services as
+/// `serving` or not.
+fn check_health(method: &str, message: &[u8], serving: bool) -> Result<Vec<u8>, Status> {
+    if method != "Check" {
+        return Err(Status::new(
+            Status::UNIMPLEMENTED,
+            format!("no such method: {method}"),
+        ));
+    }
+    let request = HealthCheckRequest::decode(message)?;
+    if !["", VECTORS_SERVICE, HEALTH_SERVICE].contains(&request.service.as_str()) {
+        return Err(Status::new(
+            Status::NOT_FOUND,
+            format!("no such service: {}", request.service),
+        ));
+    }
+    let status = if serving {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    };
+    Ok(HealthCheckResponse {
+        status: status as i32,
+    }
+    .to_bytes())
+}
+
+/// Answers the reflection requests of `body` as they come, until the client ends them.
+async fn stream_reflection(
+    mut body: RecvStream,
+    respond: &mut SendResponse<Bytes>,
+    encoding: Encoding,
+    response_encoding: Encoding,
+) -> Result<(), h2::Error> {
+    let mut stream = respond.send_response(response_head(response_encoding), false)?;
+    let mut buffer = Vec::new();
+    let status = loop {
+        match next_message(&mut body, &mut buffer, MAX_MESSAGE_BYTES, encoding).await {
+            Ok(Some(message)) => match ServerReflectionRequest::decode(&message) {
+                Ok(request) => {
+                    let response = reflect(&request).to_bytes();
+                    stream.send_data(Bytes::from(frame(&response, response_encoding)), false)?;
+                }
+                Err(error) => break Some(Status::from(error)),
+            },
+            Ok(None) => break None,
+            Err(status) => break Some(status),
+        }
+    };
+    let mut trailers = HeaderMap::new();
+    insert_status(&mut trailers, status.as_ref());
+    stream.send_trailers(trailers)
+}
+
+/// Answers one reflection request: the descriptor of a file, by name or by a symbol it
+/// defines, or the names of the services.
+fn reflect(request: &ServerReflectionRequest) -> ServerReflectionResponse {
+    let mut response = ServerReflectionResponse {
+        valid_host: request.host.clone(),
+        original_request: Some(request.clone()),
+        ..ServerReflectionResponse::default()
+    };
+    let file = if let Some(name) = &request.file_by_filename {
+        FILES
+            .iter()
+            .find(|(file, ..)| file == name)
+            .ok_or_else(|| format!("no such file: {name}"))
+    } else if let Some(symbol) = &request.file_containing_symbol {
+        FILES
+            .iter()
+            .find(|(.., symbols)| symbols.contains(&symbol.as_str()))
+            .ok_or_else(|| format!("no such symbol: {symbol}"))
+    } else if request.list_services.is_some() {
+        let service = [VECTORS_SERVICE, HEALTH_SERVICE]
+            .into_iter()
+            .map(|name| ServiceResponse {
+                name: name.to_string(),
+            })
+            .collect();
+        response.list_services_response = Some(ListServiceResponse { service });
+        return response;
+    } else {
+        response.error_response = Some(ErrorResponse {
+            error_code: i32::from(Status::UNIMPLEMENTED),
+            error_message: "only files by name or symbol and the services are described"
+                .to_string(),
+        });
+        return response;
+    };
+    match file {
+        Ok((_, descriptor, _)) => {
+            response.file_descriptor_response = Some(FileDescriptorResponse {
+                file_descriptor_proto: vec![descriptor.to_vec()],
+            });
+        }
+        Err(error_message) => {
+            response.error_response = Some(ErrorResponse {
+                error_code: i32::from(Status::NOT_FOUND),
+                error_message,
+            });
+        }
+    }
+    response
+}
+
+/// The default for an empty string, the value parsed from it otherwise.
+fn parse_or_default<T: Default + std::str::FromStr<Err = Error>>(value: &str) -> Result<T> {
+    if value.is_empty() {
+        Ok(T::default())
+    } else {
+        value.parse()
+    }
+}
+
+fn parse_json(value: Option<&str>, what: &str) -> Result<Option<Value>> {
+    value
+        .map(|json| {
+            serde_json::from_str(json)
+                .map_err(|error| Error::InvalidInput(format!("invalid {what} JSON: {error}")))
+        })
+        .transpose()
+}
+
+impl From<&CollectionInfo> for Collection {
+    fn from(info: &CollectionInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            dimensions: info.dimensions,
+            metric: info.metric.name().to_string(),
+            storage: info.storage.name().to_string(),
+            namespace: info.namespace.clone(),
+            estimated_records: info.estimated_records,
+        }
+    }
+}
+
+impl NewRecord {
+    fn into_http(self) -> Result<api::NewRecord> {
+        Ok(api::NewRecord {
+            payload: parse_json(self.payload_json.as_deref(), "payload")?,
+            id: self.id,
+            embedding: self.embedding,
+            content: self.content,
+            source: self.source,
+            language: self.language,
+        })
+    }
+}
+
+impl From<StoredRecord> for Record {
+    fn from(record: StoredRecord) -> Self {
+        Self {
+            id: record.id,
+            embedding: record.embedding,
+            content: record.content,
+            source: record.source,
+            payload_json: record.payload.map(|payload| payload.to_string()),
+        }
+    }
+}
+
+impl SearchRequest {
+    fn into_http(self) -> Result<api::SearchRequest> {
+        let filter = match parse_json(self.filter_json.as_deref(), "filter")? {
+            Some(filter) => serde_json::from_value(filter)
+                .map_err(|error| Error::InvalidInput(format!("invalid filter: {error}")))?,
+            None => Filter::All,
+        };
+        Ok(api::SearchRequest {
+            vector: self.vector,
+            limit: (self.limit > 0).then_some(self.limit),
+            offset: self.offset,
+            filter,
+            max_distance: self.max_distance,
+            min_score: self.min_score,
+            ef_search: self.ef_search,
+            probes: self.probes,
+        })
+    }
+}
+
+impl From<Hit> for SearchResult {
+    fn from(hit: Hit) -> Self {
+        Self {
+            id: hit.id,
+            distance: hit.distance,
+            score: hit.score,
+            rerank_score: hit.rerank_score,
+            payload_json: hit.payload.map(|payload| payload.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use std::io;
+
+    use super::*;
+    use crate::config::{ApiKey, Scope};
+    use crate::protobuf::{Encoder, Field};
+
+    fn round_trip<M: Message + PartialEq + Debug>(message: &M) -> Result<()> {
+        assert_eq!(&M::decode(&message.to_bytes())?, message);
+        Ok(())
+    }
+
+    fn code<T>(result: Result<T, Status>) -> u16 {
+        result.map_or_else(|status| status.code, |_| 0)
+    }
+
+    fn collection() -> Collection {
+        Collection {
+            name: "docs".to_string(),
+            dimensions: 384,
+            metric: "cosine".to_string(),
+            storage: "f16".to_string(),
+            namespace: "default".to_string(),
+            estimated_records: Some(0),
+        }
+    }
+
+    fn new_record() -> NewRecord {
+        NewRecord {
+            id: Some(-1),
+            embedding: vec![0.5, -0.0, f32::MAX],
+            content: Some(String::new()),
+            source: Some("a.md".to_string()),
+            language: Some("french".to_string()),
+            payload_json: Some(r#"{"lang": "fr"}"#.to_string()),
+        }
+    }
+
+    fn search_result() -> SearchResult {
+        SearchResult {
+            id: 7,
+            distance: 0.25,
+            score: 0.75,
+            rerank_score: Some(0.0),
+            payload_json: None,
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|&(name, value)| {
+                (
+                    ::http::header::HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    fn gzip(message: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(message)?;
+        encoder.finish()
+    }
+
+    /// The top-level fields of a `google.protobuf.FileDescriptorProto`.
+    #[derive(Debug, Default)]
+    struct FileDescriptor {
+        name: String,
+        package: String,
+        messages: usize,
+        services: usize,
+        syntax: String,
+    }
+
+    impl Message for FileDescriptor {
+        fn merge_field(&mut self, number: u32, field: Field<'_>) -> Result<()> {
+            match number {
+                1 => self.name = field.string()?,
+                2 => self.package = field.string()?,
+                4 => self.messages += 1,
+                6 => self.services += 1,
+                12 => self.syntax = field.string()?,
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn encode(&self, _: &mut Encoder) {}
+    }
+
+    #[test]
+    fn collection_messages_round_trip() -> Result<()> {
+        round_trip(&collection())?;
+        round_trip(&Collection::default())?;
+        round_trip(&ListCollectionsRequest {})?;
+        round_trip(&ListCollectionsResponse {
+            collections: vec![collection(), Collection::default()],
+        })?;
+        round_trip(&CreateCollectionRequest {
+            name: "docs".to_string(),
+            dimensions: 3,
+            metric: "l2".to_string(),
+            storage: String::new(),
+        })?;
+        round_trip(&GetCollectionRequest {
+            name: "docs".to_string(),
+        })?;
+        round_trip(&DeleteCollectionResponse {})
+    }
+
+    #[test]
+    fn record_messages_round_trip() -> Result<()> {
+        round_trip(&new_record())?;
+        round_trip(&NewRecord::default())?;
+        round_trip(&InsertRecordsRequest {
+            collection: "docs".to_string(),
+            records: vec![new_record(), NewRecord::default()],
+        })?;
+        round_trip(&InsertRecordsResponse {
+            ids: vec![1, i64::MAX, -1],
+        })?;
+        round_trip(&GetRecordRequest {
+            collection: "docs".to_string(),
+            id: i64::MIN,
+        })?;
+        round_trip(&Record {
+            id: 3,
+            embedding: vec![1.0, 2.0],
+            content: Some("text".to_string()),
+            source: None,
+            payload_json: Some("null".to_string()),
+        })?;
+        round_trip(&DeleteRecordsRequest {
+            collection: "docs".to_string(),
+            ids: vec![3, 0, 300],
+        })?;
+        round_trip(&DeleteRecordsResponse { deleted: u64::MAX })
+    }
+
+    #[test]
+    fn search_messages_round_trip() -> Result<()> {
+        round_trip(&SearchRequest {
+            collection: "docs".to_string(),
+            vector: vec![0.1, 0.2, 0.3],
+            limit: 5,
+            offset: 10,
+            filter_json: Some(r#"{"ids": [1]}"#.to_string()),
+            max_distance: Some(0.0),
+            min_score: Some(0.5),
+            ef_search: Some(0),
+            probes: Some(u32::MAX),
+        })?;
+        round_trip(&SearchRequest::default())?;
+        round_trip(&search_result())?;
+        round_trip(&SearchResponse {
+            results: vec![search_result(), SearchResult::default()],
+        })
+    }
+
+    #[test]
+    fn reflection_messages_round_trip() -> Result<()> {
+        let request = ServerReflectionRequest {
+            host: "localhost".to_string(),
+            file_containing_symbol: Some("pgevdb.v1.Vectors".to_string()),
+            ..ServerReflectionRequest::default()
+        };
+        round_trip(&ServerReflectionResponse {
+            original_request: Some(request),
+            file_descriptor_response: Some(FileDescriptorResponse {
+                file_descriptor_proto: vec![Vec::new(), vec![1, 2]],
+            }),
+            error_response: Some(ErrorResponse {
+                error_code: -1,
+                error_message: "broken".to_string(),
+            }),
+            ..ServerReflectionResponse::default()
+        })
+    }
+
+    #[test]
+    fn encodes_as_protoc_does() {
+        let request = SearchRequest {
+            collection: "docs".to_string(),
+            vector: vec![1.0],
+            limit: 5,
+            ..SearchRequest::default()
+        };
+        assert_eq!(
+            request.to_bytes(),
+            [
+                0x0a, 4, b'd', b'o', b'c', b's', // collection
+                0x12, 4, 0x00, 0x00, 0x80, 0x3f, // vector, packed
+                0x18, 5, // limit
+            ]
+        );
+    }
+
+    #[test]
+    fn describes_the_files_it_serves() -> Result<()> {
+        let file = FileDescriptor::decode(vectors::FILE_DESCRIPTOR)?;
+        assert_eq!(file.name, "pgevdb.proto");
+        assert_eq!(file.package, "pgevdb.v1");
+        assert_eq!(file.messages, PROTO.matches("\nmessage ").count());
+        assert_eq!(file.services, 1);
+        assert_eq!(file.syntax, "proto3");
+        assert_eq!(FileDescriptor::decode(health::FILE_DESCRIPTOR)?.messages, 2);
+        for method in WRITE_METHODS {
+            assert!(vectors::VECTORS_METHODS.contains(method), "{method}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reads_search_requests_as_the_http_api_does() -> Result<()> {
+        let request = SearchRequest {
+            filter_json: Some(r#"{"ids": [1, 2]}"#.to_string()),
+            ..SearchRequest::default()
+        }
+        .into_http()?;
+        assert_eq!(request.limit, None);
+        assert_eq!(request.filter, Filter::Ids(vec![1, 2]));
+        let invalid = SearchRequest {
+            filter_json: Some("{".to_string()),
+            ..SearchRequest::default()
+        };
+        assert!(matches!(invalid.into_http(), Err(Error::InvalidInput(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn frames_and_unframes_messages() -> io::Result<()> {
+        let framed = frame(b"message", Encoding::Identity);
+        assert_eq!(framed[..5], [0, 0, 0, 0, 7]);
+        assert_eq!(frame_length(&framed, 7).ok(), Some(Some(framed.len())));
+        let unframed = unframe(&framed, Encoding::Identity, 7).ok();
+        assert_eq!(unframed.as_deref(), Some(&b"message"[..]));
+        assert_eq!(
+            frame_length(&frame(b"", Encoding::Identity), 0).ok(),
+            Some(Some(5))
+        );
+
+        // Small messages aren't worth compressing
+        assert_eq!(frame(b"message", Encoding::Gzip)[0], 0);
+        let large = vec![b'a'; MIN_COMPRESSED_BYTES];
+        let framed = frame(&large, Encoding::Gzip);
+        assert_eq!(framed[0], 1);
+        assert!(framed.len() < large.len());
+        let unframed = unframe(&framed, Encoding::Gzip, large.len()).ok();
+        assert_eq!(unframed, Some(large));
+
+        let mut compressed = vec![1, 0, 0, 0, 0];
+        compressed.extend(gzip(b"message")?);
+        compressed[4] = u8::try_from(compressed.len() - 5).unwrap_or(u8::MAX);
+        let unframed = unframe(&compressed, Encoding::Gzip, 7).ok();
+        assert_eq!(unframed.as_deref(), Some(&b"message"[..]));
+        Ok(())
+    }
+
+    #[test]
+    fn waits_for_whole_frames() {
+        assert_eq!(frame_length(b"", 10).ok(), Some(None));
+        assert_eq!(frame_length(&[0, 0, 0, 0], 10).ok(), Some(None));
+        assert_eq!(frame_length(&[0, 0, 0, 0, 2, 1], 10).ok(), Some(None));
+        // Bytes past the frame are the next one's
+        assert_eq!(frame_length(&[0, 0, 0, 0, 1, 1, 0], 10).ok(), Some(Some(6)));
+    }
+
+    #[test]
+    fn refuses_malformed_and_oversized_frames() -> io::Result<()> {
+        // Refused from the length alone, before the message arrives
+        assert_eq!(
+            code(frame_length(&[0, 0, 0, 0, 11], 10)),
+            Status::RESOURCE_EXHAUSTED
+        );
+        let bomb = gzip(&[0; 11])?;
+        let mut framed = vec![1, 0, 0, 0, u8::try_from(bomb.len()).unwrap_or(u8::MAX)];
+        framed.extend(&bomb);
+        assert_eq!(
+            code(unframe(&framed, Encoding::Gzip, 10)),
+            Status::RESOURCE_EXHAUSTED
+        );
+        assert_eq!(
+            code(unframe(&framed, Encoding::Identity, 100)),
+            Status::INTERNAL
+        );
+        assert_eq!(
+            code(unframe(&[1, 0, 0, 0, 1, 0], Encoding::Gzip, 10)),
+            Status::INTERNAL
+        );
+        assert_eq!(
+            code(unframe(&[2, 0, 0, 0, 0], Encoding::Identity, 10)),
+            Status::INVALID_ARGUMENT
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn negotiates_compression() {
+        assert_eq!(
+            Encoding::of_request(&HeaderMap::new()).ok(),
+            Some(Encoding::Identity)
+        );
+        let gzip = headers(&[("grpc-encoding", "gzip")]);
+        assert_eq!(Encoding::of_request(&gzip).ok(), Some(Encoding::Gzip));
+        let snappy = headers(&[("grpc-encoding", "snappy")]);
+        assert_eq!(code(Encoding::of_request(&snappy)), Status::UNIMPLEMENTED);
+
+        let accepts = headers(&[("grpc-accept-encoding", "identity, deflate,gzip")]);
+        assert_eq!(Encoding::of_response(&accepts), Encoding::Gzip);
+        let refuses = headers(&[("grpc-accept-encoding", "identity")]);
+        assert_eq!(Encoding::of_response(&refuses), Encoding::Identity);
+        assert_eq!(Encoding::of_response(&HeaderMap::new()), Encoding::Identity);
+    }
+
+    #[test]
+    fn reads_timeouts() {
+        let timeout = |value| timeout(&headers(&[("grpc-timeout", value)])).ok().flatten();
+        assert_eq!(timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(timeout("10S"), Some(Duration::from_secs(10)));
+        assert_eq!(timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(
+            timeout("99999999u"),
+            Some(Duration::from_micros(99_999_999))
+        );
+        assert_eq!(timeout("1n"), Some(Duration::from_nanos(1)));
+        assert_eq!(super::timeout(&HeaderMap::new()).ok(), Some(None));
+        for invalid in ["", "S", "10", "10s", "-1S", "123456789S", "1.5S"] {
+            let headers = headers(&[("grpc-timeout", invalid)]);
+            assert_eq!(
+                code(super::timeout(&headers)),
+                Status::INVALID_ARGUMENT,
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn checks_health() {
+        let check = |service: &str, serving| {
+            let request = HealthCheckRequest {
+                service: service.to_string(),
+            };
+            check_health("Check", &request.to_bytes(), serving)
+                .map(|response| HealthCheckResponse::decode(&response).map(|r| r.status))
+        };
+        let serving = ServingStatus::Serving as i32;
+        assert!(matches!(check("", true), Ok(Ok(status)) if status == serving));
+        assert!(matches!(check(VECTORS_SERVICE, true), Ok(Ok(status)) if status == serving));
+        let not_serving = ServingStatus::NotServing as i32;
+        assert!(matches!(check("", false), Ok(Ok(status)) if status == not_serving));
+        assert_eq!(code(check("other.Service", true)), Status::NOT_FOUND);
+        assert_eq!(
+            code(check_health("Watch", &[], true)),
+            Status::UNIMPLEMENTED
+        );
+    }
+
+    #[test]
+    fn reflects_files_and_services() -> Result<()> {
+        let reflect = |request| reflect(&request);
+        let by_symbol = |symbol: &str| {
+            reflect(ServerReflectionRequest {
+                file_containing_symbol: Some(symbol.to_string()),
+                ..ServerReflectionRequest::default()
+            })
+        };
+        let response = by_symbol("pgevdb.v1.Vectors.Search");
+        let Some(files) = response.file_descriptor_response else {
+            panic!("no file for the method: {:?}", response.error_response);
+        };
+        assert_eq!(files.file_descriptor_proto, [vectors::FILE_DESCRIPTOR]);
+        let Some(files) =
+            by_symbol("grpc.health.v1.HealthCheckResponse.ServingStatus").file_descriptor_response
+        else {
+            panic!("no file for the nested enum");
+        };
+        assert_eq!(
+            FileDescriptor::decode(&files.file_descriptor_proto[0])?.name,
+            "health.proto"
+        );
+        let Some(error) = by_symbol("pgevdb.v1.Missing").error_response else {
+            panic!("found a missing symbol");
+        };
+        assert_eq!(error.error_code, i32::from(Status::NOT_FOUND));
+
+        let request = ServerReflectionRequest {
+            host: "localhost".to_string(),
+            file_by_filename: Some("pgevdb.proto".to_string()),
+            ..ServerReflectionRequest::default()
+        };
+        let response = reflect(request.clone());
+        assert_eq!(response.valid_host, "localhost");
+        assert_eq!(response.original_request, Some(request));
+        assert!(response.file_descriptor_response.is_some());
+
+        let response = reflect(ServerReflectionRequest {
+            list_services: Some(String::new()),
+            ..ServerReflectionRequest::default()
+        });
+        let Some(list) = response.list_services_response else {
+            panic!("no services");
+        };
+        let names: Vec<&str> = list.service.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, [VECTORS_SERVICE, HEALTH_SERVICE]);
+
+        let Some(error) = reflect(ServerReflectionRequest::default()).error_response else {
+            panic!("answered an empty request");
+        };
+        assert_eq!(error.error_code, i32::from(Status::UNIMPLEMENTED));
+        Ok(())
+    }
+
+    /// What a call to a test server answered: its headers, its messages and its trailers.
+    struct Answer {
+        headers: HeaderMap,
+        messages: Vec<Vec<u8>>,
+        trailers: HeaderMap,
+    }
+
+    /// Makes a call of `path` with `headers` and request `frames` to a server with a pool that
+    /// never connects and an API key, without the key.
+    async fn call_server(path: &str, headers: HeaderMap, frames: Vec<u8>) -> io::Result<Answer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").map_err(io::Error::other)?;
+        let api = ApiConfig {
+            keys: vec![ApiKey {
+                name: "writer".to_string(),
+                key: "w".repeat(32),
+                scope: Scope::ReadWrite,
+            }],
+            ..ApiConfig::default()
+        };
+        let cancel = CancellationToken::new();
+        let server = tokio::spawn({
+            let cancel = cancel.clone();
+            async move { serve(&pool, listener, &api, cancel).await }
+        });
+
+        let stream = TcpStream::connect(address).await?;
+        let (mut client, connection) = h2::client::handshake(stream)
+            .await
+            .map_err(io::Error::other)?;
+        tokio::spawn(connection);
+        let mut request = Request::post(format!("http://{address}{path}"))
+            .header("content-type", "application/grpc")
+            .body(())
+            .map_err(io::Error::other)?;
+        request.headers_mut().extend(headers);
+        let (response, mut send) = client
+            .send_request(request, false)
+            .map_err(io::Error::other)?;
+        send.send_data(Bytes::from(frames), true)
+            .map_err(io::Error::other)?;
+        let response = response.await.map_err(io::Error::other)?;
+        let (parts, mut body) = response.into_parts();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.map_err(io::Error::other)?);
+        }
+        let trailers = body
+            .trailers()
+            .await
+            .map_err(io::Error::other)?
+            .unwrap_or_default();
+        cancel.cancel();
+        let _ = server.await;
+
+        let mut messages = Vec::new();
+        while let Ok(Some(length)) = frame_length(&bytes, usize::MAX) {
+            let Ok(message) = unframe(&bytes[..length], Encoding::Gzip, usize::MAX) else {
+                panic!("undecodable response message");
+            };
+            messages.push(message);
+            bytes.drain(..length);
+        }
+        Ok(Answer {
+            headers: parts.headers,
+            messages,
+            trailers,
+        })
+    }
+
+    #[tokio::test]
+    async fn answers_health_checks_without_a_key() -> Result<()> {
+        let request = HealthCheckRequest::default().to_bytes();
+        let mut compressed = vec![1];
+        let gzipped = gzip(&request)?;
+        compressed.extend(u32::try_from(gzipped.len()).unwrap_or(0).to_be_bytes());
+        compressed.extend(gzipped);
+        let headers = headers(&[
+            ("grpc-encoding", "gzip"),
+            ("grpc-accept-encoding", "gzip"),
+            ("grpc-timeout", "10S"),
+        ]);
+        let answer = call_server("/grpc.health.v1.Health/Check", headers, compressed).await?;
+        assert_eq!(answer.headers["grpc-encoding"], "gzip");
+        assert_eq!(answer.trailers["grpc-status"], "0");
+        let [message] = answer.messages.as_slice() else {
+            panic!("not one response message");
+        };
+        let response = HealthCheckResponse::decode(message)?;
+        assert_eq!(response.status, ServingStatus::Serving as i32);
+
+        // The vectors service needs the key
+        let framed = frame(&ListCollectionsRequest {}.to_bytes(), Encoding::Identity);
+        let path = "/pgevdb.v1.Vectors/ListCollections";
+        let answer = call_server(path, HeaderMap::new(), framed).await?;
+        assert_eq!(answer.headers["grpc-status"], "16");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streams_reflection_answers() -> Result<()> {
+        let mut frames = Vec::new();
+        for request in [
+            ServerReflectionRequest {
+                list_services: Some(String::new()),
+                ..ServerReflectionRequest::default()
+            },
+            ServerReflectionRequest {
+                file_containing_symbol: Some("pgevdb.v1.Vectors".to_string()),
+                ..ServerReflectionRequest::default()
+            },
+        ] {
+            frames.extend(frame(&request.to_bytes(), Encoding::Identity));
+        }
+        let path = "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo";
+        let answer = call_server(path, HeaderMap::new(), frames).await?;
+        assert_eq!(answer.trailers["grpc-status"], "0");
+        let [services, file] = answer.messages.as_slice() else {
+            panic!("not two response messages");
+        };
+        let services = ServerReflectionResponse::decode(services)?;
+        assert!(services.list_services_response.is_some());
+        let file = ServerReflectionResponse::decode(file)?;
+        assert!(file.file_descriptor_response.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_malformed_messages() {
+        // A string field holding invalid UTF-8
+        let error = GetCollectionRequest::decode(&[0x0a, 1, 0xff]);
+        assert!(matches!(error, Err(Error::InvalidInput(_))));
+        // A float cut short, then one sent as a varint
+        let error = SearchResult::decode(&[0x15, 1, 0, 0]);
+        assert!(matches!(error, Err(Error::InvalidInput(_))));
+        let error = SearchResult::decode(&[0x10, 1]);
+        assert!(matches!(error, Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn maps_errors_to_status_codes() {
+        let code = |error| Status::from(error).code;
+        assert_eq!(
+            code(Error::InvalidInput(String::new())),
+            Status::INVALID_ARGUMENT
+        );
+        assert_eq!(code(Error::NotFound(String::new())), Status::NOT_FOUND);
+        assert_eq!(
+            code(Error::Io(io::Error::other("broken"))),
+            Status::INTERNAL
+        );
+        let code = |denied| Status::from(denied).code;
+        assert_eq!(code(Denied::Unauthenticated), Status::UNAUTHENTICATED);
+        assert_eq!(
+            code(Denied::Forbidden {
+                name: "reader".to_string()
+            }),
+            Status::PERMISSION_DENIED
+        );
+        assert_eq!(
+            code(Denied::RateLimited {
+                retry_after: Duration::from_secs(1)
+            }),
+            Status::RESOURCE_EXHAUSTED
+        );
+    }
+
+    #[test]
+    fn percent_encodes_status_messages() {
+        assert_eq!(
+            percent_encode("collection 'dócs': 100%\n"),
+            "collection 'd%C3%B3cs': 100%25%0A"
+        );
+    }
+}
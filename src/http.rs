@@ -0,0 +1,1307 @@
+//! JSON API over HTTP, for services that don't speak the PostgreSQL wire protocol. Built with
+//! the `server` feature and run by `pgevdb serve --http ADDRESS`.
+//!
+//! | Method   | Path                                | Does                                      |
+//! |----------|-------------------------------------|-------------------------------------------|
+//! | `GET`    | `/health`                           | Reports that the server is up             |
+//! | `GET`    | `/openapi.json`                     | The [OpenAPI document](crate::openapi)    |
+//! | `GET`    | `/collections`                      | Lists collections                         |
+//! | `POST`   | `/collections`                      | Creates a collection                      |
+//! | `GET`    | `/collections/{name}`               | Describes a collection                    |
+//! | `DELETE` | `/collections/{name}`               | Deletes a collection and its records      |
+//! | `POST`   | `/collections/{name}/records`       | Inserts or replaces records               |
+//! | `GET`    | `/collections/{name}/records/{id}`  | Reads a record                            |
+//! | `DELETE` | `/collections/{name}/records/{id}`  | Deletes a record                          |
+//! | `POST`   | `/collections/{name}/search`        | Finds the records nearest to a vector     |
+//!
+//! Path parameters may be percent-encoded, and a trailing slash is ignored. Other methods on
+//! these paths are answered with `405` and an `Allow` header. Request and response bodies are
+//! JSON, e.g. for a search, which returns up to 1000 results and skips up to 10,000:
+//!
+//! ```text
+//! POST /collections/docs/search
+//! {"vector": [0.1, 0.2, 0.3], "limit": 5, "filter": {"payload": {"key": "lang", "comparison": "eq", "value": "en"}}}
+//!
+//! 200 OK
+//! {"results": [{"id": 42, "distance": 0.12, "score": 0.94, "payload": {"lang": "en"}}]}
+//! ```
+//!
+//! Failures answer with an `{"error": "..."}` body and a status for the [`Error`]: `400` for
+//! invalid input, `404` for missing collections and records, `403` for exceeded quotas and
+//! `500` otherwise.
+//!
+//! When the [`ApiConfig`] has keys, every request but those of `/health` and `/openapi.json`
+//! must carry one as `Authorization: Bearer KEY` or `X-API-Key: KEY`, or is answered with
+//! `401`. Read-only keys are refused with `403` on the endpoints that write, all but `GET`s
+//! and searches.
+//!
+//! With a rate limit, clients that exceed it are answered with `429` and a `Retry-After`
+//! header. Request bodies of bulk inserts are limited to the configured
+//! [`max_insert_bytes`](ApiConfig::max_insert_bytes) and others to 1 MiB; larger ones are
+//! refused with `413`, before they're read when they have a `Content-Length`.
+//!
+//! The server speaks HTTP/1.1 with keep-alive, and takes request bodies with a `Content-Length`
+//! or chunked; put a reverse proxy in front of it for TLS, HTTP/2 or exposure beyond a trusted
+//! network.
+
+use std::borrow::Cow;
+use std::io;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::{Method, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPool;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::auth::{Access, Denied, Guard};
+use crate::collection::{
+    Collection, Content, Exactness, Metric, NewRow, Record, SearchResult, Storage,
+};
+use crate::config::ApiConfig;
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::maintenance;
+use crate::openapi;
+use crate::search::SearchOptions;
+
+/// Largest request line and headers accepted, and largest chunk header or trailers.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+const MAX_HEADERS: usize = 64;
+/// Largest request body accepted but for bulk inserts, which have their own limit.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// How long a connection may stay silent, between requests or within one, before it's closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Results of a search that doesn't say.
+const DEFAULT_LIMIT: u32 = 10;
+/// Most results a search may ask for.
+pub(crate) const MAX_LIMIT: u32 = 1000;
+/// Most results a search may skip; deeper pages are better read with a filter.
+pub(crate) const MAX_OFFSET: u32 = 10_000;
+
+/// Serves the API of the database `pool` connects to on `listener` to the clients `api`
+/// allows until `cancel` is cancelled, then lets requests in progress finish and returns how
+/// many were served.
+///
+/// # Errors
+///
+/// Doesn't fail once listening: failed requests are answered with an error status, and
+/// failures to accept a connection are logged and retried.
+pub async fn serve(
+    pool: &PgPool,
+    listener: TcpListener,
+    api: &ApiConfig,
+    cancel: CancellationToken,
+) -> Result<u64> {
+    let guard = Arc::new(Guard::new(api));
+    let served = Arc::new(AtomicU64::new(0));
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, address)) => {
+                    connections.spawn(connection(
+                        stream,
+                        address.ip(),
+                        pool.clone(),
+                        Arc::clone(&guard),
+                        Arc::clone(&served),
+                        cancel.clone(),
+                    ));
+                }
+                Err(error) => {
+                    // E.g. out of file descriptors; pause rather than spin until some close
+                    warn!("Failed to accept a connection: {error}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            Some(_) = connections.join_next() => {}
+            () = cancel.cancelled() => break,
+        }
+    }
+    while connections.join_next().await.is_some() {}
+    Ok(served.load(Ordering::Relaxed))
+}
+
+/// The endpoints of the API, one per path; [`handle`] tells their methods apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Endpoint {
+    Health,
+    OpenApi,
+    Collections,
+    Collection,
+    Records,
+    Record,
+    Search,
+}
+
+impl Endpoint {
+    /// The methods the endpoint answers, for the `Allow` header of `405`s.
+    fn methods(self) -> &'static str {
+        match self {
+            Self::Health | Self::OpenApi => "GET",
+            Self::Collections => "GET, POST",
+            Self::Collection | Self::Record => "GET, DELETE",
+            Self::Records | Self::Search => "POST",
+        }
+    }
+}
+
+/// Routes of the endpoints, in the syntax of [`matchit`].
+const ROUTES: [(&str, Endpoint); 7] = [
+    ("/health", Endpoint::Health),
+    ("/openapi.json", Endpoint::OpenApi),
+    ("/collections", Endpoint::Collections),
+    ("/collections/{name}", Endpoint::Collection),
+    ("/collections/{name}/records", Endpoint::Records),
+    ("/collections/{name}/records/{id}", Endpoint::Record),
+    ("/collections/{name}/search", Endpoint::Search),
+];
+
+fn router() -> &'static matchit::Router<Endpoint> {
+    static ROUTER: OnceLock<matchit::Router<Endpoint>> = OnceLock::new();
+    ROUTER.get_or_init(|| {
+        let mut router = matchit::Router::new();
+        for (route, endpoint) in ROUTES {
+            // The routes are fixed and don't conflict
+            let _ = router.insert(route, endpoint);
+        }
+        router
+    })
+}
+
+/// The percent-decoded parameters of a path, empty for endpoints without them.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Params {
+    name: String,
+    id: String,
+}
+
+/// The endpoint at `path` and its parameters, or `None` if there's none.
+fn resolve(path: &str) -> Result<Option<(Endpoint, Params)>, Response> {
+    let trimmed = path.trim_end_matches('/');
+    let Ok(matched) = router().at(if trimmed.is_empty() { "/" } else { trimmed }) else {
+        return Ok(None);
+    };
+    // Decoded after matching, so an encoded slash stays within its parameter
+    let decode = |name| {
+        matched.params.get(name).map_or(Ok(String::new()), |value| {
+            percent_decode_str(value)
+                .decode_utf8()
+                .map(Cow::into_owned)
+                .map_err(|_| {
+                    Response::error(
+                        StatusCode::BAD_REQUEST,
+                        "the request path isn't UTF-8 once decoded",
+                    )
+                })
+        })
+    };
+    let params = Params {
+        name: decode("name")?,
+        id: decode("id")?,
+    };
+    Ok(Some((*matched.value, params)))
+}
+
+/// A parsed request.
+struct Request {
+    method: Method,
+    /// Without the query string, which the API doesn't use.
+    path: String,
+    /// The endpoint at `path`, or `None` if there's none.
+    route: Option<(Endpoint, Params)>,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    keep_alive: bool,
+}
+
+impl Request {
+    /// The value of the first header called `name`, if it's text.
+    fn header(&self, name: impl header::AsHeaderName) -> Option<&str> {
+        self.headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        self.route.as_ref().map(|(endpoint, _)| *endpoint)
+    }
+}
+
+/// A response with a JSON body, or none for `204`.
+struct Response {
+    status: StatusCode,
+    /// Headers besides those describing the body and connection.
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Option<Value>,
+}
+
+impl Response {
+    fn ok(body: impl Serialize) -> Result<Self> {
+        Ok(Self {
+            status: StatusCode::OK,
+            headers: Vec::new(),
+            body: Some(serde_json::to_value(body)?),
+        })
+    }
+
+    fn created(body: impl Serialize) -> Result<Self> {
+        Ok(Self {
+            status: StatusCode::CREATED,
+            ..Self::ok(body)?
+        })
+    }
+
+    fn no_content() -> Self {
+        Self {
+            status: StatusCode::NO_CONTENT,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    fn error(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Some(json!({ "error": message.into() })),
+        }
+    }
+
+    fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    fn from_denied(denied: &Denied) -> Self {
+        match denied {
+            Denied::Unauthenticated => Self::error(StatusCode::UNAUTHORIZED, denied.message())
+                .with_header(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer")),
+            Denied::Forbidden { .. } => Self::error(StatusCode::FORBIDDEN, denied.message()),
+            Denied::RateLimited { retry_after } => {
+                // Whole seconds, rounded up so the retry isn't limited again
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                Self::error(StatusCode::TOO_MANY_REQUESTS, denied.message())
+                    .with_header(header::RETRY_AFTER, HeaderValue::from(seconds))
+            }
+        }
+    }
+
+    fn from_error(error: &Error) -> Self {
+        let status = match error {
+            Error::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            Error::QuotaExceeded { .. } => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        if status.is_server_error() {
+            warn!("Request failed: {error}");
+        }
+        Self::error(status, error.to_string())
+    }
+}
+
+/// What reading the next request of a connection gave.
+enum Incoming {
+    Request(Request),
+    /// The client closed the connection between requests, or within one.
+    Closed,
+    /// The request can't be served; the response explains why and the connection closes.
+    Rejected(Response),
+}
+
+/// Serves the requests of one connection until the client closes it, it idles for
+/// [`IDLE_TIMEOUT`] or the server shuts down.
+async fn connection(
+    mut stream: TcpStream,
+    address: IpAddr,
+    pool: PgPool,
+    guard: Arc<Guard>,
+    served: Arc<AtomicU64>,
+    cancel: CancellationToken,
+) {
+    // Responses are written whole, so Nagle's algorithm would only add latency
+    let _ = stream.set_nodelay(true);
+    let mut buffer = Vec::new();
+    loop {
+        let incoming = tokio::select! {
+            incoming = tokio::time::timeout(
+                IDLE_TIMEOUT,
+                read_request(&mut stream, &mut buffer, guard.max_insert_bytes),
+            ) => incoming,
+            () = cancel.cancelled() => return,
+        };
+        let (response, keep_alive) = match incoming {
+            Ok(Ok(Incoming::Request(request))) => {
+                let started = Instant::now();
+                let response = route(&pool, &guard, address, &request).await;
+                debug!(
+                    "{} {} -> {} in {:?}",
+                    request.method,
+                    request.path,
+                    response.status,
+                    started.elapsed()
+                );
+                served.fetch_add(1, Ordering::Relaxed);
+                (response, request.keep_alive)
+            }
+            Ok(Ok(Incoming::Rejected(response))) => (response, false),
+            Ok(Ok(Incoming::Closed)) | Err(_) => return,
+            Ok(Err(error)) => {
+                debug!("Connection failed: {error}");
+                return;
+            }
+        };
+        if let Err(error) = write_response(&mut stream, &response, keep_alive).await {
+            debug!("Failed to write a response: {error}");
+            return;
+        }
+        if !keep_alive {
+            return;
+        }
+    }
+}
+
+/// Reads the next request from `stream`, keeping bytes past its end in `buffer` for the one
+/// after. Bodies of bulk inserts may have up to `max_insert_bytes`.
+async fn read_request(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    max_insert_bytes: usize,
+) -> io::Result<Incoming> {
+    let (head, head_length) = loop {
+        match parse_head(buffer) {
+            Ok(Some(parsed)) => break parsed,
+            Ok(None) if buffer.len() > MAX_HEAD_BYTES => {
+                return Ok(Incoming::Rejected(Response::error(
+                    StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                    "request headers are too large",
+                )))
+            }
+            Ok(None) => {
+                if stream.read_buf(buffer).await? == 0 {
+                    return Ok(Incoming::Closed);
+                }
+            }
+            Err(response) => return Ok(Incoming::Rejected(response)),
+        }
+    };
+
+    let (limit, bodies) = match (&head.method, head.endpoint()) {
+        (&Method::POST, Some(Endpoint::Records)) => (max_insert_bytes, "bulk insert bodies"),
+        _ => (MAX_BODY_BYTES, "request bodies"),
+    };
+    let too_large = || {
+        Incoming::Rejected(Response::error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("{bodies} are limited to {limit} bytes"),
+        ))
+    };
+    let continues = head
+        .header(header::EXPECT)
+        .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"));
+    let chunked = match head.header(header::TRANSFER_ENCODING) {
+        None => false,
+        Some(encoding) if encoding.eq_ignore_ascii_case("chunked") => true,
+        Some(_) => {
+            return Ok(Incoming::Rejected(Response::error(
+                StatusCode::NOT_IMPLEMENTED,
+                "request bodies may only be chunked, not otherwise transfer-encoded",
+            )))
+        }
+    };
+    if chunked {
+        if continues && buffer.len() == head_length {
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+        }
+        return Ok(
+            match read_chunked(stream, buffer, head_length, limit).await? {
+                Chunked::Body(body) => Incoming::Request(Request { body, ..head }),
+                Chunked::TooLarge => too_large(),
+                Chunked::Closed => Incoming::Closed,
+                Chunked::Malformed(what) => Incoming::Rejected(Response::error(
+                    StatusCode::BAD_REQUEST,
+                    format!("malformed chunked body: {what}"),
+                )),
+            },
+        );
+    }
+
+    let body_length = match head.header(header::CONTENT_LENGTH).map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(length)) => length,
+        Some(Err(_)) => {
+            return Ok(Incoming::Rejected(Response::error(
+                StatusCode::BAD_REQUEST,
+                "invalid Content-Length",
+            )))
+        }
+    };
+    if body_length > limit {
+        return Ok(too_large());
+    }
+    let end = head_length + body_length;
+    if buffer.len() < end && continues {
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+    }
+    while buffer.len() < end {
+        if stream.read_buf(buffer).await? == 0 {
+            return Ok(Incoming::Closed);
+        }
+    }
+    let body = buffer[head_length..end].to_vec();
+    buffer.drain(..end);
+    Ok(Incoming::Request(Request { body, ..head }))
+}
+
+/// What reading a chunked body gave.
+enum Chunked {
+    Body(Vec<u8>),
+    /// The body grew past its limit.
+    TooLarge,
+    /// The client closed the connection before the end of the body.
+    Closed,
+    Malformed(&'static str),
+}
+
+/// Reads the chunked body starting at `start` in `buffer` up to `limit` bytes, and drains it
+/// and the head before it from `buffer`. Trailers are skipped.
+async fn read_chunked(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    start: usize,
+    limit: usize,
+) -> io::Result<Chunked> {
+    let mut body = Vec::new();
+    let mut position = start;
+    loop {
+        let parsed = httparse::parse_chunk_size(&buffer[position..]);
+        let (size, data) = match parsed {
+            Ok(httparse::Status::Complete((length, size))) => (size, position + length),
+            Ok(httparse::Status::Partial) if buffer.len() - position > MAX_HEAD_BYTES => {
+                return Ok(Chunked::Malformed("chunk header too long"))
+            }
+            Ok(httparse::Status::Partial) => {
+                if stream.read_buf(buffer).await? == 0 {
+                    return Ok(Chunked::Closed);
+                }
+                continue;
+            }
+            Err(_) => return Ok(Chunked::Malformed("invalid chunk size")),
+        };
+        if size == 0 {
+            return skip_trailers(stream, buffer, data, body).await;
+        }
+        let Some(size) = usize::try_from(size)
+            .ok()
+            .filter(|size| body.len() + size <= limit)
+        else {
+            return Ok(Chunked::TooLarge);
+        };
+        let end = data + size + 2;
+        while buffer.len() < end {
+            if stream.read_buf(buffer).await? == 0 {
+                return Ok(Chunked::Closed);
+            }
+        }
+        if &buffer[end - 2..end] != b"\r\n" {
+            return Ok(Chunked::Malformed("chunk longer than its size"));
+        }
+        body.extend_from_slice(&buffer[data..end - 2]);
+        position = end;
+    }
+}
+
+/// Skips the trailers starting at `start` in `buffer`, drains everything up to their end and
+/// returns the `body` they end.
+async fn skip_trailers(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    start: usize,
+    body: Vec<u8>,
+) -> io::Result<Chunked> {
+    loop {
+        let mut trailers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        match httparse::parse_headers(&buffer[start..], &mut trailers) {
+            Ok(httparse::Status::Complete((length, _))) => {
+                buffer.drain(..start + length);
+                return Ok(Chunked::Body(body));
+            }
+            Ok(httparse::Status::Partial) if buffer.len() - start <= MAX_HEAD_BYTES => {
+                if stream.read_buf(buffer).await? == 0 {
+                    return Ok(Chunked::Closed);
+                }
+            }
+            _ => return Ok(Chunked::Malformed("invalid trailers")),
+        }
+    }
+}
+
+/// The request line and headers at the start of `buffer` and their length in bytes, or `None`
+/// if they haven't all arrived yet.
+fn parse_head(buffer: &[u8]) -> Result<Option<(Request, usize)>, Response> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut request = httparse::Request::new(&mut headers);
+    let length = match request.parse(buffer) {
+        Ok(httparse::Status::Complete(length)) => length,
+        Ok(httparse::Status::Partial) => return Ok(None),
+        Err(httparse::Error::TooManyHeaders) => {
+            return Err(Response::error(
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                "too many request headers",
+            ))
+        }
+        Err(error) => {
+            return Err(Response::error(
+                StatusCode::BAD_REQUEST,
+                format!("malformed request: {error}"),
+            ))
+        }
+    };
+    let malformed = |what| Response::error(StatusCode::BAD_REQUEST, format!("invalid {what}"));
+    let method = Method::from_bytes(request.method.unwrap_or_default().as_bytes())
+        .map_err(|_| malformed("method"))?;
+    let mut map = HeaderMap::with_capacity(request.headers.len());
+    for header in &*request.headers {
+        let name =
+            HeaderName::from_bytes(header.name.as_bytes()).map_err(|_| malformed("header name"))?;
+        let value = HeaderValue::from_bytes(header.value).map_err(|_| malformed("header value"))?;
+        map.append(name, value);
+    }
+    let connection = map
+        .get(header::CONNECTION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase());
+    // HTTP/1.1 keeps connections open unless told otherwise, HTTP/1.0 closes them
+    let keep_alive = match request.version {
+        Some(1) => connection.as_deref() != Some("close"),
+        _ => connection.as_deref() == Some("keep-alive"),
+    };
+    let target = request.path.unwrap_or("/");
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    Ok(Some((
+        Request {
+            method,
+            path: path.to_string(),
+            route: resolve(path)?,
+            headers: map,
+            body: Vec::new(),
+            keep_alive,
+        },
+        length,
+    )))
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    response: &Response,
+    keep_alive: bool,
+) -> io::Result<()> {
+    let body = response
+        .body
+        .as_ref()
+        .map(|body| body.to_string().into_bytes())
+        .unwrap_or_default();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\ncontent-length: {}\r\n",
+        response.status.as_u16(),
+        response.status.canonical_reason().unwrap_or_default(),
+        body.len()
+    )
+    .into_bytes();
+    if response.body.is_some() {
+        head.extend_from_slice(b"content-type: application/json\r\n");
+    }
+    for (name, value) in &response.headers {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    if !keep_alive {
+        head.extend_from_slice(b"connection: close\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    stream.write_all(&head).await?;
+    stream.write_all(&body).await?;
+    stream.flush().await
+}
+
+/// Answers `request` from `address` if the guard admits it, turning failures into error
+/// responses.
+async fn route(pool: &PgPool, guard: &Guard, address: IpAddr, request: &Request) -> Response {
+    if let Some(access) = request
+        .endpoint()
+        .and_then(|endpoint| access(&request.method, endpoint))
+    {
+        match guard.admit(
+            address,
+            request.header(header::AUTHORIZATION),
+            request.header("x-api-key"),
+            access,
+        ) {
+            Ok(Some(name)) => debug!("{} {} with key '{name}'", request.method, request.path),
+            Ok(None) => {}
+            Err(denied) => return Response::from_denied(&denied),
+        }
+    }
+    handle(pool, request)
+        .await
+        .unwrap_or_else(|error| Response::from_error(&error))
+}
+
+/// What a request to `endpoint` does with the database, or `None` for the endpoints open to
+/// every client.
+fn access(method: &Method, endpoint: Endpoint) -> Option<Access> {
+    match (method, endpoint) {
+        (_, Endpoint::Health | Endpoint::OpenApi) => None,
+        // Searches are POSTs but only read
+        (&Method::GET, _) | (&Method::POST, Endpoint::Search) => Some(Access::Read),
+        _ => Some(Access::Write),
+    }
+}
+
+async fn handle(pool: &PgPool, request: &Request) -> Result<Response> {
+    let Some((endpoint, Params { name, id })) = &request.route else {
+        return Ok(Response::error(
+            StatusCode::NOT_FOUND,
+            format!("no such endpoint: {}", request.path),
+        ));
+    };
+    match (&request.method, endpoint) {
+        (&Method::GET, Endpoint::Health) => Response::ok(json!({ "status": "ok" })),
+        (&Method::GET, Endpoint::OpenApi) => Response::ok(openapi::document()),
+        (&Method::GET, Endpoint::Collections) => {
+            Response::ok(json!({ "collections": list_collections(pool).await? }))
+        }
+        (&Method::POST, Endpoint::Collections) => {
+            Response::created(create_collection(pool, parse_body(&request.body)?).await?)
+        }
+        (&Method::GET, Endpoint::Collection) => {
+            Response::ok(describe_collection(pool, name).await?)
+        }
+        (&Method::DELETE, Endpoint::Collection) => {
+            delete_collection(pool, name).await?;
+            Ok(Response::no_content())
+        }
+        (&Method::POST, Endpoint::Records) => {
+            let request: InsertRecords = parse_body(&request.body)?;
+            let ids = insert_records(pool, name, &request.records).await?;
+            Response::ok(json!({ "ids": ids }))
+        }
+        (&Method::GET, Endpoint::Record) => {
+            Response::ok(get_record(pool, name, parse_id(id)?).await?)
+        }
+        (&Method::DELETE, Endpoint::Record) => {
+            let id = parse_id(id)?;
+            if delete_records(pool, name, &[id]).await? == 0 {
+                return Err(record_not_found(name, id));
+            }
+            Ok(Response::no_content())
+        }
+        (&Method::POST, Endpoint::Search) => {
+            let results = search(pool, name, parse_body(&request.body)?).await?;
+            Response::ok(json!({ "results": results }))
+        }
+        _ => Ok(Response::error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            format!("{} isn't supported on {}", request.method, request.path),
+        )
+        .with_header(header::ALLOW, HeaderValue::from_static(endpoint.methods()))),
+    }
+}
+
+// The operations of the API, shared with the gRPC API.
+
+/// A collection as the APIs describe it.
+#[derive(Serialize)]
+pub(crate) struct CollectionInfo {
+    pub(crate) name: String,
+    pub(crate) dimensions: u32,
+    pub(crate) metric: Metric,
+    pub(crate) storage: Storage,
+    pub(crate) namespace: String,
+    /// The planner's estimate, see [`Exactness::Estimated`]; only when describing one
+    /// collection.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) estimated_records: Option<u64>,
+}
+
+impl From<&Collection> for CollectionInfo {
+    fn from(collection: &Collection) -> Self {
+        Self {
+            name: collection.name().to_string(),
+            dimensions: collection.dimensions(),
+            metric: collection.metric(),
+            storage: collection.storage(),
+            namespace: collection.namespace().to_string(),
+            estimated_records: None,
+        }
+    }
+}
+
+pub(crate) async fn list_collections(pool: &PgPool) -> Result<Vec<CollectionInfo>> {
+    let rows: Vec<(String, i32, String, String, String)> = sqlx::query_as(
+        "SELECT name, dimensions, metric, storage, namespace FROM pgevdb.collections
+         ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|(name, dimensions, metric, storage, namespace)| {
+            Ok(CollectionInfo {
+                name,
+                dimensions: dimensions.unsigned_abs(),
+                metric: metric.parse()?,
+                storage: storage.parse()?,
+                namespace,
+                estimated_records: None,
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct CreateCollection {
+    pub(crate) name: String,
+    pub(crate) dimensions: u32,
+    #[serde(default)]
+    pub(crate) metric: Metric,
+    #[serde(default)]
+    pub(crate) storage: Storage,
+}
+
+pub(crate) async fn create_collection(
+    pool: &PgPool,
+    request: CreateCollection,
+) -> Result<CollectionInfo> {
+    let collection = Collection::create_with_storage(
+        pool,
+        &request.name,
+        request.dimensions,
+        request.metric,
+        request.storage,
+    )
+    .await?;
+    Ok(CollectionInfo::from(&collection))
+}
+
+pub(crate) async fn describe_collection(pool: &PgPool, name: &str) -> Result<CollectionInfo> {
+    let collection = Collection::open(pool, name).await?;
+    Ok(CollectionInfo {
+        estimated_records: Some(collection.count(None, Exactness::Estimated).await?),
+        ..CollectionInfo::from(&collection)
+    })
+}
+
+pub(crate) async fn delete_collection(pool: &PgPool, name: &str) -> Result<()> {
+    Collection::open(pool, name).await?.delete().await
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct InsertRecords {
+    pub(crate) records: Vec<NewRecord>,
+}
+
+/// A record to insert; one with an `id` replaces the record stored under it.
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct NewRecord {
+    pub(crate) id: Option<i64>,
+    pub(crate) embedding: Vec<f32>,
+    pub(crate) content: Option<String>,
+    pub(crate) source: Option<String>,
+    pub(crate) language: Option<String>,
+    pub(crate) payload: Option<Value>,
+}
+
+/// Inserts `records` in one transaction after checking all their dimensions, returning their
+/// ids.
+pub(crate) async fn insert_records(
+    pool: &PgPool,
+    name: &str,
+    records: &[NewRecord],
+) -> Result<Vec<i64>> {
+    let collection = Collection::open(pool, name).await?;
+    let rows: Vec<_> = records
+        .iter()
+        .map(|record| {
+            let content = record.content.as_deref().map(|text| Content {
+                text,
+                source: record.source.as_deref(),
+                language: record.language.as_deref(),
+            });
+            NewRow {
+                id: record.id,
+                embedding: &record.embedding,
+                content,
+                payload: record.payload.as_ref(),
+            }
+        })
+        .collect();
+    let ids = collection.insert_records(&rows).await?;
+    maintenance::analyze_after_ingest(&collection, ids.len() as u64).await?;
+    Ok(ids)
+}
+
+pub(crate) async fn get_record(pool: &PgPool, name: &str, id: i64) -> Result<Record> {
+    let collection = Collection::open(pool, name).await?;
+    let mut page = collection.scroll(&Filter::Ids(vec![id]), None, 1).await?;
+    page.records.pop().ok_or_else(|| record_not_found(name, id))
+}
+
+/// Deletes the records with these ids, returning how many there were.
+pub(crate) async fn delete_records(pool: &PgPool, name: &str, ids: &[i64]) -> Result<u64> {
+    Collection::open(pool, name).await?.delete_ids(ids).await
+}
+
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct SearchRequest {
+    pub(crate) vector: Vec<f32>,
+    pub(crate) limit: Option<u32>,
+    #[serde(default)]
+    pub(crate) offset: u32,
+    #[serde(default)]
+    pub(crate) filter: Filter,
+    pub(crate) max_distance: Option<f32>,
+    pub(crate) min_score: Option<f32>,
+    pub(crate) ef_search: Option<u32>,
+    pub(crate) probes: Option<u32>,
+}
+
+pub(crate) async fn search(
+    pool: &PgPool,
+    name: &str,
+    request: SearchRequest,
+) -> Result<Vec<SearchResult>> {
+    let limit = request.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit > MAX_LIMIT || request.offset > MAX_OFFSET {
+        return Err(Error::InvalidInput(format!(
+            "searches return at most {MAX_LIMIT} results, skipping at most {MAX_OFFSET}"
+        )));
+    }
+    let collection = Collection::open(pool, name).await?;
+    let mut options = SearchOptions::new(limit)
+        .offset(request.offset)
+        .filter(request.filter);
+    if let Some(max_distance) = request.max_distance {
+        options = options.max_distance(max_distance);
+    }
+    if let Some(min_score) = request.min_score {
+        options = options.min_score(min_score);
+    }
+    if let Some(ef_search) = request.ef_search {
+        options = options.ef_search(ef_search);
+    }
+    if let Some(probes) = request.probes {
+        options = options.probes(probes);
+    }
+    collection.search_with(&request.vector, &options).await
+}
+
+fn record_not_found(name: &str, id: i64) -> Error {
+    Error::NotFound(format!("record {id} of collection '{name}'"))
+}
+
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T> {
+    serde_json::from_slice(body)
+        .map_err(|error| Error::InvalidInput(format!("invalid request body: {error}")))
+}
+
+fn parse_id(id: &str) -> Result<i64> {
+    id.parse()
+        .map_err(|_| Error::InvalidInput(format!("record id '{id}' isn't an integer")))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::config::{ApiKey, Scope};
+
+    fn parse(head: &str) -> Request {
+        match parse_head(head.as_bytes()) {
+            Ok(Some((request, length))) => {
+                assert_eq!(length, head.len());
+                request
+            }
+            Ok(None) => panic!("incomplete head: {head:?}"),
+            Err(response) => panic!("rejected with {}: {head:?}", response.status),
+        }
+    }
+
+    fn rejection(head: &str) -> StatusCode {
+        match parse_head(head.as_bytes()) {
+            Err(response) => response.status,
+            Ok(_) => panic!("accepted: {head:?}"),
+        }
+    }
+
+    /// A pool that never connects, for requests answered before the database is asked.
+    fn pool() -> Result<PgPool> {
+        Ok(PgPool::connect_lazy("postgres://localhost/unused")?)
+    }
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        let mut request = parse(&format!("{method} {path} HTTP/1.1\r\n\r\n"));
+        request.body = body.as_bytes().to_vec();
+        request
+    }
+
+    async fn respond(method: &str, path: &str, body: &str) -> Result<Response> {
+        respond_to(&Guard::new(&ApiConfig::default()), method, path, body, None).await
+    }
+
+    async fn respond_to(
+        guard: &Guard,
+        method: &str,
+        path: &str,
+        body: &str,
+        key: Option<&str>,
+    ) -> Result<Response> {
+        let mut request = request(method, path, body);
+        if let Some(key) = key {
+            let Ok(key) = HeaderValue::from_str(key) else {
+                panic!("invalid key: {key:?}");
+            };
+            request.headers.insert("x-api-key", key);
+        }
+        let address = IpAddr::from([127, 0, 0, 1]);
+        Ok(route(&pool()?, guard, address, &request).await)
+    }
+
+    /// Sends `bytes` to [`read_request`] over a loopback connection and returns what it read,
+    /// up to the end of the stream or the first rejection.
+    async fn read(bytes: &[u8], max_insert_bytes: usize) -> io::Result<Vec<Incoming>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let mut client = TcpStream::connect(address).await?;
+        let (mut server, _) = listener.accept().await?;
+        client.write_all(bytes).await?;
+        client.shutdown().await?;
+        let mut buffer = Vec::new();
+        let mut read = Vec::new();
+        loop {
+            let incoming = read_request(&mut server, &mut buffer, max_insert_bytes).await?;
+            match incoming {
+                Incoming::Request(_) => read.push(incoming),
+                Incoming::Closed => return Ok(read),
+                Incoming::Rejected(_) => {
+                    read.push(incoming);
+                    return Ok(read);
+                }
+            }
+        }
+    }
+
+    /// The status of the rejection `read` ended with.
+    fn rejected(mut read: Vec<Incoming>) -> StatusCode {
+        match read.pop() {
+            Some(Incoming::Rejected(response)) => response.status,
+            _ => panic!("not rejected"),
+        }
+    }
+
+    #[test]
+    fn parses_the_request_line_and_headers() {
+        let request =
+            parse("GET /collections/docs?verbose=1 HTTP/1.1\r\nX-API-Key:  secret \r\n\r\n");
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.path, "/collections/docs");
+        assert_eq!(request.endpoint(), Some(Endpoint::Collection));
+        assert_eq!(request.header("x-api-key"), Some("secret"));
+        assert!(request.keep_alive);
+    }
+
+    #[test]
+    fn waits_for_the_whole_head() {
+        assert!(matches!(
+            parse_head(b"GET /health HTTP/1.1\r\nHost: x"),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn keeps_connections_alive_as_the_version_says() {
+        assert!(!parse("GET / HTTP/1.1\r\nConnection: close\r\n\r\n").keep_alive);
+        assert!(!parse("GET / HTTP/1.0\r\n\r\n").keep_alive);
+        assert!(parse("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").keep_alive);
+    }
+
+    #[test]
+    fn decodes_each_path_parameter() {
+        let request = parse("GET /collections/%64ocs/records/a%2Fb HTTP/1.1\r\n\r\n");
+        let params = Params {
+            name: "docs".to_string(),
+            id: "a/b".to_string(),
+        };
+        assert_eq!(request.route, Some((Endpoint::Record, params)));
+        assert_eq!(
+            rejection("GET /collections/%FF HTTP/1.1\r\n\r\n"),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn resolves_paths_to_endpoints() {
+        let endpoint = |path| match resolve(path) {
+            Ok(route) => route.map(|(endpoint, _)| endpoint),
+            Err(response) => panic!("rejected with {}: {path}", response.status),
+        };
+        assert_eq!(endpoint("/"), None);
+        assert_eq!(endpoint("/health/"), Some(Endpoint::Health));
+        assert_eq!(endpoint("/collections"), Some(Endpoint::Collections));
+        // A collection named "search"
+        assert_eq!(endpoint("/collections/search"), Some(Endpoint::Collection));
+        assert_eq!(endpoint("/collections/docs/search"), Some(Endpoint::Search));
+        assert_eq!(
+            endpoint("/collections/docs/records"),
+            Some(Endpoint::Records)
+        );
+        assert_eq!(endpoint("/collections/docs/other"), None);
+        assert_eq!(endpoint("/collections/docs/records/1/more"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_heads() {
+        assert_eq!(
+            rejection("GET /health HTTP/9\r\n\r\n"),
+            StatusCode::BAD_REQUEST
+        );
+        let headers = "X: y\r\n".repeat(MAX_HEADERS + 1);
+        assert_eq!(
+            rejection(&format!("GET / HTTP/1.1\r\n{headers}\r\n")),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_bodies_and_keeps_the_next_request() -> io::Result<()> {
+        let read = read(
+            b"POST /collections HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}GET /health HTTP/1.1\r\n\r\n",
+            MAX_BODY_BYTES,
+        )
+        .await?;
+        let [Incoming::Request(first), Incoming::Request(second)] = read.as_slice() else {
+            panic!("not two requests");
+        };
+        assert_eq!(first.body, b"{}");
+        assert_eq!(second.path, "/health");
+        assert!(second.body.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_bodies() -> io::Result<()> {
+        let read = read(
+            b"POST /collections HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+              1;name=value\r\n{\r\n1\r\n}\r\n0\r\nX-Trailer: y\r\n\r\n\
+              GET /health HTTP/1.1\r\n\r\n",
+            MAX_BODY_BYTES,
+        )
+        .await?;
+        let [Incoming::Request(first), Incoming::Request(second)] = read.as_slice() else {
+            panic!("not two requests");
+        };
+        assert_eq!(first.body, b"{}");
+        assert_eq!(second.path, "/health");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_it_wont_read() -> io::Result<()> {
+        let head = |headers: &str| format!("POST /collections HTTP/1.1\r\n{headers}\r\n\r\n");
+        let invalid = head("Content-Length: x");
+        assert_eq!(
+            rejected(read(invalid.as_bytes(), 10).await?),
+            StatusCode::BAD_REQUEST
+        );
+        let compressed = head("Transfer-Encoding: gzip, chunked");
+        assert_eq!(
+            rejected(read(compressed.as_bytes(), 10).await?),
+            StatusCode::NOT_IMPLEMENTED
+        );
+        let oversized = head(&format!("Content-Length: {}", MAX_BODY_BYTES + 1));
+        assert_eq!(
+            rejected(read(oversized.as_bytes(), usize::MAX).await?),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        // Inserts have their own limit
+        let insert = b"POST /collections/docs/records HTTP/1.1\r\nContent-Length: 11\r\n\r\n";
+        assert_eq!(
+            rejected(read(insert, 10).await?),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        let chunked = head("Transfer-Encoding: chunked");
+        let oversized = format!("{chunked}{:x}\r\n", MAX_BODY_BYTES + 1);
+        assert_eq!(
+            rejected(read(oversized.as_bytes(), 10).await?),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        let overlong = format!("{chunked}1\r\nab\r\n0\r\n\r\n");
+        assert_eq!(
+            rejected(read(overlong.as_bytes(), 10).await?),
+            StatusCode::BAD_REQUEST
+        );
+        let unparsable = format!("{chunked}x\r\n");
+        assert_eq!(
+            rejected(read(unparsable.as_bytes(), 10).await?),
+            StatusCode::BAD_REQUEST
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn routes_requests_that_need_no_database() -> Result<()> {
+        let response = respond("GET", "/health", "").await?;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, Some(json!({ "status": "ok" })));
+        let response = respond("GET", "/openapi.json/", "").await?;
+        assert_eq!(response.status, StatusCode::OK);
+        let response = respond("PUT", "/collections/docs", "").await?;
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            response.headers,
+            [(header::ALLOW, HeaderValue::from_static("GET, DELETE"))]
+        );
+        assert_eq!(
+            respond("GET", "/collections/docs/other", "").await?.status,
+            StatusCode::NOT_FOUND
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn answers_invalid_requests_with_400() -> Result<()> {
+        let response = respond("POST", "/collections", "{").await?;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert!(response.body.is_some_and(|body| body["error"]
+            .as_str()
+            .is_some_and(|error| error.contains("invalid request body"))));
+        assert_eq!(
+            respond("GET", "/collections/docs/records/x", "")
+                .await?
+                .status,
+            StatusCode::BAD_REQUEST
+        );
+        let search = |limit, offset| {
+            json!({ "vector": [0.0], "limit": limit, "offset": offset }).to_string()
+        };
+        let path = "/collections/docs/search";
+        assert_eq!(
+            respond("POST", path, &search(MAX_LIMIT + 1, 0))
+                .await?
+                .status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            respond("POST", path, &search(1, MAX_OFFSET + 1))
+                .await?
+                .status,
+            StatusCode::BAD_REQUEST
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn only_gets_and_searches_read() {
+        assert_eq!(access(&Method::GET, Endpoint::Health), None);
+        assert_eq!(access(&Method::POST, Endpoint::OpenApi), None);
+        assert_eq!(
+            access(&Method::GET, Endpoint::Collection),
+            Some(Access::Read)
+        );
+        assert_eq!(access(&Method::POST, Endpoint::Search), Some(Access::Read));
+        assert_eq!(
+            access(&Method::DELETE, Endpoint::Search),
+            Some(Access::Write)
+        );
+        assert_eq!(
+            access(&Method::POST, Endpoint::Collection),
+            Some(Access::Write)
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_writes_to_read_only_keys() -> Result<()> {
+        let key = "r".repeat(32);
+        let guard = Guard::new(&ApiConfig {
+            keys: vec![ApiKey {
+                name: "reader".to_string(),
+                key: key.clone(),
+                scope: Scope::ReadOnly,
+            }],
+            ..ApiConfig::default()
+        });
+        let key = Some(key.as_str());
+        let response = respond_to(&guard, "DELETE", "/collections/search", "", key).await?;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        let insert = r#"{"records": [{"embedding": [0.0]}]}"#;
+        let response = respond_to(&guard, "POST", "/collections/docs/records", insert, key).await?;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        // Searches are let through, and fail on the body
+        let response = respond_to(&guard, "POST", "/collections/docs/search", "{", key).await?;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let response = respond_to(&guard, "GET", "/collections/docs", "", None).await?;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[test]
+    fn maps_errors_to_statuses() {
+        let status = |error| Response::from_error(&error).status;
+        assert_eq!(
+            status(Error::InvalidInput(String::new())),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(Error::NotFound(String::new())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(Error::Io(io::Error::other("broken"))),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn tells_denied_clients_what_to_do() {
+        let response = Response::from_denied(&Denied::Unauthenticated);
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers,
+            [(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))]
+        );
+        let response = Response::from_denied(&Denied::RateLimited {
+            retry_after: Duration::from_millis(1500),
+        });
+        assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            response.headers,
+            [(header::RETRY_AFTER, HeaderValue::from_static("2"))]
+        );
+        let response = Response::from_denied(&Denied::RateLimited {
+            retry_after: Duration::from_secs(3),
+        });
+        assert_eq!(
+            response.headers,
+            [(header::RETRY_AFTER, HeaderValue::from_static("3"))]
+        );
+    }
+}
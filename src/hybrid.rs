@@ -0,0 +1,243 @@
+//! Hybrid retrieval: keyword and semantic search of the same collection, merged into one
+//! ranking.
+//!
+//! Full-text search finds exact terms, names and codes that embeddings blur; vector search
+//! finds paraphrases that share no words with the query. [`hybrid_search`] runs both and
+//! merges them into one ranking, by default with reciprocal rank fusion; [`Fusion`] lists the
+//! strategies, and [`fuse`] applies one to rankings obtained elsewhere.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::collection::{Collection, SearchResult};
+use crate::error::Result;
+
+/// How [`hybrid_search_with`] merges the full-text and the vector ranking.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fusion {
+    /// Reciprocal rank fusion: a record scores `1 / (k + rank)` for each list it appears in at
+    /// 1-based `rank`. Only positions count, so no calibration between `ts_rank_cd` ranks and
+    /// vector distances is needed; larger `k` flattens the advantage of the top positions.
+    Rrf { k: f32 },
+    /// Weighted sum of the vector [score](crate::Metric::score) and the text rank divided by the
+    /// best text rank of the search, both in `[0, 1]`. A record missing from a list scores `0`
+    /// there. `vector_weight` is clamped to `[0, 1]`; the text rank weighs the rest.
+    Weighted { vector_weight: f32 },
+}
+
+impl Default for Fusion {
+    /// Reciprocal rank fusion with `k = 60`, the value from Cormack et al.
+    fn default() -> Self {
+        Fusion::Rrf { k: 60.0 }
+    }
+}
+
+/// How [`hybrid_search_with`] runs a query.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HybridOptions {
+    pub(crate) limit: u32,
+    pub(crate) candidates: Option<u32>,
+    pub(crate) fusion: Fusion,
+}
+
+impl HybridOptions {
+    /// Returns the `limit` best results, fused with [`Fusion::default`].
+    #[must_use]
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            candidates: None,
+            fusion: Fusion::default(),
+        }
+    }
+
+    /// Merges the rankings with `fusion`.
+    #[must_use]
+    pub fn fusion(mut self, fusion: Fusion) -> Self {
+        self.fusion = fusion;
+        self
+    }
+
+    /// Candidates each search contributes; more lets records ranked lower by one search still
+    /// be lifted by the other. Defaults to four times the limit.
+    #[must_use]
+    pub fn candidates(mut self, candidates: u32) -> Self {
+        self.candidates = Some(candidates);
+        self
+    }
+
+    fn candidate_count(&self) -> u32 {
+        self.candidates
+            .unwrap_or_else(|| self.limit.saturating_mul(4))
+            .max(self.limit)
+    }
+}
+
+/// One hybrid search hit, best first.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HybridResult {
+    pub id: i64,
+    /// Fused relevance; higher is better, comparable only within one search.
+    pub score: f32,
+    /// Distance to the query vector, if the vector search found the record.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f32>,
+    /// `ts_rank_cd` of the content, if the full-text search matched the record.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_rank: Option<f32>,
+    /// Metadata stored with the vector, see [`Collection::insert_with_payload`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+}
+
+/// [`hybrid_search_with`] with [`HybridOptions::new(limit)`](HybridOptions::new): reciprocal
+/// rank fusion of four times `limit` candidates from each search.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`](crate::Error::InvalidInput) if `query` doesn't have the
+/// collection's dimensions.
+pub async fn hybrid_search(
+    collection: &Collection,
+    query_text: &str,
+    query: &[f32],
+    limit: u32,
+) -> Result<Vec<HybridResult>> {
+    hybrid_search_with(collection, query_text, query, &HybridOptions::new(limit)).await
+}
+
+/// Finds the records of `collection` most relevant to both `query_text`, searched in the stored
+/// content in the collection's text search language, and `query`, by vector similarity, merging
+/// the two rankings as `options` say.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`](crate::Error::InvalidInput) if `query` doesn't have the
+/// collection's dimensions.
+pub async fn hybrid_search_with(
+    collection: &Collection,
+    query_text: &str,
+    query: &[f32],
+    options: &HybridOptions,
+) -> Result<Vec<HybridResult>> {
+    let started = Instant::now();
+    let candidates = options.candidate_count();
+    let (vector_hits, text_hits) = tokio::try_join!(
+        collection.search(query, candidates),
+        collection.search_text(query_text, None, candidates),
+    )?;
+
+    let vector: Vec<(i64, f32)> = vector_hits.iter().map(|hit| (hit.id, hit.score)).collect();
+    let text: Vec<(i64, f32)> = text_hits.iter().map(|hit| (hit.id, hit.rank)).collect();
+    let mut vector_hits: HashMap<i64, SearchResult> =
+        vector_hits.into_iter().map(|hit| (hit.id, hit)).collect();
+    let text_ranks: HashMap<i64, f32> = text.iter().copied().collect();
+    let mut results: Vec<HybridResult> = fuse(options.fusion, &vector, &text)
+        .into_iter()
+        .take(options.limit as usize)
+        .map(|(id, score)| {
+            let hit = vector_hits.remove(&id);
+            HybridResult {
+                id,
+                score,
+                distance: hit.as_ref().map(|hit| hit.distance),
+                text_rank: text_ranks.get(&id).copied(),
+                payload: hit.and_then(|hit| hit.payload),
+            }
+        })
+        .collect();
+
+    // Records only the full-text search found still need their payload
+    let missing: Vec<i64> = results
+        .iter()
+        .filter(|result| result.distance.is_none())
+        .map(|result| result.id)
+        .collect();
+    if !missing.is_empty() {
+        let mut payloads = collection.payloads(&missing).await?;
+        for result in results
+            .iter_mut()
+            .filter(|result| result.distance.is_none())
+        {
+            result.payload = payloads.remove(&result.id);
+        }
+    }
+    let shape = match options.fusion {
+        Fusion::Rrf { .. } => "rrf",
+        Fusion::Weighted { .. } => "weighted",
+    };
+    collection
+        .record(
+            "search",
+            &format!("hybrid {shape} k={}", options.limit),
+            started,
+        )
+        .await;
+    Ok(results)
+}
+
+/// Merges a vector ranking of `(id, score)` pairs and a full-text ranking of `(id, rank)` pairs,
+/// each best first with higher values better, into one ranking of `(id, fused score)`, best
+/// first. Records with equal scores are ordered by id.
+///
+/// With reciprocal rank fusion, a record both searches found beats one that only a single search
+/// ranked first:
+///
+/// ```
+/// use pgevdb::hybrid::{fuse, Fusion};
+///
+/// let vector = [(1, 0.9), (2, 0.8)];
+/// let text = [(3, 0.5), (2, 0.4)];
+/// let ids: Vec<i64> = fuse(Fusion::default(), &vector, &text).iter().map(|(id, _)| *id).collect();
+/// assert_eq!(ids, [2, 1, 3]);
+/// ```
+///
+/// A weighted sum slides between the two rankings:
+///
+/// ```
+/// # use pgevdb::hybrid::{fuse, Fusion};
+/// # let vector = [(1, 0.9), (2, 0.8)];
+/// # let text = [(3, 0.5), (2, 0.4)];
+/// let ranked = |vector_weight| -> Vec<i64> {
+///     fuse(Fusion::Weighted { vector_weight }, &vector, &text).iter().map(|(id, _)| *id).collect()
+/// };
+/// assert_eq!(ranked(1.0), [1, 2, 3]);
+/// assert_eq!(ranked(0.0), [3, 2, 1]);
+/// assert_eq!(ranked(0.5), [2, 3, 1]);
+/// ```
+#[must_use]
+pub fn fuse(fusion: Fusion, vector: &[(i64, f32)], text: &[(i64, f32)]) -> Vec<(i64, f32)> {
+    let mut scores: HashMap<i64, f32> = HashMap::new();
+    match fusion {
+        Fusion::Rrf { k } => {
+            for ranking in [vector, text] {
+                for (position, (id, _)) in ranking.iter().enumerate() {
+                    *scores.entry(*id).or_default() += reciprocal_rank(k, position);
+                }
+            }
+        }
+        Fusion::Weighted { vector_weight } => {
+            let vector_weight = vector_weight.clamp(0.0, 1.0);
+            for (id, score) in vector {
+                *scores.entry(*id).or_default() += vector_weight * score;
+            }
+            let best = text.iter().map(|(_, rank)| *rank).fold(0.0, f32::max);
+            for (id, rank) in text {
+                let normalized = if best > 0.0 { rank / best } else { 0.0 };
+                *scores.entry(*id).or_default() += (1.0 - vector_weight) * normalized;
+            }
+        }
+    }
+    let mut fused: Vec<(i64, f32)> = scores.into_iter().collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    fused
+}
+
+/// Reciprocal rank fusion contribution of the 0-based `position` in one ranking.
+#[allow(clippy::cast_precision_loss)]
+fn reciprocal_rank(k: f32, position: usize) -> f32 {
+    1.0 / (k + position as f32 + 1.0)
+}
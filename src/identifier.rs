@@ -0,0 +1,61 @@
+//! Names of the databases and tables pgevdb creates, which are interpolated into SQL and so
+//! restricted to lowercase identifiers that never need quoting.
+
+use crate::error::{Error, Result};
+
+/// Checks that `name` is 1 to `max_length` lowercase letters, digits or underscores, starting
+/// with a letter or underscore, describing it as a `kind` name.
+pub(crate) fn validate(kind: &str, name: &str, max_length: usize) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= max_length
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidInput(format!(
+            "{kind} name '{name}' must be 1 to {max_length} lowercase letters, digits or \
+             underscores, starting with a letter or underscore"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_lowercase_identifiers_up_to_the_limit() {
+        for name in ["docs", "_staging", "docs_24", &"a".repeat(8)] {
+            assert!(validate("collection", name, 8).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_names_that_would_need_quoting_or_are_too_long() {
+        for name in [
+            "",
+            "Docs",
+            "2024_docs",
+            "my-docs",
+            "docs;",
+            "dócs",
+            &"a".repeat(9),
+        ] {
+            assert!(
+                matches!(validate("collection", name, 8), Err(Error::InvalidInput(_))),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn names_the_kind_and_limit() {
+        let Err(Error::InvalidInput(message)) = validate("branch", "Main", 63) else {
+            panic!("'Main' was accepted");
+        };
+        assert!(message.starts_with("branch name 'Main' must be 1 to 63 "));
+    }
+}
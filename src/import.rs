@@ -0,0 +1,210 @@
+//! Importers for embeddings computed outside pgevdb.
+//!
+//! [`openai_batch`] reads the JSON Lines output file of the `OpenAI` Batch API for the
+//! `/v1/embeddings` endpoint, which providers with OpenAI-compatible batch APIs also produce:
+//!
+//! ```json
+//! {"custom_id": "doc-42", "response": {"status_code": 200, "body": {"data": [{"embedding": [...]}]}}, "error": null}
+//! ```
+//!
+//! Each request's `custom_id` names the record: its trailing number becomes the record id, so
+//! `42`, `doc-42` and `chunk_42` all import as record 42 and re-importing replaces it. Content
+//! isn't part of the output, so it can be joined from a sidecar JSON Lines file with one
+//! `{"custom_id": ..., "content": ..., "source": ..., "language": ...}` object per record.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::collection::{Collection, Content};
+use crate::error::{Error, Result};
+use crate::maintenance::{self, AnalyzeReport};
+
+/// Failed requests listed in [`ImportReport::failed`]; the rest are only counted.
+const MAX_LISTED_FAILURES: usize = 20;
+
+/// Result of [`openai_batch`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub imported: u64,
+    /// Imported records that had an entry in the sidecar file.
+    pub with_payload: u64,
+    /// Requests the provider reported as failed, which were skipped.
+    pub failed_requests: u64,
+    /// `custom_id`s of the first failed requests.
+    pub failed: Vec<String>,
+    /// Planner check after a large import, see [`maintenance::analyze`].
+    pub analyze: Option<AnalyzeReport>,
+}
+
+#[derive(Deserialize)]
+struct BatchLine {
+    custom_id: String,
+    response: Option<BatchResponse>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    status_code: u16,
+    body: Option<EmbeddingsBody>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsBody {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    custom_id: String,
+    content: String,
+    source: Option<String>,
+    language: Option<String>,
+}
+
+/// Imports a batch embeddings output file into `collection`, joining content from `payload` if
+/// given. Records are inserted or replaced one by one, so an interrupted import can be rerun.
+/// Large imports are followed by [`maintenance::analyze`].
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] for a line that isn't a batch output or payload object, a
+/// `custom_id` without a trailing number, a request that embedded more than one input, or an
+/// embedding with the wrong dimensions, and [`Error::Io`] if a file can't be read. Records before
+/// the failing line stay imported.
+///
+/// Each record is written in a statement of its own, so dropping the future leaves the records
+/// before it imported and none half-written.
+pub async fn openai_batch(
+    collection: &Collection,
+    output: &Path,
+    payload: Option<&Path>,
+) -> Result<ImportReport> {
+    openai_batch_with_cancellation(collection, output, payload, &CancellationToken::new()).await
+}
+
+/// [`openai_batch`], stopping before the next record once `cancel` fires. The records imported
+/// until then stay, and rerunning the import completes it.
+///
+/// # Errors
+///
+/// Returns [`Error::Cancelled`] if cancelled, and the errors of [`openai_batch`] otherwise.
+pub async fn openai_batch_with_cancellation(
+    collection: &Collection,
+    output: &Path,
+    payload: Option<&Path>,
+    cancel: &CancellationToken,
+) -> Result<ImportReport> {
+    let mut payloads = match payload {
+        Some(path) => read_payloads(path)?,
+        None => HashMap::new(),
+    };
+    let mut report = ImportReport {
+        imported: 0,
+        with_payload: 0,
+        failed_requests: 0,
+        failed: Vec::new(),
+        analyze: None,
+    };
+
+    let file = std::io::BufReader::new(std::fs::File::open(output)?);
+    for (number, line) in file.lines().enumerate() {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |reason: String| {
+            Error::InvalidInput(format!("{}:{}: {reason}", output.display(), number + 1))
+        };
+        let line: BatchLine =
+            serde_json::from_str(&line).map_err(|error| invalid(error.to_string()))?;
+        let embedding = match line.response {
+            Some(BatchResponse {
+                status_code: 200,
+                body: Some(mut body),
+            }) if line.error.is_none() => {
+                if body.data.len() != 1 {
+                    return Err(invalid(format!(
+                        "request '{}' embedded {} inputs; import needs one input per request",
+                        line.custom_id,
+                        body.data.len()
+                    )));
+                }
+                body.data.remove(0).embedding
+            }
+            _ => {
+                report.failed_requests += 1;
+                if report.failed.len() < MAX_LISTED_FAILURES {
+                    report.failed.push(line.custom_id);
+                }
+                continue;
+            }
+        };
+
+        let id = record_id(&line.custom_id).ok_or_else(|| {
+            invalid(format!(
+                "custom_id '{}' doesn't end in a record id",
+                line.custom_id
+            ))
+        })?;
+        let payload = payloads.remove(&line.custom_id);
+        let content = payload.as_ref().map(|payload| Content {
+            text: &payload.content,
+            source: payload.source.as_deref(),
+            language: payload.language.as_deref(),
+        });
+        collection
+            .insert_or_update(id, &embedding, content.as_ref())
+            .await
+            .map_err(|error| match error {
+                Error::InvalidInput(reason) => invalid(reason),
+                error => error,
+            })?;
+        report.imported += 1;
+        if payload.is_some() {
+            report.with_payload += 1;
+        }
+    }
+    report.analyze = maintenance::analyze_after_ingest(collection, report.imported).await?;
+    Ok(report)
+}
+
+/// The trailing number of a `custom_id`.
+fn record_id(custom_id: &str) -> Option<i64> {
+    let digits = custom_id.len()
+        - custom_id
+            .bytes()
+            .rev()
+            .take_while(u8::is_ascii_digit)
+            .count();
+    custom_id[digits..].parse().ok()
+}
+
+/// Reads one payload object per line, keyed by `custom_id`, skipping blank lines.
+fn read_payloads(path: &Path) -> Result<HashMap<String, Payload>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut payloads = HashMap::new();
+    for (number, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let payload: Payload = serde_json::from_str(&line).map_err(|error| {
+            Error::InvalidInput(format!("{}:{}: {error}", path.display(), number + 1))
+        })?;
+        payloads.insert(payload.custom_id.clone(), payload);
+    }
+    Ok(payloads)
+}
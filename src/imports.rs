@@ -0,0 +1,51 @@
+use crate::cli::ImportCommand;
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::import::{self, ImportReport};
+use pgevdb::{Collection, Config, Result};
+
+impl Report for ImportReport {
+    fn print_text(&self) {
+        println!(
+            "Imported {} records, {} with payload",
+            self.imported, self.with_payload
+        );
+        if self.failed_requests > 0 {
+            println!(
+                "Skipped {} failed requests: {}",
+                self.failed_requests,
+                self.failed.join(", ")
+            );
+        }
+        if let Some(analyze) = &self.analyze {
+            println!(
+                "Analyzed: ~{} rows, searches {}",
+                analyze.estimated_rows,
+                if analyze.knn_uses_index {
+                    "use the index"
+                } else if analyze.indexed {
+                    "still planned as sequential scans"
+                } else {
+                    "scan sequentially until an index is built"
+                }
+            );
+        }
+    }
+}
+
+pub async fn run(command: ImportCommand, config: Config, output: OutputFormat) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+    match command {
+        ImportCommand::OpenaiBatch {
+            collection,
+            file,
+            payload,
+        } => {
+            let collection = Collection::open(&pool, &collection).await?;
+            emit(
+                output,
+                &import::openai_batch(&collection, &file, payload.as_deref()).await?,
+            )
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Approximate nearest neighbor indexes over a collection's vectors.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Backend;
+use crate::error::{Error, Result};
+
+/// Index type and build parameters, written like `hnsw:m=16,ef_construction=100` on the command
+/// line.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum IndexConfig {
+    /// Exhaustive scan kept in index form; exact results.
+    Flat,
+    /// Hierarchical navigable small world graph: fast, accurate queries, slower builds.
+    Hnsw { m: u32, ef_construction: u32 },
+    /// Inverted file: vectors are bucketed around `lists` centroids and only the nearest buckets
+    /// are scanned.
+    Ivf { lists: u32 },
+}
+
+impl IndexConfig {
+    /// `USING` and `WITH` clauses of `CREATE INDEX` for `backend`, indexing `column` with the
+    /// operator class `class`.
+    ///
+    /// pgvecto.rs takes all index types through its own access method with TOML options;
+    /// pgvector has an access method per type, `hnsw` and `ivfflat`, and no flat index.
+    pub(crate) fn using(&self, backend: Backend, column: &str, class: &str) -> Result<String> {
+        match backend {
+            Backend::PgVectoRs => {
+                let options = match self {
+                    IndexConfig::Flat => "[indexing.flat]".to_string(),
+                    IndexConfig::Hnsw { m, ef_construction } => {
+                        format!("[indexing.hnsw]\nm = {m}\nef_construction = {ef_construction}")
+                    }
+                    IndexConfig::Ivf { lists } => format!("[indexing.ivf]\nnlist = {lists}"),
+                };
+                Ok(format!(
+                    "USING vectors ({column} {class}) WITH (options = $${options}$$)"
+                ))
+            }
+            Backend::PgVector => match self {
+                IndexConfig::Flat => Err(invalid(
+                    "flat",
+                    "pgvector has no flat index; searches without an index are exact scans",
+                )),
+                IndexConfig::Hnsw { m, ef_construction } => Ok(format!(
+                    "USING hnsw ({column} {class}) WITH (m = {m}, ef_construction = {ef_construction})"
+                )),
+                IndexConfig::Ivf { lists } => Ok(format!(
+                    "USING ivfflat ({column} {class}) WITH (lists = {lists})"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for IndexConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexConfig::Flat => f.write_str("flat"),
+            IndexConfig::Hnsw { m, ef_construction } => {
+                write!(f, "hnsw:m={m},ef_construction={ef_construction}")
+            }
+            IndexConfig::Ivf { lists } => write!(f, "ivf:lists={lists}"),
+        }
+    }
+}
+
+impl FromStr for IndexConfig {
+    type Err = Error;
+
+    /// Parses `flat`, `hnsw[:m=N,ef_construction=N]` or `ivf[:lists=N]`; omitted parameters
+    /// take pgvecto.rs's defaults.
+    fn from_str(spec: &str) -> Result<Self> {
+        let (kind, parameters) = spec.split_once(':').unwrap_or((spec, ""));
+        let mut values = Vec::new();
+        for parameter in parameters
+            .split(',')
+            .filter(|parameter| !parameter.is_empty())
+        {
+            let (key, value) = parameter
+                .split_once('=')
+                .ok_or_else(|| invalid(spec, "parameters are written key=value"))?;
+            let value: u32 = value
+                .parse()
+                .map_err(|_| invalid(spec, "parameter values are positive integers"))?;
+            values.push((key, value));
+        }
+        let mut take = |key: &str, default: u32| {
+            values
+                .iter()
+                .position(|(name, _)| *name == key)
+                .map_or(default, |position| values.remove(position).1)
+        };
+
+        let config = match kind {
+            "flat" => IndexConfig::Flat,
+            "hnsw" => IndexConfig::Hnsw {
+                m: take("m", 12),
+                ef_construction: take("ef_construction", 300),
+            },
+            "ivf" => IndexConfig::Ivf {
+                lists: take("lists", 1000),
+            },
+            _ => return Err(invalid(spec, "index types are flat, hnsw and ivf")),
+        };
+        if let Some((key, _)) = values.first() {
+            return Err(invalid(spec, &format!("unknown parameter '{key}'")));
+        }
+        Ok(config)
+    }
+}
+
+fn invalid(spec: &str, reason: &str) -> Error {
+    Error::InvalidInput(format!("index '{spec}': {reason}"))
+}
@@ -0,0 +1,211 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::output::Report;
+use pgevdb::config::{Config, LATEST_EXTENSION_VERSION};
+use pgevdb::platform::Platform;
+use pgevdb::{Error, Result};
+
+const REDACTED: &str = "<redacted>";
+
+/// Environment report printed by `pgevdb info`, intended to be pasted into bug reports.
+#[derive(Serialize)]
+pub struct InfoReport<'a> {
+    pub pgevdb_version: &'static str,
+    pub platform: &'static str,
+    pub host: String,
+    pub postgresql: PostgresInfo,
+    pub extension: ExtensionInfo,
+    pub storage: StorageInfo,
+    pub config: EffectiveConfig<'a>,
+}
+
+#[derive(Serialize)]
+pub struct PostgresInfo {
+    pub configured_version: String,
+    pub installed_versions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ExtensionInfo {
+    pub backend: &'static str,
+    pub configured_version: String,
+    pub installed_version: Option<String>,
+    /// Prebuilt archive for this host, or why there is none.
+    pub artifact_url: Option<String>,
+    pub unsupported: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct StorageInfo {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+}
+
+/// The configuration in effect, with secrets replaced by a placeholder.
+#[derive(Serialize)]
+pub struct EffectiveConfig<'a> {
+    #[serde(flatten)]
+    pub config: Cow<'a, Config>,
+    pub password: Option<&'static str>,
+}
+
+impl<'a> InfoReport<'a> {
+    pub fn collect(config: &'a Config) -> Result<Self> {
+        let installed_version = config.installed_extension_version()?;
+        let (artifact_url, unsupported) = match artifact_url(config, installed_version.as_deref()) {
+            Ok(url) => (url, None),
+            Err(Error::UnsupportedPlatform(reason)) => (None, Some(reason)),
+            Err(error) => return Err(error),
+        };
+        Ok(Self {
+            pgevdb_version: env!("CARGO_PKG_VERSION"),
+            platform: env!("PGEVDB_TARGET"),
+            host: Platform::current().to_string(),
+            postgresql: PostgresInfo {
+                configured_version: config.pg_version.clone(),
+                installed_versions: installed_pg_versions(&config.installation_dir())?,
+            },
+            extension: ExtensionInfo {
+                backend: config.backend.name(),
+                configured_version: config.extension_version.clone(),
+                installed_version,
+                artifact_url,
+                unsupported,
+            },
+            storage: StorageInfo {
+                path: config.storage_dir.clone(),
+                size_bytes: dir_size(&config.storage_dir)?,
+            },
+            config: EffectiveConfig {
+                config: redact(config),
+                password: config.password_file().exists().then_some(REDACTED),
+            },
+        })
+    }
+}
+
+impl Report for InfoReport<'_> {
+    fn print_text(&self) {
+        println!("pgevdb version:     {}", self.pgevdb_version);
+        println!("platform:           {}", self.platform);
+        println!("host:               {}", self.host);
+        println!(
+            "postgresql:         {} (installed: {})",
+            self.postgresql.configured_version,
+            list_or_none(&self.postgresql.installed_versions)
+        );
+        println!(
+            "extension:          {} {} (installed: {})",
+            self.extension.backend,
+            self.extension.configured_version,
+            self.extension
+                .installed_version
+                .as_deref()
+                .unwrap_or("none")
+        );
+        match (&self.extension.artifact_url, &self.extension.unsupported) {
+            (Some(url), _) => println!("extension artifact: {url}"),
+            (None, None) => {
+                println!("extension artifact: newest compatible release, looked up on first start");
+            }
+            (None, Some(reason)) => println!("extension artifact: none ({reason})"),
+        }
+        let extensions: Vec<String> = self
+            .config
+            .config
+            .extensions
+            .iter()
+            .map(|extension| extension.name.clone())
+            .collect();
+        println!("other extensions:   {}", list_or_none(&extensions));
+        println!(
+            "storage:            {} ({})",
+            self.storage.path.display(),
+            indicatif::HumanBytes(self.storage.size_bytes)
+        );
+        println!("configuration:");
+        println!("  database_name:    {}", self.config.config.database_name);
+        println!(
+            "  password:         {}",
+            self.config.password.unwrap_or("none")
+        );
+    }
+}
+
+/// The archive installed for the configured version, or `None` for an `extension_version` of
+/// `latest` that hasn't been resolved by installing yet.
+fn artifact_url(config: &Config, installed_version: Option<&str>) -> Result<Option<String>> {
+    if config.extension_version != LATEST_EXTENSION_VERSION {
+        return config.extension_artifact_url(Platform::current()).map(Some);
+    }
+    let Some(installed_version) = installed_version else {
+        return Ok(None);
+    };
+    let config = Config {
+        extension_version: installed_version.to_string(),
+        ..config.clone()
+    };
+    config.extension_artifact_url(Platform::current()).map(Some)
+}
+
+/// The config with its secrets replaced by a placeholder.
+fn redact(config: &Config) -> Cow<'_, Config> {
+    if config.github_token.is_none() && config.api.keys.is_empty() {
+        return Cow::Borrowed(config);
+    }
+    let mut redacted = config.clone();
+    if redacted.github_token.is_some() {
+        redacted.github_token = Some(REDACTED.to_string());
+    }
+    for api_key in &mut redacted.api.keys {
+        api_key.key = REDACTED.to_string();
+    }
+    Cow::Owned(redacted)
+}
+
+fn list_or_none(values: &[String]) -> String {
+    if values.is_empty() {
+        "none".to_string()
+    } else {
+        values.join(", ")
+    }
+}
+
+/// Versions installed by `postgresql_embedded`, which unpacks each into a directory named after it.
+fn installed_pg_versions(installation_dir: &Path) -> Result<Vec<String>> {
+    if !installation_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut versions = Vec::new();
+    for entry in std::fs::read_dir(installation_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            versions.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+/// Total size of the files under `path`, or zero if it doesn't exist.
+pub fn dir_size(path: &Path) -> Result<u64> {
+    if !path.exists() {
+        return Ok(0);
+    }
+
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
@@ -0,0 +1,270 @@
+//! Bulk loading through `COPY`, for ingesting millions of vectors.
+//!
+//! [`copy_in`] streams records in PostgreSQL's binary `COPY` format into a temporary table and
+//! moves each batch into the collection with one `INSERT ... SELECT`, so a batch costs a few
+//! round trips instead of one per record. Vectors travel as `real[]` and are cast to the
+//! collection's [storage](crate::collection::Storage) type on the server, which works the same
+//! for every backend.
+//!
+//! Each batch is its own transaction: an interrupted load keeps the batches before it, and a
+//! batch that fails leaves nothing of itself behind.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::collection::{Collection, Content};
+use crate::error::{Error, Result};
+use crate::maintenance::{self, AnalyzeReport};
+use crate::progress::Progress;
+use crate::quota;
+
+/// Start of every binary `COPY` stream: signature, flags and header extension length.
+const HEADER: &[u8] = b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0";
+
+/// Columns of the staging table, in `COPY` order.
+const COLUMNS: &str = "embedding, reduced, content, source, language, regconfig";
+
+/// OID of PostgreSQL's `real` type, the element type of the vectors sent.
+const FLOAT4_OID: i32 = 700;
+
+/// Batching of [`copy_in`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CopyOptions {
+    /// Records sent per `COPY` and committed per transaction.
+    pub batch_size: u32,
+    /// Refresh the planner statistics of the collection afterwards, if the load was at least
+    /// [`ANALYZE_THRESHOLD`](maintenance::ANALYZE_THRESHOLD) records.
+    pub analyze: bool,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 10_000,
+            analyze: true,
+        }
+    }
+}
+
+/// Result of [`copy_in`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CopyReport {
+    pub loaded: u64,
+    pub batches: u64,
+    /// Planner check after a large load, see [`maintenance::analyze`].
+    pub analyze: Option<AnalyzeReport>,
+}
+
+/// Loads `records`, each a vector with optional content, into `collection` in batches through
+/// `COPY`, reporting progress. Ids are generated as by [`Collection::insert`]; records that need
+/// caller-chosen ids go through [`Collection::insert_or_update_many`].
+///
+/// ```no_run
+/// # async fn demo(docs: &pgevdb::Collection, vectors: Vec<Vec<f32>>) -> pgevdb::Result<()> {
+/// use pgevdb::ingest::{self, CopyOptions};
+///
+/// let records = vectors.iter().map(|vector| (vector, None));
+/// ingest::copy_in(docs, records, &CopyOptions::default()).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if a vector doesn't have the collection's dimensions or
+/// PostgreSQL has no text search configuration for a record's language, and
+/// [`Error::QuotaExceeded`] if a batch would take the collection's namespace past its quota.
+/// Batches before the failing one stay loaded.
+pub async fn copy_in<'a, I, V>(
+    collection: &Collection,
+    records: I,
+    options: &CopyOptions,
+) -> Result<CopyReport>
+where
+    I: IntoIterator<Item = (V, Option<Content<'a>>)>,
+    V: AsRef<[f32]>,
+{
+    let started = Instant::now();
+    let records = records.into_iter();
+    let total = match records.size_hint() {
+        (lower, Some(upper)) if lower == upper => Some(lower as u64),
+        _ => None,
+    };
+    let mut progress = Progress::items(
+        format!("Loading into {}", collection.name()),
+        total,
+        "records",
+    );
+    let batch_size = options.batch_size.max(1) as usize;
+    let mut report = CopyReport {
+        loaded: 0,
+        batches: 0,
+        analyze: None,
+    };
+    let mut batch = Batch::default();
+    // Resolving a language can take a query, so each is resolved once per load
+    let mut regconfigs: HashMap<Option<&str>, String> = HashMap::new();
+    for (position, (embedding, content)) in records.enumerate() {
+        let embedding = embedding.as_ref();
+        collection
+            .check_dimensions(embedding)
+            .map_err(|error| match error {
+                Error::InvalidInput(reason) => {
+                    Error::InvalidInput(format!("record {position}: {reason}"))
+                }
+                error => error,
+            })?;
+        let language = content.and_then(|content| content.language);
+        if let Entry::Vacant(entry) = regconfigs.entry(language) {
+            entry.insert(collection.regconfig(language).await?);
+        }
+        batch.push(
+            embedding,
+            collection.reduce(embedding)?.as_deref(),
+            content.as_ref(),
+            &regconfigs[&language],
+        );
+        if batch.rows == batch_size {
+            let loaded = load(collection, std::mem::take(&mut batch)).await?;
+            report.loaded += loaded;
+            report.batches += 1;
+            progress.inc(loaded);
+        }
+    }
+    if batch.rows > 0 {
+        let loaded = load(collection, batch).await?;
+        report.loaded += loaded;
+        report.batches += 1;
+        progress.inc(loaded);
+    }
+    progress.finish();
+
+    if options.analyze {
+        report.analyze = maintenance::analyze_after_ingest(collection, report.loaded).await?;
+    }
+    collection.record("copy", "", started).await;
+    Ok(report)
+}
+
+/// Records encoded as binary `COPY` tuples for the staging table.
+#[derive(Default)]
+struct Batch {
+    data: Vec<u8>,
+    rows: usize,
+}
+
+impl Batch {
+    fn push(
+        &mut self,
+        embedding: &[f32],
+        reduced: Option<&[f32]>,
+        content: Option<&Content<'_>>,
+        regconfig: &str,
+    ) {
+        if self.data.is_empty() {
+            self.data.extend_from_slice(HEADER);
+        }
+        self.data.extend_from_slice(&6_i16.to_be_bytes());
+        self.push_vector(Some(embedding));
+        self.push_vector(reduced);
+        self.push_text(content.map(|content| content.text));
+        self.push_text(content.and_then(|content| content.source));
+        self.push_text(content.and_then(|content| content.language));
+        self.push_text(Some(regconfig));
+        self.rows += 1;
+    }
+
+    /// A one-dimensional `real[]` without NULL elements, or NULL.
+    fn push_vector(&mut self, vector: Option<&[f32]>) {
+        let Some(vector) = vector else {
+            self.data.extend_from_slice(&(-1_i32).to_be_bytes());
+            return;
+        };
+        // Dimensions are capped far below i32::MAX, so neither length overflows
+        let len = i32::try_from(vector.len()).unwrap_or(i32::MAX);
+        self.data.extend_from_slice(&(20 + 8 * len).to_be_bytes());
+        for field in [1, 0, FLOAT4_OID, len, 1] {
+            self.data.extend_from_slice(&field.to_be_bytes());
+        }
+        for value in vector {
+            self.data.extend_from_slice(&4_i32.to_be_bytes());
+            self.data.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    fn push_text(&mut self, text: Option<&str>) {
+        match text {
+            Some(text) => {
+                let len = i32::try_from(text.len()).unwrap_or(i32::MAX);
+                self.data.extend_from_slice(&len.to_be_bytes());
+                self.data.extend_from_slice(text.as_bytes());
+            }
+            None => self.data.extend_from_slice(&(-1_i32).to_be_bytes()),
+        }
+    }
+}
+
+/// Copies `batch` into a staging table and moves it into the collection in one transaction,
+/// charging the namespace's quota, returning the number of records loaded.
+async fn load(collection: &Collection, mut batch: Batch) -> Result<u64> {
+    batch.data.extend_from_slice(&(-1_i16).to_be_bytes());
+    let table = collection.table();
+    let mut transaction = collection.pool().begin().await?;
+    sqlx::query(
+        "CREATE TEMPORARY TABLE pgevdb_copy (
+             embedding real[], reduced real[], content text, source text, language text,
+             regconfig text
+         ) ON COMMIT DROP",
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let mut copy = transaction
+        .copy_in_raw(&format!(
+            "COPY pgevdb_copy ({COLUMNS}) FROM STDIN (FORMAT binary)"
+        ))
+        .await?;
+    if let Err(error) = copy.send(batch.data).await {
+        copy.abort(error.to_string()).await?;
+        return Err(error.into());
+    }
+    copy.finish().await?;
+
+    // The quota row stays locked until commit, so concurrent loads are charged in turn
+    let quota = quota::lock(&mut transaction, collection.namespace()).await?;
+    let reduced = if collection.has_reduced_column() {
+        (
+            ", reduced",
+            format!(", {}", collection.vector_value("reduced")),
+        )
+    } else {
+        ("", String::new())
+    };
+    let (rows, bytes): (i64, i64) = sqlx::query_as(&format!(
+        "WITH inserted AS (
+             INSERT INTO {table} AS t (embedding, content, source, language, content_tsv{column})
+             SELECT {embedding}, content, source, language,
+                    to_tsvector(regconfig::regconfig, content){value}
+             FROM pgevdb_copy
+             RETURNING pg_column_size(t.*) AS size
+         )
+         SELECT count(*), coalesce(sum(size), 0)::bigint FROM inserted",
+        embedding = collection.vector_value("embedding"),
+        column = reduced.0,
+        value = reduced.1,
+    ))
+    .fetch_one(&mut *transaction)
+    .await?;
+    if let Some(quota) = quota {
+        quota::charge(
+            &mut transaction,
+            &quota,
+            rows.unsigned_abs(),
+            bytes.unsigned_abs(),
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(rows.unsigned_abs())
+}
@@ -0,0 +1,549 @@
+use std::borrow::Cow;
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use postgresql_embedded::{PostgreSQL, Settings};
+use serde::Serialize;
+use sqlx::postgres::{PgConnectOptions, PgPool};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::builder::PgEvDbBuilder;
+use crate::catalog;
+use crate::collection::{Collection, Metric};
+use crate::compat;
+use crate::config::{Config, Timeouts, LATEST_EXTENSION_VERSION};
+use crate::database::{self, Database};
+use crate::download;
+use crate::error::{Error, Result};
+use crate::extension::{self, Extension, Reconfigured};
+use crate::platform::Platform;
+use crate::privileges;
+use crate::progress::Progress;
+use crate::tenant::Tenant;
+
+/// An embedded vector database: a PostgreSQL installation with the vector extension, described by
+/// a validated [`Config`].
+#[derive(Clone, Debug)]
+pub struct PgEvDb {
+    config: Config,
+    /// Set up after the vector extension, see [`extension`].
+    extensions: Vec<Arc<dyn Extension>>,
+}
+
+impl PgEvDb {
+    pub fn builder() -> PgEvDbBuilder {
+        PgEvDbBuilder::default()
+    }
+
+    /// Wraps a config loaded from a file or assembled by hand, after validating it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if a setting is invalid.
+    pub fn from_config(config: Config) -> Result<Self> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            extensions: Vec::new(),
+        })
+    }
+
+    pub(crate) fn with_extensions(mut self, extensions: Vec<Arc<dyn Extension>>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Installs (on first run) and starts PostgreSQL, creates the database and sets up the
+    /// extension, returning the running server and a pool connected to the database.
+    ///
+    /// The server stops when the returned [`PostgreSQL`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::AlreadyRunning`] if a server is already running on the data directory
+    /// (use [`connect`](Self::connect) then), [`Error::StartupTimeout`] if the server doesn't come
+    /// up, [`Error::UnsupportedPlatform`] if no extension build matches the host,
+    /// [`Error::Incompatible`] if the data was created by other PostgreSQL, extension or pgevdb
+    /// versions, and the download, install and database errors of the individual steps otherwise.
+    ///
+    /// Dropping the future cancels setup safely: a half-installed extension is installed again
+    /// on the next start, temporary files are removed, and a started server is stopped.
+    pub async fn start(&self) -> Result<(PostgreSQL, PgPool)> {
+        let (postgresql, pool, _config) = self.start_resolved().await?;
+        Ok((postgresql, pool))
+    }
+
+    /// [`start`](Self::start), also returning the config it resolved.
+    async fn start_resolved(&self) -> Result<(PostgreSQL, PgPool, Config)> {
+        let config = self.resolved_config(false).await?.into_owned();
+        let (postgresql, pool) = provision(&config, &self.extensions).await?;
+        compat::check_database(&pool, &config).await?;
+        Ok((postgresql, pool, config))
+    }
+
+    /// [`start`](Self::start), returning the server and pool as one handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`start`](Self::start).
+    pub async fn launch(&self) -> Result<Instance> {
+        let (postgresql, pool, config) = self.start_resolved().await?;
+        Ok(Instance {
+            postgresql,
+            pool,
+            config,
+            extensions: self.extensions.clone(),
+        })
+    }
+
+    /// Port of the server running on the configured data directory, such as one left running by
+    /// `pgevdb start`, or `None` if there is none. A `postmaster.pid` left behind by a crash
+    /// doesn't count, since nothing accepts connections on its port.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if `postmaster.pid` exists but can't be read.
+    pub fn running_port(&self) -> Result<Option<u16>> {
+        running_port(&self.config)
+    }
+
+    /// Connects to the server already running on the configured data directory, or returns
+    /// `None` if there is none. Unlike [`start`](Self::start) this installs nothing, and the
+    /// server keeps running when the pool is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Incompatible`] if the database was created by other extension or pgevdb
+    /// versions, and [`Error::Database`] if connecting fails.
+    pub async fn connect(&self) -> Result<Option<PgPool>> {
+        let Some(port) = self.running_port()? else {
+            return Ok(None);
+        };
+        let mut settings = self.config.settings()?;
+        settings.port = port;
+        let pool = connect(
+            &settings.url(&self.config.database_name),
+            self.config.timeouts,
+        )
+        .await?;
+        compat::check_database(&pool, &*self.resolved_config(false).await?).await?;
+        Ok(Some(pool))
+    }
+
+    /// Stops the server running on the configured data directory, returning whether there was
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PostgreSQL`] if the server doesn't stop cleanly.
+    pub async fn stop_running(&self) -> Result<bool> {
+        let Some(port) = self.running_port()? else {
+            return Ok(false);
+        };
+        let mut settings = self.config.settings()?;
+        settings.port = port;
+        PostgreSQL::new(settings).stop().await?;
+        Ok(true)
+    }
+
+    /// Brings an existing database up to the configured extension version and this binary's
+    /// catalog version, which [`start`](Self::start) refuses to do implicitly.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Incompatible`] if the data directory belongs to another PostgreSQL major
+    /// version, the installed extension is newer than `extension_version` (extensions can't be
+    /// downgraded) or the database was last used by a newer pgevdb, and the errors of
+    /// [`start`](Self::start) otherwise.
+    pub async fn upgrade(&self) -> Result<UpgradeReport> {
+        let config = &*self.resolved_config(true).await?;
+        // Replace outdated extension files while the server is down, so it loads the new library
+        if let Some(installed) = config
+            .installed_extension_version()?
+            .filter(|version| *version != config.extension_version)
+        {
+            compat::check_extension_upgrade(config, &installed)?;
+            extension::install(&config.backend, config, Platform::current()).await?;
+        }
+        let (postgresql, pool) = provision(config, &self.extensions).await?;
+
+        let extension_from = compat::extension_version(&pool, config.backend).await?;
+        if let Some(from) = extension_from
+            .as_ref()
+            .filter(|version| **version != config.extension_version)
+        {
+            compat::check_extension_upgrade(config, from)?;
+            info!("Updating extension to {}", config.extension_version);
+            sqlx::query(&format!(
+                "ALTER EXTENSION {} UPDATE TO '{}'",
+                config.backend.extension_name(),
+                config.extension_version
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        let catalog_from = catalog::schema_version(&pool).await?;
+        match catalog_from {
+            Some(version) if version > catalog::SCHEMA_VERSION => {
+                return Err(compat::newer_catalog(version))
+            }
+            Some(version) if version == catalog::SCHEMA_VERSION => {}
+            version => {
+                info!("Migrating catalog to version {}", catalog::SCHEMA_VERSION);
+                catalog::migrate(&pool, version.unwrap_or(0)).await?;
+            }
+        }
+
+        pool.close().await;
+        postgresql.stop().await?;
+        Ok(UpgradeReport {
+            extension_from,
+            extension_to: config.extension_version.clone(),
+            catalog_from,
+            catalog_to: catalog::SCHEMA_VERSION,
+        })
+    }
+
+    /// The config with an `extension_version` of [`LATEST_EXTENSION_VERSION`] replaced by the
+    /// installed version, or with `refresh` or nothing installed yet, by the newest compatible
+    /// release.
+    async fn resolved_config(&self, refresh: bool) -> Result<Cow<'_, Config>> {
+        if self.config.extension_version != LATEST_EXTENSION_VERSION {
+            return Ok(Cow::Borrowed(&self.config));
+        }
+        let mut config = self.config.clone();
+        config.extension_version = match config.installed_extension_version()? {
+            Some(installed) if !refresh => installed,
+            _ => {
+                let latest = extension::latest_version(&config, Platform::current()).await?;
+                info!("The newest compatible pgvecto.rs release is {latest}");
+                latest
+            }
+        };
+        Ok(Cow::Owned(config))
+    }
+
+    /// [`start`](Self::start), stopping early with [`Error::Cancelled`] once `cancel` fires.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Cancelled`] if cancelled, and the errors of [`start`](Self::start)
+    /// otherwise.
+    pub async fn start_with_cancellation(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<(PostgreSQL, PgPool)> {
+        tokio::select! {
+            () = cancel.cancelled() => Err(Error::Cancelled),
+            result = self.start() => result,
+        }
+    }
+}
+
+/// A running server started by [`PgEvDb::launch`]. The server stops when this is dropped.
+#[derive(Debug)]
+pub struct Instance {
+    postgresql: PostgreSQL,
+    pool: PgPool,
+    /// With the extension version resolved, for setting up further databases.
+    config: Config,
+    extensions: Vec<Arc<dyn Extension>>,
+}
+
+impl Instance {
+    /// Pool connected to the configured database.
+    #[must_use]
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// The embedded server, for operations on other databases such as [`branch`](crate::branch).
+    #[must_use]
+    pub fn postgresql(&self) -> &PostgreSQL {
+        &self.postgresql
+    }
+
+    /// Splits the handle into the values [`PgEvDb::start`] returns.
+    #[must_use]
+    pub fn into_parts(self) -> (PostgreSQL, PgPool) {
+        (self.postgresql, self.pool)
+    }
+
+    /// Creates an empty collection of `dimensions`-dimensional vectors compared by `metric` in
+    /// the configured database, see [`Collection::create`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the name or dimension count is invalid or the
+    /// collection already exists.
+    pub async fn create_collection(
+        &self,
+        name: &str,
+        dimensions: u32,
+        metric: Metric,
+    ) -> Result<Collection> {
+        Collection::create(&self.pool, name, dimensions, metric).await
+    }
+
+    /// Opens an existing collection of the configured database, see [`Collection::open`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if there is no collection called `name`.
+    pub async fn collection(&self, name: &str) -> Result<Collection> {
+        Collection::open(&self.pool, name).await
+    }
+
+    /// Handle to the collections of tenant `name` in the configured database, see [`Tenant`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `name` isn't a valid tenant name.
+    pub fn tenant(&self, name: &str) -> Result<Tenant> {
+        Tenant::new(&self.pool, name)
+    }
+
+    /// Creates the database `name` on the server with the extensions and the pgevdb catalog
+    /// set up as in the configured database, returning a pool connected to it. Each database
+    /// has its own collections, quotas and statistics.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `name` isn't a valid database name or already exists.
+    pub async fn create_database(&self, name: &str) -> Result<PgPool> {
+        database::create(&self.postgresql, name).await?;
+        let pool = connect(&self.postgresql.settings().url(name), self.config.timeouts).await?;
+        for extension in all_extensions(&self.config, &self.extensions) {
+            extension::enable(&pool, extension).await?;
+        }
+        compat::check_database(&pool, &self.config).await?;
+        Ok(pool)
+    }
+
+    /// A new pool connected to the existing database `name` of the server, such as one made
+    /// with [`create_database`](Self::create_database) or a [branch](crate::branch).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if there is no database called `name`, and
+    /// [`Error::Incompatible`] if it was set up by other extension or pgevdb versions.
+    pub async fn database(&self, name: &str) -> Result<PgPool> {
+        if !database::exists_on(&self.postgresql, name).await? {
+            return Err(Error::NotFound(format!("database '{name}'")));
+        }
+        let pool = connect(&self.postgresql.settings().url(name), self.config.timeouts).await?;
+        compat::check_database(&pool, &self.config).await?;
+        Ok(pool)
+    }
+
+    /// The databases of the server, see [`database::list`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the server can't be queried.
+    pub async fn databases(&self) -> Result<Vec<Database>> {
+        database::list(&self.postgresql).await
+    }
+
+    /// Drops the database `name` with everything in it, closing the connections to it. The
+    /// configured database, which [`pool`](Self::pool) connects to, can't be dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] for the configured database, and the errors of
+    /// [`database::delete`] otherwise.
+    pub async fn drop_database(&self, name: &str) -> Result<()> {
+        if name == self.config.database_name {
+            return Err(Error::InvalidInput(format!(
+                "'{name}' is the configured database of this instance"
+            )));
+        }
+        database::delete(&self.postgresql, name).await
+    }
+
+    /// Closes the pool and stops the server, reporting shutdown errors that dropping would ignore.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PostgreSQL`] if the server doesn't stop cleanly.
+    pub async fn stop(self) -> Result<()> {
+        self.pool.close().await;
+        self.postgresql.stop().await?;
+        Ok(())
+    }
+}
+
+/// Result of [`PgEvDb::upgrade`].
+#[derive(Debug, Serialize)]
+pub struct UpgradeReport {
+    pub extension_from: Option<String>,
+    pub extension_to: String,
+    pub catalog_from: Option<u32>,
+    pub catalog_to: u32,
+}
+
+/// Installs (on first run) and starts PostgreSQL, creates the database and sets up the vector
+/// extension followed by the configured `extensions` and then `extensions`, all with at most
+/// one restart, returning the running server and a pool connected to the
+/// database.
+async fn provision(
+    config: &Config,
+    extensions: &[Arc<dyn Extension>],
+) -> Result<(PostgreSQL, PgPool)> {
+    let pg_version = &config.pg_version;
+    let database_name = config.database_name.as_str();
+    privileges::ensure_unprivileged(config)?;
+    compat::check_data_dir(config)?;
+    if let Some(port) = running_port(config)? {
+        return Err(Error::AlreadyRunning {
+            data_dir: config.data_dir(),
+            port,
+        });
+    }
+    let settings = config.settings()?;
+
+    info!("Password file: {}", settings.password_file.display());
+    // Written before setup, which would create it with default permissions
+    privileges::secure_password_file(&settings.password_file, &settings.password)?;
+
+    info!("Starting PostgreSQL v{}", pg_version);
+    let mut postgresql = PostgreSQL::new(settings);
+    // The PostgreSQL archive download doesn't report progress, so show a spinner
+    let progress = Progress::spinner(format!("Setting up PostgreSQL v{pg_version}"));
+    if !cfg!(feature = "bundled-postgresql") {
+        install_postgresql(config, postgresql.settings()).await?;
+    }
+    postgresql.setup().await?;
+    progress.finish();
+    postgresql.start().await.map_err(Error::StartupTimeout)?;
+
+    if !postgresql.database_exists(database_name).await? {
+        info!("Creating database '{}'", database_name);
+        postgresql.create_database(database_name).await?;
+    }
+    let database_url = postgresql.settings().url(database_name);
+
+    let mut pool = connect(&database_url, config.timeouts).await?;
+
+    let platform = Platform::current();
+    let extensions = all_extensions(config, extensions);
+    // Each step checks its own state rather than assuming the previous run finished, so a
+    // cancelled or failed setup resumes where it stopped
+    for extension in &extensions {
+        info!(
+            "Checking if the {} extension is installed",
+            extension.name()
+        );
+        if !extension::is_installed(*extension, config, platform) {
+            extension::install(*extension, config, platform).await?;
+        }
+    }
+
+    match extension::configure(&pool, &extensions).await? {
+        Reconfigured::Unchanged => {}
+        Reconfigured::Reconnect => {
+            pool.close().await;
+            pool = connect(&database_url, config.timeouts).await?;
+        }
+        Reconfigured::Restart => {
+            // Restart PostgreSQL to load the libraries and reconnect pool
+            info!("Restarting PostgreSQL to load the extensions");
+            pool.close().await;
+            postgresql.stop().await?;
+            postgresql.start().await.map_err(Error::StartupTimeout)?;
+            pool = connect(&database_url, config.timeouts).await?;
+        }
+    }
+
+    for extension in &extensions {
+        info!("Enabling the {} extension", extension.name());
+        extension::enable(&pool, *extension).await?;
+    }
+
+    Ok((postgresql, pool))
+}
+
+/// The vector extension, the configured `extensions` and then `extensions`, in setup order.
+fn all_extensions<'a>(
+    config: &'a Config,
+    extensions: &'a [Arc<dyn Extension>],
+) -> Vec<&'a dyn Extension> {
+    std::iter::once(&config.backend as &dyn Extension)
+        .chain(
+            config
+                .extensions
+                .iter()
+                .map(|extension| extension as &dyn Extension),
+        )
+        .chain(extensions.iter().map(AsRef::as_ref))
+        .collect()
+}
+
+/// Installs PostgreSQL from the archive in the cache directory, downloading it there first.
+/// `setup()` would download it again for every new storage directory.
+async fn install_postgresql(config: &Config, settings: &Settings) -> Result<()> {
+    let pg_dir = config.pg_dir();
+    if pg_dir.exists() {
+        return Ok(());
+    }
+    let cache_file = config.cache_dir().join("postgresql").join(format!(
+        "postgresql-{}-{}.tar.gz",
+        config.pg_version,
+        Platform::current()
+    ));
+    let bytes = download::cached(&cache_file, async {
+        info!("Downloading PostgreSQL v{}", config.pg_version);
+        let (_version, bytes) =
+            postgresql_archive::get_archive(&settings.releases_url, &settings.version)
+                .await
+                .map_err(postgresql_embedded::Error::from)?;
+        Ok(bytes)
+    })
+    .await?;
+    postgresql_archive::extract(&settings.releases_url, &bytes, &pg_dir)
+        .await
+        .map_err(postgresql_embedded::Error::from)?;
+    Ok(())
+}
+
+/// Port of the server running on the data directory, read from the fourth line of its
+/// `postmaster.pid`, if that port accepts connections.
+fn running_port(config: &Config) -> Result<Option<u16>> {
+    let contents = match std::fs::read_to_string(config.data_dir().join("postmaster.pid")) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let Some(port) = contents
+        .lines()
+        .nth(3)
+        .and_then(|line| line.trim().parse().ok())
+    else {
+        return Ok(None);
+    };
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    Ok(TcpStream::connect_timeout(&address, Duration::from_secs(1))
+        .is_ok()
+        .then_some(port))
+}
+
+/// Connects a pool whose sessions apply the configured `statement_timeout`.
+async fn connect(database_url: &str, timeouts: Timeouts) -> Result<PgPool> {
+    let mut options = PgConnectOptions::from_str(database_url)?;
+    if let Some(statement_timeout) = timeouts.statement() {
+        options = options.options([(
+            "statement_timeout",
+            format!("{}ms", statement_timeout.as_millis()),
+        )]);
+    }
+    Ok(PgPool::connect_with(options).await?)
+}
@@ -0,0 +1,92 @@
+//! Embedded PostgreSQL with a vector search extension.
+//!
+//! [`PgEvDb::start`] installs PostgreSQL and the extension on first use, starts the server and
+//! returns a connection pool to the configured database:
+//!
+//! ```no_run
+//! # async fn demo() -> pgevdb::Result<()> {
+//! let db = pgevdb::PgEvDb::builder()
+//!     .version("16.3.0")
+//!     .data_dir("data")
+//!     .durability(pgevdb::Durability::Balanced)
+//!     .build()?;
+//! let (_postgresql, pool) = db.start().await?;
+//! sqlx::query("CREATE TABLE items (id bigserial PRIMARY KEY, embedding vector(3))")
+//!     .execute(&pool)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`PgEvDb::launch`] returns the same as one [`Instance`] handle with `pool()` and `stop()`.
+//!
+//! Every fallible function returns [`Error`], whose variants can be matched on, e.g. to retry on
+//! [`Error::StartupTimeout`] or to tell users about [`Error::UnsupportedPlatform`].
+
+#![forbid(unsafe_code)]
+#![deny(clippy::pedantic)]
+
+#[cfg(feature = "server")]
+mod auth;
+pub mod benchmark;
+pub mod blocking;
+pub mod branch;
+mod builder;
+mod catalog;
+pub mod clustering;
+pub mod collection;
+mod compat;
+pub mod config;
+pub mod database;
+pub mod diversity;
+pub mod download;
+pub mod embedder;
+mod error;
+pub mod evaluation;
+pub mod extension;
+pub mod filter;
+#[cfg(feature = "server")]
+pub mod grpc;
+#[cfg(feature = "server")]
+pub mod http;
+pub mod hybrid;
+mod identifier;
+pub mod import;
+pub mod index;
+pub mod ingest;
+mod instance;
+pub mod maintenance;
+pub mod monitoring;
+#[cfg(feature = "server")]
+pub mod openapi;
+pub mod platform;
+mod privileges;
+mod progress;
+#[cfg(feature = "server")]
+mod protobuf;
+pub mod quota;
+pub mod rag;
+pub mod rerank;
+pub mod scoring;
+pub mod search;
+mod source_build;
+pub mod stats;
+pub mod tenant;
+pub mod text_search;
+pub mod transform;
+mod vector;
+
+pub use builder::PgEvDbBuilder;
+pub use collection::{Collection, Content, Exactness, Metric, Page, Record, SearchResult, Storage};
+pub use config::{ApiConfig, ApiKey, Backend, Config, Durability, ExtensionConfig, Timeouts};
+pub use embedder::Embedder;
+pub use error::{Error, Result};
+pub use extension::Extension;
+pub use filter::Filter;
+pub use index::IndexConfig;
+pub use instance::{Instance, PgEvDb, UpgradeReport};
+pub use search::SearchOptions;
+pub use tenant::Tenant;
+pub use tokio_util::sync::CancellationToken;
+pub use transform::Transform;
+pub use vector::{BitVector, SparseVector, Vector};
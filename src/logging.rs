@@ -0,0 +1,39 @@
+use tracing_subscriber::EnvFilter;
+
+use pgevdb::{Error, Result};
+
+/// Starts tracing on stderr, so stdout only carries command results.
+///
+/// The filter is built from the verbosity flags, then `RUST_LOG`, then `--log-filter`, with later
+/// directives overriding earlier ones for the same target.
+pub fn init(verbose: u8, quiet: bool, log_filter: Option<&str>) -> Result<()> {
+    let mut directives = vec![verbosity_directive(verbose, quiet).to_string()];
+    if let Ok(env_directives) = std::env::var(EnvFilter::DEFAULT_ENV) {
+        directives.push(env_directives);
+    }
+    if let Some(log_filter) = log_filter {
+        directives.push(log_filter.to_string());
+    }
+
+    let directives = directives.join(",");
+    let filter = EnvFilter::try_new(&directives)
+        .map_err(|error| Error::Config(format!("log filter '{directives}': {error}")))?;
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+    Ok(())
+}
+
+fn verbosity_directive(verbose: u8, quiet: bool) -> &'static str {
+    if quiet {
+        return "warn";
+    }
+    match verbose {
+        0 => "info",
+        // Debug our own install/setup steps without drowning them in sqlx and hyper output
+        1 => "info,pgevdb=debug",
+        2 => "debug",
+        _ => "trace",
+    }
+}
@@ -1,234 +1,194 @@
 #![forbid(unsafe_code)]
 #![deny(clippy::pedantic)]
 
-use anyhow::Result;
-use postgresql_embedded::{PostgreSQL, Settings};
-use semver::VersionReq;
-use sqlx::postgres::PgConnection;
-use sqlx::postgres::PgPool;
-use sqlx::Executor;
-use sqlx::Row;
-use std::io::Cursor;
-use std::path::PathBuf;
-
-use tracing::info;
-
-const PG_VERSION: &str = "16.3.0";
-const DATABASE_NAME: &str = "test";
+mod bench;
+mod branches;
+mod clean;
+mod cli;
+mod demo;
+mod drift;
+mod eval;
+mod gc;
+mod imports;
+mod info;
+mod logging;
+mod output;
+mod quotas;
+mod self_update;
+#[cfg(feature = "server")]
+mod serve;
+mod server;
+mod usage;
+mod wizard;
+
+use clap::Parser;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::clean::Scope;
+use crate::cli::{Cli, Command};
+use crate::info::InfoReport;
+use crate::output::{emit, OutputFormat, Report};
+use pgevdb::{config, Config, Error, PgEvDb, Result, UpgradeReport};
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Start tracing
-    tracing_subscriber::fmt::init();
-
-    let storage_dir: PathBuf = std::env::current_dir()?.join("data");
-
-    let mut settings = Settings::default();
-    // Generate a random password
-    settings.password_file = storage_dir.join(".pgpass");
-    if settings.password_file.exists() {
-        settings.password = std::fs::read_to_string(settings.password_file.clone())?;
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Ctrl-C drops the running command, which stops the server and removes temporary files.
+    // Commands handling it themselves are waited for instead
+    let result = if cli.command.handles_ctrl_c() {
+        run(cli).await
+    } else {
+        tokio::select! {
+            result = run(cli) => result,
+            _ = tokio::signal::ctrl_c() => Err(Error::Cancelled),
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => error.report(),
     }
+}
 
-    println!("Password file: {:?}", settings.password_file);
-    println!("Password: {:?}", settings.password);
-
-    let installation_dir = storage_dir.join("pg");
-    let data_dir = storage_dir.join("pg_data");
-    settings.installation_dir = installation_dir.clone();
-    settings.data_dir = data_dir.clone();
-    settings.temporary = false;
-
-    settings.version = VersionReq::parse(format!("={}", PG_VERSION).as_str())?;
-
-    info!("Starting PostgreSQL v{}", PG_VERSION);
-    let mut postgresql = PostgreSQL::new(settings);
-    postgresql.setup().await?;
-    postgresql.start().await?;
+async fn run(cli: Cli) -> Result<()> {
+    logging::init(cli.verbose, cli.quiet, cli.log_filter.as_deref())?;
 
-    if !postgresql.database_exists(DATABASE_NAME).await? {
-        info!("Creating database '{}'", DATABASE_NAME);
-        postgresql.create_database(DATABASE_NAME).await?;
+    let root = config::portable_root(cli.root)?;
+    let config_file = cli
+        .config
+        .unwrap_or_else(|| config::default_config_file(root.as_deref()));
+    let mut config = Config::load(&config_file, root.as_deref())?;
+    if cli.build_from_source {
+        config.build_from_source = true;
     }
-    let database_url = postgresql.settings().url(DATABASE_NAME);
-
-    let mut pool = PgPool::connect(database_url.as_str()).await?;
-
-    info!("Checking if pg_vectors extension is installed");
-    if !is_pg_vectors_extension_installed(&installation_dir).await? {
-        info!("Installing pg_vectors extension");
-
-        let mut conn: PgConnection = pool.acquire().await?.detach();
-        install_pg_vectors_extension(&installation_dir).await?;
-        configure_pg_vectors_extension(&mut conn).await?;
-        info!("Successfully set up pg_vectors extension");
-
-        // Restart PostgreSQL to apply changes and reconnect pool
-        postgresql.stop().await?;
-        postgresql.start().await?;
-        pool.close().await;
-        pool = PgPool::connect(database_url.as_str()).await?;
-
-        info!("Enabling pg_vectors extension");
-        enable_pg_vectors_extension(&pool).await?;
+    if let Some(archive) = cli.extension_archive {
+        config.extension_archive = Some(archive);
     }
 
-    // Some tests to verify the extension is working
-
-    println!("Creating table 'items' with vector column");
-    create_table_items(&pool).await?;
-
-    println!("Inserting vector data");
-    insert_vector_data(&pool).await?;
-
-    println!("Demonstrating vector operations");
-    demonstrate_vector_operations(&pool).await?;
-
-    println!("Searching for similar vectors");
-    search_similar_vectors(&pool).await?;
-
-    Ok(())
-}
-
-async fn is_pg_vectors_extension_installed(install_dir: &PathBuf) -> Result<bool> {
-    Ok(install_dir
-        .join(PG_VERSION)
-        .join("lib")
-        .join("vectors.so")
-        .exists())
-}
-
-/// Downloads the pg_vectors extension from the GitHub release page and extracts it to the PostgreSQL installation directory.
-async fn install_pg_vectors_extension(install_dir: &PathBuf) -> Result<()> {
-    info!("Setting up PostgreSQL vector extension");
-
-    // Download extension
-    let url = "https://github.com/tensorchord/pgvecto.rs/releases/download/v0.3.0/vectors-pg16_x86_64-unknown-linux-gnu_0.3.0.zip";
-    info!("Downloading extension from {}", url);
-    let response = reqwest::get(url).await?;
-    let bytes = response.bytes().await?;
-
-    // Extract zip
-    let target = PathBuf::from("vectors");
-    info!("Extracting zip to {:?}", target);
-    zip_extract::extract(Cursor::new(bytes), &target, false)?;
-
-    // Get PostgreSQL directories
-    let pg_dir = install_dir.join(PG_VERSION);
-    let pkglibdir = pg_dir.join("lib").to_str().unwrap().to_string();
-    let sharedir = pg_dir.join("share").to_str().unwrap().to_string();
-    let extension_dir = format!("{}/extension", sharedir);
-
-    // Copy files
-    info!("Copying library to {}", pkglibdir);
-    std::fs::copy("vectors/vectors.so", format!("{}/vectors.so", pkglibdir))?;
-
-    info!("Copying schema files to {}", extension_dir);
-    // Copy all version-specific SQL files
-    for entry in std::fs::read_dir("vectors")? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file()
-            && path
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .starts_with("vectors--")
-        {
-            let file_name = path.file_name().unwrap();
-            std::fs::copy(
-                &path,
-                format!("{}/{}", extension_dir, file_name.to_str().unwrap()),
-            )?;
+    match cli.command {
+        Command::Init { interactive, force } => {
+            init(&config_file, config, interactive, force, cli.output).await
+        }
+        Command::Start => server::start(config, cli.output).await,
+        Command::Stop => server::stop(config, cli.output).await,
+        Command::Status => server::status(config, cli.output),
+        Command::Sql { command, psql_args } => server::sql(command, psql_args, config).await,
+        #[cfg(feature = "server")]
+        Command::Serve { http, grpc } => serve::run(http, grpc, config, cli.output).await,
+        Command::Demo => demo::run(config, cli.output).await,
+        Command::Upgrade => emit(cli.output, &PgEvDb::from_config(config)?.upgrade().await?),
+        Command::Eval { command } => eval::run(command, config, cli.output).await,
+        Command::Import { command } => imports::run(command, config, cli.output).await,
+        Command::Bench { command } => bench::run(command, config, cli.output).await,
+        Command::Drift { command } => drift::run(command, config, cli.output).await,
+        Command::Branch { command } => branches::run(command, config, cli.output).await,
+        Command::Gc {
+            retention_days,
+            batch_size,
+        } => gc::run(retention_days, batch_size, config, cli.output).await,
+        Command::Quota { command } => quotas::run(command, config, cli.output).await,
+        Command::Usage { window_hours } => usage::run(window_hours, config, cli.output).await,
+        Command::Info => emit(cli.output, &InfoReport::collect(&config)?),
+        Command::SelfUpdate { check } => {
+            emit(cli.output, &self_update::self_update(check, &config).await?)
+        }
+        Command::Clean {
+            cache,
+            data,
+            all,
+            yes,
+        } => {
+            let scopes: Vec<Scope> = [
+                (cache, Scope::Cache),
+                (data, Scope::Data),
+                (all, Scope::All),
+            ]
+            .into_iter()
+            .filter_map(|(selected, scope)| selected.then_some(scope))
+            .collect();
+            emit(cli.output, &clean::clean(&config, &scopes, yes)?)
         }
     }
-    std::fs::copy(
-        "vectors/vectors.control",
-        format!("{}/vectors.control", extension_dir),
-    )?;
-
-    // Delete the extracted vectors directory
-    info!("Deleting extracted vectors directory");
-    std::fs::remove_dir_all(target)?;
-
-    info!("PostgreSQL vector extension install complete");
-
-    Ok(())
 }
 
-async fn configure_pg_vectors_extension(conn: &mut PgConnection) -> Result<()> {
-    // Add extension to shared_preload_libraries
-    info!("Adding extension to shared_preload_libraries");
-    conn.execute("ALTER SYSTEM SET shared_preload_libraries = \"vectors.so\"")
-        .await?;
-
-    // Add extension to search_path
-    info!("Adding extension to search_path");
-    conn.execute("ALTER SYSTEM SET search_path = \"$user\", public, vectors")
-        .await?;
-
-    Ok(())
-}
-
-async fn enable_pg_vectors_extension(pool: &PgPool) -> Result<()> {
-    let query = "CREATE EXTENSION vectors;";
-    sqlx::query(query).execute(pool).await?;
-    Ok(())
+/// Result of `pgevdb init`.
+#[derive(Serialize)]
+struct InitReport {
+    config_file: PathBuf,
+    storage_dir: PathBuf,
+    pg_version: String,
+    backend: &'static str,
 }
 
-async fn create_table_items(pool: &PgPool) -> Result<()> {
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS items (
-            id bigserial PRIMARY KEY,
-            embedding vector(3) NOT NULL
-        );",
-    )
-    .execute(pool)
-    .await?;
-
-    Ok(())
-}
-
-async fn insert_vector_data(pool: &PgPool) -> Result<()> {
-    sqlx::query("INSERT INTO items (embedding) VALUES ('[1,2,3]'), ('[4,5,6]');")
-        .execute(pool)
-        .await?;
-
-    sqlx::query(
-        "INSERT INTO items (embedding) VALUES (ARRAY[1, 2, 3]::real[]), (ARRAY[4, 5, 6]::real[]);",
-    )
-    .execute(pool)
-    .await?;
-
-    Ok(())
+impl Report for InitReport {
+    fn print_text(&self) {
+        println!("Wrote {}", self.config_file.display());
+        println!(
+            "Provisioned PostgreSQL v{} with {} in {}",
+            self.pg_version,
+            self.backend,
+            self.storage_dir.display()
+        );
+    }
 }
 
-async fn demonstrate_vector_operations(pool: &PgPool) -> Result<()> {
-    let queries = [
-        "SELECT '[1, 2, 3]'::vector <-> '[3, 2, 1]'::vector AS squared_euclidean_distance;",
-        "SELECT '[1, 2, 3]'::vector <#> '[3, 2, 1]'::vector AS negative_dot_product;",
-        "SELECT '[1, 2, 3]'::vector <=> '[3, 2, 1]'::vector AS cosine_distance;",
-    ];
-
-    for query in queries {
-        let result: (f32,) = sqlx::query_as(query).fetch_one(pool).await?;
-        println!("{}: {}", query, result.0);
+/// Writes the config file and provisions the instance it describes.
+async fn init(
+    config_file: &Path,
+    defaults: Config,
+    interactive: bool,
+    force: bool,
+    output: OutputFormat,
+) -> Result<()> {
+    if config_file.exists() && !force {
+        return Err(Error::Config(format!(
+            "{} already exists; pass --force to overwrite it",
+            config_file.display()
+        )));
     }
 
-    Ok(())
+    let db = PgEvDb::from_config(if interactive {
+        wizard::prompt_config(&defaults)?
+    } else {
+        defaults
+    })?;
+    let config = db.config();
+    config.save(config_file)?;
+
+    let (postgresql, pool) = db.start().await?;
+    pool.close().await;
+    postgresql.stop().await?;
+
+    emit(
+        output,
+        &InitReport {
+            config_file: config_file.to_path_buf(),
+            storage_dir: config.storage_dir.clone(),
+            pg_version: config.pg_version.clone(),
+            backend: config.backend.name(),
+        },
+    )
 }
 
-async fn search_similar_vectors(pool: &PgPool) -> Result<()> {
-    let query = "SELECT id, embedding::text FROM items ORDER BY embedding <-> '[3,2,1]' LIMIT 5;";
-    let rows = sqlx::query(query).fetch_all(pool).await?;
-
-    println!("Similar vectors:");
-    for row in rows {
-        let id: i64 = row.get("id");
-        let embedding: String = row.get("embedding");
-        println!("ID: {}, Embedding: {}", id, embedding);
+impl Report for UpgradeReport {
+    fn print_text(&self) {
+        match &self.extension_from {
+            Some(from) if *from != self.extension_to => {
+                println!("Updated extension {from} -> {}", self.extension_to);
+            }
+            _ => println!("Extension is at {}", self.extension_to),
+        }
+        match self.catalog_from {
+            Some(from) if from == self.catalog_to => {
+                println!("Catalog is at version {}", self.catalog_to);
+            }
+            from => println!(
+                "Migrated catalog {} -> {}",
+                from.map_or_else(|| "none".to_string(), |from| from.to_string()),
+                self.catalog_to
+            ),
+        }
     }
-
-    Ok(())
 }
@@ -0,0 +1,535 @@
+//! Housekeeping that keeps the catalog from accumulating cruft (leftovers of interrupted
+//! operations, statistics about collections that no longer exist, history past retention), bulk
+//! deletion of records, eviction of expired records, and planner statistics after bulk changes.
+//!
+//! Everything is deleted in bounded batches, each its own statement, so a large deletion never
+//! holds long locks or builds one huge transaction.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use sqlx::{Postgres, QueryBuilder};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::collection::Collection;
+use crate::error::{Error, Result};
+use crate::filter::Filter;
+use crate::progress::Progress;
+use crate::stats;
+
+/// Retention and batch size of [`collect_garbage`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GcOptions {
+    /// Usage statistics and drift check history older than this are deleted.
+    pub retention: Duration,
+    /// Rows deleted per statement.
+    pub batch_size: u32,
+    /// Benchmark copies older than this are considered abandoned by a crashed run.
+    pub abandoned_after: Duration,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            retention: Duration::from_secs(90 * 86_400),
+            batch_size: 1000,
+            abandoned_after: Duration::from_secs(86_400),
+        }
+    }
+}
+
+/// Rows removed from one table.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Reclaimed {
+    pub target: String,
+    pub rows: u64,
+}
+
+/// Result of [`collect_garbage`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GcReport {
+    /// Benchmark copies left behind by interrupted `compare` runs, now dropped.
+    pub dropped_collections: Vec<String>,
+    pub reclaimed: Vec<Reclaimed>,
+    pub total_rows: u64,
+}
+
+/// Tables cleaned of rows matching a condition, with a description for the report.
+const TARGETS: &[(&str, &str, &str)] = &[
+    (
+        "usage statistics of deleted collections",
+        "pgevdb.usage_stats",
+        "collection NOT IN (SELECT name FROM pgevdb.collections)",
+    ),
+    (
+        "usage statistics past retention",
+        "pgevdb.usage_stats",
+        "hour < now() - make_interval(secs => $1)",
+    ),
+    (
+        "evaluation runs of deleted collections",
+        "pgevdb.eval_runs",
+        "collection NOT IN (SELECT name FROM pgevdb.collections)",
+    ),
+    (
+        "drift checks past retention",
+        "pgevdb.drift_checks",
+        "checked_at < now() - make_interval(secs => $1)",
+    ),
+];
+
+/// Drops abandoned benchmark copies and deletes orphaned and expired catalog rows.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if a table can't be cleaned; batches
+/// deleted before the failure stay deleted.
+pub async fn collect_garbage(pool: &PgPool, options: &GcOptions) -> Result<GcReport> {
+    let abandoned: Vec<(String,)> = sqlx::query_as(
+        "SELECT name FROM pgevdb.collections
+         WHERE starts_with(name, '_bench_') AND created_at < now() - make_interval(secs => $1)",
+    )
+    .bind(options.abandoned_after.as_secs_f64())
+    .fetch_all(pool)
+    .await?;
+    let mut dropped_collections = Vec::with_capacity(abandoned.len());
+    for (name,) in abandoned {
+        info!("Dropping abandoned benchmark collection '{name}'");
+        Collection::open(pool, &name).await?.delete().await?;
+        dropped_collections.push(name);
+    }
+
+    let mut reclaimed = Vec::with_capacity(TARGETS.len() + 1);
+    for (target, table, condition) in TARGETS {
+        let rows = delete_batched(pool, table, condition, options).await?;
+        reclaimed.push(Reclaimed {
+            target: (*target).to_string(),
+            rows,
+        });
+    }
+    let mut expired = 0;
+    for name in collection_names(pool).await? {
+        let table = format!("collections.{name}");
+        expired += delete_batched(pool, &table, EXPIRED, options).await?;
+    }
+    reclaimed.push(Reclaimed {
+        target: "expired records".to_string(),
+        rows: expired,
+    });
+    Ok(GcReport {
+        dropped_collections,
+        total_rows: reclaimed.iter().map(|reclaimed| reclaimed.rows).sum(),
+        reclaimed,
+    })
+}
+
+/// Condition of the records past their expiry time, see [`Collection::set_ttl`].
+const EXPIRED: &str = "expires_at <= now()";
+
+/// Names of all collections.
+async fn collection_names(pool: &PgPool) -> Result<Vec<String>> {
+    let names: Vec<(String,)> = sqlx::query_as("SELECT name FROM pgevdb.collections ORDER BY name")
+        .fetch_all(pool)
+        .await?;
+    Ok(names.into_iter().map(|(name,)| name).collect())
+}
+
+/// Deletes the rows of `table` matching `condition` (which may use the retention as `$1`) in
+/// batches, returning how many were deleted.
+async fn delete_batched(
+    pool: &PgPool,
+    table: &str,
+    condition: &str,
+    options: &GcOptions,
+) -> Result<u64> {
+    let sql = format!(
+        "DELETE FROM {table} WHERE ctid = ANY(ARRAY(
+             SELECT ctid FROM {table} WHERE {condition} LIMIT $2
+         ))"
+    );
+    let mut total = 0;
+    loop {
+        let deleted = sqlx::query(&sql)
+            .bind(options.retention.as_secs_f64())
+            .bind(i64::from(options.batch_size.max(1)))
+            .execute(pool)
+            .await?
+            .rows_affected();
+        total += deleted;
+        if deleted == 0 {
+            return Ok(total);
+        }
+    }
+}
+
+/// Batching and throttling of [`delete_where`] and [`compact`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeleteOptions {
+    /// Records deleted per statement and transaction.
+    pub batch_size: u32,
+    /// Sleep between batches, to leave I/O for queries on a busy database.
+    pub pause: Option<Duration>,
+    /// Refresh the planner statistics of the collection afterwards.
+    pub analyze: bool,
+}
+
+impl Default for DeleteOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 1000,
+            pause: None,
+            analyze: true,
+        }
+    }
+}
+
+/// Result of [`delete_where`] and [`compact`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct DeleteReport {
+    pub deleted: u64,
+    pub batches: u64,
+}
+
+/// Deletes the records of `collection` matching `filter` in batches, reporting progress, and
+/// runs `ANALYZE` on the collection when done so the planner sees its new size.
+///
+/// Quota usage isn't reduced until the next [reconciliation](crate::quota::reconcile).
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if a batch fails; batches deleted before
+/// the failure stay deleted.
+pub async fn delete_where(
+    collection: &Collection,
+    filter: &Filter,
+    options: &DeleteOptions,
+) -> Result<DeleteReport> {
+    delete_matching(
+        collection,
+        |builder| filter.push_where(builder),
+        options,
+        "",
+    )
+    .await
+}
+
+/// Physically deletes the records of `collection` that were
+/// [soft-deleted](Collection::soft_delete), in batches as [`delete_where`] does, and runs
+/// `ANALYZE` on the collection when done. Run it when few searches are expected: each batch
+/// updates the vector index.
+///
+/// Quota usage isn't reduced until the next [reconciliation](crate::quota::reconcile).
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if a batch fails; batches deleted before the failure stay
+/// deleted.
+pub async fn compact(collection: &Collection, options: &DeleteOptions) -> Result<DeleteReport> {
+    delete_matching(
+        collection,
+        |builder| {
+            builder.push(" WHERE deleted_at IS NOT NULL");
+        },
+        options,
+        "compact",
+    )
+    .await
+}
+
+/// Deletes the records of `collection` selected by the `WHERE` clause that `push_where` appends
+/// in batches, as [`delete_where`] describes, recording the deletion with `shape`.
+async fn delete_matching(
+    collection: &Collection,
+    push_where: impl Fn(&mut QueryBuilder<'_, Postgres>),
+    options: &DeleteOptions,
+    shape: &str,
+) -> Result<DeleteReport> {
+    let started = Instant::now();
+    let table = collection.table();
+    let mut count = QueryBuilder::new(format!("SELECT count(*) FROM {table}"));
+    push_where(&mut count);
+    let (matching,): (i64,) = count.build_query_as().fetch_one(collection.pool()).await?;
+
+    let mut progress = Progress::items(
+        format!("Deleting from {}", collection.name()),
+        Some(matching.unsigned_abs()),
+        "records",
+    );
+    let mut report = DeleteReport {
+        deleted: 0,
+        batches: 0,
+    };
+    loop {
+        let mut batch = QueryBuilder::new(format!(
+            "DELETE FROM {table} WHERE id = ANY(ARRAY(SELECT id FROM {table}"
+        ));
+        push_where(&mut batch);
+        batch
+            .push(" LIMIT ")
+            .push_bind(i64::from(options.batch_size.max(1)))
+            .push("))");
+        let deleted = batch
+            .build()
+            .execute(collection.pool())
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            break;
+        }
+        report.deleted += deleted;
+        report.batches += 1;
+        progress.inc(deleted);
+        if let Some(pause) = options.pause {
+            tokio::time::sleep(pause).await;
+        }
+    }
+    progress.finish();
+
+    if options.analyze && report.deleted > 0 {
+        sqlx::query(&format!("ANALYZE {table}"))
+            .execute(collection.pool())
+            .await?;
+    }
+    stats::record(
+        collection.pool(),
+        collection.name(),
+        "delete",
+        shape,
+        started,
+    )
+    .await;
+    Ok(report)
+}
+
+/// Deletes the records of `collection` whose expiry time has passed, see
+/// [`Collection::set_ttl`], like [`delete_where`] with [`Filter::Expired`].
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if a batch fails; batches deleted before
+/// the failure stay deleted.
+pub async fn delete_expired(
+    collection: &Collection,
+    options: &DeleteOptions,
+) -> Result<DeleteReport> {
+    delete_where(collection, &Filter::Expired, options).await
+}
+
+/// Deletes the expired records of every collection with [`delete_expired`], returning how many
+/// there were.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if a collection can't be cleaned;
+/// collections cleaned before it stay cleaned.
+pub async fn delete_all_expired(pool: &PgPool, options: &DeleteOptions) -> Result<u64> {
+    let mut deleted = 0;
+    for name in collection_names(pool).await? {
+        let collection = Collection::open(pool, &name).await?;
+        deleted += delete_expired(&collection, options).await?.deleted;
+    }
+    Ok(deleted)
+}
+
+/// Starts a task on the current Tokio runtime that deletes the expired records of every
+/// collection with [`delete_all_expired`] right away and then every `period`, until `cancel`
+/// is cancelled. Failed rounds are logged and retried at the next period. The task doesn't
+/// analyze after deleting; autovacuum keeps the statistics of a steadily expiring collection
+/// current.
+///
+/// ```no_run
+/// # async fn demo(pool: sqlx::PgPool) {
+/// use std::time::Duration;
+/// use pgevdb::{maintenance, CancellationToken};
+///
+/// let cancel = CancellationToken::new();
+/// let sweeper = maintenance::spawn_expiry(pool, Duration::from_secs(60), cancel.clone());
+/// // ... serve requests ...
+/// cancel.cancel();
+/// sweeper.await.ok();
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// Panics if called outside a Tokio runtime, or if `period` is zero.
+#[must_use = "the task stops only when `cancel` is cancelled; await the handle to wait for it"]
+pub fn spawn_expiry(pool: PgPool, period: Duration, cancel: CancellationToken) -> JoinHandle<()> {
+    let options = DeleteOptions {
+        analyze: false,
+        ..DeleteOptions::default()
+    };
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = cancel.cancelled() => return,
+                _ = ticks.tick() => {}
+            }
+            match delete_all_expired(&pool, &options).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("Deleted {deleted} expired records"),
+                Err(error) => warn!("Deleting expired records failed: {error}"),
+            }
+        }
+    })
+}
+
+/// Schedules a `pg_cron` job deleting the expired records of `collection` on the cron
+/// `schedule`, e.g. `"*/5 * * * *"` for every five minutes, replacing an earlier job for the
+/// collection. Unlike [`spawn_expiry`], the job keeps running inside PostgreSQL while no
+/// application is connected. `pg_cron` must be loaded through `shared_preload_libraries` and run
+/// in the collection's database; see [`ExtensionConfig`](crate::ExtensionConfig).
+/// Unschedule the job with [`unschedule_expiry`] before deleting the collection.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if the `pg_cron` extension isn't
+/// created in the database or rejects `schedule`.
+pub async fn schedule_expiry(collection: &Collection, schedule: &str) -> Result<()> {
+    require_pg_cron(collection.pool()).await?;
+    sqlx::query("SELECT cron.schedule($1, $2, $3)")
+        .bind(expiry_job(collection))
+        .bind(schedule)
+        .bind(format!(
+            "DELETE FROM {} WHERE {EXPIRED}",
+            collection.table()
+        ))
+        .execute(collection.pool())
+        .await
+        .map_err(|error| match error {
+            sqlx::Error::Database(error) => Error::InvalidInput(format!(
+                "pg_cron didn't schedule '{schedule}': {}",
+                error.message()
+            )),
+            error => error.into(),
+        })?;
+    Ok(())
+}
+
+/// Removes the `pg_cron` job of [`schedule_expiry`] for `collection`, returning whether there
+/// was one.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if the `pg_cron` extension isn't
+/// created in the database.
+pub async fn unschedule_expiry(collection: &Collection) -> Result<bool> {
+    require_pg_cron(collection.pool()).await?;
+    let removed: Vec<(bool,)> =
+        sqlx::query_as("SELECT cron.unschedule(jobid) FROM cron.job WHERE jobname = $1")
+            .bind(expiry_job(collection))
+            .fetch_all(collection.pool())
+            .await?;
+    Ok(removed.iter().any(|(removed,)| *removed))
+}
+
+fn expiry_job(collection: &Collection) -> String {
+    format!("pgevdb_expire_{}", collection.name())
+}
+
+async fn require_pg_cron(pool: &PgPool) -> Result<()> {
+    let (installed,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT FROM pg_extension WHERE extname = 'pg_cron')")
+            .fetch_one(pool)
+            .await?;
+    if installed {
+        Ok(())
+    } else {
+        Err(Error::InvalidInput(
+            "scheduling needs the pg_cron extension; preload and create it first".to_string(),
+        ))
+    }
+}
+
+/// Records a bulk ingest must add before [`analyze`] runs automatically afterwards.
+pub const ANALYZE_THRESHOLD: u64 = 10_000;
+
+/// Result of [`analyze`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct AnalyzeReport {
+    /// The planner's row estimate after analyzing.
+    pub estimated_rows: u64,
+    /// Whether the collection has a vector index.
+    pub indexed: bool,
+    /// Whether the planner chooses the index for a nearest-neighbor search; `false` without
+    /// an index or records.
+    pub knn_uses_index: bool,
+}
+
+/// Refreshes the planner statistics of `collection` and checks that nearest-neighbor searches
+/// are planned to use its index, logging a warning if an index exists but isn't chosen.
+///
+/// Right after a bulk load the planner still works from the old, often tiny, row estimate and
+/// can pick a sequential scan for the first queries; analyzing fixes the estimate.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if analyzing or planning fails.
+pub async fn analyze(collection: &Collection) -> Result<AnalyzeReport> {
+    let table = collection.table();
+    sqlx::query(&format!("ANALYZE {table}"))
+        .execute(collection.pool())
+        .await?;
+    let (estimated_rows,): (i64,) = sqlx::query_as(
+        "SELECT greatest(reltuples, 0)::bigint FROM pg_class WHERE oid = $1::regclass",
+    )
+    .bind(&table)
+    .fetch_one(collection.pool())
+    .await?;
+    let indexed = collection.index_size().await?.is_some();
+
+    // Plan a search for a stored vector; without any there's nothing to check
+    let probe: Option<(Vec<f32>,)> = sqlx::query_as(&format!(
+        "SELECT {} FROM {table} LIMIT 1",
+        collection.as_array("embedding")
+    ))
+    .fetch_optional(collection.pool())
+    .await?;
+    let mut knn_uses_index = false;
+    if let Some((probe,)) = probe {
+        let plan: Vec<(String,)> = sqlx::query_as(&format!(
+            "EXPLAIN SELECT id FROM {table} ORDER BY embedding {operator} {probe} LIMIT 10",
+            operator = collection.metric().operator(collection.backend()),
+            probe = collection.vector_value("$1")
+        ))
+        .bind(probe)
+        .fetch_all(collection.pool())
+        .await?;
+        knn_uses_index = plan.iter().any(|(line,)| line.contains("Index Scan"));
+        if indexed && !knn_uses_index {
+            warn!(
+                "The planner still chooses a sequential scan for nearest-neighbor searches on '{}' \
+                 despite its index; searches will be slow",
+                collection.name()
+            );
+        }
+    }
+
+    Ok(AnalyzeReport {
+        estimated_rows: estimated_rows.unsigned_abs(),
+        indexed,
+        knn_uses_index,
+    })
+}
+
+/// Runs [`analyze`] after a bulk ingest of `rows` records if there were at least
+/// [`ANALYZE_THRESHOLD`].
+pub(crate) async fn analyze_after_ingest(
+    collection: &Collection,
+    rows: u64,
+) -> Result<Option<AnalyzeReport>> {
+    if rows < ANALYZE_THRESHOLD {
+        return Ok(None);
+    }
+    info!(
+        "Analyzing '{}' after ingesting {rows} records",
+        collection.name()
+    );
+    analyze(collection).await.map(Some)
+}
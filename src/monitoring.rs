@@ -0,0 +1,298 @@
+//! Drift and outlier detection on the distribution of a collection's vectors.
+//!
+//! Recently inserted vectors are compared against a baseline of older ones by their distance to
+//! the baseline centroid. A model upgrade, a broken preprocessing step or a shift in the incoming
+//! data moves new vectors away from where the old ones sit long before search quality visibly
+//! degrades.
+
+use std::time::Duration;
+
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+
+use crate::collection::Collection;
+use crate::error::{Error, Result};
+
+/// Time windows and thresholds of a drift check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriftOptions {
+    /// Length of each window of recent inserts.
+    pub window: Duration,
+    /// Number of recent windows checked; everything older forms the baseline.
+    pub windows: u32,
+    /// Standard deviations from the baseline mean distance beyond which a record is an outlier.
+    pub outlier_threshold: f64,
+    /// Standard deviations the mean distance of a window may shift before it counts as drifted.
+    pub window_threshold: f64,
+    /// Most distant outliers listed in the report.
+    pub max_outliers: u32,
+}
+
+impl Default for DriftOptions {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(86_400),
+            windows: 7,
+            outlier_threshold: 3.0,
+            window_threshold: 1.0,
+            max_outliers: 20,
+        }
+    }
+}
+
+/// Distance statistics of one window of recent inserts.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WindowStats {
+    /// Windows back from now; 0 is the most recent.
+    pub age: u32,
+    pub vectors: u64,
+    pub mean_distance: f64,
+    /// Shift of the mean distance from the baseline, in baseline standard deviations.
+    pub shift: f64,
+    pub outliers: u64,
+    pub drifted: bool,
+}
+
+/// A recent record unusually far from the baseline centroid.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Outlier {
+    pub id: i64,
+    pub distance: f64,
+    /// Distance from the baseline mean, in baseline standard deviations.
+    pub z_score: f64,
+}
+
+/// Result of [`check_drift`], or a summary row of [`drift_history`] with empty `windows` and
+/// `listed_outliers`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DriftReport {
+    pub collection: String,
+    pub baseline_vectors: u64,
+    pub baseline_mean: f64,
+    pub baseline_stddev: f64,
+    pub recent_vectors: u64,
+    pub outliers: u64,
+    pub drifted_windows: u64,
+    pub windows: Vec<WindowStats>,
+    pub listed_outliers: Vec<Outlier>,
+    pub checked_at: String,
+}
+
+/// Compares the vectors inserted in the last `options.windows` windows against the older ones
+/// and records a summary of the check.
+///
+/// Distances are to the mean of the baseline vectors under the collection's metric. When fewer
+/// than two vectors predate the windows, the baseline is the whole collection, so the check still
+/// finds individual outliers but window shifts are measured against data that includes them.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if the collection is empty or `options` has no windows.
+pub async fn check_drift(collection: &Collection, options: &DriftOptions) -> Result<DriftReport> {
+    let pool = collection.pool();
+    let table = collection.table();
+    let operator = collection.metric().operator(collection.backend());
+    let window_secs = options.window.as_secs_f64();
+    if options.windows == 0 || window_secs <= 0.0 {
+        return Err(Error::InvalidInput(
+            "drift checks need at least one non-empty window".to_string(),
+        ));
+    }
+    let recent_secs = window_secs * f64::from(options.windows);
+
+    let older = "inserted_at IS NULL OR inserted_at < now() - make_interval(secs => $2)";
+    let (centroid, baseline_vectors, everything) =
+        baseline_centroid(collection, older, recent_secs).await?;
+
+    let distances = format!(
+        "SELECT id, inserted_at, (embedding {operator} {centroid})::real AS distance FROM {table}",
+        centroid = collection.vector_value("$3")
+    );
+    let (baseline_mean, baseline_stddev): (Option<f64>, Option<f64>) = sqlx::query_as(&format!(
+        "SELECT avg(distance), stddev_pop(distance) FROM ({distances}) d WHERE {older} OR $1"
+    ))
+    .bind(everything)
+    .bind(recent_secs)
+    .bind(&centroid)
+    .fetch_one(pool)
+    .await?;
+    let baseline_mean = baseline_mean.unwrap_or_default();
+    let baseline_stddev = baseline_stddev.unwrap_or_default();
+    let outlier_limit = baseline_mean + options.outlier_threshold * baseline_stddev;
+    let z_score = |distance: f64| {
+        if baseline_stddev > 0.0 {
+            (distance - baseline_mean) / baseline_stddev
+        } else {
+            0.0
+        }
+    };
+
+    let recent = "inserted_at >= now() - make_interval(secs => $2)";
+    let window_rows: Vec<(i64, i64, f64, i64)> = sqlx::query_as(&format!(
+        "SELECT floor(extract(epoch FROM now() - inserted_at)::double precision / $1)::bigint
+                    AS age,
+                count(*), avg(distance), count(*) FILTER (WHERE distance > $4)
+         FROM ({distances}) d WHERE {recent} GROUP BY age ORDER BY age"
+    ))
+    .bind(window_secs)
+    .bind(recent_secs)
+    .bind(&centroid)
+    .bind(outlier_limit)
+    .fetch_all(pool)
+    .await?;
+    let windows: Vec<WindowStats> = window_rows
+        .into_iter()
+        .map(|(age, vectors, mean_distance, outliers)| {
+            let shift = z_score(mean_distance);
+            WindowStats {
+                age: u32::try_from(age).unwrap_or_default(),
+                vectors: vectors.unsigned_abs(),
+                mean_distance,
+                shift,
+                outliers: outliers.unsigned_abs(),
+                drifted: shift.abs() > options.window_threshold,
+            }
+        })
+        .collect();
+
+    let listed: Vec<(i64, f64)> = sqlx::query_as(&format!(
+        "SELECT id, distance::double precision FROM ({distances}) d
+         WHERE {recent} AND distance > $4 ORDER BY distance DESC LIMIT $1"
+    ))
+    .bind(i64::from(options.max_outliers))
+    .bind(recent_secs)
+    .bind(&centroid)
+    .bind(outlier_limit)
+    .fetch_all(pool)
+    .await?;
+
+    let mut report = DriftReport {
+        collection: collection.name().to_string(),
+        baseline_vectors,
+        baseline_mean,
+        baseline_stddev,
+        recent_vectors: windows.iter().map(|window| window.vectors).sum(),
+        outliers: windows.iter().map(|window| window.outliers).sum(),
+        drifted_windows: windows.iter().filter(|window| window.drifted).count() as u64,
+        windows,
+        listed_outliers: listed
+            .into_iter()
+            .map(|(id, distance)| Outlier {
+                id,
+                distance,
+                z_score: z_score(distance),
+            })
+            .collect(),
+        checked_at: String::new(),
+    };
+    report.checked_at = record(pool, &report).await?;
+    Ok(report)
+}
+
+/// Stores the summary of `report` in the check history, returning when it was recorded.
+async fn record(pool: &PgPool, report: &DriftReport) -> Result<String> {
+    let (checked_at,): (String,) = sqlx::query_as(
+        "INSERT INTO pgevdb.drift_checks
+             (collection, baseline_mean, baseline_stddev, recent_vectors, outliers,
+              drifted_windows)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING checked_at::text",
+    )
+    .bind(&report.collection)
+    .bind(report.baseline_mean)
+    .bind(report.baseline_stddev)
+    .bind(i64::try_from(report.recent_vectors).unwrap_or(i64::MAX))
+    .bind(i64::try_from(report.outliers).unwrap_or(i64::MAX))
+    .bind(i64::try_from(report.drifted_windows).unwrap_or(i64::MAX))
+    .fetch_one(pool)
+    .await?;
+    Ok(checked_at)
+}
+
+/// Mean of the vectors inserted before the last `recent_secs`, their count, and whether the
+/// baseline had to be widened to the whole collection because too few vectors are that old.
+async fn baseline_centroid(
+    collection: &Collection,
+    older: &str,
+    recent_secs: f64,
+) -> Result<(Vec<f32>, u64, bool)> {
+    let pool = collection.pool();
+    let table = collection.table();
+    // `$1` widens the baseline to every row
+    let (older_count,): (i64,) =
+        sqlx::query_as(&format!("SELECT count(*) FROM {table} WHERE {older} OR $1"))
+            .bind(false)
+            .bind(recent_secs)
+            .fetch_one(pool)
+            .await?;
+    let everything = older_count < 2;
+
+    let baseline = format!(
+        "SELECT {} FROM {table} WHERE {older} OR $1",
+        collection.as_array("embedding")
+    );
+    let mut rows = sqlx::query_as::<_, (Vec<f32>,)>(&baseline)
+        .bind(everything)
+        .bind(recent_secs)
+        .fetch(pool);
+    let mut sum = vec![0.0_f64; collection.dimensions() as usize];
+    let mut count = 0_u64;
+    while let Some((embedding,)) = rows.try_next().await? {
+        for (sum, x) in sum.iter_mut().zip(embedding) {
+            *sum += f64::from(x);
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return Err(Error::InvalidInput(format!(
+            "collection '{}' is empty",
+            collection.name()
+        )));
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    let centroid = sum.iter().map(|x| (x / count as f64) as f32).collect();
+    Ok((centroid, count, everything))
+}
+
+/// Summaries of earlier drift checks of `collection`, oldest first.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the checks can't be read.
+pub async fn drift_history(pool: &PgPool, collection: &str) -> Result<Vec<DriftReport>> {
+    let rows: Vec<(f64, f64, i64, i64, i64, String)> = sqlx::query_as(
+        "SELECT baseline_mean, baseline_stddev, recent_vectors, outliers, drifted_windows,
+                checked_at::text
+         FROM pgevdb.drift_checks WHERE collection = $1 ORDER BY checked_at",
+    )
+    .bind(collection)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(
+            |(
+                baseline_mean,
+                baseline_stddev,
+                recent_vectors,
+                outliers,
+                drifted_windows,
+                checked_at,
+            )| {
+                DriftReport {
+                    collection: collection.to_string(),
+                    baseline_vectors: 0,
+                    baseline_mean,
+                    baseline_stddev,
+                    recent_vectors: recent_vectors.unsigned_abs(),
+                    outliers: outliers.unsigned_abs(),
+                    drifted_windows: drifted_windows.unsigned_abs(),
+                    windows: Vec::new(),
+                    listed_outliers: Vec::new(),
+                    checked_at,
+                }
+            },
+        )
+        .collect())
+}
@@ -0,0 +1,707 @@
+//! OpenAPI 3 description of the [HTTP API](crate::http), served at `/openapi.json` for client
+//! generators such as `openapi-generator` or `openapi-typescript`:
+//!
+//! ```text
+//! curl localhost:8080/openapi.json > pgevdb.json
+//! openapi-generator generate -i pgevdb.json -g python -o clients/python
+//! ```
+//!
+//! The document is written out here rather than derived from the handlers, so changes to the
+//! API's requests and responses must be made in both places; the tests check each schema
+//! against what serde reads and writes for its type.
+
+use serde_json::{json, Map, Value};
+
+use crate::http;
+
+/// The OpenAPI document of the HTTP API of this version.
+#[must_use]
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "pgevdb",
+            "description": "Collections of vectors with JSON payloads, stored in embedded PostgreSQL.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": merge([collection_paths(), record_paths()]),
+        // Only enforced when the server has keys
+        "security": [{ "bearer": [] }, { "apiKey": [] }],
+        "components": {
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-API-Key" },
+            },
+            "schemas": merge([collection_schemas(), record_schemas()]),
+            "responses": {
+                "BadRequest": error_response("The request is invalid, e.g. a vector with the wrong dimensions"),
+                "Unauthorized": error_response("The request has no API key, or an unknown one"),
+                "Forbidden": error_response("The API key is read-only, or the insert would exceed the namespace's quota"),
+                "NotFound": error_response("The collection or record doesn't exist"),
+                "ContentTooLarge": error_response("The request body is over the server's limit"),
+                "TooManyRequests": too_many_requests(),
+                "InternalError": error_response("The database failed"),
+            },
+        },
+    })
+}
+
+/// Operations on the server and on collections as a whole.
+fn collection_paths() -> Value {
+    json!({
+        "/health": {
+            "get": {
+                "operationId": "health",
+                "summary": "Reports that the server is up",
+                "security": [],
+                "responses": {
+                    "200": json_response("The server is up", json!({
+                        "type": "object",
+                        "required": ["status"],
+                        "properties": { "status": { "type": "string", "enum": ["ok"] } },
+                    })),
+                },
+            },
+        },
+        "/openapi.json": {
+            "get": {
+                "operationId": "openapi",
+                "summary": "This document",
+                "security": [],
+                "responses": {
+                    "200": json_response("The OpenAPI document", json!({ "type": "object" })),
+                },
+            },
+        },
+        "/collections": {
+            "get": {
+                "operationId": "listCollections",
+                "summary": "Lists collections by name",
+                "responses": {
+                    "200": json_response("The collections", json!({
+                        "type": "object",
+                        "required": ["collections"],
+                        "properties": { "collections": array_of("Collection") },
+                    })),
+                    "401": reference("responses", "Unauthorized"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+            "post": {
+                "operationId": "createCollection",
+                "summary": "Creates an empty collection",
+                "requestBody": json_body("CreateCollection"),
+                "responses": {
+                    "201": json_response("The new collection", reference("schemas", "Collection")),
+                    "400": reference("responses", "BadRequest"),
+                    "401": reference("responses", "Unauthorized"),
+                    "403": reference("responses", "Forbidden"),
+                    "413": reference("responses", "ContentTooLarge"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+        },
+        "/collections/{name}": {
+            "parameters": [name_parameter()],
+            "get": {
+                "operationId": "getCollection",
+                "summary": "Describes a collection, with an estimate of its record count",
+                "responses": {
+                    "200": json_response("The collection", reference("schemas", "Collection")),
+                    "401": reference("responses", "Unauthorized"),
+                    "404": reference("responses", "NotFound"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+            "delete": {
+                "operationId": "deleteCollection",
+                "summary": "Deletes a collection and all of its records",
+                "responses": {
+                    "204": { "description": "Deleted" },
+                    "401": reference("responses", "Unauthorized"),
+                    "403": reference("responses", "Forbidden"),
+                    "404": reference("responses", "NotFound"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+        },
+    })
+}
+
+/// Operations on the records of a collection.
+fn record_paths() -> Value {
+    json!({
+        "/collections/{name}/records": {
+            "parameters": [name_parameter()],
+            "post": {
+                "operationId": "insertRecords",
+                "summary": "Inserts records, replacing those whose id is already stored",
+                "requestBody": json_body("InsertRecords"),
+                "responses": {
+                    "200": json_response("The ids of the records, in request order", json!({
+                        "type": "object",
+                        "required": ["ids"],
+                        "properties": {
+                            "ids": { "type": "array", "items": { "type": "integer", "format": "int64" } },
+                        },
+                    })),
+                    "400": reference("responses", "BadRequest"),
+                    "401": reference("responses", "Unauthorized"),
+                    "403": reference("responses", "Forbidden"),
+                    "404": reference("responses", "NotFound"),
+                    "413": reference("responses", "ContentTooLarge"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+        },
+        "/collections/{name}/records/{id}": {
+            "parameters": [
+                name_parameter(),
+                {
+                    "name": "id",
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "integer", "format": "int64" },
+                },
+            ],
+            "get": {
+                "operationId": "getRecord",
+                "summary": "Reads a record",
+                "responses": {
+                    "200": json_response("The record", reference("schemas", "Record")),
+                    "400": reference("responses", "BadRequest"),
+                    "401": reference("responses", "Unauthorized"),
+                    "404": reference("responses", "NotFound"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+            "delete": {
+                "operationId": "deleteRecord",
+                "summary": "Deletes a record",
+                "responses": {
+                    "204": { "description": "Deleted" },
+                    "400": reference("responses", "BadRequest"),
+                    "401": reference("responses", "Unauthorized"),
+                    "403": reference("responses", "Forbidden"),
+                    "404": reference("responses", "NotFound"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+        },
+        "/collections/{name}/search": {
+            "parameters": [name_parameter()],
+            "post": {
+                "operationId": "search",
+                "summary": "Finds the records nearest to a vector, nearest first",
+                "requestBody": json_body("SearchRequest"),
+                "responses": {
+                    "200": json_response("The results", json!({
+                        "type": "object",
+                        "required": ["results"],
+                        "properties": { "results": array_of("SearchResult") },
+                    })),
+                    "400": reference("responses", "BadRequest"),
+                    "401": reference("responses", "Unauthorized"),
+                    "404": reference("responses", "NotFound"),
+                    "413": reference("responses", "ContentTooLarge"),
+                    "429": reference("responses", "TooManyRequests"),
+                    "500": reference("responses", "InternalError"),
+                },
+            },
+        },
+    })
+}
+
+fn collection_schemas() -> Value {
+    let metric = json!({
+        "type": "string",
+        "enum": ["l2", "cosine", "dot", "hamming", "jaccard"],
+        "default": "l2",
+    });
+    let storage = json!({
+        "type": "string",
+        "enum": ["f32", "f16", "sparse", "binary"],
+        "default": "f32",
+    });
+    json!({
+        "Error": {
+            "type": "object",
+            "required": ["error"],
+            "properties": { "error": { "type": "string" } },
+        },
+        "Collection": {
+            "type": "object",
+            "required": ["name", "dimensions", "metric", "storage", "namespace"],
+            "properties": {
+                "name": { "type": "string" },
+                "dimensions": { "type": "integer", "format": "int32", "minimum": 1 },
+                "metric": metric,
+                "storage": storage,
+                "namespace": { "type": "string" },
+                "estimated_records": {
+                    "type": "integer",
+                    "format": "int64",
+                    "description": "The planner's estimate of the record count; only when describing one collection",
+                },
+            },
+        },
+        "CreateCollection": {
+            "type": "object",
+            "required": ["name", "dimensions"],
+            "properties": {
+                "name": { "type": "string", "pattern": "^[a-z_][a-z0-9_]*$" },
+                "dimensions": { "type": "integer", "format": "int32", "minimum": 1 },
+                "metric": metric,
+                "storage": storage,
+            },
+        },
+    })
+}
+
+fn record_schemas() -> Value {
+    let vector = json!({ "type": "array", "items": { "type": "number", "format": "float" } });
+    json!({
+        "NewRecord": {
+            "type": "object",
+            "required": ["embedding"],
+            "properties": {
+                "id": {
+                    "type": "integer",
+                    "format": "int64",
+                    "description": "Replaces the record stored under this id; generated when missing",
+                },
+                "embedding": vector,
+                "content": { "type": "string", "description": "Text the vector was computed from" },
+                "source": { "type": "string", "description": "Document the content was taken from" },
+                "language": { "type": "string", "description": "Text search language of the content" },
+                "payload": { "description": "Metadata returned with the record and tested by filters" },
+            },
+        },
+        "InsertRecords": {
+            "type": "object",
+            "required": ["records"],
+            "properties": { "records": array_of("NewRecord") },
+        },
+        "Record": {
+            "type": "object",
+            "required": ["id", "embedding"],
+            "properties": {
+                "id": { "type": "integer", "format": "int64" },
+                "embedding": vector,
+                "content": { "type": "string", "nullable": true },
+                "source": { "type": "string", "nullable": true },
+                "payload": { "nullable": true },
+            },
+        },
+        "Filter": filter_schema(),
+        "SearchRequest": {
+            "type": "object",
+            "required": ["vector"],
+            "properties": {
+                "vector": vector,
+                "limit": {
+                    "type": "integer",
+                    "format": "int32",
+                    "default": 10,
+                    "maximum": http::MAX_LIMIT,
+                },
+                "offset": {
+                    "type": "integer",
+                    "format": "int32",
+                    "default": 0,
+                    "maximum": http::MAX_OFFSET,
+                },
+                "filter": reference("schemas", "Filter"),
+                "max_distance": { "type": "number", "format": "float" },
+                "min_score": { "type": "number", "format": "float" },
+                "ef_search": { "type": "integer", "format": "int32" },
+                "probes": { "type": "integer", "format": "int32" },
+            },
+        },
+        "SearchResult": {
+            "type": "object",
+            "required": ["id", "distance", "score"],
+            "properties": {
+                "id": { "type": "integer", "format": "int64" },
+                "distance": {
+                    "type": "number",
+                    "format": "float",
+                    "description": "Raw distance of the metric; lower is closer",
+                },
+                "score": {
+                    "type": "number",
+                    "format": "float",
+                    "description": "Similarity in [0, 1]; higher is closer",
+                },
+                "rerank_score": { "type": "number", "format": "float" },
+                "payload": {},
+            },
+        },
+    })
+}
+
+/// [`Filter`](crate::Filter) in its JSON form: a name for filters without data, otherwise an
+/// object with one key naming the variant.
+fn filter_schema() -> Value {
+    let filters = json!({ "type": "array", "items": reference("schemas", "Filter") });
+    json!({
+        "description": "Records to include, e.g. {\"payload\": {\"key\": \"lang\", \"comparison\": \"eq\", \"value\": \"en\"}}",
+        "oneOf": [
+            { "type": "string", "enum": ["all", "expired"] },
+            single_key("ids", json!({ "type": "array", "items": { "type": "integer", "format": "int64" } })),
+            single_key("cluster", json!({ "type": "integer", "format": "int32" })),
+            single_key("payload", json!({
+                "type": "object",
+                "required": ["key", "comparison", "value"],
+                "properties": {
+                    "key": { "type": "string" },
+                    "comparison": { "type": "string", "enum": ["eq", "ne", "gt", "gte", "lt", "lte"] },
+                    "value": {},
+                },
+            })),
+            single_key("and", filters.clone()),
+            single_key("or", filters),
+            single_key("not", reference("schemas", "Filter")),
+        ],
+    })
+}
+
+/// An object with just `key`, holding a `schema` value.
+fn single_key(key: &str, schema: Value) -> Value {
+    let mut properties = Map::new();
+    properties.insert(key.to_string(), schema);
+    json!({
+        "type": "object",
+        "required": [key],
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+fn reference(kind: &str, name: &str) -> Value {
+    json!({ "$ref": format!("#/components/{kind}/{name}") })
+}
+
+fn array_of(schema: &str) -> Value {
+    json!({ "type": "array", "items": reference("schemas", schema) })
+}
+
+fn name_parameter() -> Value {
+    json!({
+        "name": "name",
+        "in": "path",
+        "required": true,
+        "description": "Collection name",
+        "schema": { "type": "string" },
+    })
+}
+
+fn json_body(schema: &str) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": reference("schemas", schema) } },
+    })
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    let mut response = json!({ "description": description });
+    response["content"]["application/json"]["schema"] = schema;
+    response
+}
+
+/// `429`, with the seconds to wait before retrying.
+fn too_many_requests() -> Value {
+    let mut response = error_response("The client is over its rate limit");
+    response["headers"] = json!({
+        "Retry-After": { "schema": { "type": "integer" } },
+    });
+    response
+}
+
+fn error_response(description: &str) -> Value {
+    json_response(description, reference("schemas", "Error"))
+}
+
+/// The members of all the `objects` in one object.
+fn merge<const N: usize>(objects: [Value; N]) -> Value {
+    let mut merged = Map::new();
+    for object in objects {
+        if let Value::Object(object) = object {
+            merged.extend(object);
+        }
+    }
+    Value::Object(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::collection::{Metric, Record, SearchResult, Storage};
+    use crate::error::Result;
+    use crate::filter::{Comparison, Filter};
+    use crate::http::{CollectionInfo, CreateCollection, InsertRecords, NewRecord, SearchRequest};
+
+    fn schema(name: &str) -> Value {
+        document()["components"]["schemas"][name].clone()
+    }
+
+    /// Checks `value`, found at `path`, against the parts of JSON Schema the document uses.
+    /// Objects may only have the properties their schema lists, so that a field missing from
+    /// the document is caught too.
+    fn conforms(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(Value::String(target)) = schema.get("$ref") {
+            let name = target.trim_start_matches("#/components/schemas/");
+            return conforms(&self::schema(name), value, path);
+        }
+        if let Some(Value::Array(alternatives)) = schema.get("oneOf") {
+            let matching = alternatives
+                .iter()
+                .filter(|alternative| conforms(alternative, value, path).is_ok())
+                .count();
+            return match matching {
+                1 => Ok(()),
+                _ => Err(format!("{path} matches {matching} alternatives")),
+            };
+        }
+        if value.is_null() {
+            return if schema["nullable"] == true {
+                Ok(())
+            } else {
+                Err(format!("{path} is null"))
+            };
+        }
+        let typed = match schema["type"].as_str() {
+            None => true,
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            Some(other) => return Err(format!("{path} has the unknown type {other}")),
+        };
+        if !typed {
+            return Err(format!("{path} isn't of type {}", schema["type"]));
+        }
+        if let Some(Value::Array(allowed)) = schema.get("enum") {
+            if !allowed.contains(value) {
+                return Err(format!("{path} is {value}, not one of {allowed:?}"));
+            }
+        }
+        if let (Some(minimum), Some(number)) = (schema["minimum"].as_f64(), value.as_f64()) {
+            if number < minimum {
+                return Err(format!("{path} is below {minimum}"));
+            }
+        }
+        if let (Some(maximum), Some(number)) = (schema["maximum"].as_f64(), value.as_f64()) {
+            if number > maximum {
+                return Err(format!("{path} is above {maximum}"));
+            }
+        }
+        match value {
+            Value::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    conforms(&schema["items"], item, &format!("{path}[{index}]"))?;
+                }
+            }
+            Value::Object(members) => {
+                for required in schema["required"].as_array().into_iter().flatten() {
+                    let required = required.as_str().unwrap_or_default();
+                    if !members.contains_key(required) {
+                        return Err(format!("{path} lacks the required {required}"));
+                    }
+                }
+                for (key, member) in members {
+                    match schema["properties"].get(key) {
+                        Some(property) => conforms(property, member, &format!("{path}.{key}"))?,
+                        None if schema.get("properties").is_some() => {
+                            return Err(format!("{path}.{key} isn't in the schema"));
+                        }
+                        None => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// A value with every property of `schema`: its default, else its first allowed value,
+    /// else one of its type.
+    fn example(schema: &Value) -> Value {
+        if let Some(Value::String(target)) = schema.get("$ref") {
+            return example(&self::schema(
+                target.trim_start_matches("#/components/schemas/"),
+            ));
+        }
+        if let Some(Value::Array(alternatives)) = schema.get("oneOf") {
+            return example(&alternatives[0]);
+        }
+        if let Some(default) = schema.get("default") {
+            return default.clone();
+        }
+        if let Some(Value::Array(allowed)) = schema.get("enum") {
+            return allowed[0].clone();
+        }
+        match schema["type"].as_str() {
+            Some("string") => json!("docs"),
+            Some("integer") => json!(1),
+            Some("number") => json!(0.5),
+            Some("array") => json!([example(&schema["items"])]),
+            Some("object") => {
+                let properties = schema["properties"].as_object().into_iter().flatten();
+                Value::Object(
+                    properties
+                        .map(|(key, property)| (key.clone(), example(property)))
+                        .collect(),
+                )
+            }
+            // Payloads, which may be any JSON
+            _ => json!({ "lang": "en" }),
+        }
+    }
+
+    /// Checks that a body with every property of the schema `name` reads as `T` and writes
+    /// back the same, and that one with only the required properties reads but one lacking
+    /// any of them doesn't.
+    fn round_trips<T: DeserializeOwned + Serialize>(name: &str) -> Result<()> {
+        let schema = schema(name);
+        let body = example(&schema);
+        assert_eq!(conforms(&schema, &body, name), Ok(()));
+        let written = serde_json::to_value(serde_json::from_value::<T>(body.clone())?)?;
+        assert_eq!(written, body, "{name}");
+
+        let required: Vec<&str> = schema["required"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let only = |keys: &[&str]| -> Value {
+            keys.iter()
+                .map(|&key| (key.to_string(), body[key].clone()))
+                .collect::<Map<_, _>>()
+                .into()
+        };
+        serde_json::from_value::<T>(only(&required))?;
+        for missing in &required {
+            let lacking: Vec<&str> = required
+                .iter()
+                .copied()
+                .filter(|key| key != missing)
+                .collect();
+            assert!(
+                serde_json::from_value::<T>(only(&lacking)).is_err(),
+                "{name} without {missing}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that `values` written as JSON conform to the schema `name`, and that together
+    /// they have each of its properties.
+    fn writes<T: Serialize>(name: &str, values: &[T]) -> Result<()> {
+        let schema = schema(name);
+        let mut unwritten: Vec<&String> = schema["properties"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(key, _)| key)
+            .collect();
+        for value in values {
+            let value = serde_json::to_value(value)?;
+            assert_eq!(conforms(&schema, &value, name), Ok(()));
+            unwritten.retain(|key| value.get(key.as_str()).is_none());
+        }
+        assert!(unwritten.is_empty(), "{name} never has {unwritten:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn describes_request_bodies_as_serde_reads_them() -> Result<()> {
+        round_trips::<CreateCollection>("CreateCollection")?;
+        round_trips::<NewRecord>("NewRecord")?;
+        round_trips::<InsertRecords>("InsertRecords")?;
+        round_trips::<SearchRequest>("SearchRequest")
+    }
+
+    #[test]
+    fn describes_responses_as_serde_writes_them() -> Result<()> {
+        let collection = |estimated_records| CollectionInfo {
+            name: "docs".to_string(),
+            dimensions: 384,
+            metric: Metric::Cosine,
+            storage: Storage::F16,
+            namespace: "default".to_string(),
+            estimated_records,
+        };
+        writes("Collection", &[collection(Some(0)), collection(None)])?;
+        let record = Record {
+            id: 1,
+            embedding: vec![0.5, 1.0],
+            content: Some("text".to_string()),
+            source: Some("a.md".to_string()),
+            payload: Some(json!({ "lang": "en" })),
+        };
+        let bare = Record {
+            content: None,
+            source: None,
+            payload: None,
+            ..record.clone()
+        };
+        writes("Record", &[record, bare])?;
+        let result = SearchResult {
+            id: 1,
+            distance: 0.25,
+            score: 0.75,
+            rerank_score: Some(0.5),
+            payload: Some(json!({ "lang": "en" })),
+        };
+        let bare = SearchResult {
+            rerank_score: None,
+            payload: None,
+            ..result.clone()
+        };
+        writes("SearchResult", &[result, bare])
+    }
+
+    #[test]
+    fn describes_every_filter() -> Result<()> {
+        let payload = Filter::Payload {
+            key: "lang".to_string(),
+            comparison: Comparison::Gte,
+            value: json!(["en"]),
+        };
+        let filters = [
+            Filter::All,
+            Filter::Expired,
+            Filter::Ids(vec![1, -1]),
+            Filter::Cluster(3),
+            payload.clone(),
+            Filter::And(vec![payload.clone(), Filter::All]),
+            Filter::Or(Vec::new()),
+            Filter::Not(Box::new(payload)),
+        ];
+        let schema = schema("Filter");
+        for filter in &filters {
+            let written = serde_json::to_value(filter)?;
+            assert_eq!(conforms(&schema, &written, "Filter"), Ok(()));
+            assert_eq!(&serde_json::from_value::<Filter>(written)?, filter);
+        }
+        for alternative in schema["oneOf"].as_array().into_iter().flatten() {
+            let body = example(alternative);
+            let written = serde_json::to_value(serde_json::from_value::<Filter>(body.clone())?)?;
+            assert_eq!(written, body);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use pgevdb::Result;
+
+/// How command results are written to stdout.
+///
+/// JSON field names are a compatibility surface for scripts and CI: add fields freely, but
+/// don't rename or remove existing ones.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// A command result that can be printed for humans or serialized for scripts.
+pub trait Report: Serialize {
+    fn print_text(&self);
+}
+
+/// Writes `report` to stdout in the requested format.
+pub fn emit<R: Report>(format: OutputFormat, report: &R) -> Result<()> {
+    match format {
+        OutputFormat::Text => report.print_text(),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+    }
+    Ok(())
+}
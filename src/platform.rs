@@ -0,0 +1,168 @@
+use std::fmt;
+
+/// Operating system of the host, as far as extension artifacts are concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Os {
+    Linux,
+    MacOS,
+    Windows,
+    Other(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+    Other(&'static str),
+}
+
+/// C library of a Linux host; prebuilt extensions link against glibc and can't load on musl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Libc {
+    Gnu,
+    Musl,
+}
+
+/// The platform pgevdb is running on, used to pick extension artifacts and library file names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Platform {
+    pub os: Os,
+    pub arch: Arch,
+    pub libc: Libc,
+}
+
+impl Platform {
+    #[must_use]
+    pub fn current() -> Self {
+        let os = match std::env::consts::OS {
+            "linux" => Os::Linux,
+            "macos" => Os::MacOS,
+            "windows" => Os::Windows,
+            other => Os::Other(other),
+        };
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => Arch::X86_64,
+            "aarch64" => Arch::Aarch64,
+            other => Arch::Other(other),
+        };
+        Self {
+            os,
+            arch,
+            libc: detect_libc(),
+        }
+    }
+
+    /// File name PostgreSQL major version `pg_major` loads for a shared library called `name`
+    /// (`vectors.so`, `vectors.dll`, ...). On macOS, PostgreSQL 16 switched from `.so` to
+    /// `.dylib`.
+    #[must_use]
+    pub fn library_file_name(self, name: &str, pg_major: &str) -> String {
+        let suffix = match self.os {
+            Os::MacOS if pg_major.parse::<u64>().is_ok_and(|major| major >= 16) => ".dylib",
+            Os::Windows => ".dll",
+            Os::MacOS | Os::Linux | Os::Other(_) => ".so",
+        };
+        format!("{name}{suffix}")
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arch = match self.arch {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+            Arch::Other(arch) => arch,
+        };
+        let os = match self.os {
+            Os::Linux => "linux",
+            Os::MacOS => "macos",
+            Os::Windows => "windows",
+            Os::Other(os) => os,
+        };
+        match (self.os, self.libc) {
+            (Os::Linux, Libc::Musl) => write!(f, "{arch}-{os}-musl"),
+            _ => write!(f, "{arch}-{os}"),
+        }
+    }
+}
+
+/// Detects musl from the build target or, for a glibc build running somewhere unexpected such
+/// as an Alpine container with gcompat, from the presence of musl's dynamic loader.
+fn detect_libc() -> Libc {
+    if cfg!(target_env = "musl") {
+        return Libc::Musl;
+    }
+
+    let has_musl_loader = std::fs::read_dir("/lib").is_ok_and(|entries| {
+        entries.flatten().any(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with("ld-musl-"))
+        })
+    });
+    if has_musl_loader {
+        Libc::Musl
+    } else {
+        Libc::Gnu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(os: Os) -> Platform {
+        Platform {
+            os,
+            arch: Arch::Aarch64,
+            libc: Libc::Gnu,
+        }
+    }
+
+    #[test]
+    fn macos_switches_to_dylib_from_postgresql_16() {
+        let macos = on(Os::MacOS);
+        assert_eq!(macos.library_file_name("vectors", "14"), "vectors.so");
+        assert_eq!(macos.library_file_name("vectors", "15"), "vectors.so");
+        assert_eq!(macos.library_file_name("vectors", "16"), "vectors.dylib");
+        assert_eq!(macos.library_file_name("vectors", "17"), "vectors.dylib");
+    }
+
+    #[test]
+    fn other_systems_keep_one_suffix_across_majors() {
+        for major in ["14", "15", "16", "17"] {
+            assert_eq!(
+                on(Os::Linux).library_file_name("vector", major),
+                "vector.so"
+            );
+            assert_eq!(
+                on(Os::Windows).library_file_name("vector", major),
+                "vector.dll"
+            );
+            assert_eq!(
+                on(Os::Other("freebsd")).library_file_name("vector", major),
+                "vector.so"
+            );
+        }
+    }
+
+    #[test]
+    fn unparsable_majors_fall_back_to_so_on_macos() {
+        assert_eq!(
+            on(Os::MacOS).library_file_name("vectors", "devel"),
+            "vectors.so"
+        );
+    }
+
+    #[test]
+    fn names_musl_hosts_in_display() {
+        let alpine = Platform {
+            os: Os::Linux,
+            arch: Arch::X86_64,
+            libc: Libc::Musl,
+        };
+        assert_eq!(alpine.to_string(), "x86_64-linux-musl");
+        assert_eq!(on(Os::MacOS).to_string(), "aarch64-macos");
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// Makes sure PostgreSQL won't be started as root and that the storage directory is writable,
+/// failing early with guidance instead of deep inside `initdb` or `pg_ctl`.
+///
+/// When running as root with `run_as_user` configured, the storage directory is handed over to
+/// that user and the whole process drops to its uid/gid, so every PostgreSQL child process runs
+/// unprivileged.
+pub fn ensure_unprivileged(config: &Config) -> Result<()> {
+    #[cfg(unix)]
+    if nix::unistd::Uid::effective().is_root() {
+        let Some(user_name) = &config.run_as_user else {
+            return Err(Error::Privileges(
+                "PostgreSQL refuses to run as root. Run pgevdb as a regular user, or set \
+                 `run_as_user` in the config file to a dedicated account (e.g. `postgres`) that \
+                 pgevdb should switch to"
+                    .to_string(),
+            ));
+        };
+        std::fs::create_dir_all(&config.storage_dir)?;
+        unix::switch_user(user_name, config)?;
+    }
+
+    match std::fs::create_dir_all(&config.storage_dir) {
+        Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+            Err(Error::Privileges(format!(
+                "{} isn't writable by the current user; choose another `storage_dir` or fix its \
+                 permissions",
+                config.storage_dir.display()
+            )))
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Writes the generated superuser password to `path` readable only by the current user, or
+/// restricts an existing password file written world-readable by earlier releases.
+///
+/// On Windows the file inherits the ACL of the storage directory, which under the default
+/// location in the user's profile is private to the user.
+pub fn secure_password_file(path: &Path, password: &str) -> Result<()> {
+    if !path.exists() {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)?.write_all(password.as_bytes())?;
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        if permissions.mode() & 0o077 != 0 {
+            permissions.set_mode(0o600);
+            std::fs::set_permissions(path, permissions)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::path::Path;
+
+    use nix::unistd::{chown, setgid, setgroups, setuid, Gid, Uid, User};
+    use tracing::info;
+
+    use crate::config::Config;
+    use crate::error::{Error, Result};
+
+    pub fn switch_user(user_name: &str, config: &Config) -> Result<()> {
+        let user = User::from_name(user_name)
+            .map_err(|error| Error::Privileges(format!("looking up user '{user_name}': {error}")))?
+            .ok_or_else(|| Error::Privileges(format!("user '{user_name}' doesn't exist")))?;
+
+        info!(
+            "Running as root; handing {} to '{}' and dropping privileges",
+            config.storage_dir.display(),
+            user_name
+        );
+        chown_recursive(&config.storage_dir, user.uid, user.gid)?;
+
+        // Group membership has to change while we still have the privileges to do so
+        setgroups(&[user.gid])
+            .and_then(|()| setgid(user.gid))
+            .and_then(|()| setuid(user.uid))
+            .map_err(|error| {
+                Error::Privileges(format!("switching to user '{user_name}': {error}"))
+            })?;
+        std::env::set_var("HOME", &user.dir);
+        std::env::set_var("USER", user_name);
+        Ok(())
+    }
+
+    fn chown_recursive(path: &Path, uid: Uid, gid: Gid) -> Result<()> {
+        chown(path, Some(uid), Some(gid)).map_err(std::io::Error::from)?;
+        if path.is_dir() && !path.is_symlink() {
+            for entry in std::fs::read_dir(path)? {
+                chown_recursive(&entry?.path(), uid, gid)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use tracing::info;
+
+/// How often progress is logged when stderr is not a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a progress counter is measuring, which controls how positions are rendered.
+#[derive(Clone, Copy)]
+enum Unit {
+    Bytes,
+    Items(&'static str),
+}
+
+/// Progress reporting for long-running operations.
+///
+/// Renders an indicatif progress bar when stderr is an interactive terminal and
+/// falls back to periodic log lines otherwise, so CI logs and redirected output
+/// still show that work is happening.
+pub struct Progress {
+    label: String,
+    unit: Unit,
+    total: Option<u64>,
+    position: u64,
+    bar: Option<ProgressBar>,
+    started: Instant,
+    last_log: Instant,
+}
+
+impl Progress {
+    /// Tracks a byte transfer such as a download, with an optional known length.
+    pub fn bytes(label: impl Into<String>, total: Option<u64>) -> Self {
+        Self::new(label.into(), Unit::Bytes, total)
+    }
+
+    /// Tracks a count of discrete items (files extracted, rows ingested, ...).
+    pub fn items(label: impl Into<String>, total: Option<u64>, unit: &'static str) -> Self {
+        Self::new(label.into(), Unit::Items(unit), total)
+    }
+
+    /// Tracks an operation with no measurable progress; only elapsed time is shown.
+    pub fn spinner(label: impl Into<String>) -> Self {
+        Self::new(label.into(), Unit::Items(""), None)
+    }
+
+    fn new(label: String, unit: Unit, total: Option<u64>) -> Self {
+        let bar = std::io::stderr()
+            .is_terminal()
+            .then(|| Self::create_bar(&label, unit, total));
+        if bar.is_none() {
+            info!("{label}: started");
+        }
+
+        let now = Instant::now();
+        Self {
+            label,
+            unit,
+            total,
+            position: 0,
+            bar,
+            started: now,
+            last_log: now,
+        }
+    }
+
+    fn create_bar(label: &str, unit: Unit, total: Option<u64>) -> ProgressBar {
+        let template = match (unit, total) {
+            (Unit::Bytes, Some(_)) => {
+                "{spinner} {msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})"
+            }
+            (Unit::Bytes, None) => "{spinner} {msg} {bytes} ({bytes_per_sec})",
+            (Unit::Items(_), Some(_)) => "{spinner} {msg} [{bar:30}] {human_pos}/{human_len}",
+            (Unit::Items(_), None) => "{spinner} {msg} {human_pos} [{elapsed}]",
+        };
+        let bar = match total {
+            Some(total) => ProgressBar::new(total),
+            None => ProgressBar::new_spinner(),
+        };
+        bar.set_style(
+            ProgressStyle::with_template(template)
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("=> "),
+        );
+        bar.set_message(label.to_string());
+        bar.enable_steady_tick(Duration::from_millis(120));
+        bar
+    }
+
+    /// Advances the counter by `delta`.
+    pub fn inc(&mut self, delta: u64) {
+        self.set_position(self.position.saturating_add(delta));
+    }
+
+    /// Moves the counter to an absolute position.
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+        match &self.bar {
+            Some(bar) => bar.set_position(position),
+            None if self.last_log.elapsed() >= LOG_INTERVAL => {
+                self.last_log = Instant::now();
+                info!("{}: {}", self.label, self.describe());
+            }
+            None => {}
+        }
+    }
+
+    /// Marks the operation as complete.
+    pub fn finish(self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        } else {
+            info!(
+                "{}: done ({}) in {:.1?}",
+                self.label,
+                self.describe(),
+                self.started.elapsed()
+            );
+        }
+    }
+
+    fn describe(&self) -> String {
+        let position = self.position;
+        let described = match (self.unit, self.total) {
+            (Unit::Bytes, Some(total)) => {
+                format!("{} of {}", format_bytes(position), format_bytes(total))
+            }
+            (Unit::Bytes, None) => format_bytes(position),
+            (Unit::Items(unit), Some(total)) => format!("{position}/{total} {unit}"),
+            (Unit::Items(unit), None) => format!("{position} {unit}"),
+        };
+        described.trim_end().to_string()
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        // Don't leave a dangling bar on the terminal when an operation fails part way.
+        if let Some(bar) = &self.bar {
+            if !bar.is_finished() {
+                bar.abandon();
+            }
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    indicatif::HumanBytes(bytes).to_string()
+}
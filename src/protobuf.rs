@@ -0,0 +1,494 @@
+//! The Protocol Buffers wire format, for the messages of the [gRPC API](crate::grpc), which
+//! `build.rs` generates from the files in `proto/`.
+//!
+//! Fields with implicit presence are skipped when they hold their default, as proto3 encoders
+//! do; repeated numbers are written packed and read packed or not.
+
+use crate::error::{Error, Result};
+
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+/// A message of `proto/pgevdb.proto`.
+pub(crate) trait Message: Default {
+    /// Takes field `number` of an encoded message into this one. Unknown fields are skipped,
+    /// so messages can gain fields without breaking older servers.
+    fn merge_field(&mut self, number: u32, field: Field<'_>) -> Result<()>;
+
+    fn encode(&self, encoder: &mut Encoder);
+
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `bytes` isn't an encoding of this message.
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut message = Self::default();
+        let mut reader = Reader { bytes };
+        while !reader.bytes.is_empty() {
+            let key = reader.varint()?;
+            let number = u32::try_from(key >> 3)
+                .ok()
+                .filter(|&number| number > 0)
+                .ok_or_else(|| malformed("invalid field number"))?;
+            #[allow(clippy::cast_possible_truncation)]
+            let field = match (key & 7) as u8 {
+                VARINT => Field::Varint(reader.varint()?),
+                FIXED64 => {
+                    reader.take(8)?;
+                    Field::Fixed64
+                }
+                LENGTH_DELIMITED => {
+                    let length = usize::try_from(reader.varint()?)
+                        .map_err(|_| malformed("field length out of range"))?;
+                    Field::Bytes(reader.take(length)?)
+                }
+                FIXED32 => Field::Fixed32(u32::from_le_bytes(reader.array()?)),
+                wire_type => return Err(malformed(&format!("unsupported wire type {wire_type}"))),
+            };
+            message.merge_field(number, field)?;
+        }
+        Ok(message)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        self.encode(&mut encoder);
+        encoder.bytes
+    }
+}
+
+/// The value of one field of an encoded message.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Field<'a> {
+    Varint(u64),
+    /// Skipped: no message has 64-bit fixed-size fields.
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Field<'a> {
+    pub(crate) fn uint64(self) -> Result<u64> {
+        match self {
+            Field::Varint(value) => Ok(value),
+            _ => Err(wrong_type("an integer")),
+        }
+    }
+
+    /// As protobuf decoders do, keeps the low 32 bits of larger values.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn uint32(self) -> Result<u32> {
+        self.uint64().map(|value| value as u32)
+    }
+
+    /// As protobuf decoders do, keeps the low 32 bits of larger values.
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn int32(self) -> Result<i32> {
+        self.int64().map(|value| value as i32)
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    pub(crate) fn int64(self) -> Result<i64> {
+        self.uint64().map(|value| value as i64)
+    }
+
+    pub(crate) fn float(self) -> Result<f32> {
+        match self {
+            Field::Fixed32(bits) => Ok(f32::from_bits(bits)),
+            _ => Err(wrong_type("a float")),
+        }
+    }
+
+    pub(crate) fn bytes(self) -> Result<&'a [u8]> {
+        match self {
+            Field::Bytes(bytes) => Ok(bytes),
+            _ => Err(wrong_type("a length-delimited value")),
+        }
+    }
+
+    pub(crate) fn string(self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| malformed("string isn't UTF-8"))
+    }
+
+    pub(crate) fn message<M: Message>(self) -> Result<M> {
+        M::decode(self.bytes()?)
+    }
+
+    /// Appends the floats of a repeated field, packed or not.
+    pub(crate) fn floats(self, values: &mut Vec<f32>) -> Result<()> {
+        match self {
+            Field::Bytes(bytes) if bytes.len() % 4 == 0 => {
+                values.extend(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])),
+                );
+                Ok(())
+            }
+            Field::Bytes(_) => Err(malformed("packed floats aren't a multiple of 4 bytes")),
+            field => {
+                values.push(field.float()?);
+                Ok(())
+            }
+        }
+    }
+
+    /// Appends the integers of a repeated field, packed or not.
+    #[allow(clippy::cast_possible_wrap)]
+    pub(crate) fn int64s(self, values: &mut Vec<i64>) -> Result<()> {
+        match self {
+            Field::Bytes(bytes) => {
+                let mut reader = Reader { bytes };
+                while !reader.bytes.is_empty() {
+                    values.push(reader.varint()? as i64);
+                }
+                Ok(())
+            }
+            field => {
+                values.push(field.int64()?);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Builds an encoded message field by field.
+#[derive(Debug, Default)]
+pub(crate) struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn uint64(&mut self, number: u32, value: u64) {
+        if value != 0 {
+            self.optional_uint64(number, Some(value));
+        }
+    }
+
+    pub(crate) fn optional_uint64(&mut self, number: u32, value: Option<u64>) {
+        if let Some(value) = value {
+            self.key(number, VARINT);
+            self.varint(value);
+        }
+    }
+
+    pub(crate) fn uint32(&mut self, number: u32, value: u32) {
+        self.uint64(number, u64::from(value));
+    }
+
+    pub(crate) fn optional_uint32(&mut self, number: u32, value: Option<u32>) {
+        self.optional_uint64(number, value.map(u64::from));
+    }
+
+    /// Negative values are sign-extended to 64 bits, as protoc encodes them.
+    pub(crate) fn int32(&mut self, number: u32, value: i32) {
+        self.int64(number, i64::from(value));
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    pub(crate) fn int64(&mut self, number: u32, value: i64) {
+        self.uint64(number, value as u64);
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    pub(crate) fn optional_int64(&mut self, number: u32, value: Option<i64>) {
+        self.optional_uint64(number, value.map(|value| value as u64));
+    }
+
+    pub(crate) fn float(&mut self, number: u32, value: f32) {
+        // Negative zero isn't the default
+        if value.to_bits() != 0 {
+            self.optional_float(number, Some(value));
+        }
+    }
+
+    pub(crate) fn optional_float(&mut self, number: u32, value: Option<f32>) {
+        if let Some(value) = value {
+            self.key(number, FIXED32);
+            self.bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    pub(crate) fn string(&mut self, number: u32, value: &str) {
+        if !value.is_empty() {
+            self.optional_string(number, Some(value));
+        }
+    }
+
+    pub(crate) fn optional_string(&mut self, number: u32, value: Option<&str>) {
+        if let Some(value) = value {
+            self.length_delimited(number, value.as_bytes());
+        }
+    }
+
+    pub(crate) fn optional_bytes(&mut self, number: u32, value: Option<&[u8]>) {
+        if let Some(value) = value {
+            self.length_delimited(number, value);
+        }
+    }
+
+    pub(crate) fn message(&mut self, number: u32, message: &impl Message) {
+        self.length_delimited(number, &message.to_bytes());
+    }
+
+    pub(crate) fn floats(&mut self, number: u32, values: &[f32]) {
+        if !values.is_empty() {
+            let packed: Vec<u8> = values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+            self.length_delimited(number, &packed);
+        }
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    pub(crate) fn int64s(&mut self, number: u32, values: &[i64]) {
+        if !values.is_empty() {
+            let mut packed = Encoder::default();
+            for &value in values {
+                packed.varint(value as u64);
+            }
+            self.length_delimited(number, &packed.bytes);
+        }
+    }
+
+    fn length_delimited(&mut self, number: u32, bytes: &[u8]) {
+        self.key(number, LENGTH_DELIMITED);
+        self.varint(bytes.len() as u64);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn key(&mut self, number: u32, wire_type: u8) {
+        self.varint(u64::from(number) << 3 | u64::from(wire_type));
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+}
+
+/// The unread rest of an encoded message.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        for (position, &byte) in self.bytes.iter().enumerate().take(10) {
+            value |= u64::from(byte & 0x7f) << (7 * position);
+            if byte < 0x80 {
+                self.bytes = &self.bytes[position + 1..];
+                return Ok(value);
+            }
+        }
+        Err(malformed("truncated or overlong varint"))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if length > self.bytes.len() {
+            return Err(malformed("truncated field"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::InvalidInput(format!("malformed protobuf message: {reason}"))
+}
+
+fn wrong_type(expected: &str) -> Error {
+    malformed(&format!("expected {expected}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A message with a field of each kind the codec handles.
+    #[derive(Debug, Default, PartialEq)]
+    struct Sample {
+        count: u64,
+        offset: i64,
+        weight: f32,
+        name: String,
+        floats: Vec<f32>,
+        ids: Vec<i64>,
+        child: Option<Box<Sample>>,
+    }
+
+    impl Message for Sample {
+        fn merge_field(&mut self, number: u32, field: Field<'_>) -> Result<()> {
+            match number {
+                1 => self.count = field.uint64()?,
+                2 => self.offset = field.int64()?,
+                3 => self.weight = field.float()?,
+                4 => self.name = field.string()?,
+                5 => field.floats(&mut self.floats)?,
+                6 => field.int64s(&mut self.ids)?,
+                7 => self.child = Some(Box::new(field.message()?)),
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn encode(&self, encoder: &mut Encoder) {
+            encoder.uint64(1, self.count);
+            encoder.int64(2, self.offset);
+            encoder.float(3, self.weight);
+            encoder.string(4, &self.name);
+            encoder.floats(5, &self.floats);
+            encoder.int64s(6, &self.ids);
+            if let Some(child) = &self.child {
+                encoder.message(7, child.as_ref());
+            }
+        }
+    }
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        encoder.varint(value);
+        encoder.bytes
+    }
+
+    fn invalid(bytes: &[u8]) -> bool {
+        matches!(Sample::decode(bytes), Err(Error::InvalidInput(_)))
+    }
+
+    #[test]
+    fn varints_round_trip() -> Result<()> {
+        for value in [0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+            let bytes = varint(value);
+            let mut reader = Reader { bytes: &bytes };
+            assert_eq!(reader.varint()?, value);
+            assert!(reader.bytes.is_empty());
+        }
+        assert_eq!(varint(300), [0xac, 0x02]);
+        assert_eq!(varint(u64::MAX).len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn messages_round_trip() -> Result<()> {
+        let sample = Sample {
+            count: 150,
+            offset: -2,
+            weight: -1.5,
+            name: "ünïcode".to_string(),
+            floats: vec![0.0, 1.0, f32::MIN_POSITIVE],
+            ids: vec![i64::MIN, 0, 1],
+            child: Some(Box::new(Sample {
+                name: "child".to_string(),
+                ..Sample::default()
+            })),
+        };
+        assert_eq!(Sample::decode(&sample.to_bytes())?, sample);
+        Ok(())
+    }
+
+    #[test]
+    fn encodes_as_protoc_does() {
+        let sample = Sample {
+            count: 150,
+            name: "testing".to_string(),
+            ..Sample::default()
+        };
+        assert_eq!(
+            sample.to_bytes(),
+            [0x08, 0x96, 0x01, 0x22, 7, b't', b'e', b's', b't', b'i', b'n', b'g']
+        );
+        // Negative numbers take all 10 bytes
+        let sample = Sample {
+            offset: -1,
+            ..Sample::default()
+        };
+        assert_eq!(sample.to_bytes().len(), 11);
+    }
+
+    #[test]
+    fn sign_extends_negative_int32s() -> Result<()> {
+        let mut encoder = Encoder::default();
+        encoder.int32(1, -1);
+        assert_eq!(encoder.bytes.len(), 11);
+        assert_eq!(Field::Varint(u64::MAX).int32()?, -1);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_defaults() {
+        assert!(Sample::default().to_bytes().is_empty());
+        // Negative zero isn't the default
+        let sample = Sample {
+            weight: -0.0,
+            ..Sample::default()
+        };
+        assert_eq!(sample.to_bytes(), [0x1d, 0, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn reads_repeated_numbers_packed_or_not() -> Result<()> {
+        let mut unpacked = vec![0x2d];
+        unpacked.extend(1.0_f32.to_le_bytes());
+        unpacked.push(0x2d);
+        unpacked.extend(2.0_f32.to_le_bytes());
+        unpacked.extend([0x30, 3, 0x30, 4]);
+        let sample = Sample::decode(&unpacked)?;
+        assert_eq!(sample.floats, [1.0, 2.0]);
+        assert_eq!(sample.ids, [3, 4]);
+
+        let packed = Sample {
+            floats: vec![1.0, 2.0],
+            ids: vec![3, 300],
+            ..Sample::default()
+        }
+        .to_bytes();
+        assert_eq!(
+            packed,
+            [0x2a, 8, 0, 0, 0x80, 0x3f, 0, 0, 0, 0x40, 0x32, 3, 3, 0xac, 0x02]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn skips_unknown_fields() -> Result<()> {
+        // Field 15 of each wire type, then field 1
+        let mut bytes = vec![0x78, 1, 0x79, 0, 0, 0, 0, 0, 0, 0, 0, 0x7a, 2, 0, 0];
+        bytes.extend([0x7d, 0, 0, 0, 0, 0x08, 5]);
+        assert_eq!(Sample::decode(&bytes)?.count, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_malformed_messages() {
+        // Truncated and overlong varints
+        assert!(invalid(&[0x08, 0x96]));
+        assert!(invalid(&[
+            0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 1
+        ]));
+        // A length past the end, and field number 0
+        assert!(invalid(&[0x22, 5, b'a']));
+        assert!(invalid(&[0x00, 1]));
+        // Groups, which proto3 doesn't have
+        assert!(invalid(&[0x0b]));
+        // Packed floats that don't divide into 4 bytes, and a string that isn't UTF-8
+        assert!(invalid(&[0x2a, 3, 0, 0, 0]));
+        assert!(invalid(&[0x22, 1, 0xff]));
+        // A varint where a float belongs, and a malformed nested message
+        assert!(invalid(&[0x18, 1]));
+        assert!(invalid(&[0x3a, 2, 0x08, 0x96]));
+    }
+}
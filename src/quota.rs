@@ -0,0 +1,202 @@
+//! Row and byte quotas per namespace, so one tenant can't fill the disk the whole embedded
+//! database shares.
+//!
+//! Every collection belongs to a namespace, `default` unless set with
+//! [`Collection::set_namespace`](crate::Collection::set_namespace). Inserts into a namespace with
+//! a quota are counted and refused with [`Error::QuotaExceeded`] once a limit would be passed.
+//! The running counts only see inserts made through pgevdb, so [`reconcile`] recounts them from
+//! the tables; run it periodically, e.g. with `pgevdb quota reconcile` from a scheduler.
+
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use sqlx::{Postgres, Transaction};
+
+use crate::error::{Error, Result};
+
+/// Namespace of collections that were never moved to another.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Limits and current usage of one namespace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Quota {
+    pub namespace: String,
+    pub max_rows: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub used_rows: u64,
+    pub used_bytes: u64,
+    /// When usage was last recounted, `None` if never.
+    pub reconciled_at: Option<String>,
+}
+
+const SELECT: &str = "SELECT namespace, max_rows, max_bytes, used_rows, used_bytes,
+                             reconciled_at::text
+                      FROM pgevdb.quotas";
+
+type Row = (String, Option<i64>, Option<i64>, i64, i64, Option<String>);
+
+impl Quota {
+    fn from_row(
+        (namespace, max_rows, max_bytes, used_rows, used_bytes, reconciled_at): Row,
+    ) -> Self {
+        Self {
+            namespace,
+            max_rows: max_rows.map(i64::unsigned_abs),
+            max_bytes: max_bytes.map(i64::unsigned_abs),
+            used_rows: used_rows.unsigned_abs(),
+            used_bytes: used_bytes.unsigned_abs(),
+            reconciled_at,
+        }
+    }
+}
+
+/// Sets or replaces the limits of `namespace` and counts its current usage. `None` leaves a
+/// resource unlimited.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the quota can't be stored.
+pub async fn set_quota(
+    pool: &PgPool,
+    namespace: &str,
+    max_rows: Option<u64>,
+    max_bytes: Option<u64>,
+) -> Result<Quota> {
+    sqlx::query(
+        "INSERT INTO pgevdb.quotas (namespace, max_rows, max_bytes) VALUES ($1, $2, $3)
+         ON CONFLICT (namespace) DO UPDATE
+         SET max_rows = excluded.max_rows, max_bytes = excluded.max_bytes",
+    )
+    .bind(namespace)
+    .bind(max_rows.map(saturating_i64))
+    .bind(max_bytes.map(saturating_i64))
+    .execute(pool)
+    .await?;
+    reconcile_namespace(pool, namespace).await?;
+    quotas(pool)
+        .await?
+        .into_iter()
+        .find(|quota| quota.namespace == namespace)
+        .ok_or_else(|| Error::NotFound(format!("quota of namespace '{namespace}'")))
+}
+
+/// Removes the limits of `namespace`.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the quota can't be removed.
+pub async fn remove_quota(pool: &PgPool, namespace: &str) -> Result<()> {
+    sqlx::query("DELETE FROM pgevdb.quotas WHERE namespace = $1")
+        .bind(namespace)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Every namespace with a quota.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if the quotas can't be read.
+pub async fn quotas(pool: &PgPool) -> Result<Vec<Quota>> {
+    let rows: Vec<Row> = sqlx::query_as(&format!("{SELECT} ORDER BY namespace"))
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(Quota::from_row).collect())
+}
+
+/// Recounts the usage of every namespace with a quota from its collections' tables.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`] if a table can't be counted.
+pub async fn reconcile(pool: &PgPool) -> Result<Vec<Quota>> {
+    let namespaces: Vec<(String,)> = sqlx::query_as("SELECT namespace FROM pgevdb.quotas")
+        .fetch_all(pool)
+        .await?;
+    for (namespace,) in &namespaces {
+        reconcile_namespace(pool, namespace).await?;
+    }
+    quotas(pool).await
+}
+
+async fn reconcile_namespace(pool: &PgPool, namespace: &str) -> Result<()> {
+    let collections: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM pgevdb.collections WHERE namespace = $1")
+            .bind(namespace)
+            .fetch_all(pool)
+            .await?;
+    let (mut rows, mut bytes) = (0_i64, 0_i64);
+    for (collection,) in &collections {
+        let (count, size): (i64, i64) = sqlx::query_as(&format!(
+            "SELECT count(*), coalesce(sum(pg_column_size(t.*)), 0)::bigint
+             FROM collections.{collection} t"
+        ))
+        .fetch_one(pool)
+        .await?;
+        rows += count;
+        bytes += size;
+    }
+    sqlx::query(
+        "UPDATE pgevdb.quotas SET used_rows = $2, used_bytes = $3, reconciled_at = now()
+         WHERE namespace = $1",
+    )
+    .bind(namespace)
+    .bind(rows)
+    .bind(bytes)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The limits of `namespace`, locked until `transaction` ends so concurrent inserts are counted
+/// one at a time. `None` if the namespace has no quota.
+pub(crate) async fn lock(
+    transaction: &mut Transaction<'_, Postgres>,
+    namespace: &str,
+) -> Result<Option<Quota>> {
+    let row: Option<Row> = sqlx::query_as(&format!("{SELECT} WHERE namespace = $1 FOR UPDATE"))
+        .bind(namespace)
+        .fetch_optional(&mut **transaction)
+        .await?;
+    Ok(row.map(Quota::from_row))
+}
+
+/// Counts `rows` new rows totalling `bytes` against `quota`, failing if that passes a limit.
+pub(crate) async fn charge(
+    transaction: &mut Transaction<'_, Postgres>,
+    quota: &Quota,
+    rows: u64,
+    bytes: u64,
+) -> Result<()> {
+    let exceeded = |resource, limit| Error::QuotaExceeded {
+        namespace: quota.namespace.clone(),
+        resource,
+        limit,
+    };
+    if let Some(limit) = quota
+        .max_rows
+        .filter(|&limit| quota.used_rows + rows > limit)
+    {
+        return Err(exceeded("rows", limit));
+    }
+    if let Some(limit) = quota
+        .max_bytes
+        .filter(|&limit| quota.used_bytes + bytes > limit)
+    {
+        return Err(exceeded("bytes", limit));
+    }
+    sqlx::query(
+        "UPDATE pgevdb.quotas SET used_rows = used_rows + $2, used_bytes = used_bytes + $3
+         WHERE namespace = $1",
+    )
+    .bind(&quota.namespace)
+    .bind(saturating_i64(rows))
+    .bind(saturating_i64(bytes))
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+fn saturating_i64(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
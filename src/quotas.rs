@@ -0,0 +1,102 @@
+use serde::Serialize;
+
+use crate::cli::QuotaCommand;
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::quota::{self, Quota};
+use pgevdb::{Collection, Config, Result};
+
+/// Result of `pgevdb quota show`, `set` and `reconcile`.
+#[derive(Serialize)]
+struct QuotaReport {
+    quotas: Vec<Quota>,
+}
+
+impl Report for QuotaReport {
+    fn print_text(&self) {
+        if self.quotas.is_empty() {
+            println!("No quotas set");
+        }
+        for quota in &self.quotas {
+            println!(
+                "{:<20} rows {:>12} / {:<12} bytes {:>14} / {:<14} reconciled {}",
+                quota.namespace,
+                quota.used_rows,
+                limit(quota.max_rows),
+                quota.used_bytes,
+                limit(quota.max_bytes),
+                quota.reconciled_at.as_deref().unwrap_or("never")
+            );
+        }
+    }
+}
+
+/// Result of `pgevdb quota remove` and `assign`.
+#[derive(Serialize)]
+struct ChangeReport {
+    message: String,
+}
+
+impl Report for ChangeReport {
+    fn print_text(&self) {
+        println!("{}", self.message);
+    }
+}
+
+fn limit(limit: Option<u64>) -> String {
+    limit.map_or_else(|| "unlimited".to_string(), |limit| limit.to_string())
+}
+
+pub async fn run(command: QuotaCommand, config: Config, output: OutputFormat) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+    match command {
+        QuotaCommand::Set {
+            namespace,
+            max_rows,
+            max_bytes,
+        } => emit(
+            output,
+            &QuotaReport {
+                quotas: vec![quota::set_quota(&pool, &namespace, max_rows, max_bytes).await?],
+            },
+        ),
+        QuotaCommand::Remove { namespace } => {
+            quota::remove_quota(&pool, &namespace).await?;
+            emit(
+                output,
+                &ChangeReport {
+                    message: format!("Removed the quota of namespace '{namespace}'"),
+                },
+            )
+        }
+        QuotaCommand::Assign {
+            collection,
+            namespace,
+        } => {
+            let mut collection = Collection::open(&pool, &collection).await?;
+            collection.set_namespace(&namespace).await?;
+            quota::reconcile(&pool).await?;
+            emit(
+                output,
+                &ChangeReport {
+                    message: format!(
+                        "Moved collection '{}' to namespace '{namespace}'",
+                        collection.name()
+                    ),
+                },
+            )
+        }
+        QuotaCommand::Show => emit(
+            output,
+            &QuotaReport {
+                quotas: quota::quotas(&pool).await?,
+            },
+        ),
+        QuotaCommand::Reconcile => emit(
+            output,
+            &QuotaReport {
+                quotas: quota::reconcile(&pool).await?,
+            },
+        ),
+    }
+}
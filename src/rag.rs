@@ -0,0 +1,147 @@
+//! Retrieval for retrieval-augmented generation: from a question to a context block ready to
+//! paste into a prompt.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::collection::Collection;
+use crate::embedder::EmbeddingModel;
+use crate::error::{Error, Result};
+use crate::rerank::Reranker;
+use crate::search::SearchOptions;
+
+/// Settings of [`retrieve_context`].
+#[derive(Clone, Debug)]
+pub struct ContextOptions {
+    /// Embeds the query text; must be the model the collection's vectors come from.
+    pub model: Arc<dyn EmbeddingModel>,
+    /// Chunks retrieved before deduplication and budgeting.
+    pub candidates: u32,
+    /// Optional cross-encoder reordering of the candidates by relevance to the query text.
+    pub reranker: Option<Arc<dyn Reranker>>,
+    /// Most chunks taken from any one source document.
+    pub max_chunks_per_source: usize,
+    /// Budget for the formatted context, estimated at four characters per token.
+    pub max_tokens: usize,
+}
+
+impl ContextOptions {
+    #[must_use]
+    pub fn new(model: Arc<dyn EmbeddingModel>) -> Self {
+        Self {
+            model,
+            candidates: 20,
+            reranker: None,
+            max_chunks_per_source: 2,
+            max_tokens: 2000,
+        }
+    }
+}
+
+/// One chunk included in a [`Context`], in the order it appears there.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ContextChunk {
+    pub id: i64,
+    pub source: Option<String>,
+    pub content: String,
+    /// The search score, or the reranker's relevance when reranked.
+    pub score: f32,
+}
+
+/// A formatted context block and the chunks it was built from.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Context {
+    /// Numbered chunks, each headed by its source, for citing as `[n]`.
+    pub text: String,
+    pub chunks: Vec<ContextChunk>,
+    /// Estimated tokens in `text`.
+    pub tokens: usize,
+}
+
+/// Embeds `query_text`, searches `collection` (reranking if configured), keeps at most
+/// `max_chunks_per_source` chunks per document and drops repeated content, then formats the
+/// best chunks that fit the token budget with their sources.
+///
+/// Records without content are skipped.
+///
+/// # Errors
+///
+/// Returns [`Error::DownloadFailed`] if the embedding API can't be reached, [`Error::Reranker`]
+/// if the reranking API fails, and [`Error::InvalidInput`] if the model's embeddings don't match
+/// the collection's dimensions.
+pub async fn retrieve_context(
+    collection: &Collection,
+    query_text: &str,
+    options: &ContextOptions,
+) -> Result<Context> {
+    let query = options
+        .model
+        .embed(&[query_text.to_string()])
+        .await?
+        .pop()
+        .ok_or_else(|| Error::InvalidInput("the embedding model returned nothing".to_string()))?;
+
+    let mut search = SearchOptions::new(options.candidates).refine(true);
+    if let Some(reranker) = &options.reranker {
+        search = search.rerank(Arc::clone(reranker), query_text);
+    }
+    let results = collection.search_with(&query, &search).await?;
+    let ids: Vec<i64> = results.iter().map(|result| result.id).collect();
+    let mut contents = collection.contents(&ids).await?;
+
+    let mut retrieved = Context {
+        text: String::new(),
+        chunks: Vec::new(),
+        tokens: 0,
+    };
+    let mut per_source: HashMap<Option<String>, usize> = HashMap::new();
+    for result in results {
+        let Some((Some(content), source)) = contents.remove(&result.id) else {
+            continue;
+        };
+        let taken = per_source.entry(source.clone()).or_default();
+        if source.is_some() && *taken >= options.max_chunks_per_source
+            || retrieved
+                .chunks
+                .iter()
+                .any(|chunk| chunk.content == content)
+        {
+            continue;
+        }
+
+        let mut entry = format!("[{}] ", retrieved.chunks.len() + 1);
+        match &source {
+            Some(source) => {
+                let _ = write!(entry, "{source} (record {})", result.id);
+            }
+            None => {
+                let _ = write!(entry, "record {}", result.id);
+            }
+        }
+        let _ = write!(entry, "\n{content}\n\n");
+        let tokens = estimate_tokens(&entry);
+        // A long chunk that doesn't fit may still leave room for shorter, lower-ranked ones
+        if retrieved.tokens + tokens > options.max_tokens {
+            continue;
+        }
+
+        *taken += 1;
+        retrieved.text.push_str(&entry);
+        retrieved.tokens += tokens;
+        retrieved.chunks.push(ContextChunk {
+            id: result.id,
+            source,
+            content,
+            score: result.rerank_score.unwrap_or(result.score),
+        });
+    }
+    Ok(retrieved)
+}
+
+/// Rough token count for English text and common tokenizers.
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
@@ -0,0 +1,162 @@
+//! Second-stage reranking of search candidates by a model that reads the query and each
+//! candidate's text together.
+//!
+//! Cross-encoders are far more accurate than comparing embeddings but too slow to run over a
+//! whole collection, so they reorder the top candidates of a vector search instead.
+
+use std::fmt;
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::download;
+use crate::error::{Error, Result};
+
+/// Scores documents by relevance to a query. Implement it to plug in a local cross-encoder, e.g.
+/// an ONNX model; [`HttpReranker`] calls a hosted reranking API.
+pub trait Reranker: fmt::Debug + Send + Sync {
+    /// One relevance score per document, in document order; higher is more relevant.
+    fn rerank<'a>(
+        &'a self,
+        query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<f32>>>;
+}
+
+/// A reranking API in the style of Cohere, Jina and Text Embeddings Inference: a `POST` of
+/// `{"model", "query", "documents"}` answered by `{"results": [{"index", "relevance_score"}]}`.
+#[derive(Clone, Debug)]
+pub struct HttpReranker {
+    url: String,
+    model: Option<String>,
+    api_key: Option<String>,
+    timeout: Duration,
+}
+
+impl HttpReranker {
+    #[must_use]
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            model: None,
+            api_key: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Sent as a bearer token.
+    #[must_use]
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Bounds connecting and each wait for response data.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn call(&self, query: &str, documents: &[String]) -> Result<Vec<f32>> {
+        #[derive(Serialize)]
+        struct Request<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            model: Option<&'a str>,
+            query: &'a str,
+            documents: &'a [String],
+        }
+        #[derive(Deserialize)]
+        struct Response {
+            results: Vec<Ranked>,
+        }
+        #[derive(Deserialize)]
+        struct Ranked {
+            index: usize,
+            relevance_score: f32,
+        }
+
+        let failed = |source| Error::Reranker {
+            url: self.url.clone(),
+            source,
+        };
+        let mut request = download::client(self.timeout, None)
+            .map_err(failed)?
+            .post(&self.url)
+            .json(&Request {
+                model: self.model.as_deref(),
+                query,
+                documents,
+            });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response: Response = request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(failed)?
+            .json()
+            .await
+            .map_err(failed)?;
+
+        // Documents the API left out rank below every scored one
+        let mut scores = vec![f32::NEG_INFINITY; documents.len()];
+        for ranked in response.results {
+            if let Some(score) = scores.get_mut(ranked.index) {
+                *score = ranked.relevance_score;
+            }
+        }
+        Ok(scores)
+    }
+}
+
+impl Reranker for HttpReranker {
+    fn rerank<'a>(
+        &'a self,
+        query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, Result<Vec<f32>>> {
+        Box::pin(self.call(query, documents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn names_the_reranker_when_its_api_fails() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/rerank", listener.local_addr()?);
+        tokio::spawn(async move {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n")
+                .await;
+        });
+
+        let reranker = HttpReranker::new(&url);
+        let documents = ["a".to_string()];
+        let Err(Error::Reranker { url: failed, .. }) = reranker.rerank("query", &documents).await
+        else {
+            panic!("the failed call wasn't reported as a reranker error");
+        };
+        assert_eq!(failed, url);
+        Ok(())
+    }
+}
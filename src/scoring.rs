@@ -0,0 +1,353 @@
+//! Custom ranking of search results: vector similarity combined with boosts from the payload.
+//!
+//! A [`Score`] is an expression over a candidate's similarity to the query and its payload
+//! values, built from constructors and `+` / `*`, and evaluated by PostgreSQL in the `ORDER BY`
+//! of the search. Keys and numbers are bound as query parameters; no caller text becomes SQL.
+//!
+//! Favoring recent documents, with a relevance that halves every 30 days of age:
+//!
+//! ```
+//! use std::time::Duration;
+//! use pgevdb::scoring::Score;
+//!
+//! let fresh = Score::similarity()
+//!     + Score::recency("published_at", Duration::from_secs(30 * 24 * 3600)).weight(0.3);
+//! let options = pgevdb::SearchOptions::new(10).score_by(fresh);
+//! ```
+
+use std::ops::{Add, Mul};
+use std::time::Duration;
+
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::collection::Metric;
+use crate::error::{Error, Result};
+
+/// Timestamps and dates among the values jsonpath's `datetime()` parses, which include times
+/// of day.
+const TIMESTAMP_PATH: &str =
+    r#"$.datetime() ? (@.type() starts with "timestamp" || @.type() == "date")"#;
+
+/// Unix seconds beyond which numeric timestamps are clamped, about 3000 years either way, well
+/// inside the range of `timestamptz`.
+const UNIX_RANGE: i64 = 100_000_000_000;
+
+/// An expression ranking search candidates, highest first. See the [module](self) docs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Score {
+    /// The candidate's vector similarity in `[0, 1]`, see [`Metric::score`].
+    Similarity,
+    /// A fixed value.
+    Constant(f32),
+    /// The number at the top-level payload `key`, or `default` where it's missing or not a
+    /// number.
+    Field { key: String, default: f32 },
+    /// How recent the timestamp at the top-level payload `key` is, in `[0, 1]`: `1` for now or
+    /// the future, `0.5` at `scale` of age, falling as `decay` says. Timestamps are ISO 8601
+    /// strings or Unix seconds; records without a valid one score `0`.
+    Decay {
+        key: String,
+        decay: Decay,
+        scale: Duration,
+    },
+    /// The sum of the expressions; none at all is `0`.
+    Sum(Vec<Score>),
+    /// The product of the expressions; none at all is `1`.
+    Product(Vec<Score>),
+}
+
+/// Shape of a [`Score::Decay`] over age, as a fraction of its scale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decay {
+    /// `0.5^(age / scale)`: halves every `scale`, never reaching `0`.
+    Exponential,
+    /// `1 - age / (2 · scale)`: reaches `0` at twice the scale.
+    Linear,
+    /// `0.5^((age / scale)²)`: stays near `1` for young records, then falls off quickly.
+    Gauss,
+}
+
+impl Score {
+    /// The vector similarity, see [`Score::Similarity`].
+    #[must_use]
+    pub fn similarity() -> Self {
+        Score::Similarity
+    }
+
+    /// A fixed value.
+    #[must_use]
+    pub fn constant(value: f32) -> Self {
+        Score::Constant(value)
+    }
+
+    /// The number at the payload's `key`, see [`Score::Field`].
+    #[must_use]
+    pub fn field(key: impl Into<String>, default: f32) -> Self {
+        Score::Field {
+            key: key.into(),
+            default,
+        }
+    }
+
+    /// Recency of the timestamp at the payload's `key`, see [`Score::Decay`].
+    #[must_use]
+    pub fn decay(key: impl Into<String>, decay: Decay, scale: Duration) -> Self {
+        Score::Decay {
+            key: key.into(),
+            decay,
+            scale,
+        }
+    }
+
+    /// Exponential [decay](Self::decay) that halves every `half_life`.
+    #[must_use]
+    pub fn recency(key: impl Into<String>, half_life: Duration) -> Self {
+        Self::decay(key, Decay::Exponential, half_life)
+    }
+
+    /// This expression multiplied by `weight`.
+    #[must_use]
+    pub fn weight(self, weight: f32) -> Self {
+        Score::constant(weight) * self
+    }
+
+    /// Rejects expressions PostgreSQL can't evaluate for every record.
+    pub(crate) fn check(&self) -> Result<()> {
+        match self {
+            Score::Constant(value) | Score::Field { default: value, .. } if !value.is_finite() => {
+                Err(Error::InvalidInput(format!(
+                    "score values must be finite, not {value}"
+                )))
+            }
+            Score::Decay { key, scale, .. } if scale.is_zero() => Err(Error::InvalidInput(
+                format!("the decay of '{key}' needs a scale above zero"),
+            )),
+            Score::Sum(scores) | Score::Product(scores) => scores.iter().try_for_each(Score::check),
+            Score::Similarity | Score::Constant(_) | Score::Field { .. } | Score::Decay { .. } => {
+                Ok(())
+            }
+        }
+    }
+
+    /// Appends this expression as a `double precision` value of the `distance` and `payload`
+    /// columns of a search, binding its keys and numbers as parameters. `unsquared_l2` says the
+    /// distance is the plain rather than the squared L2 distance.
+    pub(crate) fn push(
+        &self,
+        builder: &mut QueryBuilder<'_, Postgres>,
+        metric: Metric,
+        unsquared_l2: bool,
+    ) {
+        match self {
+            Score::Similarity => {
+                let distance = if unsquared_l2 {
+                    "(distance::float8 * distance::float8)"
+                } else {
+                    "distance::float8"
+                };
+                // As Metric::score; exp overflows double precision past about 709
+                let similarity = match metric {
+                    Metric::L2 | Metric::Hamming => format!("1 / (1 + greatest({distance}, 0))"),
+                    Metric::Cosine => format!("1 - {distance} / 2"),
+                    Metric::Dot => format!("1 / (1 + exp(least({distance}, 700)))"),
+                    Metric::Jaccard => format!("1 - {distance}"),
+                };
+                builder.push(format!("greatest(least({similarity}, 1), 0)"));
+            }
+            Score::Constant(value) => {
+                builder.push_bind(f64::from(*value)).push("::float8");
+            }
+            Score::Field { key, default } => {
+                builder
+                    .push("coalesce(CASE WHEN jsonb_typeof(payload -> ")
+                    .push_bind(key.clone())
+                    .push(") = 'number' THEN (payload -> ")
+                    .push_bind(key.clone())
+                    .push(")::float8 END, ")
+                    .push_bind(f64::from(*default))
+                    .push("::float8)");
+            }
+            Score::Decay { key, decay, scale } => {
+                // Age in units of the scale, NULL without a valid timestamp: the jsonpath runs
+                // silently, so malformed values don't fail the search. power() raises an error
+                // on underflow, hence the caps.
+                builder
+                    .push("coalesce((SELECT CASE WHEN age < 0 THEN 1 ELSE ")
+                    .push(match decay {
+                        Decay::Exponential => "power(0.5, least(age, 1000))",
+                        Decay::Linear => "greatest(1 - age / 2, 0)",
+                        Decay::Gauss => "power(0.5, least(age * age, 1000))",
+                    })
+                    .push(format!(
+                        " END FROM (SELECT extract(epoch FROM now() - CASE jsonb_typeof(value) \
+                         WHEN 'number' THEN to_timestamp(least(greatest(value::numeric, \
+                         -{UNIX_RANGE}), {UNIX_RANGE})::float8) \
+                         WHEN 'string' THEN (jsonb_path_query_first(value, '{TIMESTAMP_PATH}', \
+                         '{{}}', true) #>> '{{}}')::timestamptz END)::float8 / "
+                    ))
+                    .push_bind(scale.as_secs_f64())
+                    .push(" AS age FROM (SELECT payload -> ")
+                    .push_bind(key.clone())
+                    .push(" AS value) stamp) decay), 0)");
+            }
+            Score::Sum(scores) | Score::Product(scores) if scores.is_empty() => {
+                builder.push(if matches!(self, Score::Sum(_)) {
+                    "0::float8"
+                } else {
+                    "1::float8"
+                });
+            }
+            Score::Sum(scores) | Score::Product(scores) => {
+                let separator = if matches!(self, Score::Sum(_)) {
+                    " + "
+                } else {
+                    " * "
+                };
+                builder.push("(");
+                for (position, score) in scores.iter().enumerate() {
+                    if position > 0 {
+                        builder.push(separator);
+                    }
+                    score.push(builder, metric, unsquared_l2);
+                }
+                builder.push(")");
+            }
+        }
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    /// The sum of both expressions.
+    fn add(self, other: Score) -> Score {
+        match self {
+            Score::Sum(mut scores) => {
+                scores.push(other);
+                Score::Sum(scores)
+            }
+            score => Score::Sum(vec![score, other]),
+        }
+    }
+}
+
+impl Mul for Score {
+    type Output = Score;
+
+    /// The product of both expressions.
+    fn mul(self, other: Score) -> Score {
+        match self {
+            Score::Product(mut scores) => {
+                scores.push(other);
+                Score::Product(scores)
+            }
+            score => Score::Product(vec![score, other]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sql(score: &Score, metric: Metric, unsquared_l2: bool) -> String {
+        let mut builder = QueryBuilder::new("");
+        score.push(&mut builder, metric, unsquared_l2);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn operators_flatten_chains() {
+        let score = Score::similarity() + Score::constant(1.0) + Score::field("votes", 0.0);
+        assert_eq!(
+            score,
+            Score::Sum(vec![
+                Score::Similarity,
+                Score::Constant(1.0),
+                Score::field("votes", 0.0)
+            ])
+        );
+        assert_eq!(
+            Score::similarity().weight(0.3),
+            Score::Product(vec![Score::Constant(0.3), Score::Similarity])
+        );
+    }
+
+    #[test]
+    fn similarity_follows_the_metric_score() {
+        assert_eq!(
+            sql(&Score::similarity(), Metric::Cosine, false),
+            "greatest(least(1 - distance::float8 / 2, 1), 0)"
+        );
+        // pgvector's plain L2 distance is squared first, as Metric::score expects
+        assert_eq!(
+            sql(&Score::similarity(), Metric::L2, true),
+            "greatest(least(1 / (1 + greatest((distance::float8 * distance::float8), 0)), 1), 0)"
+        );
+        assert!(
+            sql(&Score::similarity(), Metric::Dot, false).contains("least(distance::float8, 700)")
+        );
+    }
+
+    #[test]
+    fn binds_keys_and_numbers() {
+        let score = Score::field("votes'; DROP TABLE x", 1.5) * Score::constant(2.0);
+        assert_eq!(
+            sql(&score, Metric::L2, false),
+            "(coalesce(CASE WHEN jsonb_typeof(payload -> $1) = 'number' THEN (payload -> $2)::float8 \
+             END, $3::float8) * $4::float8)"
+        );
+    }
+
+    #[test]
+    fn empty_sums_and_products_are_identities() {
+        assert_eq!(sql(&Score::Sum(Vec::new()), Metric::L2, false), "0::float8");
+        assert_eq!(
+            sql(&Score::Product(Vec::new()), Metric::L2, false),
+            "1::float8"
+        );
+    }
+
+    #[test]
+    fn decays_by_shape() {
+        let scale = Duration::from_secs(3600);
+        for (decay, expression) in [
+            (Decay::Exponential, "power(0.5, least(age, 1000))"),
+            (Decay::Linear, "greatest(1 - age / 2, 0)"),
+            (Decay::Gauss, "power(0.5, least(age * age, 1000))"),
+        ] {
+            let sql = sql(
+                &Score::decay("published_at", decay, scale),
+                Metric::L2,
+                false,
+            );
+            assert!(sql.contains(expression), "{sql}");
+            assert!(sql.starts_with("coalesce("), "{sql}");
+            assert!(sql.ends_with(", 0)"), "{sql}");
+        }
+        assert_eq!(
+            Score::recency("published_at", scale),
+            Score::decay("published_at", Decay::Exponential, scale)
+        );
+    }
+
+    #[test]
+    fn refuses_values_postgresql_cant_rank_by() {
+        for score in [
+            Score::constant(f32::NAN),
+            Score::field("votes", f32::INFINITY),
+            Score::decay("published_at", Decay::Linear, Duration::ZERO),
+            // Nested anywhere
+            Score::similarity() + Score::constant(1.0) * Score::constant(f32::NEG_INFINITY),
+        ] {
+            assert!(
+                matches!(score.check(), Err(Error::InvalidInput(_))),
+                "{score:?}"
+            );
+        }
+        let fresh = Score::similarity()
+            + Score::recency("published_at", Duration::from_secs(60)).weight(0.3)
+            + Score::field("votes", 0.0);
+        assert!(fresh.check().is_ok());
+    }
+}
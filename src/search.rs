@@ -0,0 +1,267 @@
+//! Per-call search settings.
+
+use std::sync::Arc;
+
+use crate::collection::Metric;
+use crate::config::Backend;
+use crate::filter::Filter;
+use crate::rerank::Reranker;
+use crate::scoring::Score;
+
+/// How [`Collection::search_with`](crate::Collection::search_with) runs a query.
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    pub(crate) limit: u32,
+    pub(crate) offset: u32,
+    pub(crate) refine: bool,
+    pub(crate) candidates: Option<u32>,
+    pub(crate) metric: Option<Metric>,
+    pub(crate) filter: Filter,
+    pub(crate) probes: Option<u32>,
+    pub(crate) ef_search: Option<u32>,
+    pub(crate) max_distance: Option<f32>,
+    pub(crate) min_score: Option<f32>,
+    pub(crate) group: Option<Group>,
+    pub(crate) score: Option<Score>,
+    pub(crate) rerank: Option<Rerank>,
+    pub(crate) mmr: Option<f32>,
+}
+
+/// Grouping of results by a payload key.
+#[derive(Clone, Debug)]
+pub(crate) struct Group {
+    pub(crate) key: String,
+    pub(crate) per_group: u32,
+}
+
+/// A reranking stage and the query text it compares candidates with.
+#[derive(Clone, Debug)]
+pub(crate) struct Rerank {
+    pub(crate) reranker: Arc<dyn Reranker>,
+    pub(crate) query: String,
+    /// Top-level payload key holding the text to rank on, instead of the content.
+    pub(crate) payload_key: Option<String>,
+}
+
+impl SearchOptions {
+    /// Returns the `limit` nearest results.
+    #[must_use]
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            offset: 0,
+            refine: false,
+            candidates: None,
+            metric: None,
+            filter: Filter::All,
+            probes: None,
+            ef_search: None,
+            max_distance: None,
+            min_score: None,
+            group: None,
+            score: None,
+            rerank: None,
+            mmr: None,
+        }
+    }
+
+    /// Skips the `offset` nearest results, for paging: `SearchOptions::new(10).offset(20)`
+    /// returns results 21 to 30. Each page searches from the start again, so deep pages cost
+    /// as much as one search with `offset + limit` results.
+    #[must_use]
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// On collections searched by a reduced or prefix column, re-ranks the candidates exactly by
+    /// their full vectors, so distances and order are those of full-dimension search.
+    #[must_use]
+    pub fn refine(mut self, full_dim: bool) -> Self {
+        self.refine = full_dim;
+        self
+    }
+
+    /// Reorders the candidates with `reranker`, which reads `query_text` together with each
+    /// candidate's content, and returns the best `limit` of them. Candidates without content
+    /// are ranked on an empty text, which usually puts them last; use
+    /// [`rerank_by_payload`](Self::rerank_by_payload) for records keeping their text in the
+    /// payload.
+    #[must_use]
+    pub fn rerank(mut self, reranker: Arc<dyn Reranker>, query_text: impl Into<String>) -> Self {
+        self.rerank = Some(Rerank {
+            reranker,
+            query: query_text.into(),
+            payload_key: None,
+        });
+        self
+    }
+
+    /// [`rerank`](Self::rerank) on the string at the top-level payload `key` instead of the
+    /// content, e.g. a title or summary. Candidates without a string there are ranked on an
+    /// empty text.
+    #[must_use]
+    pub fn rerank_by_payload(
+        mut self,
+        reranker: Arc<dyn Reranker>,
+        query_text: impl Into<String>,
+        key: impl Into<String>,
+    ) -> Self {
+        self.rerank = Some(Rerank {
+            reranker,
+            query: query_text.into(),
+            payload_key: Some(key.into()),
+        });
+        self
+    }
+
+    /// Picks the results among the candidates by maximal marginal relevance, so near-duplicates
+    /// of a result already picked give way to other relevant records. `lambda` trades
+    /// relevance, at `1`, against variety, at `0`; `0.5` is a common start. With a reranker,
+    /// relevance is the rerank score. See [`diversity::mmr`](crate::diversity::mmr).
+    #[must_use]
+    pub fn mmr(mut self, lambda: f32) -> Self {
+        self.mmr = Some(lambda);
+        self
+    }
+
+    /// Candidates fetched before refining, reranking or diversifying; more improves recall at
+    /// some cost. Defaults to four times the limit.
+    #[must_use]
+    pub fn candidates(mut self, candidates: u32) -> Self {
+        self.candidates = Some(candidates);
+        self
+    }
+
+    /// Compares vectors by `metric` instead of the collection's, e.g. inner product on a
+    /// cosine collection. Indexes serve only the metric they were built for, so any other
+    /// metric scans every vector.
+    #[must_use]
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Returns only records matching `filter`, e.g. by their payload.
+    #[must_use]
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Lists an `ivf` index scans per query, instead of the backend's default (10 on
+    /// pgvecto.rs, 1 on pgvector). More lists find more of the true nearest neighbors and take
+    /// longer; other indexes ignore it.
+    #[must_use]
+    pub fn probes(mut self, probes: u32) -> Self {
+        self.probes = Some(probes);
+        self
+    }
+
+    /// Size of the candidate list an `hnsw` index keeps while searching, instead of the
+    /// backend's default (100 on pgvecto.rs, 40 on pgvector). Larger lists find more of the
+    /// true nearest neighbors and take longer; pgvector returns at most this many results from
+    /// the index. Other indexes ignore it.
+    #[must_use]
+    pub fn ef_search(mut self, ef_search: u32) -> Self {
+        self.ef_search = Some(ef_search);
+        self
+    }
+
+    /// Returns only results at most `max_distance` from the query, in the metric's raw distance
+    /// as [`SearchResult::distance`](crate::SearchResult::distance) reports it, so squared for
+    /// L2. The cutoff is applied to the nearest `limit` results, so fewer come back when some
+    /// are farther; with a reranker, it applies before reranking.
+    #[must_use]
+    pub fn max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Returns only results whose [score](crate::Metric::score) is at least `min_score`, like
+    /// [`max_distance`](Self::max_distance) with the distance of that score.
+    #[must_use]
+    pub fn min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// Returns at most `per_group` results for each value of the top-level payload `key`, e.g.
+    /// the best 3 chunks of each document with `group_by("doc_id", 3)`. Records without the key
+    /// are groups of their own. Groups are formed among the
+    /// [candidates](Self::candidates), so raise those when the nearest results crowd into few
+    /// groups and fewer than `limit` results come back.
+    #[must_use]
+    pub fn group_by(mut self, key: impl Into<String>, per_group: u32) -> Self {
+        self.group = Some(Group {
+            key: key.into(),
+            per_group,
+        });
+        self
+    }
+
+    /// Orders the [candidates](Self::candidates) by `score`, highest first, instead of by
+    /// distance, e.g. to favor recent records; see [`scoring`](crate::scoring). Results keep
+    /// their vector [`score`](crate::SearchResult::score). Grouping ranks within groups by
+    /// `score` too, and a reranker or [`mmr`](Self::mmr) reorders the scored results.
+    #[must_use]
+    pub fn score_by(mut self, score: Score) -> Self {
+        self.score = Some(score);
+        self
+    }
+
+    /// The tighter of the distance cutoffs as `metric`'s operator computes it, if any: plain
+    /// rather than squared L2 when `unsquared_l2`.
+    pub(crate) fn max_sql_distance(&self, metric: Metric, unsquared_l2: bool) -> Option<f32> {
+        let score_distance = self.min_score.map(|score| metric.distance_at_score(score));
+        let distance = match (self.max_distance, score_distance) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b)?,
+        };
+        Some(if unsquared_l2 {
+            distance.max(0.0).sqrt()
+        } else {
+            distance
+        })
+    }
+
+    /// Settings of `backend` to apply for the duration of the query, as name and value.
+    pub(crate) fn index_settings(&self, backend: Backend) -> Vec<(&'static str, String)> {
+        let mut settings = Vec::new();
+        if let Some(probes) = self.probes {
+            let name = match backend {
+                Backend::PgVectoRs => "vectors.ivf_nprobe",
+                Backend::PgVector => "ivfflat.probes",
+            };
+            settings.push((name, probes.to_string()));
+        }
+        if let Some(ef_search) = self.ef_search {
+            let name = match backend {
+                Backend::PgVectoRs => "vectors.hnsw_ef_search",
+                Backend::PgVector => "hnsw.ef_search",
+            };
+            settings.push((name, ef_search.to_string()));
+        }
+        settings
+    }
+
+    pub(crate) fn candidate_count(&self) -> u32 {
+        self.candidates
+            .unwrap_or_else(|| self.limit.saturating_mul(4))
+            .max(self.end())
+    }
+
+    /// Results to rank for the requested page: those skipped and those returned.
+    pub(crate) fn end(&self) -> u32 {
+        self.limit.saturating_add(self.offset)
+    }
+
+    /// The requested page of `results`, ranked best first.
+    pub(crate) fn page<T>(&self, results: Vec<T>) -> Vec<T> {
+        results
+            .into_iter()
+            .skip(self.offset as usize)
+            .take(self.limit as usize)
+            .collect()
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::output::Report;
+use pgevdb::config::Config;
+use pgevdb::download::{download, get_json, verify_sha256};
+use pgevdb::{Error, Result};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/portalcorp/pgevdb/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+/// Result of `pgevdb self-update`.
+#[derive(Serialize)]
+pub struct SelfUpdateReport {
+    pub current_version: String,
+    pub latest_version: String,
+    pub updated: bool,
+}
+
+impl Report for SelfUpdateReport {
+    fn print_text(&self) {
+        if self.updated {
+            println!(
+                "Updated pgevdb {} -> {}",
+                self.current_version, self.latest_version
+            );
+        } else if self.current_version == self.latest_version {
+            println!("pgevdb {} is up to date", self.current_version);
+        } else {
+            println!(
+                "pgevdb {} is available (installed: {})",
+                self.latest_version, self.current_version
+            );
+        }
+    }
+}
+
+/// Checks the latest GitHub release and, unless `check_only` is set, replaces the running
+/// executable with it after comparing it with its published SHA-256 checksum. The checksum
+/// comes from the same release as the binary, so it catches corrupted downloads, not tampered
+/// releases.
+///
+/// Releases are expected to carry a `pgevdb-<target>` binary (`.exe` on Windows) and a
+/// `pgevdb-<target>.sha256` checksum file for each supported target.
+pub async fn self_update(check_only: bool, config: &Config) -> Result<SelfUpdateReport> {
+    let current = Version::parse(env!("CARGO_PKG_VERSION"))
+        .map_err(|error| Error::Config(error.to_string()))?;
+    let release: Release = get_json(LATEST_RELEASE_URL, config).await?;
+    let latest = Version::parse(release.tag_name.trim_start_matches('v'))
+        .map_err(|error| Error::Config(format!("release tag '{}': {error}", release.tag_name)))?;
+
+    let mut report = SelfUpdateReport {
+        current_version: current.to_string(),
+        latest_version: latest.to_string(),
+        updated: false,
+    };
+    if latest <= current || check_only {
+        return Ok(report);
+    }
+
+    let target = env!("PGEVDB_TARGET");
+    let binary_name = format!("pgevdb-{target}{}", std::env::consts::EXE_SUFFIX);
+    let (Some(binary), Some(checksum)) = (
+        release.asset(&binary_name),
+        release.asset(&format!("{binary_name}.sha256")),
+    ) else {
+        return Err(Error::UnsupportedPlatform(format!(
+            "{target} (release {} has no {binary_name} binary with a checksum)",
+            release.tag_name
+        )));
+    };
+
+    let checksum = download(
+        &checksum.browser_download_url,
+        "Downloading checksum",
+        config,
+    )
+    .await?;
+    let bytes = download(&binary.browser_download_url, "Downloading pgevdb", config).await?;
+    // The checksum file is `sha256sum` output: the hash, then the file name
+    let checksum = String::from_utf8_lossy(&checksum);
+    verify_sha256(
+        &binary_name,
+        &bytes,
+        checksum.split_whitespace().next().unwrap_or_default(),
+    )?;
+
+    let executable = std::env::current_exe()?;
+    info!("Replacing {}", executable.display());
+    replace_executable(&executable, &bytes)?;
+
+    report.updated = true;
+    Ok(report)
+}
+
+/// Writes the new binary next to `executable` and renames it into place, so an interrupted
+/// update never leaves a truncated executable behind, or no executable at all.
+fn replace_executable(executable: &Path, bytes: &[u8]) -> Result<()> {
+    let staged = executable.with_extension("update");
+    std::fs::write(&staged, bytes)?;
+    std::fs::set_permissions(&staged, std::fs::metadata(executable)?.permissions())?;
+
+    // Windows can't overwrite a running executable, but it can rename it out of the way
+    #[cfg(windows)]
+    let previous = {
+        let previous = executable.with_extension("old");
+        let _ = std::fs::remove_file(&previous);
+        std::fs::rename(executable, &previous)?;
+        previous
+    };
+
+    if let Err(error) = std::fs::rename(&staged, executable) {
+        let _ = std::fs::remove_file(&staged);
+        // Put the running executable back, so the installed version still works
+        #[cfg(windows)]
+        let _ = std::fs::rename(&previous, executable);
+        return Err(error.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::net::SocketAddr;
+
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::{grpc, http, CancellationToken, Config, Result};
+
+/// Requests served by one API of `pgevdb serve`.
+#[derive(Serialize)]
+struct Listener {
+    protocol: &'static str,
+    address: SocketAddr,
+    requests: u64,
+}
+
+/// Result of `pgevdb serve`, once stopped.
+#[derive(Serialize)]
+struct ServeReport {
+    listeners: Vec<Listener>,
+}
+
+impl Report for ServeReport {
+    fn print_text(&self) {
+        for listener in &self.listeners {
+            println!(
+                "Stopped serving {} on {} after {} requests",
+                listener.protocol, listener.address, listener.requests
+            );
+        }
+    }
+}
+
+/// Serves the HTTP API on `http` and the gRPC API on `grpc`, whichever are given, until
+/// Ctrl-C, using the background server if one is running.
+pub async fn run(
+    http: Option<SocketAddr>,
+    grpc: Option<SocketAddr>,
+    config: Config,
+    output: OutputFormat,
+) -> Result<()> {
+    let api = config.api.clone();
+    let (_postgresql, pool) = server::connect(config).await?;
+    let http = bind(http, "HTTP").await?;
+    let grpc = bind(grpc, "gRPC").await?;
+    let http_address = http.as_ref().map(TcpListener::local_addr).transpose()?;
+    let grpc_address = grpc.as_ref().map(TcpListener::local_addr).transpose()?;
+    if api.keys.is_empty()
+        && [http_address, grpc_address]
+            .into_iter()
+            .flatten()
+            .any(|address| !address.ip().is_loopback())
+    {
+        warn!(
+            "No [[api.keys]] are configured, so every client that can connect may read and write"
+        );
+    }
+
+    let cancel = CancellationToken::new();
+    let stop = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Finishing requests in progress");
+            stop.cancel();
+        }
+    });
+    let (http_requests, grpc_requests) = tokio::try_join!(
+        async {
+            match http {
+                Some(listener) => http::serve(&pool, listener, &api, cancel.clone()).await,
+                None => Ok(0),
+            }
+        },
+        async {
+            match grpc {
+                Some(listener) => grpc::serve(&pool, listener, &api, cancel.clone()).await,
+                None => Ok(0),
+            }
+        },
+    )?;
+    pool.close().await;
+
+    let listeners = [
+        ("HTTP", http_address, http_requests),
+        ("gRPC", grpc_address, grpc_requests),
+    ]
+    .into_iter()
+    .filter_map(|(protocol, address, requests)| {
+        address.map(|address| Listener {
+            protocol,
+            address,
+            requests,
+        })
+    })
+    .collect();
+    emit(output, &ServeReport { listeners })
+}
+
+/// Listens on `address` if given.
+async fn bind(address: Option<SocketAddr>, protocol: &str) -> Result<Option<TcpListener>> {
+    let Some(address) = address else {
+        return Ok(None);
+    };
+    let listener = TcpListener::bind(address).await?;
+    info!(
+        "Serving the {protocol} API on {}; stop with Ctrl-C",
+        listener.local_addr()?
+    );
+    Ok(Some(listener))
+}
@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+
+use postgresql_embedded::PostgreSQL;
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+
+use crate::output::{emit, OutputFormat, Report};
+use pgevdb::{Config, Error, PgEvDb, Result};
+
+/// Connects to the server left running by `pgevdb start`, or starts one that stops again when
+/// the returned handle is dropped.
+pub async fn connect(config: Config) -> Result<(Option<PostgreSQL>, PgPool)> {
+    let db = PgEvDb::from_config(config)?;
+    if let Some(pool) = db.connect().await? {
+        return Ok((None, pool));
+    }
+    let (postgresql, pool) = db.start().await?;
+    Ok((Some(postgresql), pool))
+}
+
+/// Result of `pgevdb start`.
+#[derive(Serialize)]
+struct StartReport {
+    port: u16,
+    database: String,
+    already_running: bool,
+}
+
+impl Report for StartReport {
+    fn print_text(&self) {
+        if self.already_running {
+            println!("PostgreSQL is already running on port {}", self.port);
+        } else {
+            println!(
+                "Started PostgreSQL on port {} with database '{}'; stop it with `pgevdb stop`",
+                self.port, self.database
+            );
+        }
+    }
+}
+
+/// Provisions and starts the server, leaving it running in the background.
+pub async fn start(config: Config, output: OutputFormat) -> Result<()> {
+    let db = PgEvDb::from_config(config)?;
+    let running = db.running_port()?;
+    let port = if let Some(port) = running {
+        port
+    } else {
+        let (postgresql, pool) = db.start().await?;
+        pool.close().await;
+        let port = postgresql.settings().port;
+        // Dropping the handle would stop the server; `pgevdb stop` does that instead
+        std::mem::forget(postgresql);
+        port
+    };
+    emit(
+        output,
+        &StartReport {
+            port,
+            database: db.config().database_name.clone(),
+            already_running: running.is_some(),
+        },
+    )
+}
+
+/// Result of `pgevdb stop`.
+#[derive(Serialize)]
+struct StopReport {
+    stopped: bool,
+}
+
+impl Report for StopReport {
+    fn print_text(&self) {
+        if self.stopped {
+            println!("Stopped PostgreSQL");
+        } else {
+            println!("PostgreSQL isn't running");
+        }
+    }
+}
+
+pub async fn stop(config: Config, output: OutputFormat) -> Result<()> {
+    let stopped = PgEvDb::from_config(config)?.stop_running().await?;
+    emit(output, &StopReport { stopped })
+}
+
+/// Result of `pgevdb status`.
+#[derive(Serialize)]
+struct StatusReport {
+    initialized: bool,
+    running: bool,
+    port: Option<u16>,
+    database: String,
+    data_dir: PathBuf,
+    pg_version: String,
+    backend: &'static str,
+}
+
+impl Report for StatusReport {
+    fn print_text(&self) {
+        println!(
+            "PostgreSQL v{} with {} in {}",
+            self.pg_version,
+            self.backend,
+            self.data_dir.display()
+        );
+        match self.port {
+            Some(port) => println!("Running on port {port}, database '{}'", self.database),
+            None if self.initialized => println!("Stopped"),
+            None => println!("Not initialized; run `pgevdb init`"),
+        }
+    }
+}
+
+pub fn status(config: Config, output: OutputFormat) -> Result<()> {
+    let db = PgEvDb::from_config(config)?;
+    let port = db.running_port()?;
+    let config = db.config();
+    emit(
+        output,
+        &StatusReport {
+            initialized: config.data_dir().join("PG_VERSION").exists(),
+            running: port.is_some(),
+            port,
+            database: config.database_name.clone(),
+            data_dir: config.data_dir(),
+            pg_version: config.pg_version.clone(),
+            backend: config.backend.name(),
+        },
+    )
+}
+
+/// Runs the installation's `psql` against the database, starting a server for the session if
+/// none is running in the background.
+pub async fn sql(command: Option<String>, psql_args: Vec<String>, config: Config) -> Result<()> {
+    let db = PgEvDb::from_config(config)?;
+    let (postgresql, port) = if let Some(port) = db.running_port()? {
+        (None, port)
+    } else {
+        let (postgresql, pool) = db.start().await?;
+        pool.close().await;
+        let port = postgresql.settings().port;
+        (Some(postgresql), port)
+    };
+    let config = db.config();
+    // Read after starting, which generates the password on first run
+    let settings = config.settings()?;
+
+    let psql = config
+        .pg_dir()
+        .join("bin")
+        .join(format!("psql{}", std::env::consts::EXE_SUFFIX));
+    let mut psql = tokio::process::Command::new(psql);
+    psql.arg("--host")
+        .arg(&settings.host)
+        .arg("--port")
+        .arg(port.to_string())
+        .arg("--username")
+        .arg(&settings.username)
+        .arg("--dbname")
+        .arg(&config.database_name)
+        .env("PGPASSWORD", &settings.password);
+    if let Some(command) = command {
+        psql.arg("--command").arg(command);
+    }
+    let mut child = psql.args(psql_args).spawn()?;
+    // psql handles Ctrl-C itself by cancelling the running query, so keep waiting for it
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status?,
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    };
+    drop(postgresql);
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Io(std::io::Error::other(format!("psql {status}"))))
+    }
+}
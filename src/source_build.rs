@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use tokio::process::Command;
+use tracing::info;
+
+use crate::config::{Backend, Config};
+use crate::error::{Error, Result};
+use crate::platform::Platform;
+use crate::progress::Progress;
+
+/// Builds the backend's extension from source at the configured release tag against the
+/// embedded PostgreSQL's `pg_config`, returning a directory laid out like a release archive.
+///
+/// Results are cached per extension version, PostgreSQL version and platform, so only the first
+/// install on a machine pays for the build. Requires `git`, and a Rust toolchain with
+/// `cargo-pgrx` for pgvecto.rs or `make` and a C compiler for pgvector.
+pub async fn build(config: &Config, platform: Platform) -> Result<PathBuf> {
+    let backend = config.backend;
+    let name = backend.extension_name();
+    let version = &config.extension_version;
+    let output_dir = config.cache_dir().join("source-builds").join(format!(
+        "{name}-{version}-pg{}-{platform}",
+        config.pg_version
+    ));
+    if output_dir.join(format!("{name}.control")).exists() {
+        info!("Using cached source build from {}", output_dir.display());
+        return Ok(output_dir);
+    }
+
+    let source_dir = config
+        .cache_dir()
+        .join("sources")
+        .join(format!("{}-{version}", backend.name()));
+    let pg_config = config.pg_dir().join("bin").join("pg_config");
+    let pg_major = config.pg_major();
+
+    let progress = Progress::spinner(format!(
+        "Building {} v{version} from source",
+        backend.name()
+    ));
+    if !source_dir.exists() {
+        let repository_url = repository_url(backend);
+        info!(
+            "Cloning {} v{} into {}",
+            repository_url,
+            version,
+            source_dir.display()
+        );
+        // Clone next to the final location and rename, so an interrupted clone isn't mistaken
+        // for a complete checkout on the next run
+        let partial_dir = source_dir.with_extension("partial");
+        if partial_dir.exists() {
+            std::fs::remove_dir_all(&partial_dir)?;
+        }
+        let mut clone = Command::new("git");
+        clone
+            .args(["clone", "--depth", "1", "--branch"])
+            .arg(format!("v{version}"))
+            .arg(repository_url)
+            .arg(&partial_dir);
+        run(clone, "git clone").await?;
+        std::fs::rename(&partial_dir, &source_dir)?;
+    }
+
+    let package_root = match backend {
+        Backend::PgVectoRs => {
+            info!("Building with cargo pgrx against {}", pg_config.display());
+            let mut package = Command::new("cargo");
+            package
+                .args(["pgrx", "package", "--no-default-features", "--features"])
+                .arg(format!("pg{pg_major}"))
+                .arg("--pg-config")
+                .arg(&pg_config)
+                .current_dir(&source_dir);
+            run(package, "cargo pgrx package").await?;
+            // pgrx mirrors pg_config's absolute install paths under target/release/vectors-pgNN
+            source_dir
+                .join("target")
+                .join("release")
+                .join(format!("vectors-pg{pg_major}"))
+        }
+        Backend::PgVector => {
+            info!("Building with make against {}", pg_config.display());
+            // The library, control file and SQL scripts all end up in the source tree
+            let mut make = Command::new("make");
+            make.arg(format!("PG_CONFIG={}", pg_config.display()))
+                .current_dir(&source_dir);
+            run(make, "make").await?;
+            source_dir.clone()
+        }
+    };
+    progress.finish();
+
+    collect_outputs(
+        &package_root,
+        &output_dir,
+        name,
+        &platform.library_file_name(name, pg_major),
+    )
+    .map_err(|source| Error::ExtensionInstall {
+        message: format!("collecting build outputs from {}", package_root.display()),
+        source,
+    })?;
+
+    Ok(output_dir)
+}
+
+fn repository_url(backend: Backend) -> &'static str {
+    match backend {
+        Backend::PgVectoRs => "https://github.com/tensorchord/pgvecto.rs",
+        Backend::PgVector => "https://github.com/pgvector/pgvector",
+    }
+}
+
+async fn run(mut command: Command, description: &str) -> Result<()> {
+    let output = command
+        .stdin(Stdio::null())
+        // Don't leave the build running when the install is cancelled
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|source| Error::ExtensionInstall {
+            message: format!("running {description} (is it installed?)"),
+            source,
+        })?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let tail: Vec<&str> = stderr.lines().rev().take(20).collect();
+    Err(Error::ExtensionInstall {
+        message: format!("{description} exited with {}", output.status),
+        source: std::io::Error::other(tail.into_iter().rev().collect::<Vec<_>>().join("\n")),
+    })
+}
+
+/// Copies the library, control file and SQL scripts found anywhere under `package_root` into
+/// the flat layout used by release archives.
+fn collect_outputs(
+    package_root: &Path,
+    output_dir: &Path,
+    name: &str,
+    library_file: &str,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(output_dir)?;
+
+    let mut pending = vec![package_root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+            let file_name = entry.file_name();
+            let is_output = file_name.to_str().is_some_and(|file_name| {
+                file_name == library_file
+                    || file_name == format!("{name}.so")
+                    || file_name == format!("{name}.control")
+                    || file_name.starts_with(&format!("{name}--"))
+            });
+            if is_output {
+                std::fs::copy(entry.path(), output_dir.join(&file_name))?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+//! Local usage statistics for capacity planning: how often each operation runs, in which shapes,
+//! how long it takes and how collections grow.
+//!
+//! Operations on a [`Collection`](crate::Collection) add to hourly counters in
+//! `pgevdb.usage_stats`; nothing leaves the database. Recording is best effort: a failure to
+//! update a counter is logged and never fails the operation itself.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use tracing::warn;
+
+use crate::error::Result;
+
+/// Calls of one operation, or one shape of it, within the report window.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OperationUsage {
+    pub operation: String,
+    /// Parameters that distinguish how the operation was called, e.g. `knn k=10 refine`; empty
+    /// in the per-operation totals.
+    pub shape: String,
+    pub calls: u64,
+    pub mean_ms: f64,
+}
+
+/// Size and growth of one collection.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CollectionUsage {
+    pub collection: String,
+    pub namespace: String,
+    pub rows: u64,
+    /// Rows inserted within the report window.
+    pub inserted: u64,
+    /// Table, index and TOAST size on disk.
+    pub bytes: u64,
+    /// Index builds within the report window.
+    pub index_builds: u64,
+}
+
+/// Result of [`report`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UsageReport {
+    pub window_hours: u64,
+    pub operations: Vec<OperationUsage>,
+    pub collections: Vec<CollectionUsage>,
+    /// Most frequent operation shapes, most calls first.
+    pub top_shapes: Vec<OperationUsage>,
+}
+
+/// Number of shapes listed in [`UsageReport::top_shapes`].
+const TOP_SHAPES: i64 = 10;
+
+/// Aggregates the counters of the last `window` (rounded to whole hours, at least one) and the
+/// current size of every collection.
+///
+/// # Errors
+///
+/// Returns [`Error::Database`](crate::Error::Database) if the statistics or a collection table
+/// can't be read.
+pub async fn report(pool: &PgPool, window: Duration) -> Result<UsageReport> {
+    let window_hours = (window.as_secs() / 3600).max(1);
+    let since = i32::try_from(window_hours).unwrap_or(i32::MAX);
+
+    let operations = operation_usage(pool, since, "''", i64::MAX).await?;
+    let top_shapes = operation_usage(pool, since, "shape", TOP_SHAPES).await?;
+
+    let rows: Vec<(String, String, i64, i64)> = sqlx::query_as(
+        "SELECT c.name, c.namespace,
+                pg_total_relation_size(to_regclass('collections.' || c.name)),
+                coalesce((SELECT sum(s.calls) FROM pgevdb.usage_stats s
+                          WHERE s.collection = c.name AND s.operation = 'index_build'
+                            AND s.hour > now() - make_interval(hours => $1)), 0)::bigint
+         FROM pgevdb.collections c ORDER BY c.name",
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+    let mut collections = Vec::with_capacity(rows.len());
+    for (collection, namespace, bytes, index_builds) in rows {
+        // inserted_at is NULL for rows stored before insert times were recorded
+        let (total, inserted): (i64, i64) = sqlx::query_as(&format!(
+            "SELECT count(*),
+                    count(*) FILTER (WHERE inserted_at > now() - make_interval(hours => $1))
+             FROM collections.{collection}"
+        ))
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+        collections.push(CollectionUsage {
+            collection,
+            namespace,
+            rows: total.unsigned_abs(),
+            inserted: inserted.unsigned_abs(),
+            bytes: bytes.unsigned_abs(),
+            index_builds: index_builds.unsigned_abs(),
+        });
+    }
+
+    Ok(UsageReport {
+        window_hours,
+        operations,
+        collections,
+        top_shapes,
+    })
+}
+
+/// Counters of the last `hours` grouped by operation and `shape` (an expression), most calls
+/// first.
+async fn operation_usage(
+    pool: &PgPool,
+    hours: i32,
+    shape: &str,
+    limit: i64,
+) -> Result<Vec<OperationUsage>> {
+    let rows: Vec<(String, String, i64, f64)> = sqlx::query_as(&format!(
+        "SELECT operation, {shape}, sum(calls)::bigint, sum(total_ms) / sum(calls)
+         FROM pgevdb.usage_stats
+         WHERE hour > now() - make_interval(hours => $1)
+         GROUP BY 1, 2 ORDER BY 3 DESC, 1, 2 LIMIT $2"
+    ))
+    .bind(hours)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(operation, shape, calls, mean_ms)| OperationUsage {
+            operation,
+            shape,
+            calls: calls.unsigned_abs(),
+            mean_ms,
+        })
+        .collect())
+}
+
+/// Counts one call of `operation` on `collection` that started at `started`.
+pub(crate) async fn record(
+    pool: &PgPool,
+    collection: &str,
+    operation: &str,
+    shape: &str,
+    started: Instant,
+) {
+    let result = sqlx::query(
+        "INSERT INTO pgevdb.usage_stats (hour, collection, operation, shape, calls, total_ms)
+         VALUES (date_trunc('hour', now()), $1, $2, $3, 1, $4)
+         ON CONFLICT (hour, collection, operation, shape) DO UPDATE
+         SET calls = usage_stats.calls + 1, total_ms = usage_stats.total_ms + excluded.total_ms",
+    )
+    .bind(collection)
+    .bind(operation)
+    .bind(shape)
+    .bind(started.elapsed().as_secs_f64() * 1000.0)
+    .execute(pool)
+    .await;
+    if let Err(error) = result {
+        warn!("Couldn't record usage of {operation} on {collection}: {error}");
+    }
+}
@@ -0,0 +1,184 @@
+//! Tenants: customers sharing one instance, each with their own collections.
+//!
+//! A [`Tenant`] handle only creates, opens and lists the collections of its tenant, so code
+//! serving a customer can't reach another customer's data by mistake:
+//!
+//! ```no_run
+//! # async fn demo(db: &pgevdb::Instance) -> pgevdb::Result<()> {
+//! let acme = db.tenant("acme")?;
+//! let docs = acme.collection("docs").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A tenant's collections are ordinary collections named `{tenant}__{name}` in the tenant's
+//! [namespace](crate::quota), so a [quota](Tenant::set_quota) limits the tenant as a whole
+//! and maintenance, statistics and the command line see them like any other collection.
+
+use sqlx::postgres::PgPool;
+
+use crate::collection::{self, Collection, Metric, Storage};
+use crate::error::{Error, Result};
+use crate::quota::{self, Quota};
+
+/// Separates the tenant from the collection name in the names of a tenant's collections.
+const SEPARATOR: &str = "__";
+
+/// Handle to the collections of one tenant. Cheap to clone; it shares the pool it was created
+/// with.
+#[derive(Clone, Debug)]
+pub struct Tenant {
+    pool: PgPool,
+    name: String,
+}
+
+impl Tenant {
+    /// Handle to tenant `name` of the database `pool` connects to. Tenants need no setup: one
+    /// exists as soon as it has a collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `name` isn't made of lowercase letters, digits and
+    /// single underscores, starting with a letter, or is the default namespace.
+    pub fn new(pool: &PgPool, name: &str) -> Result<Self> {
+        let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            && !name.contains(SEPARATOR)
+            && !name.ends_with('_')
+            && name.len() + SEPARATOR.len() < collection::MAX_NAME_LENGTH;
+        if !valid {
+            return Err(Error::InvalidInput(format!(
+                "tenant name '{name}' must be lowercase letters, digits and single underscores, \
+                 starting with a letter, and shorter than {} characters",
+                collection::MAX_NAME_LENGTH - SEPARATOR.len()
+            )));
+        }
+        if name == quota::DEFAULT_NAMESPACE {
+            return Err(Error::InvalidInput(format!(
+                "'{name}' is the namespace of collections without a tenant"
+            )));
+        }
+        Ok(Self {
+            pool: pool.clone(),
+            name: name.to_string(),
+        })
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Creates an empty collection of the tenant storing 32-bit floats, see
+    /// [`Collection::create`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the name or dimension count is invalid or the tenant
+    /// already has a collection called `name`.
+    pub async fn create_collection(
+        &self,
+        name: &str,
+        dimensions: u32,
+        metric: Metric,
+    ) -> Result<Collection> {
+        self.create_collection_with_storage(name, dimensions, metric, Storage::F32)
+            .await
+    }
+
+    /// [`create_collection`](Self::create_collection) for a collection storing its vectors in
+    /// `storage` precision.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if the name or dimension count is invalid or the tenant
+    /// already has a collection called `name`.
+    pub async fn create_collection_with_storage(
+        &self,
+        name: &str,
+        dimensions: u32,
+        metric: Metric,
+        storage: Storage,
+    ) -> Result<Collection> {
+        Collection::create_in_namespace(
+            &self.pool,
+            &self.qualified(name),
+            dimensions,
+            metric,
+            storage,
+            &self.name,
+        )
+        .await
+    }
+
+    /// Opens the tenant's collection `name`. Its [`name`](Collection::name) is the qualified
+    /// `{tenant}__{name}`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if the tenant has no collection called `name`.
+    pub async fn collection(&self, name: &str) -> Result<Collection> {
+        Collection::open(&self.pool, &self.qualified(name))
+            .await
+            .map_err(|error| match error {
+                Error::NotFound(_) => {
+                    Error::NotFound(format!("collection '{name}' of tenant '{}'", self.name))
+                }
+                error => error,
+            })
+    }
+
+    /// Names of the tenant's collections, without the tenant prefix, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the catalog can't be read.
+    pub async fn collections(&self) -> Result<Vec<String>> {
+        let prefix = format!("{}{SEPARATOR}", self.name);
+        let names: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM pgevdb.collections WHERE starts_with(name, $1) ORDER BY name",
+        )
+        .bind(&prefix)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(names
+            .into_iter()
+            .filter_map(|(name,)| name.strip_prefix(&prefix).map(str::to_string))
+            .collect())
+    }
+
+    /// Limits the rows and bytes of all the tenant's collections together, see
+    /// [`quota::set_quota`]. `None` leaves a resource unlimited.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if the quota can't be stored.
+    pub async fn set_quota(&self, max_rows: Option<u64>, max_bytes: Option<u64>) -> Result<Quota> {
+        quota::set_quota(&self.pool, &self.name, max_rows, max_bytes).await
+    }
+
+    /// Deletes every collection of the tenant and its quota, e.g. when a customer leaves,
+    /// returning the names of the deleted collections.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Database`] if a collection can't be dropped; collections dropped before
+    /// it stay dropped.
+    pub async fn delete(&self) -> Result<Vec<String>> {
+        let names = self.collections().await?;
+        for name in &names {
+            self.collection(name).await?.delete().await?;
+        }
+        sqlx::query("DELETE FROM pgevdb.quotas WHERE namespace = $1")
+            .bind(&self.name)
+            .execute(&self.pool)
+            .await?;
+        Ok(names)
+    }
+
+    fn qualified(&self, name: &str) -> String {
+        format!("{}{SEPARATOR}{name}", self.name)
+    }
+}
@@ -0,0 +1,95 @@
+//! Full-text search over the content stored with vectors, in the language it is written in.
+//!
+//! Stemming and stop words depend on the language, so indexing German text with the English
+//! configuration silently loses most matches. Each collection declares a PostgreSQL text search
+//! configuration, which individual records can override, and optionally strips accents with the
+//! `unaccent` dictionary so `café` matches `cafe`. Collation-style ICU normalization isn't
+//! available to PostgreSQL full-text search.
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+use crate::error::{Error, Result};
+
+/// Language handling of a collection's full-text search.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextSearchConfig {
+    /// A built-in PostgreSQL text search configuration such as `english`, `german` or `simple`
+    /// (no stemming, for mixed or unsupported languages).
+    pub language: String,
+    /// Ignore accents when indexing and querying.
+    pub unaccent: bool,
+}
+
+impl Default for TextSearchConfig {
+    fn default() -> Self {
+        Self {
+            language: "english".to_string(),
+            unaccent: false,
+        }
+    }
+}
+
+/// A full-text match, best first.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TextMatch {
+    pub id: i64,
+    /// `ts_rank_cd` cover density rank; higher is better, comparable only within one query.
+    pub rank: f32,
+}
+
+/// Name of the text search configuration for `language`, creating its accent-insensitive copy
+/// in the `pgevdb` schema on first use.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if PostgreSQL has no configuration called `language`.
+pub(crate) async fn regconfig(pool: &PgPool, language: &str, unaccent: bool) -> Result<String> {
+    let (exists,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (
+             SELECT FROM pg_ts_config c JOIN pg_namespace n ON n.oid = c.cfgnamespace
+             WHERE n.nspname = 'pg_catalog' AND c.cfgname = $1
+         )",
+    )
+    .bind(language)
+    .fetch_one(pool)
+    .await?;
+    // Built-in names are plain lowercase words, which also makes them safe to interpolate
+    if !exists || !language.chars().all(|c| c.is_ascii_lowercase()) {
+        return Err(Error::InvalidInput(format!(
+            "unknown text search language '{language}'"
+        )));
+    }
+    if !unaccent {
+        return Ok(language.to_string());
+    }
+
+    let name = format!("{language}_unaccent");
+    let (created,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (
+             SELECT FROM pg_ts_config c JOIN pg_namespace n ON n.oid = c.cfgnamespace
+             WHERE n.nspname = 'pgevdb' AND c.cfgname = $1
+         )",
+    )
+    .bind(&name)
+    .fetch_one(pool)
+    .await?;
+    if !created {
+        // Snowball languages stem with `<language>_stem`; the rest, like `simple`, don't stem
+        let stemmer: Option<(String,)> =
+            sqlx::query_as("SELECT dictname::text FROM pg_ts_dict WHERE dictname = $1 || '_stem'")
+                .bind(language)
+                .fetch_optional(pool)
+                .await?;
+        let stemmer = stemmer.map_or_else(|| "simple".to_string(), |(stemmer,)| stemmer);
+        sqlx::raw_sql(&format!(
+            "CREATE EXTENSION IF NOT EXISTS unaccent;
+             CREATE TEXT SEARCH CONFIGURATION pgevdb.{name} (COPY = pg_catalog.{language});
+             ALTER TEXT SEARCH CONFIGURATION pgevdb.{name}
+                 ALTER MAPPING FOR hword, hword_part, word WITH unaccent, {stemmer};"
+        ))
+        .execute(pool)
+        .await?;
+    }
+    Ok(format!("pgevdb.{name}"))
+}
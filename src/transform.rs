@@ -0,0 +1,363 @@
+//! Dimensionality reductions stored with a collection, so inserts and queries are always reduced
+//! the same way.
+//!
+//! A reduced copy of each vector can be kept in a shadow column next to the full one and searched
+//! with [`Collection::search_reduced`](crate::Collection::search_reduced): a smaller index and
+//! faster distance computations, in exchange for coarser results.
+
+use sqlx::postgres::PgPool;
+
+use crate::collection::Collection;
+use crate::error::{Error, Result};
+
+/// Subspace iterations when fitting PCA; enough for the leading components of embedding data to
+/// settle.
+const PCA_ITERATIONS: usize = 12;
+
+/// A linear map from a collection's vectors to fewer dimensions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transform {
+    /// Keeps the first `output` of `input` dimensions, for Matryoshka-trained models whose
+    /// prefixes are embeddings in their own right.
+    Truncate { input: u32, output: u32 },
+    /// Projects centered vectors onto principal components fitted on a sample.
+    Pca {
+        mean: Vec<f32>,
+        /// Orthonormal rows spanning the directions of greatest variance.
+        components: Vec<Vec<f32>>,
+    },
+}
+
+impl Transform {
+    /// Plain truncation to the first `output` dimensions.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] unless `0 < output <= input`.
+    pub fn truncate(input: u32, output: u32) -> Result<Self> {
+        check_output(input as usize, output as usize)?;
+        Ok(Transform::Truncate { input, output })
+    }
+
+    /// Fits a PCA on `samples`, keeping `output` components.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if there are fewer than two samples, their dimensions
+    /// differ, or `output` exceeds them.
+    pub fn pca(samples: &[Vec<f32>], output: u32) -> Result<Self> {
+        let input = samples.first().map_or(0, Vec::len);
+        if samples.len() < 2 || samples.iter().any(|sample| sample.len() != input) {
+            return Err(Error::InvalidInput(
+                "PCA needs at least two samples of equal dimensions".to_string(),
+            ));
+        }
+        let output = output as usize;
+        check_output(input, output)?;
+
+        #[allow(clippy::cast_precision_loss)]
+        let count = samples.len() as f32;
+        let mut mean = vec![0.0; input];
+        for sample in samples {
+            for (sum, x) in mean.iter_mut().zip(sample) {
+                *sum += x / count;
+            }
+        }
+        let centered: Vec<Vec<f32>> = samples
+            .iter()
+            .map(|sample| sample.iter().zip(&mean).map(|(x, m)| x - m).collect())
+            .collect();
+
+        // Subspace iteration: repeatedly multiply a basis by the covariance X^T X and
+        // re-orthonormalize, without ever forming the input x input covariance matrix
+        let mut basis = initial_basis(output, input);
+        for _ in 0..PCA_ITERATIONS {
+            let mut next = vec![vec![0.0; input]; output];
+            for row in &centered {
+                for (component, next) in basis.iter().zip(&mut next) {
+                    let weight = dot(row, component);
+                    for (y, x) in next.iter_mut().zip(row) {
+                        *y += weight * x;
+                    }
+                }
+            }
+            basis = orthonormalize(next);
+        }
+
+        Ok(Transform::Pca {
+            mean,
+            components: basis,
+        })
+    }
+
+    #[must_use]
+    pub fn input_dimensions(&self) -> u32 {
+        match self {
+            Transform::Truncate { input, .. } => *input,
+            Transform::Pca { mean, .. } => u32::try_from(mean.len()).unwrap_or(u32::MAX),
+        }
+    }
+
+    #[must_use]
+    pub fn output_dimensions(&self) -> u32 {
+        match self {
+            Transform::Truncate { output, .. } => *output,
+            Transform::Pca { components, .. } => {
+                u32::try_from(components.len()).unwrap_or(u32::MAX)
+            }
+        }
+    }
+
+    /// Reduces one vector.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `vector` doesn't have the input dimensions.
+    pub fn apply(&self, vector: &[f32]) -> Result<Vec<f32>> {
+        if vector.len() != self.input_dimensions() as usize {
+            return Err(Error::InvalidInput(format!(
+                "transform expects {} dimensions, got a vector with {}",
+                self.input_dimensions(),
+                vector.len()
+            )));
+        }
+        Ok(match self {
+            Transform::Truncate { output, .. } => vector[..*output as usize].to_vec(),
+            Transform::Pca { mean, components } => {
+                let centered: Vec<f32> = vector.iter().zip(mean).map(|(x, m)| x - m).collect();
+                components
+                    .iter()
+                    .map(|component| dot(&centered, component))
+                    .collect()
+            }
+        })
+    }
+
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Transform::Truncate { .. } => "truncate",
+            Transform::Pca { .. } => "pca",
+        }
+    }
+}
+
+/// Fits a PCA on a random sample of up to `sample` vectors from `collection`.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidInput`] if the collection has fewer than two vectors or `output`
+/// exceeds its dimensions.
+pub async fn fit_pca(collection: &Collection, output: u32, sample: u32) -> Result<Transform> {
+    let rows: Vec<(Vec<f32>,)> = sqlx::query_as(&format!(
+        "SELECT {} FROM {} ORDER BY random() LIMIT $1",
+        collection.as_array("embedding"),
+        collection.table()
+    ))
+    .bind(i64::from(sample))
+    .fetch_all(collection.pool())
+    .await?;
+    let samples: Vec<Vec<f32>> = rows.into_iter().map(|(embedding,)| embedding).collect();
+    Transform::pca(&samples, output)
+}
+
+/// The transform stored with `collection`, and whether it keeps a reduced column.
+pub(crate) async fn load(pool: &PgPool, collection: &str) -> Result<Option<(Transform, bool)>> {
+    type Row = (String, i32, i32, Option<Vec<f32>>, Option<Vec<f32>>, bool);
+    let row: Option<Row> = sqlx::query_as(
+        "SELECT kind, input_dimensions, output_dimensions, mean, components, reduced_column
+         FROM pgevdb.transforms WHERE collection = $1",
+    )
+    .bind(collection)
+    .fetch_optional(pool)
+    .await?;
+    let Some((kind, input, output, mean, components, reduced_column)) = row else {
+        return Ok(None);
+    };
+
+    let transform = match (kind.as_str(), mean, components) {
+        ("truncate", _, _) => Transform::Truncate {
+            input: input.unsigned_abs(),
+            output: output.unsigned_abs(),
+        },
+        ("pca", Some(mean), Some(components)) => Transform::Pca {
+            components: components
+                .chunks(mean.len().max(1))
+                .map(<[f32]>::to_vec)
+                .collect(),
+            mean,
+        },
+        _ => {
+            return Err(Error::Incompatible(format!(
+                "unreadable '{kind}' transform of collection '{collection}'"
+            )))
+        }
+    };
+    Ok(Some((transform, reduced_column)))
+}
+
+fn check_output(input: usize, output: usize) -> Result<()> {
+    if output == 0 || output > input {
+        return Err(Error::InvalidInput(format!(
+            "reduced dimensions must be between 1 and {input}, got {output}"
+        )));
+    }
+    Ok(())
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Deterministic pseudo-random starting vectors, so refitting the same sample gives the same
+/// transform.
+fn initial_basis(rows: usize, dimensions: usize) -> Vec<Vec<f32>> {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let rows = (0..rows)
+        .map(|_| {
+            (0..dimensions)
+                .map(|_| {
+                    // xorshift64
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    #[allow(clippy::cast_precision_loss)]
+                    let unit = (state >> 40) as f32 / (1u64 << 24) as f32;
+                    unit - 0.5
+                })
+                .collect()
+        })
+        .collect();
+    orthonormalize(rows)
+}
+
+/// Modified Gram-Schmidt. A row that collapses to zero is left as zero rather than divided by
+/// it.
+fn orthonormalize(mut rows: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+    for i in 0..rows.len() {
+        let (done, rest) = rows.split_at_mut(i);
+        let row = &mut rest[0];
+        for previous in done.iter() {
+            let projection = dot(row, previous);
+            for (x, p) in row.iter_mut().zip(previous) {
+                *x -= projection * p;
+            }
+        }
+        let norm = dot(row, row).sqrt();
+        if norm > f32::EPSILON {
+            for x in row.iter_mut() {
+                *x /= norm;
+            }
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn truncation_keeps_the_prefix() -> Result<()> {
+        let transform = Transform::truncate(4, 2)?;
+        assert_eq!(transform.apply(&[1.0, 2.0, 3.0, 4.0])?, [1.0, 2.0]);
+        assert_eq!(transform.input_dimensions(), 4);
+        assert_eq!(transform.output_dimensions(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_reductions_outside_the_input() {
+        for output in [0, 5] {
+            assert!(matches!(
+                Transform::truncate(4, output),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        assert!(matches!(
+            Transform::pca(&[vec![1.0, 0.0]], 1),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            Transform::pca(&[vec![1.0, 0.0], vec![1.0]], 1),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_vectors_of_other_dimensions() -> Result<()> {
+        let transform = Transform::truncate(3, 2)?;
+        for vector in [&[1.0, 2.0][..], &[1.0, 2.0, 3.0, 4.0]] {
+            assert!(matches!(
+                transform.apply(vector),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pca_projects_centered_vectors_onto_the_main_direction() -> Result<()> {
+        // Spread along the diagonal x = y around (1, 1), with a little noise across it
+        let samples: Vec<Vec<f32>> = [-1.5_f32, -0.5, 0.5, 1.5]
+            .iter()
+            .enumerate()
+            .map(|(index, t)| {
+                // Uncorrelated with t, so it doesn't tilt the main direction
+                let noise = if index % 3 == 0 { 0.05 } else { -0.05 };
+                vec![1.0 + t + noise, 1.0 + t - noise]
+            })
+            .collect();
+        let transform = Transform::pca(&samples, 1)?;
+        let Transform::Pca { mean, components } = &transform else {
+            panic!("fitted {transform:?}");
+        };
+        assert!(close(mean[0], 1.0) && close(mean[1], 1.0), "{mean:?}");
+        let direction = &components[0];
+        assert!(close(dot(direction, direction), 1.0));
+        assert!(
+            close(direction[0].abs(), direction[1].abs()),
+            "{direction:?}"
+        );
+
+        // The mean maps to the origin, and distances along the diagonal are kept
+        assert!(close(transform.apply(&[1.0, 1.0])?[0], 0.0));
+        let far = transform.apply(&[3.0, 3.0])?[0];
+        assert!(close(far.abs(), 2.0 * std::f32::consts::SQRT_2), "{far}");
+        Ok(())
+    }
+
+    #[test]
+    fn pca_components_are_orthonormal() -> Result<()> {
+        let samples: Vec<Vec<f32>> = (0..20_u8)
+            .map(|i| {
+                let x = f32::from(i);
+                vec![x, (x * 0.7).sin() * 3.0, (x * 1.3).cos(), x * 0.1]
+            })
+            .collect();
+        let Transform::Pca { components, .. } = Transform::pca(&samples, 3)? else {
+            panic!("not a PCA");
+        };
+        for (i, a) in components.iter().enumerate() {
+            for (j, b) in components.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!(close(dot(a, b), expected), "{i}·{j} = {}", dot(a, b));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn refitting_gives_the_same_transform() -> Result<()> {
+        let samples = vec![
+            vec![1.0, 0.0, 2.0],
+            vec![0.0, 1.0, 1.0],
+            vec![2.0, 2.0, 0.0],
+        ];
+        assert_eq!(Transform::pca(&samples, 2)?, Transform::pca(&samples, 2)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use crate::output::{emit, OutputFormat, Report};
+use crate::server;
+use pgevdb::stats::{self, OperationUsage, UsageReport};
+use pgevdb::{Config, Result};
+
+impl Report for UsageReport {
+    fn print_text(&self) {
+        println!("Last {} hours", self.window_hours);
+        println!("Operations:");
+        print_operations(&self.operations);
+        println!("Top query shapes:");
+        print_operations(&self.top_shapes);
+        println!("Collections:");
+        for collection in &self.collections {
+            println!(
+                "  {:<24} {:<12} {:>10} rows  +{:<10} {:>12} bytes  {} index builds",
+                collection.collection,
+                collection.namespace,
+                collection.rows,
+                collection.inserted,
+                collection.bytes,
+                collection.index_builds
+            );
+        }
+    }
+}
+
+fn print_operations(operations: &[OperationUsage]) {
+    if operations.is_empty() {
+        println!("  none recorded");
+    }
+    for operation in operations {
+        println!(
+            "  {:<12} {:<28} {:>10} calls  {:>9.2} ms mean",
+            operation.operation, operation.shape, operation.calls, operation.mean_ms
+        );
+    }
+}
+
+pub async fn run(window_hours: u64, config: Config, output: OutputFormat) -> Result<()> {
+    let (_postgresql, pool) = server::connect(config).await?;
+    let window = Duration::from_secs(window_hours.saturating_mul(3600));
+    emit(output, &stats::report(&pool, window).await?)
+}
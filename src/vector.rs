@@ -0,0 +1,442 @@
+//! Owned embeddings: dense ones with the arithmetic recommendation features need, sparse ones
+//! that keep only their nonzero components, and binary ones of one bit per dimension.
+
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// An embedding, such as a centroid or weighted combination computed by the server. Dereferences
+/// to `[f32]`, so it can be passed straight to search and insert calls.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Vector(Vec<f32>);
+
+impl Vector {
+    #[must_use]
+    pub fn new(components: Vec<f32>) -> Self {
+        Self(components)
+    }
+
+    /// Component-wise mean of `vectors`, e.g. to search with several example queries at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `vectors` is empty or their dimensions differ.
+    pub fn mean<V: AsRef<[f32]>>(vectors: &[V]) -> Result<Self> {
+        let weights = vec![1.0; vectors.len()];
+        let mut sum = Self::weighted_sum(vectors, &weights)?;
+        #[allow(clippy::cast_precision_loss)]
+        let count = vectors.len() as f32;
+        for x in &mut sum.0 {
+            *x /= count;
+        }
+        Ok(sum)
+    }
+
+    /// `Σ weights[i] · vectors[i]`; negative weights subtract.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `vectors` is empty, their dimensions differ, or there
+    /// isn't one weight per vector.
+    pub fn weighted_sum<V: AsRef<[f32]>>(vectors: &[V], weights: &[f32]) -> Result<Self> {
+        let dimensions = vectors.first().map(|vector| vector.as_ref().len());
+        let Some(dimensions) = dimensions.filter(|_| vectors.len() == weights.len()) else {
+            return Err(Error::InvalidInput(
+                "expected one weight for each of at least one vector".to_string(),
+            ));
+        };
+        let mut sum = vec![0.0; dimensions];
+        for (vector, weight) in vectors.iter().zip(weights) {
+            let vector = vector.as_ref();
+            if vector.len() != dimensions {
+                return Err(Error::InvalidInput(format!(
+                    "cannot combine vectors of {dimensions} and {} dimensions",
+                    vector.len()
+                )));
+            }
+            for (sum, x) in sum.iter_mut().zip(vector) {
+                *sum += weight * x;
+            }
+        }
+        Ok(Self(sum))
+    }
+
+    /// Euclidean length.
+    #[must_use]
+    pub fn norm(&self) -> f32 {
+        self.0.iter().map(|x| x * x).sum::<f32>().sqrt()
+    }
+
+    /// This vector scaled to unit length; a zero vector stays zero.
+    #[must_use]
+    pub fn normalized(mut self) -> Self {
+        let norm = self.norm();
+        if norm > 0.0 {
+            for x in &mut self.0 {
+                *x /= norm;
+            }
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Vec<f32> {
+        self.0
+    }
+}
+
+impl Deref for Vector {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        &self.0
+    }
+}
+
+impl AsRef<[f32]> for Vector {
+    fn as_ref(&self) -> &[f32] {
+        &self.0
+    }
+}
+
+impl From<Vec<f32>> for Vector {
+    fn from(components: Vec<f32>) -> Self {
+        Self(components)
+    }
+}
+
+impl From<Vector> for Vec<f32> {
+    fn from(vector: Vector) -> Self {
+        vector.0
+    }
+}
+
+/// An embedding stored as its nonzero components, such as a SPLADE or BM25 term weighting over a
+/// vocabulary of tens of thousands of dimensions. Indices are 0-based and kept sorted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "SparseParts")]
+pub struct SparseVector {
+    dimensions: u32,
+    indices: Vec<u32>,
+    values: Vec<f32>,
+}
+
+/// Unchecked form of a [`SparseVector`], validated on deserialization.
+#[derive(Deserialize)]
+struct SparseParts {
+    dimensions: u32,
+    indices: Vec<u32>,
+    values: Vec<f32>,
+}
+
+impl SparseVector {
+    /// A `dimensions`-long vector whose component `indices[i]` is `values[i]` and whose other
+    /// components are zero. Indices may come in any order; explicit zeros are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if there isn't one value per index, an index is repeated
+    /// or an index is out of range.
+    pub fn new(dimensions: u32, indices: Vec<u32>, values: Vec<f32>) -> Result<Self> {
+        if indices.len() != values.len() {
+            return Err(Error::InvalidInput(format!(
+                "sparse vector has {} indices but {} values",
+                indices.len(),
+                values.len()
+            )));
+        }
+        let mut components: Vec<(u32, f32)> = indices.into_iter().zip(values).collect();
+        components.sort_by_key(|(index, _)| *index);
+        if let Some(pair) = components.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::InvalidInput(format!(
+                "sparse vector repeats index {}",
+                pair[0].0
+            )));
+        }
+        if let Some((index, _)) = components.last().filter(|(index, _)| *index >= dimensions) {
+            return Err(Error::InvalidInput(format!(
+                "sparse vector index {index} is out of range for {dimensions} dimensions"
+            )));
+        }
+        let (indices, values) = components
+            .into_iter()
+            .filter(|(_, value)| *value != 0.0)
+            .unzip();
+        Ok(Self {
+            dimensions,
+            indices,
+            values,
+        })
+    }
+
+    /// The nonzero components of `dense`.
+    ///
+    /// ```
+    /// let sparse = pgevdb::SparseVector::from_dense(&[0.0, 1.5, 0.0, 2.0]);
+    /// assert_eq!(sparse.indices(), [1, 3]);
+    /// assert_eq!(sparse.to_dense(), [0.0, 1.5, 0.0, 2.0]);
+    /// ```
+    #[must_use]
+    pub fn from_dense(dense: &[f32]) -> Self {
+        let (indices, values) = dense
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| **value != 0.0)
+            .map(|(index, value)| (u32::try_from(index).unwrap_or(u32::MAX), *value))
+            .unzip();
+        Self {
+            dimensions: u32::try_from(dense.len()).unwrap_or(u32::MAX),
+            indices,
+            values,
+        }
+    }
+
+    #[must_use]
+    pub fn dimensions(&self) -> u32 {
+        self.dimensions
+    }
+
+    /// Positions of the nonzero components, ascending.
+    #[must_use]
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Nonzero components, in the order of [`indices`](Self::indices).
+    #[must_use]
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Every component, zeros included.
+    #[must_use]
+    pub fn to_dense(&self) -> Vec<f32> {
+        let mut dense = vec![0.0; self.dimensions as usize];
+        for (index, value) in self.indices.iter().zip(&self.values) {
+            dense[*index as usize] = *value;
+        }
+        dense
+    }
+}
+
+impl TryFrom<SparseParts> for SparseVector {
+    type Error = Error;
+
+    fn try_from(parts: SparseParts) -> Result<Self> {
+        Self::new(parts.dimensions, parts.indices, parts.values)
+    }
+}
+
+/// An embedding of one bit per dimension, such as a binary-quantized dense embedding: 32 times
+/// smaller than `f32` components and compared by [Hamming](crate::Metric::Hamming) or
+/// [Jaccard](crate::Metric::Jaccard) distance.
+///
+/// ```
+/// use pgevdb::BitVector;
+///
+/// let a = BitVector::from_bools(&[true, false, true, true]);
+/// let b = BitVector::from_dense(&[0.3, 0.1, -0.2, 0.9]);
+/// assert_eq!(a.hamming(&b), 2);
+/// assert_eq!(a.jaccard(&b), 0.5);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "BitParts")]
+pub struct BitVector {
+    dimensions: u32,
+    /// Bits packed eight to a byte, most significant first, unused trailing bits zero.
+    bytes: Vec<u8>,
+}
+
+/// Unchecked form of a [`BitVector`], validated on deserialization.
+#[derive(Deserialize)]
+struct BitParts {
+    dimensions: u32,
+    bytes: Vec<u8>,
+}
+
+impl BitVector {
+    /// `dimensions` bits packed eight to a byte, most significant bit first, as PostgreSQL's
+    /// `bit` type stores them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidInput`] if `bytes` doesn't hold exactly `dimensions` bits,
+    /// rounded up to whole bytes.
+    pub fn from_bytes(dimensions: u32, mut bytes: Vec<u8>) -> Result<Self> {
+        if bytes.len() != dimensions.div_ceil(8) as usize {
+            return Err(Error::InvalidInput(format!(
+                "{dimensions} bits take {} bytes, got {}",
+                dimensions.div_ceil(8),
+                bytes.len()
+            )));
+        }
+        if let (Some(last), unused @ 1..) = (bytes.last_mut(), (8 - dimensions % 8) % 8) {
+            *last &= 0xff << unused;
+        }
+        Ok(Self { dimensions, bytes })
+    }
+
+    #[must_use]
+    pub fn from_bools(bits: &[bool]) -> Self {
+        let mut bytes = vec![0; bits.len().div_ceil(8)];
+        for (index, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+            bytes[index / 8] |= 0x80 >> (index % 8);
+        }
+        Self {
+            dimensions: u32::try_from(bits.len()).unwrap_or(u32::MAX),
+            bytes,
+        }
+    }
+
+    /// Binary quantization of `dense`: a bit is set where the component is positive, as the
+    /// server quantizes dense vectors stored in a [binary](crate::Storage::Binary) collection.
+    #[must_use]
+    pub fn from_dense(dense: &[f32]) -> Self {
+        let bits: Vec<bool> = dense.iter().map(|x| *x > 0.0).collect();
+        Self::from_bools(&bits)
+    }
+
+    #[must_use]
+    pub fn dimensions(&self) -> u32 {
+        self.dimensions
+    }
+
+    /// The packed bits, see [`from_bytes`](Self::from_bytes).
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Bit `index`; `false` past the end.
+    #[must_use]
+    pub fn get(&self, index: u32) -> bool {
+        index < self.dimensions && self.bytes[index as usize / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    /// Sets bit `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not below [`dimensions`](Self::dimensions).
+    pub fn set(&mut self, index: u32, bit: bool) {
+        assert!(
+            index < self.dimensions,
+            "bit {index} out of range for {} dimensions",
+            self.dimensions
+        );
+        let mask = 0x80 >> (index % 8);
+        if bit {
+            self.bytes[index as usize / 8] |= mask;
+        } else {
+            self.bytes[index as usize / 8] &= !mask;
+        }
+    }
+
+    /// The bits in order.
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.dimensions).map(|index| self.get(index))
+    }
+
+    /// Number of set bits.
+    #[must_use]
+    pub fn count_ones(&self) -> u32 {
+        self.bytes.iter().map(|byte| byte.count_ones()).sum()
+    }
+
+    /// Number of positions where `self` and `other` differ; bits past the shorter one count as
+    /// unset.
+    #[must_use]
+    pub fn hamming(&self, other: &BitVector) -> u32 {
+        self.zip_bytes(other)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+
+    /// `1 - |a ∩ b| / |a ∪ b|` of the set bits, `0` if neither has any.
+    #[must_use]
+    pub fn jaccard(&self, other: &BitVector) -> f32 {
+        let (both, either) = self
+            .zip_bytes(other)
+            .fold((0, 0), |(both, either), (a, b)| {
+                (both + (a & b).count_ones(), either + (a | b).count_ones())
+            });
+        if either == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let similarity = both as f32 / either as f32;
+        1.0 - similarity
+    }
+
+    /// Every bit as a `0.0` or `1.0` component.
+    #[must_use]
+    pub fn to_dense(&self) -> Vec<f32> {
+        self.iter().map(|bit| if bit { 1.0 } else { 0.0 }).collect()
+    }
+
+    fn zip_bytes<'a>(&'a self, other: &'a BitVector) -> impl Iterator<Item = (u8, u8)> + 'a {
+        let len = self.bytes.len().max(other.bytes.len());
+        (0..len).map(|index| {
+            (
+                self.bytes.get(index).copied().unwrap_or(0),
+                other.bytes.get(index).copied().unwrap_or(0),
+            )
+        })
+    }
+}
+
+impl TryFrom<BitParts> for BitVector {
+    type Error = Error;
+
+    fn try_from(parts: BitParts) -> Result<Self> {
+        Self::from_bytes(parts.dimensions, parts.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_component_wise() -> Result<()> {
+        let mean = Vector::mean(&[[1.0, 2.0], [3.0, 6.0]])?;
+        assert_eq!(mean.into_inner(), [2.0, 4.0]);
+        Ok(())
+    }
+
+    #[test]
+    fn weighs_and_subtracts() -> Result<()> {
+        let liked = vec![1.0, 0.0, 1.0];
+        let disliked = vec![0.0, 2.0, 1.0];
+        let combined = Vector::weighted_sum(&[liked, disliked], &[1.0, -0.5])?;
+        assert_eq!(*combined, [1.0, -1.0, 0.5]);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_mismatched_inputs() {
+        let empty: [[f32; 2]; 0] = [];
+        assert!(matches!(Vector::mean(&empty), Err(Error::InvalidInput(_))));
+        assert!(matches!(
+            Vector::weighted_sum(&[[1.0, 2.0]], &[1.0, 1.0]),
+            Err(Error::InvalidInput(_))
+        ));
+        let Err(Error::InvalidInput(message)) =
+            Vector::weighted_sum(&[&[1.0, 2.0][..], &[1.0]], &[1.0, 1.0])
+        else {
+            panic!("vectors of 2 and 1 dimensions were combined");
+        };
+        assert!(message.contains("2 and 1 dimensions"));
+    }
+
+    #[test]
+    fn normalizes_to_unit_length_except_zero() {
+        let unit = Vector::new(vec![3.0, 4.0]).normalized();
+        assert_eq!(*unit, [0.6, 0.8]);
+        assert!((unit.norm() - 1.0).abs() < 1e-6);
+        assert_eq!(*Vector::new(vec![0.0, 0.0]).normalized(), [0.0, 0.0]);
+    }
+}
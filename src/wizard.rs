@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use dialoguer::{Input, Select};
+use semver::Version;
+
+use pgevdb::config::{Backend, Config, Durability};
+use pgevdb::Result;
+
+/// Asks for each setting on the terminal, offering the values in `defaults`.
+pub fn prompt_config(defaults: &Config) -> Result<Config> {
+    let storage_dir: String = Input::new()
+        .with_prompt("Data directory")
+        .default(defaults.storage_dir.display().to_string())
+        .interact_text()?;
+
+    let pg_version: String = Input::new()
+        .with_prompt("PostgreSQL version")
+        .default(defaults.pg_version.clone())
+        .validate_with(|input: &String| {
+            Version::parse(input)
+                .map(|_| ())
+                .map_err(|_| "expected an exact version such as 16.3.0")
+        })
+        .interact_text()?;
+
+    let backend_names: Vec<&str> = Backend::ALL.iter().map(|backend| backend.name()).collect();
+    let backend = Select::new()
+        .with_prompt("Vector extension")
+        .items(&backend_names)
+        .default(index_of(&Backend::ALL, defaults.backend))
+        .interact()?;
+
+    let port: u16 = Input::new()
+        .with_prompt("Port (0 picks a free port on every start)")
+        .default(defaults.port)
+        .interact_text()?;
+
+    let durability_descriptions: Vec<&str> = Durability::ALL
+        .iter()
+        .map(|durability| durability.description())
+        .collect();
+    let durability = Select::new()
+        .with_prompt("Durability profile")
+        .items(&durability_descriptions)
+        .default(index_of(&Durability::ALL, defaults.durability))
+        .interact()?;
+
+    Ok(Config {
+        storage_dir: PathBuf::from(storage_dir),
+        pg_version,
+        port,
+        backend: Backend::ALL[backend],
+        extension_version: if Backend::ALL[backend] == defaults.backend {
+            defaults.extension_version.clone()
+        } else {
+            Backend::ALL[backend]
+                .default_extension_version()
+                .to_string()
+        },
+        durability: Durability::ALL[durability],
+        ..defaults.clone()
+    })
+}
+
+fn index_of<T: PartialEq + Copy>(values: &[T], value: T) -> usize {
+    values
+        .iter()
+        .position(|candidate| *candidate == value)
+        .unwrap_or_default()
+}
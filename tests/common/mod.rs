@@ -0,0 +1,20 @@
+//! Embedded instances for the integration tests. Provisioning downloads PostgreSQL and installs
+//! the vector extension, so the tests that need an instance are ignored by default; run them
+//! with `cargo test -- --ignored`.
+
+use pgevdb::{Backend, Instance, PgEvDb};
+use tempfile::TempDir;
+
+/// Starts an instance of `backend` in a fresh directory, removed when the returned guard drops.
+/// pgvector has no prebuilt archives and is compiled from source.
+pub async fn launch(backend: Backend) -> pgevdb::Result<(TempDir, Instance)> {
+    let dir = TempDir::new()?;
+    let instance = PgEvDb::builder()
+        .data_dir(dir.path().join("storage"))
+        .backend(backend)
+        .build_from_source(backend == Backend::PgVector)
+        .build()?
+        .launch()
+        .await?;
+    Ok((dir, instance))
+}
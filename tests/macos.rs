@@ -0,0 +1,29 @@
+//! macOS hosts, where PostgreSQL 16 and later load extensions as `.dylib` and pgvecto.rs ships
+//! Apple builds.
+
+#![cfg(target_os = "macos")]
+
+mod common;
+
+use pgevdb::platform::{Os, Platform};
+use pgevdb::{Backend, Collection, Metric};
+
+#[test]
+fn detects_macos() {
+    let platform = Platform::current();
+    assert_eq!(platform.os, Os::MacOS);
+    assert_eq!(platform.library_file_name("vectors", "16"), "vectors.dylib");
+    assert_eq!(platform.library_file_name("vectors", "15"), "vectors.so");
+}
+
+/// The default PostgreSQL is 16, so this loads the `.dylib` from the Apple release build.
+#[tokio::test]
+#[ignore = "downloads PostgreSQL and pgvecto.rs"]
+async fn loads_the_pgvecto_rs_dylib() -> pgevdb::Result<()> {
+    let (_dir, instance) = common::launch(Backend::PgVectoRs).await?;
+    let collection = Collection::create(instance.pool(), "docs", 2, Metric::L2).await?;
+    let ids = collection.insert_many([[1.0, 0.0], [0.0, 1.0]]).await?;
+    let results = collection.search(&[1.0, 0.1], 1).await?;
+    assert_eq!(results[0].id, ids[0]);
+    instance.stop().await
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use pgevdb::ingest::{self, CopyOptions};
+use pgevdb::monitoring::{check_drift, DriftOptions};
+use pgevdb::{Backend, Collection, Metric, SearchOptions, Storage};
+
+/// pgvector's distance operators return double precision where pgvecto.rs's return real; every
+/// query reading distances must decode both.
+#[tokio::test]
+#[ignore = "downloads PostgreSQL and builds pgvector"]
+async fn searches_read_pgvector_distances() -> pgevdb::Result<()> {
+    let (_dir, instance) = common::launch(Backend::PgVector).await?;
+    let mut collection = Collection::create(instance.pool(), "docs", 3, Metric::L2).await?;
+    let ids = collection
+        .insert_many([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+        .await?;
+
+    let results = collection.search(&[1.0, 0.1, 0.0], 2).await?;
+    assert_eq!(results[0].id, ids[0]);
+    assert!(results[0].distance < results[1].distance);
+
+    let batches = collection
+        .search_many(&[[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]], 1)
+        .await?;
+    assert_eq!(batches[0][0].id, ids[0]);
+    assert_eq!(batches[1][0].id, ids[2]);
+
+    let within = collection.search_within(&[1.0, 0.0, 0.0], 0.5, 10).await?;
+    assert_eq!(within.len(), 1);
+
+    // Candidates from the reduced column, refined by exact distances
+    collection.set_search_dimensions(2).await?;
+    let refined = collection
+        .search_with(&[0.0, 1.0, 0.0], &SearchOptions::new(1).refine(true))
+        .await?;
+    assert_eq!(refined[0].id, ids[1]);
+
+    check_drift(&collection, &DriftOptions::default()).await?;
+    instance.stop().await
+}
+
+/// Dense vectors sent to a sparse or binary collection are converted to its storage type on the
+/// server, without going through pgvector's `vector`, which holds fewer dimensions.
+#[tokio::test]
+#[ignore = "downloads PostgreSQL and builds pgvector"]
+async fn dense_vectors_reach_wide_sparse_and_binary_collections() -> pgevdb::Result<()> {
+    const DIMENSIONS: usize = 20_000;
+    let (_dir, instance) = common::launch(Backend::PgVector).await?;
+    let one_hot = |index: usize| {
+        let mut vector = vec![0.0; DIMENSIONS];
+        vector[index] = 1.0;
+        vector
+    };
+    let vectors = [one_hot(0), one_hot(DIMENSIONS / 2), one_hot(DIMENSIONS - 1)];
+
+    for (name, metric, storage) in [
+        ("sparse", Metric::L2, Storage::Sparse),
+        ("binary", Metric::Hamming, Storage::Binary),
+    ] {
+        let collection = Collection::create_with_storage(
+            instance.pool(),
+            name,
+            u32::try_from(DIMENSIONS).unwrap_or(u32::MAX),
+            metric,
+            storage,
+        )
+        .await?;
+        collection.insert_many(&vectors).await?;
+        let report = ingest::copy_in(
+            &collection,
+            vectors.iter().map(|vector| (vector, None)),
+            &CopyOptions::default(),
+        )
+        .await?;
+        assert_eq!(report.loaded, 3);
+
+        let results = collection.search(&vectors[1], 1).await?;
+        assert_eq!(results[0].distance, 0.0);
+
+        let batches = collection
+            .search_many(&[&vectors[0], &vectors[2]], 2)
+            .await?;
+        for batch in &batches {
+            assert_eq!(batch.len(), 2);
+            assert!(batch.iter().all(|result| result.distance == 0.0));
+        }
+
+        // The vector and its copy from the load, but none of the others
+        let within = collection.search_within(&vectors[1], 0.5, 10).await?;
+        assert_eq!(within.len(), 2);
+    }
+    instance.stop().await
+}
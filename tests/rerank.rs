@@ -0,0 +1,71 @@
+mod common;
+
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+use pgevdb::rerank::Reranker;
+use pgevdb::{Backend, Collection, Content, Error, Metric, SearchOptions};
+use serde_json::json;
+
+/// Scores by text length, and drops the last score when `short` is set.
+#[derive(Debug)]
+struct ByLength {
+    short: bool,
+}
+
+impl Reranker for ByLength {
+    fn rerank<'a>(
+        &'a self,
+        _query: &'a str,
+        documents: &'a [String],
+    ) -> BoxFuture<'a, pgevdb::Result<Vec<f32>>> {
+        let mut scores: Vec<f32> = documents.iter().map(|text| text.len() as f32).collect();
+        if self.short {
+            scores.pop();
+        }
+        Box::pin(async move { Ok(scores) })
+    }
+}
+
+#[tokio::test]
+#[ignore = "downloads PostgreSQL and pgvecto.rs"]
+async fn reranks_on_a_payload_key_and_refuses_missing_scores() -> pgevdb::Result<()> {
+    let (_dir, instance) = common::launch(Backend::PgVectoRs).await?;
+    let collection = Collection::create(instance.pool(), "docs", 2, Metric::L2).await?;
+    let near = collection
+        .insert_with_payload(&[1.0, 0.0], &json!({"title": "a"}))
+        .await?;
+    let far = collection
+        .insert_with_payload(&[0.0, 1.0], &json!({"title": "a much longer title"}))
+        .await?;
+    collection
+        .insert_or_update(
+            near,
+            &[1.0, 0.0],
+            Some(&Content {
+                text: "the longest content of them all",
+                source: None,
+                language: None,
+            }),
+        )
+        .await?;
+
+    let by_title = SearchOptions::new(2).rerank_by_payload(
+        Arc::new(ByLength { short: false }),
+        "query",
+        "title",
+    );
+    let results = collection.search_with(&[1.0, 0.0], &by_title).await?;
+    assert_eq!(results[0].id, far);
+
+    let by_content = SearchOptions::new(2).rerank(Arc::new(ByLength { short: false }), "query");
+    let results = collection.search_with(&[1.0, 0.0], &by_content).await?;
+    assert_eq!(results[0].id, near);
+
+    let short = SearchOptions::new(2).rerank(Arc::new(ByLength { short: true }), "query");
+    assert!(matches!(
+        collection.search_with(&[1.0, 0.0], &short).await,
+        Err(Error::InvalidInput(_))
+    ));
+    instance.stop().await
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use pgevdb::{Backend, Collection, Error, Metric};
+use serde_json::json;
+
+/// Soft-deleted records are gone for every read and write by id until stored again.
+#[tokio::test]
+#[ignore = "downloads PostgreSQL and pgvecto.rs"]
+async fn soft_deleted_records_are_not_found() -> pgevdb::Result<()> {
+    let (_dir, instance) = common::launch(Backend::PgVectoRs).await?;
+    let collection = Collection::create(instance.pool(), "docs", 2, Metric::L2).await?;
+    let kept = collection.insert(&[1.0, 0.0]).await?;
+    let deleted = collection
+        .insert_with_payload(&[0.0, 1.0], &json!({"tag": "old"}))
+        .await?;
+    assert_eq!(collection.soft_delete(&[deleted]).await?, 1);
+
+    assert!(matches!(
+        collection
+            .set_payload(deleted, &json!({"tag": "new"}))
+            .await,
+        Err(Error::NotFound(_))
+    ));
+    assert!(matches!(
+        collection
+            .update_payload(deleted, &json!({"tag": "new"}))
+            .await,
+        Err(Error::NotFound(_))
+    ));
+    assert!(matches!(
+        collection.combine(&[(kept, 1.0), (deleted, 1.0)]).await,
+        Err(Error::NotFound(_))
+    ));
+    collection.combine(&[(kept, 1.0)]).await?;
+
+    // Storing the record again revives it
+    collection
+        .insert_or_update(deleted, &[0.0, 1.0], None)
+        .await?;
+    collection
+        .set_payload(deleted, &json!({"tag": "new"}))
+        .await?;
+    instance.stop().await
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use pgevdb::{Backend, Collection, Metric, Transform};
+
+/// Records imported with ids of their own may have any bigint id; the backfill of a reduced
+/// column reaches all of them.
+#[tokio::test]
+#[ignore = "downloads PostgreSQL and pgvecto.rs"]
+async fn backfills_records_with_non_positive_ids() -> pgevdb::Result<()> {
+    let (_dir, instance) = common::launch(Backend::PgVectoRs).await?;
+    let mut collection = Collection::create(instance.pool(), "docs", 3, Metric::L2).await?;
+    for (id, embedding) in [
+        (i64::MIN, [0.0, 0.0, 1.0]),
+        (-7, [1.0, 0.0, 0.0]),
+        (0, [0.0, 1.0, 0.0]),
+        (5, [1.0, 1.0, 0.0]),
+    ] {
+        collection.insert_or_update(id, &embedding, None).await?;
+    }
+
+    collection
+        .set_transform(Transform::truncate(3, 2)?, true)
+        .await?;
+    for (id, query) in [(i64::MIN, [0.0, 0.0]), (-7, [1.0, 0.0]), (0, [0.0, 1.0])] {
+        let results = collection.search_reduced(&query, 1).await?;
+        assert_eq!(results[0].id, id);
+    }
+    instance.stop().await
+}